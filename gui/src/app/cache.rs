@@ -8,6 +8,11 @@ pub struct Cache {
     pub coins: Vec<Coin>,
     pub spend_txs: Vec<SpendTx>,
     pub rescan_progress: Option<f64>,
+    /// Number of unconfirmed coins which disappeared, for instance because their deposit
+    /// transaction was replaced.
+    pub expired_coins: usize,
+    /// Number of disappeared unconfirmed coins the user was already notified of.
+    pub acknowledged_expired_coins: usize,
 }
 
 impl std::default::Default for Cache {
@@ -18,6 +23,8 @@ impl std::default::Default for Cache {
             coins: Vec::new(),
             spend_txs: Vec::new(),
             rescan_progress: None,
+            expired_coins: 0,
+            acknowledged_expired_coins: 0,
         }
     }
 }
@@ -36,4 +36,6 @@ pub enum Message {
     HistoryTransactions(Result<Vec<HistoryTransaction>, Error>),
     PendingTransactions(Result<Vec<HistoryTransaction>, Error>),
     LabelsUpdated(Result<HashMap<String, String>, Error>),
+    WalletBackup(Result<String, Error>),
+    ExpiredCoinsAcknowledged(Result<(), Error>),
 }
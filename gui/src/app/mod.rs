@@ -81,7 +81,7 @@ impl App {
             menu::Menu::Home => Home::new(self.wallet.clone(), &self.cache.coins).into(),
             menu::Menu::Coins => CoinsPanel::new(
                 &self.cache.coins,
-                self.wallet.main_descriptor.first_timelock_value(),
+                self.wallet.main_descriptor.first_timelock(),
             )
             .into(),
             menu::Menu::Recovery => RecoveryPanel::new(
@@ -143,6 +143,7 @@ impl App {
             Message::Info(Ok(info)) => {
                 self.cache.blockheight = info.block_height;
                 self.cache.rescan_progress = info.rescan_progress;
+                self.cache.expired_coins = info.expired_coins;
             }
             Message::StartRescan(Ok(())) => {
                 self.cache.rescan_progress = Some(0.0);
@@ -170,6 +171,24 @@ impl App {
                 self.update(Message::WalletLoaded(res))
             }
             Message::View(view::Message::Menu(menu)) => self.load_state(&menu),
+            Message::View(view::Message::AcknowledgeExpiredCoins) => {
+                self.cache.acknowledged_expired_coins = self.cache.expired_coins;
+                Command::perform(
+                    acknowledge_expired_coins(
+                        self.data_dir.clone(),
+                        self.cache.network,
+                        self.wallet.clone(),
+                        self.cache.expired_coins,
+                    ),
+                    Message::ExpiredCoinsAcknowledged,
+                )
+            }
+            Message::ExpiredCoinsAcknowledged(res) => {
+                if let Err(e) = res {
+                    warn!("Failed to store the acknowledged expired coins: {}", e);
+                }
+                Command::none()
+            }
             Message::View(view::Message::Clipboard(text)) => clipboard::write(text),
             _ => self.state.update(self.daemon.clone(), &self.cache, message),
         }
@@ -218,3 +237,24 @@ impl App {
         self.state.view(&self.cache).map(Message::View)
     }
 }
+
+// Store the number of disappeared coins the user was notified of in the wallet settings, for the
+// notification not to show up again at the next start.
+async fn acknowledge_expired_coins(
+    data_dir: PathBuf,
+    network: bitcoin::Network,
+    wallet: Arc<Wallet>,
+    count: usize,
+) -> Result<(), Error> {
+    let mut settings = settings::Settings::from_file(data_dir.clone(), network)?;
+    let checksum = wallet.descriptor_checksum();
+    if let Some(wallet_setting) = settings
+        .wallets
+        .iter_mut()
+        .find(|w| w.descriptor_checksum == checksum)
+    {
+        wallet_setting.acknowledged_expired_coins = count;
+    }
+    settings.to_file(data_dir, network)?;
+    Ok(())
+}
@@ -67,6 +67,9 @@ pub struct WalletSetting {
     pub keys: Vec<KeySetting>,
     #[serde(default)]
     pub hardware_wallets: Vec<HardwareWalletConfig>,
+    /// Number of disappeared unconfirmed coins the user was already notified of.
+    #[serde(default)]
+    pub acknowledged_expired_coins: usize,
 }
 
 impl WalletSetting {
@@ -94,6 +97,7 @@ impl From<&Wallet> for WalletSetting {
                 })
                 .collect(),
             descriptor_checksum: w.descriptor_checksum(),
+            acknowledged_expired_coins: w.acknowledged_expired_coins,
         }
     }
 }
@@ -16,7 +16,7 @@ use crate::{
         view,
     },
     daemon::{
-        model::{Coin, LabelItem, Labelled},
+        model::{Coin, LabelItem, Labelled, RecoveryTimelock},
         Daemon,
     },
 };
@@ -45,11 +45,11 @@ pub struct CoinsPanel {
     labels_edited: LabelsEdited,
     warning: Option<Error>,
     /// timelock value to pass for the heir to consume a coin.
-    timelock: u16,
+    timelock: RecoveryTimelock,
 }
 
 impl CoinsPanel {
-    pub fn new(coins: &[Coin], timelock: u16) -> Self {
+    pub fn new(coins: &[Coin], timelock: RecoveryTimelock) -> Self {
         let mut panel = Self {
             labels_edited: LabelsEdited::default(),
             coins: Coins::default(),
@@ -188,13 +188,13 @@ impl From<CoinsPanel> for Box<dyn State> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::daemon::model::Coin;
+    use crate::daemon::model::{Coin, RecoveryTimelock};
     use liana::miniscript::bitcoin;
     use std::str::FromStr;
 
     #[test]
     fn test_coins_panel_update_coins() {
-        let mut panel = CoinsPanel::new(&[], 0);
+        let mut panel = CoinsPanel::new(&[], RecoveryTimelock::Blocks(0));
         let txid = bitcoin::Txid::from_str(
             "f7bd1b2a995b689d326e51eb742eb1088c4a8f110d9cb56128fd553acc9f88e5",
         )
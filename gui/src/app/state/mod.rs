@@ -141,12 +141,16 @@ impl State for Home {
                         if coin.spend_info.is_none() {
                             if coin.block_height.is_some() {
                                 self.balance += coin.amount;
-                                let timelock = self.wallet.main_descriptor.first_timelock_value();
+                                let timelock = self.wallet.main_descriptor.first_timelock();
                                 let seq =
                                     remaining_sequence(&coin, cache.blockheight as u32, timelock);
                                 // Warn user for coins that are expiring in less than 10 percent of
-                                // the timelock.
-                                if seq <= timelock as u32 * 10 / 100 {
+                                // the timelock. Coins don't expire with an absolute timelock.
+                                if timelock
+                                    .estimated_blocks()
+                                    .map(|blocks| seq <= blocks * 10 / 100)
+                                    .unwrap_or(false)
+                                {
                                     self.expiring_coins.push(coin.outpoint);
                                 }
                                 if let Some(last) = &mut self.remaining_sequence {
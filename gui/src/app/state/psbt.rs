@@ -1,7 +1,8 @@
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
-use iced::Command;
+use iced::{time, widget::qr_code, Command, Subscription};
 use liana::{
     descriptors::LianaPolicy,
     miniscript::bitcoin::{bip32::Fingerprint, psbt::Psbt},
@@ -26,6 +27,7 @@ use crate::{
         Daemon,
     },
     hw::{list_hardware_wallets, HardwareWallet},
+    qr::{self, QrFormat},
 };
 
 pub trait Action {
@@ -43,6 +45,9 @@ pub trait Action {
     ) -> Command<Message> {
         Command::none()
     }
+    fn subscription(&self) -> Subscription<Message> {
+        Subscription::none()
+    }
     fn view(&self) -> Element<view::Message>;
 }
 
@@ -77,6 +82,14 @@ impl PsbtState {
         }
     }
 
+    pub fn subscription(&self) -> Subscription<Message> {
+        if let Some(action) = &self.action {
+            action.subscription()
+        } else {
+            Subscription::none()
+        }
+    }
+
     pub fn update(
         &mut self,
         daemon: Arc<dyn Daemon + Sync + Send>,
@@ -103,6 +116,9 @@ impl PsbtState {
                     self.action = Some(Box::new(action));
                     return cmd;
                 }
+                view::SpendTxMessage::ShowQrCode => {
+                    self.action = Some(Box::new(QrCodeAction::new(self.tx.psbt.clone())));
+                }
                 view::SpendTxMessage::Broadcast => {
                     self.action = Some(Box::<BroadcastAction>::default());
                 }
@@ -300,6 +316,8 @@ pub struct SignAction {
     hws: Vec<HardwareWallet>,
     error: Option<Error>,
     signed: HashSet<Fingerprint>,
+    // Passphrase of the hot signer, in case its mnemonic is encrypted.
+    passphrase: form::Value<String>,
 }
 
 impl SignAction {
@@ -311,6 +329,7 @@ impl SignAction {
             hws: Vec::new(),
             error: None,
             signed,
+            passphrase: form::Value::default(),
         }
     }
 }
@@ -352,11 +371,26 @@ impl Action for SignAction {
             }
             Message::View(view::Message::Spend(view::SpendTxMessage::SelectHotSigner)) => {
                 self.processing = true;
+                if self.wallet.signer.is_none() && self.wallet.encrypted_signer.is_some() {
+                    return Command::perform(
+                        sign_psbt_with_encrypted_signer(
+                            self.wallet.clone(),
+                            std::mem::take(&mut self.passphrase.value),
+                            tx.psbt.clone(),
+                        ),
+                        Message::Signed,
+                    );
+                }
                 return Command::perform(
                     sign_psbt_with_hot_signer(self.wallet.clone(), tx.psbt.clone()),
                     Message::Signed,
                 );
             }
+            Message::View(view::Message::Spend(
+                view::SpendTxMessage::HotSignerPassphraseEdited(passphrase),
+            )) => {
+                self.passphrase.value = passphrase;
+            }
             Message::Signed(res) => match res {
                 Err(e) => self.error = Some(e),
                 Ok((psbt, fingerprint)) => {
@@ -407,11 +441,14 @@ impl Action for SignAction {
         view::psbt::sign_action(
             self.error.as_ref(),
             &self.hws,
-            self.wallet.signer.as_ref().map(|s| s.fingerprint()),
+            self.wallet.hot_signer_fingerprint(),
             self.wallet
-                .signer
+                .hot_signer_fingerprint()
+                .and_then(|fingerprint| self.wallet.keys_aliases.get(&fingerprint)),
+            self.wallet
+                .encrypted_signer
                 .as_ref()
-                .and_then(|signer| self.wallet.keys_aliases.get(&signer.fingerprint)),
+                .map(|_| &self.passphrase),
             self.processing,
             self.chosen_hw,
             &self.signed,
@@ -424,9 +461,32 @@ async fn sign_psbt_with_hot_signer(
     psbt: Psbt,
 ) -> Result<(Psbt, Fingerprint), Error> {
     if let Some(signer) = &wallet.signer {
-        let psbt = signer.sign_psbt(psbt).map_err(|e| {
-            WalletError::HotSigner(format!("Hot signer failed to sign psbt: {}", e))
-        })?;
+        let psbt = signer
+            .sign_psbt_checked(psbt, &wallet.main_descriptor)
+            .map_err(|e| {
+                WalletError::HotSigner(format!("Hot signer failed to sign psbt: {}", e))
+            })?;
+        Ok((psbt, signer.fingerprint()))
+    } else {
+        Err(WalletError::HotSigner("Hot signer not loaded".to_string()).into())
+    }
+}
+
+// The signer is only decrypted for signing, and its key material erased right after.
+async fn sign_psbt_with_encrypted_signer(
+    wallet: Arc<Wallet>,
+    passphrase: String,
+    psbt: Psbt,
+) -> Result<(Psbt, Fingerprint), Error> {
+    if let Some(encrypted) = &wallet.encrypted_signer {
+        let signer = encrypted
+            .decrypt(&passphrase)
+            .map_err(|e| WalletError::HotSigner(e.to_string()))?;
+        let psbt = signer
+            .sign_psbt_checked(psbt, &wallet.main_descriptor)
+            .map_err(|e| {
+                WalletError::HotSigner(format!("Hot signer failed to sign psbt: {}", e))
+            })?;
         Ok((psbt, signer.fingerprint()))
     } else {
         Err(WalletError::HotSigner("Hot signer not loaded".to_string()).into())
@@ -491,8 +551,7 @@ impl Action for UpdateAction {
                     Ok(()) => {
                         self.success = true;
                         self.error = None;
-                        let psbt = Psbt::deserialize(&base64::decode(&self.updated.value).unwrap())
-                            .expect("Already checked");
+                        let psbt = parse_psbt(&self.updated.value).expect("Already checked");
                         for (i, input) in tx.psbt.inputs.iter_mut().enumerate() {
                             if tx
                                 .psbt
@@ -525,10 +584,7 @@ impl Action for UpdateAction {
             }
             Message::View(view::Message::ImportSpend(view::ImportSpendMessage::PsbtEdited(s))) => {
                 self.updated.value = s;
-                if let Some(psbt) = base64::decode(&self.updated.value)
-                    .ok()
-                    .and_then(|bytes| Psbt::deserialize(&bytes).ok())
-                {
+                if let Some(psbt) = parse_psbt(&self.updated.value) {
                     self.updated.valid = tx.psbt.unsigned_tx.txid() == psbt.unsigned_tx.txid();
                 }
             }
@@ -536,10 +592,7 @@ impl Action for UpdateAction {
                 if self.updated.valid {
                     self.processing = true;
                     self.error = None;
-                    let updated = Psbt::deserialize(
-                        &base64::decode(&self.updated.value).expect("Already checked"),
-                    )
-                    .unwrap();
+                    let updated = parse_psbt(&self.updated.value).expect("Already checked");
                     return Command::perform(
                         async move { daemon.update_spend_tx(&updated).map_err(|e| e.into()) },
                         Message::Updated,
@@ -552,3 +605,88 @@ impl Action for UpdateAction {
         Command::none()
     }
 }
+
+/// Parse a PSBT of either version given base64 encoded or as the frames of an animated QR code
+/// (UR or BBQr) scanned from a signing device, separated by whitespaces.
+pub fn parse_psbt(s: &str) -> Option<Psbt> {
+    liana::psbt::from_base64(s)
+        .ok()
+        .or_else(|| qr::decode_psbt(s.split_whitespace()).ok())
+}
+
+// The number of bytes of the PSBT in each frame of the animated QR code, small enough for the
+// codes to be scanned reliably by the signing devices cameras.
+const QR_FRAGMENT_LEN: usize = 200;
+
+/// Display the PSBT as an animated QR code, for air-gapped signing devices.
+pub struct QrCodeAction {
+    psbt: Psbt,
+    format: QrFormat,
+    frames: Vec<qr_code::State>,
+    current: usize,
+}
+
+impl QrCodeAction {
+    pub fn new(psbt: Psbt) -> Self {
+        let mut action = Self {
+            psbt,
+            format: QrFormat::Ur,
+            frames: Vec::new(),
+            current: 0,
+        };
+        action.set_format(QrFormat::Ur);
+        action
+    }
+
+    fn set_format(&mut self, format: QrFormat) {
+        self.format = format;
+        self.current = 0;
+        // Uppercase frames are encoded in the denser alphanumeric mode of QR codes.
+        self.frames = qr::encode_psbt(&self.psbt, format, QR_FRAGMENT_LEN)
+            .into_iter()
+            .map(|frame| {
+                qr_code::State::new(frame.to_uppercase()).expect("Frames are small enough")
+            })
+            .collect();
+    }
+}
+
+impl Action for QrCodeAction {
+    fn subscription(&self) -> Subscription<Message> {
+        if self.frames.len() > 1 {
+            time::every(Duration::from_millis(300))
+                .map(|_| Message::View(view::Message::Spend(view::SpendTxMessage::NextQrFrame)))
+        } else {
+            Subscription::none()
+        }
+    }
+
+    fn update(
+        &mut self,
+        _daemon: Arc<dyn Daemon + Sync + Send>,
+        message: Message,
+        _tx: &mut SpendTx,
+    ) -> Command<Message> {
+        match message {
+            Message::View(view::Message::Spend(view::SpendTxMessage::NextQrFrame)) => {
+                self.current = (self.current + 1) % self.frames.len();
+            }
+            Message::View(view::Message::Spend(view::SpendTxMessage::SelectQrFormat(format))) => {
+                if format != self.format {
+                    self.set_format(format);
+                }
+            }
+            _ => {}
+        }
+        Command::none()
+    }
+
+    fn view(&self) -> Element<view::Message> {
+        view::psbt::qr_code_action(
+            &self.frames[self.current],
+            self.format,
+            self.current,
+            self.frames.len(),
+        )
+    }
+}
@@ -1,8 +1,7 @@
 use std::sync::Arc;
 
-use iced::Command;
+use iced::{Command, Subscription};
 
-use liana::miniscript::bitcoin::psbt::Psbt;
 use liana_ui::{
     component::{form, modal},
     widget::Element,
@@ -109,6 +108,14 @@ impl State for PsbtsPanel {
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        if let Some(tx) = &self.selected_tx {
+            tx.subscription()
+        } else {
+            Subscription::none()
+        }
+    }
+
     fn load(&self, daemon: Arc<dyn Daemon + Sync + Send>) -> Command<Message> {
         let daemon = daemon.clone();
         Command::perform(
@@ -170,19 +177,13 @@ impl ImportPsbtModal {
             }
             Message::View(view::Message::ImportSpend(view::ImportSpendMessage::PsbtEdited(s))) => {
                 self.imported.value = s;
-                self.imported.valid = base64::decode(&self.imported.value)
-                    .ok()
-                    .and_then(|bytes| Psbt::deserialize(&bytes).ok())
-                    .is_some();
+                self.imported.valid = psbt::parse_psbt(&self.imported.value).is_some();
             }
             Message::View(view::Message::ImportSpend(view::ImportSpendMessage::Confirm)) => {
                 if self.imported.valid {
                     self.processing = true;
                     self.error = None;
-                    let imported = Psbt::deserialize(
-                        &base64::decode(&self.imported.value).expect("Already checked"),
-                    )
-                    .unwrap();
+                    let imported = psbt::parse_psbt(&self.imported.value).expect("Already checked");
                     return Command::perform(
                         async move { daemon.update_spend_tx(&imported).map_err(|e| e.into()) },
                         Message::Updated,
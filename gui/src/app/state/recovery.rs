@@ -1,10 +1,9 @@
-use std::collections::HashSet;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use iced::Command;
+use iced::{Command, Subscription};
 
-use liana::miniscript::bitcoin::bip32::{DerivationPath, Fingerprint};
+use liana::descriptors::PathInfo;
 use liana_ui::{component::form, widget::Element};
 
 use crate::{
@@ -19,7 +18,7 @@ use crate::{
         wallet::Wallet,
     },
     daemon::{
-        model::{remaining_sequence, Coin, SpendTx},
+        model::{remaining_sequence, Coin, RecoveryTimelock, SpendTx},
         Daemon,
     },
 };
@@ -64,8 +63,7 @@ impl State for RecoveryPanel {
                         if path.number_of_coins > 0 {
                             Some(view::recovery::recovery_path_view(
                                 i,
-                                path.threshold,
-                                &path.origins,
+                                &path.path,
                                 path.total_amount,
                                 path.number_of_coins,
                                 &self.wallet.keys_aliases,
@@ -182,6 +180,14 @@ impl State for RecoveryPanel {
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        if let Some(generated) = &self.generated {
+            generated.subscription()
+        } else {
+            Subscription::none()
+        }
+    }
+
     fn load(&self, daemon: Arc<dyn Daemon + Sync + Send>) -> Command<Message> {
         let daemon = daemon.clone();
         Command::perform(
@@ -203,9 +209,8 @@ impl From<RecoveryPanel> for Box<dyn State> {
 }
 
 pub struct RecoveryPath {
-    threshold: usize,
-    sequence: u16,
-    origins: Vec<(Fingerprint, HashSet<DerivationPath>)>,
+    path: PathInfo,
+    sequence: RecoveryTimelock,
     total_amount: Amount,
     number_of_coins: usize,
 }
@@ -230,13 +235,11 @@ fn recovery_paths(wallet: &Wallet, coins: &[Coin], blockheight: i32) -> Vec<Reco
                     },
                 );
 
-            let (threshold, origins) = path.thresh_origins();
             RecoveryPath {
+                path: path.clone(),
                 total_amount,
                 number_of_coins,
                 sequence,
-                threshold,
-                origins: origins.into_iter().collect(),
             }
         })
         .collect()
@@ -3,9 +3,12 @@ use std::convert::From;
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use iced::Command;
+use iced::{clipboard, Command};
 
-use liana::miniscript::bitcoin::{bip32::Fingerprint, Network};
+use liana::{
+    backup::{HardwareWalletRegistration, KeyAlias},
+    miniscript::bitcoin::{bip32::Fingerprint, Network},
+};
 
 use liana_ui::{
     component::{form, modal},
@@ -156,6 +159,19 @@ impl State for WalletSettingsState {
                 self.modal = None;
                 Command::none()
             }
+            Message::View(view::Message::Settings(view::SettingsMessage::ExportBackup)) => {
+                Command::perform(
+                    export_backup(daemon, self.wallet.clone()),
+                    Message::WalletBackup,
+                )
+            }
+            Message::WalletBackup(res) => match res {
+                Ok(backup) => clipboard::write(backup),
+                Err(e) => {
+                    self.warning = Some(e);
+                    Command::none()
+                }
+            },
             Message::View(view::Message::Settings(view::SettingsMessage::RegisterWallet)) => {
                 self.modal = Some(RegisterWalletModal::new(
                     self.data_dir.clone(),
@@ -360,3 +376,30 @@ async fn update_keys_aliases(
 
     Ok(())
 }
+
+/// Get the wallet backup from the daemon, along with the metadata only known to the GUI.
+async fn export_backup(
+    daemon: Arc<dyn Daemon + Sync + Send>,
+    wallet: Arc<Wallet>,
+) -> Result<String, Error> {
+    let mut backup = daemon.get_backup()?;
+    backup.name = Some(wallet.name.clone());
+    backup.keys = wallet
+        .keys_aliases
+        .iter()
+        .map(|(fingerprint, name)| KeyAlias {
+            master_fingerprint: *fingerprint,
+            name: name.clone(),
+        })
+        .collect();
+    backup.hardware_wallets = wallet
+        .hardware_wallets
+        .iter()
+        .map(|hw| HardwareWalletRegistration {
+            kind: hw.kind.clone(),
+            fingerprint: hw.fingerprint,
+            token: hw.token.clone(),
+        })
+        .collect();
+    Ok(backup.to_json())
+}
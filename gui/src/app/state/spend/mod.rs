@@ -3,7 +3,7 @@ mod step;
 use std::collections::HashSet;
 use std::sync::Arc;
 
-use iced::Command;
+use iced::{Command, Subscription};
 
 use liana::miniscript::bitcoin::{Network, OutPoint};
 use liana_ui::widget::Element;
@@ -26,7 +26,7 @@ pub struct CreateSpendPanel {
 impl CreateSpendPanel {
     pub fn new(wallet: Arc<Wallet>, coins: &[Coin], blockheight: u32, network: Network) -> Self {
         let descriptor = wallet.main_descriptor.clone();
-        let timelock = descriptor.first_timelock_value();
+        let timelock = descriptor.first_timelock();
         Self {
             draft: step::TransactionDraft::new(network),
             current: 0,
@@ -48,7 +48,7 @@ impl CreateSpendPanel {
         network: Network,
     ) -> Self {
         let descriptor = wallet.main_descriptor.clone();
-        let timelock = descriptor.first_timelock_value();
+        let timelock = descriptor.first_timelock();
         Self {
             draft: step::TransactionDraft::new(network),
             current: 0,
@@ -104,6 +104,14 @@ impl State for CreateSpendPanel {
         Command::none()
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        if let Some(step) = self.steps.get(self.current) {
+            step.subscription()
+        } else {
+            Subscription::none()
+        }
+    }
+
     fn load(&self, daemon: Arc<dyn Daemon + Sync + Send>) -> Command<Message> {
         let daemon1 = daemon.clone();
         let daemon2 = daemon.clone();
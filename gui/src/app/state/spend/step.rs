@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use iced::Command;
+use iced::{Command, Subscription};
 use liana::{
     descriptors::LianaDescriptor,
     miniscript::bitcoin::{
@@ -18,7 +18,7 @@ use liana_ui::{
 use crate::{
     app::{cache::Cache, error::Error, message::Message, state::psbt, view, wallet::Wallet},
     daemon::{
-        model::{remaining_sequence, Coin, SpendTx},
+        model::{remaining_sequence, Coin, RecoveryTimelock, SpendTx},
         Daemon,
     },
 };
@@ -59,6 +59,9 @@ pub trait Step {
     ) -> Command<Message>;
     fn apply(&self, _draft: &mut TransactionDraft) {}
     fn load(&mut self, _draft: &TransactionDraft) {}
+    fn subscription(&self) -> Subscription<Message> {
+        Subscription::none()
+    }
 }
 
 pub struct DefineSpend {
@@ -68,7 +71,7 @@ pub struct DefineSpend {
     is_duplicate: bool,
 
     descriptor: LianaDescriptor,
-    timelock: u16,
+    timelock: RecoveryTimelock,
     coins: Vec<(Coin, bool)>,
     coins_labels: HashMap<String, String>,
     batch_label: form::Value<String>,
@@ -79,7 +82,7 @@ pub struct DefineSpend {
 }
 
 impl DefineSpend {
-    pub fn new(descriptor: LianaDescriptor, coins: &[Coin], timelock: u16) -> Self {
+    pub fn new(descriptor: LianaDescriptor, coins: &[Coin], timelock: RecoveryTimelock) -> Self {
         let balance_available = coins
             .iter()
             .filter_map(|coin| {
@@ -558,6 +561,14 @@ impl Step for SaveSpend {
         self.spend = Some(psbt::PsbtState::new(self.wallet.clone(), tx, false));
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        if let Some(spend) = &self.spend {
+            spend.subscription()
+        } else {
+            Subscription::none()
+        }
+    }
+
     fn update(
         &mut self,
         daemon: Arc<dyn Daemon + Sync + Send>,
@@ -16,13 +16,13 @@ use crate::{
         menu::Menu,
         view::{label, message::Message},
     },
-    daemon::model::{remaining_sequence, Coin},
+    daemon::model::{remaining_sequence, Coin, RecoveryTimelock},
 };
 
 pub fn coins_view<'a>(
     cache: &Cache,
     coins: &'a [Coin],
-    timelock: u16,
+    timelock: RecoveryTimelock,
     selected: &[usize],
     labels: &'a HashMap<String, String>,
     labels_editing: &'a HashMap<String, form::Value<String>>,
@@ -55,7 +55,7 @@ pub fn coins_view<'a>(
 #[allow(clippy::collapsible_else_if)]
 fn coin_list_view<'a>(
     coin: &'a Coin,
-    timelock: u16,
+    timelock: RecoveryTimelock,
     blockheight: u32,
     index: usize,
     collapsed: bool,
@@ -89,7 +89,10 @@ fn coin_list_view<'a>(
                                     badge::unconfirmed()
                                 } else {
                                     let seq = remaining_sequence(coin, blockheight, timelock);
-                                    coin_sequence_label(seq, timelock as u32)
+                                    coin_sequence_label(
+                                        seq,
+                                        timelock.estimated_blocks().unwrap_or(seq),
+                                    )
                                 })
                                 .spacing(10)
                                 .align_items(Alignment::Center)
@@ -121,8 +124,9 @@ fn coin_list_view<'a>(
                             .width(Length::Fill),
                         )
                         .push_maybe(if coin.spend_info.is_none() {
-                            if let Some(b) = coin.block_height {
-                                if blockheight > b as u32 + timelock as u32 {
+                            if coin.block_height.is_some() {
+                                let seq = remaining_sequence(coin, blockheight, timelock);
+                                if seq == 0 {
                                     Some(Container::new(
                                         p1_bold("One of the recovery path is available")
                                             .style(color::RED),
@@ -130,7 +134,7 @@ fn coin_list_view<'a>(
                                 } else {
                                     Some(Container::new(p1_bold(format!(
                                         "One of the recovery path will be available in {} blocks",
-                                        b as u32 + timelock as u32 - blockheight
+                                        seq
                                     ))))
                                 }
                             } else {
@@ -1,4 +1,4 @@
-use crate::app::menu::Menu;
+use crate::{app::menu::Menu, qr::QrFormat};
 use liana::miniscript::bitcoin::bip32::Fingerprint;
 
 #[derive(Debug, Clone)]
@@ -17,6 +17,7 @@ pub enum Message {
     Next,
     Previous,
     SelectHardwareWallet(usize),
+    AcknowledgeExpiredCoins,
 }
 
 #[derive(Debug, Clone)]
@@ -54,8 +55,12 @@ pub enum SpendTxMessage {
     Confirm,
     Cancel,
     SelectHotSigner,
+    HotSignerPassphraseEdited(String),
     EditPsbt,
     PsbtEdited(String),
+    ShowQrCode,
+    SelectQrFormat(QrFormat),
+    NextQrFrame,
     Next,
 }
 
@@ -65,6 +70,7 @@ pub enum SettingsMessage {
     EditWalletSettings,
     AboutSection,
     RegisterWallet,
+    ExportBackup,
     FingerprintAliasEdited(Fingerprint, String),
     Save,
     Edit(usize, SettingsEditMessage),
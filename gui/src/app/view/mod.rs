@@ -18,7 +18,7 @@ use warning::warn;
 
 use iced::{
     widget::{column, row, scrollable, Space},
-    Length,
+    Alignment, Length,
 };
 
 use liana_ui::{
@@ -163,6 +163,25 @@ pub fn sidebar<'a>(menu: &Menu, cache: &'a Cache) -> Container<'a, Message> {
                                 .padding(5)
                                 .style(theme::Pill::Simple)
                         }))
+                        .push_maybe(new_expired_coins(cache).map(|count| {
+                            Container::new(
+                                Row::new()
+                                    .spacing(5)
+                                    .align_items(Alignment::Center)
+                                    .push(text(format!(
+                                        "  {} unconfirmed coin{} disappeared",
+                                        count,
+                                        if count > 1 { "s" } else { "" }
+                                    )))
+                                    .push(
+                                        Button::new(cross_icon())
+                                            .style(theme::Button::Transparent)
+                                            .on_press(Message::AcknowledgeExpiredCoins),
+                                    ),
+                            )
+                            .padding(5)
+                            .style(theme::Pill::Warning)
+                        }))
                         .push(settings_button),
                 )
                 .height(Length::Shrink),
@@ -171,6 +190,15 @@ pub fn sidebar<'a>(menu: &Menu, cache: &'a Cache) -> Container<'a, Message> {
     .style(theme::Container::Foreground)
 }
 
+// The number of unconfirmed coins which disappeared since the user last dismissed the
+// notification, if any.
+fn new_expired_coins(cache: &Cache) -> Option<usize> {
+    cache
+        .expired_coins
+        .checked_sub(cache.acknowledged_expired_coins)
+        .filter(|count| *count > 0)
+}
+
 pub fn dashboard<'a, T: Into<Element<'a, Message>>>(
     menu: &'a Menu,
     cache: &'a Cache,
@@ -1,7 +1,10 @@
 use std::collections::{HashMap, HashSet};
 
 use iced::{
-    widget::{scrollable, tooltip, Space},
+    widget::{
+        qr_code::{self, QRCode},
+        scrollable, tooltip, Space,
+    },
     Alignment, Length,
 };
 
@@ -37,6 +40,7 @@ use crate::{
     },
     daemon::model::{Coin, SpendStatus, SpendTx},
     hw::HardwareWallet,
+    qr::QrFormat,
 };
 
 #[allow(clippy::too_many_arguments)]
@@ -254,6 +258,9 @@ pub fn spend_overview_view<'a>(
                                                 )
                                                 .on_press(Message::Clipboard(tx.psbt.to_string())),
                                             )
+                                            .push(button::secondary(None, "QR code").on_press(
+                                                Message::Spend(SpendTxMessage::ShowQrCode),
+                                            ))
                                             .push(
                                                 button::secondary(
                                                     Some(icon::import_icon()),
@@ -425,6 +432,13 @@ pub fn path_view<'a>(
     sigs: &'a PathSpendInfo,
     key_aliases: &'a HashMap<Fingerprint, String>,
 ) -> Element<'a, Message> {
+    // For a threshold of sub-paths, the threshold and the signatures count are expressed in
+    // number of sub-paths. Show the signatures status of each of them.
+    if let PathInfo::Thresh(threshold, subs) = path {
+        if subs.len() == sigs.sub_paths.len() {
+            return thresh_path_view(*threshold, subs, sigs, key_aliases);
+        }
+    }
     let mut keys: Vec<(Fingerprint, HashSet<DerivationPath>)> =
         path.thresh_origins().1.into_iter().collect();
     let missing_signatures = if sigs.sigs_count >= sigs.threshold {
@@ -517,6 +531,51 @@ pub fn path_view<'a>(
     .into()
 }
 
+fn thresh_path_view<'a>(
+    threshold: usize,
+    subs: &'a [PathInfo],
+    sigs: &'a PathSpendInfo,
+    key_aliases: &'a HashMap<Fingerprint, String>,
+) -> Element<'a, Message> {
+    let missing_sub_paths = threshold.saturating_sub(sigs.sigs_count);
+    Column::new()
+        .spacing(10)
+        .push(
+            Row::new()
+                .align_items(Alignment::Center)
+                .push(if missing_sub_paths == 0 {
+                    icon::circle_check_icon().style(color::GREEN)
+                } else {
+                    icon::circle_cross_icon().style(color::GREY_3)
+                })
+                .push(Space::with_width(Length::Fixed(20.0)))
+                .push(
+                    p1_regular(if missing_sub_paths == 0 {
+                        format!("{} of the following are satisfied:", threshold)
+                    } else {
+                        format!(
+                            "{} more of the following {} to be satisfied:",
+                            missing_sub_paths,
+                            subs.len()
+                        )
+                    })
+                    .style(color::GREY_3),
+                ),
+        )
+        .push(
+            Row::new()
+                .push(Space::with_width(Length::Fixed(40.0)))
+                .push(
+                    subs.iter()
+                        .zip(sigs.sub_paths.iter())
+                        .fold(Column::new().spacing(10), |col, (sub, sub_sigs)| {
+                            col.push(path_view(sub, sub_sigs, key_aliases))
+                        }),
+                ),
+        )
+        .into()
+}
+
 pub fn inputs_and_outputs_view<'a>(
     coins: &'a [Coin],
     tx: &'a Transaction,
@@ -960,6 +1019,7 @@ pub fn sign_action<'a>(
     hws: &'a [HardwareWallet],
     signer: Option<Fingerprint>,
     signer_alias: Option<&'a String>,
+    signer_passphrase: Option<&form::Value<String>>,
     processing: bool,
     chosen_hw: Option<usize>,
     signed: &HashSet<Fingerprint>,
@@ -996,15 +1056,36 @@ pub fn sign_action<'a>(
                             },
                         ))
                         .push_maybe(signer.map(|fingerprint| {
-                            Button::new(if signed.contains(&fingerprint) {
+                            let button = Button::new(if signed.contains(&fingerprint) {
                                 hw::sign_success_hot_signer(fingerprint, signer_alias)
                             } else {
                                 hw::hot_signer(fingerprint, signer_alias)
                             })
-                            .on_press(Message::Spend(SpendTxMessage::SelectHotSigner))
                             .padding(10)
                             .style(theme::Button::Border)
-                            .width(Length::Fill)
+                            .width(Length::Fill);
+                            // An encrypted mnemonic can only be used once the passphrase is given.
+                            let button = if signer_passphrase
+                                .map(|p| !p.value.is_empty())
+                                .unwrap_or(true)
+                            {
+                                button.on_press(Message::Spend(SpendTxMessage::SelectHotSigner))
+                            } else {
+                                button
+                            };
+                            Column::new()
+                                .spacing(5)
+                                .push(button)
+                                .push_maybe(signer_passphrase.map(|passphrase| {
+                                    form::Form::new("Passphrase", passphrase, |msg| {
+                                        Message::Spend(SpendTxMessage::HotSignerPassphraseEdited(
+                                            msg,
+                                        ))
+                                    })
+                                    .password()
+                                    .size(20)
+                                    .padding(10)
+                                }))
                         }))
                         .width(Length::Fill),
                 )
@@ -1041,11 +1122,16 @@ pub fn update_spend_view<'a>(
                     Column::new()
                         .spacing(10)
                         .push(text("Insert updated PSBT:").bold())
+                        .push(text(
+                            "Either base64 encoded, or the scanned frames of an animated QR code separated by spaces or new lines.",
+                        ))
                         .push(
                             form::Form::new_trimmed("PSBT", updated, move |msg| {
                                 Message::ImportSpend(ImportSpendMessage::PsbtEdited(msg))
                             })
-                            .warning("Please enter the correct base64 encoded PSBT")
+                            .warning(
+                                "Please enter the correct base64 encoded PSBT or the scanned QR codes",
+                            )
                             .size(20)
                             .padding(10),
                         )
@@ -1065,6 +1151,41 @@ pub fn update_spend_view<'a>(
         .into()
 }
 
+pub fn qr_code_action<'a>(
+    qr: &'a qr_code::State,
+    format: QrFormat,
+    frame: usize,
+    frames: usize,
+) -> Element<'a, Message> {
+    let format_button = |f: QrFormat, name: &'static str| {
+        if f == format {
+            button::primary(None, name)
+        } else {
+            button::border(None, name).on_press(Message::Spend(SpendTxMessage::SelectQrFormat(f)))
+        }
+    };
+    card::simple(
+        Column::new()
+            .spacing(20)
+            .align_items(Alignment::Center)
+            .push(text("Scan the PSBT with your signing device").bold())
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .push(format_button(QrFormat::Ur, "UR"))
+                    .push(format_button(QrFormat::Bbqr, "BBQr")),
+            )
+            .push(
+                Container::new(QRCode::new(qr).cell_size(4))
+                    .padding(10)
+                    .style(theme::Container::QrCode),
+            )
+            .push(p2_regular(format!("Frame {}/{}", frame + 1, frames)).style(color::GREY_3)),
+    )
+    .width(Length::Fixed(500.0))
+    .into()
+}
+
 pub fn update_spend_success_view<'a>() -> Element<'a, Message> {
     Column::new()
         .push(
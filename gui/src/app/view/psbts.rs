@@ -30,7 +30,7 @@ pub fn import_psbt_view<'a>(
                     form::Form::new_trimmed("PSBT", imported, move |msg| {
                         Message::ImportSpend(ImportSpendMessage::PsbtEdited(msg))
                     })
-                    .warning("Please enter a base64 encoded PSBT")
+                    .warning("Please enter a base64 encoded PSBT or the scanned QR codes")
                     .size(20)
                     .padding(10),
                 )
@@ -1,13 +1,13 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use iced::{
     widget::{checkbox, tooltip, Space},
     Alignment, Length,
 };
 
-use liana::miniscript::bitcoin::{
-    bip32::{DerivationPath, Fingerprint},
-    Amount,
+use liana::{
+    descriptors::PathInfo,
+    miniscript::bitcoin::{bip32::Fingerprint, Amount},
 };
 
 use liana_ui::{
@@ -136,8 +136,7 @@ pub fn recovery<'a>(
 
 pub fn recovery_path_view<'a>(
     index: usize,
-    threshold: usize,
-    origins: &'a [(Fingerprint, HashSet<DerivationPath>)],
+    path: &'a PathInfo,
     total_amount: Amount,
     number_of_coins: usize,
     key_aliases: &'a HashMap<Fingerprint, String>,
@@ -149,40 +148,7 @@ pub fn recovery_path_view<'a>(
         }))
         .push(
             Column::new()
-                .push(
-                    Row::new()
-                        .align_items(Alignment::Center)
-                        .spacing(10)
-                        .push(
-                            text(format!(
-                                "{} signature{} from",
-                                threshold,
-                                if threshold > 1 { "s" } else { "" }
-                            ))
-                            .bold(),
-                        )
-                        .push(origins.iter().fold(
-                            Row::new().align_items(Alignment::Center).spacing(5),
-                            |row, (fg, _)| {
-                                row.push(if let Some(alias) = key_aliases.get(fg) {
-                                    Container::new(
-                                        tooltip::Tooltip::new(
-                                            Container::new(text(alias))
-                                                .padding(5)
-                                                .style(theme::Container::Pill(theme::Pill::Simple)),
-                                            fg.to_string(),
-                                            tooltip::Position::Bottom,
-                                        )
-                                        .style(theme::Container::Card(theme::Card::Simple)),
-                                    )
-                                } else {
-                                    Container::new(text(fg.to_string()))
-                                        .padding(5)
-                                        .style(theme::Container::Pill(theme::Pill::Simple))
-                                })
-                            },
-                        )),
-                )
+                .push(path_keys_view(path, key_aliases))
                 .push(
                     Row::new()
                         .spacing(5)
@@ -201,3 +167,60 @@ pub fn recovery_path_view<'a>(
         .spacing(20)
         .into()
 }
+
+// The signatures required by this path. A threshold of sub-paths is shown as the list of its
+// sub-paths.
+fn path_keys_view<'a>(
+    path: &'a PathInfo,
+    key_aliases: &'a HashMap<Fingerprint, String>,
+) -> Element<'a, Message> {
+    if let PathInfo::Thresh(threshold, subs) = path {
+        return Column::new()
+            .spacing(5)
+            .push(text(format!("{} of:", threshold)).bold())
+            .push(
+                Row::new()
+                    .push(Space::with_width(Length::Fixed(20.0)))
+                    .push(subs.iter().fold(Column::new().spacing(5), |col, sub| {
+                        col.push(path_keys_view(sub, key_aliases))
+                    })),
+            )
+            .into();
+    }
+    let (threshold, origins) = path.thresh_origins();
+    let mut origins: Vec<Fingerprint> = origins.into_keys().collect();
+    origins.sort();
+    Row::new()
+        .align_items(Alignment::Center)
+        .spacing(10)
+        .push(
+            text(format!(
+                "{} signature{} from",
+                threshold,
+                if threshold > 1 { "s" } else { "" }
+            ))
+            .bold(),
+        )
+        .push(origins.into_iter().fold(
+            Row::new().align_items(Alignment::Center).spacing(5),
+            |row, fg| {
+                row.push(if let Some(alias) = key_aliases.get(&fg) {
+                    Container::new(
+                        tooltip::Tooltip::new(
+                            Container::new(text(alias))
+                                .padding(5)
+                                .style(theme::Container::Pill(theme::Pill::Simple)),
+                            fg.to_string(),
+                            tooltip::Position::Bottom,
+                        )
+                        .style(theme::Container::Card(theme::Card::Simple)),
+                    )
+                } else {
+                    Container::new(text(fg.to_string()))
+                        .padding(5)
+                        .style(theme::Container::Pill(theme::Pill::Simple))
+                })
+            },
+        ))
+        .into()
+}
@@ -582,6 +582,10 @@ pub fn wallet_settings<'a>(
                                 button::secondary(Some(icon::clipboard_icon()), "Copy")
                                     .on_press(Message::Clipboard(descriptor.to_owned())),
                             )
+                            .push(
+                                button::secondary(Some(icon::clipboard_icon()), "Copy backup")
+                                    .on_press(Message::Settings(SettingsMessage::ExportBackup)),
+                            )
                             .push(
                                 button::primary(
                                     Some(icon::chip_icon()),
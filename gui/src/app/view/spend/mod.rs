@@ -26,7 +26,7 @@ use crate::{
         menu::Menu,
         view::{coins, dashboard, message::*, psbt},
     },
-    daemon::model::{remaining_sequence, Coin, SpendTx},
+    daemon::model::{remaining_sequence, Coin, RecoveryTimelock, SpendTx},
 };
 
 #[allow(clippy::too_many_arguments)]
@@ -91,7 +91,7 @@ pub fn create_spend_tx<'a>(
     total_amount: Amount,
     is_valid: bool,
     duplicate: bool,
-    timelock: u16,
+    timelock: RecoveryTimelock,
     coins: &[(Coin, bool)],
     coins_labels: &'a HashMap<String, String>,
     batch_label: &form::Value<String>,
@@ -348,7 +348,7 @@ fn coin_list_view<'a>(
     i: usize,
     coin: &Coin,
     coins_labels: &'a HashMap<String, String>,
-    timelock: u16,
+    timelock: RecoveryTimelock,
     blockheight: u32,
     selected: bool,
 ) -> Element<'a, Message> {
@@ -371,7 +371,7 @@ fn coin_list_view<'a>(
                     badge::unconfirmed()
                 } else {
                     let seq = remaining_sequence(coin, blockheight, timelock);
-                    coins::coin_sequence_label(seq, timelock as u32)
+                    coins::coin_sequence_label(seq, timelock.estimated_blocks().unwrap_or(seq))
                 })
                 .spacing(10)
                 .align_items(Alignment::Center)
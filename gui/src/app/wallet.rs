@@ -4,10 +4,13 @@ use std::path::Path;
 use crate::{
     app::{config::Config, settings},
     hw::HardwareWalletConfig,
-    signer::Signer,
+    signer::{EncryptedSigner, Signer},
 };
 
-use liana::{miniscript::bitcoin, signer::HotSigner};
+use liana::{
+    miniscript::bitcoin,
+    signer::{HotSigner, SignerError},
+};
 
 use liana::descriptors::LianaDescriptor;
 use liana::miniscript::bitcoin::bip32::Fingerprint;
@@ -21,6 +24,9 @@ pub struct Wallet {
     pub keys_aliases: HashMap<Fingerprint, String>,
     pub hardware_wallets: Vec<HardwareWalletConfig>,
     pub signer: Option<Signer>,
+    pub encrypted_signer: Option<EncryptedSigner>,
+    /// Number of disappeared unconfirmed coins the user was already notified of.
+    pub acknowledged_expired_coins: usize,
 }
 
 impl Wallet {
@@ -31,6 +37,8 @@ impl Wallet {
             keys_aliases: HashMap::new(),
             hardware_wallets: Vec::new(),
             signer: None,
+            encrypted_signer: None,
+            acknowledged_expired_coins: 0,
         }
     }
 
@@ -44,11 +52,29 @@ impl Wallet {
         self
     }
 
+    pub fn with_acknowledged_expired_coins(mut self, count: usize) -> Self {
+        self.acknowledged_expired_coins = count;
+        self
+    }
+
     pub fn with_signer(mut self, signer: Signer) -> Self {
         self.signer = Some(signer);
         self
     }
 
+    pub fn with_encrypted_signer(mut self, signer: EncryptedSigner) -> Self {
+        self.encrypted_signer = Some(signer);
+        self
+    }
+
+    /// The fingerprint of the hot signer of this wallet, whether its mnemonic is encrypted or not.
+    pub fn hot_signer_fingerprint(&self) -> Option<Fingerprint> {
+        self.signer
+            .as_ref()
+            .map(|s| s.fingerprint())
+            .or_else(|| self.encrypted_signer.as_ref().map(|s| s.fingerprint()))
+    }
+
     pub fn descriptor_keys(&self) -> HashSet<Fingerprint> {
         let info = self.main_descriptor.policy();
         let mut descriptor_keys = HashSet::new();
@@ -89,6 +115,7 @@ impl Wallet {
                 if let Some(wallet_setting) = settings.wallets.first() {
                     self.with_hardware_wallets(wallet_setting.hardware_wallets.clone())
                         .with_key_aliases(wallet_setting.keys_aliases())
+                        .with_acknowledged_expired_coins(wallet_setting.acknowledged_expired_coins)
                 } else {
                     self.with_hardware_wallets(gui_config_hws)
                 }
@@ -106,19 +133,7 @@ impl Wallet {
             Err(e) => return Err(e.into()),
         };
 
-        let hot_signers = match HotSigner::from_datadir(datadir_path, network) {
-            Ok(signers) => signers,
-            Err(e) => match e {
-                liana::signer::SignerError::MnemonicStorage(e) => {
-                    if e.kind() == std::io::ErrorKind::NotFound {
-                        Vec::new()
-                    } else {
-                        return Err(WalletError::HotSigner(e.to_string()));
-                    }
-                }
-                _ => return Err(WalletError::HotSigner(e.to_string())),
-            },
-        };
+        let hot_signers = or_no_signers(HotSigner::from_datadir(datadir_path, network))?;
 
         let curve = bitcoin::secp256k1::Secp256k1::signing_only();
         let keys = wallet.descriptor_keys();
@@ -127,12 +142,29 @@ impl Wallet {
             .find(|s| keys.contains(&s.fingerprint(&curve)))
         {
             wallet = wallet.with_signer(Signer::new(hot_signer));
+        } else if let Some(encrypted) =
+            or_no_signers(HotSigner::encrypted_from_datadir(datadir_path, network))?
+                .into_iter()
+                .find(|s| keys.contains(&s.fingerprint()))
+        {
+            wallet = wallet.with_encrypted_signer(EncryptedSigner::new(encrypted, network));
         }
 
         Ok(wallet)
     }
 }
 
+// A missing mnemonics folder means there is no hot signer.
+fn or_no_signers<T>(res: Result<Vec<T>, SignerError>) -> Result<Vec<T>, WalletError> {
+    match res {
+        Ok(signers) => Ok(signers),
+        Err(SignerError::MnemonicStorage(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok(Vec::new())
+        }
+        Err(e) => Err(WalletError::HotSigner(e.to_string())),
+    }
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum WalletError {
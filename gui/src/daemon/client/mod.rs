@@ -11,6 +11,7 @@ pub mod error;
 pub mod jsonrpc;
 
 use liana::{
+    backup::WalletBackup,
     commands::LabelItem,
     config::Config,
     miniscript::bitcoin::{address, psbt::Psbt, Address, OutPoint, Txid},
@@ -139,11 +140,24 @@ impl<C: Client + Debug> Daemon for Lianad<C> {
         &self,
         address: Address<address::NetworkUnchecked>,
         feerate_vb: u64,
-        sequence: Option<u16>,
+        sequence: Option<RecoveryTimelock>,
     ) -> Result<Psbt, DaemonError> {
         let res: CreateSpendResult = self.call(
             "createrecovery",
-            Some(vec![json!(address), json!(feerate_vb), json!(sequence)]),
+            Some(vec![
+                json!(address),
+                json!(feerate_vb),
+                // An absolute timelock is identified by its descriptor fragment.
+                sequence
+                    .map(|s| {
+                        if s.is_relative() {
+                            json!(s.sequence().to_consensus_u32())
+                        } else {
+                            json!(format!("after({})", s.value()))
+                        }
+                    })
+                    .unwrap_or(serde_json::Value::Null),
+            ]),
         )?;
         Ok(res.psbt)
     }
@@ -163,6 +177,10 @@ impl<C: Client + Debug> Daemon for Lianad<C> {
         let _res: serde_json::value::Value = self.call("updatelabels", Some(vec![labels]))?;
         Ok(())
     }
+
+    fn get_backup(&self) -> Result<WalletBackup, DaemonError> {
+        self.call("getbackup", Option::<Request>::None)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
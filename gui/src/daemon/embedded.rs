@@ -2,6 +2,7 @@ use std::collections::{HashMap, HashSet};
 
 use super::{model::*, Daemon, DaemonError};
 use liana::{
+    backup::WalletBackup,
     commands::LabelItem,
     config::Config,
     miniscript::bitcoin::{address, psbt::Psbt, Address, OutPoint, Txid},
@@ -60,7 +61,7 @@ impl Daemon for EmbeddedDaemon {
     }
 
     fn list_coins(&self) -> Result<ListCoinsResult, DaemonError> {
-        Ok(self.control()?.list_coins(&[], &[]))
+        Ok(self.control()?.list_coins(&[], &[], &Default::default()))
     }
 
     fn list_spend_txs(&self) -> Result<ListSpendResult, DaemonError> {
@@ -120,7 +121,7 @@ impl Daemon for EmbeddedDaemon {
         &self,
         address: Address<address::NetworkUnchecked>,
         feerate_vb: u64,
-        sequence: Option<u16>,
+        sequence: Option<RecoveryTimelock>,
     ) -> Result<Psbt, DaemonError> {
         self.control()?
             .create_recovery(address, feerate_vb, sequence)
@@ -139,4 +140,8 @@ impl Daemon for EmbeddedDaemon {
         self.handle.control.update_labels(items);
         Ok(())
     }
+
+    fn get_backup(&self) -> Result<WalletBackup, DaemonError> {
+        Ok(self.control()?.get_backup())
+    }
 }
@@ -7,6 +7,7 @@ use std::fmt::Debug;
 use std::io::ErrorKind;
 
 use liana::{
+    backup::WalletBackup,
     commands::LabelItem,
     config::Config,
     miniscript::bitcoin::{address, psbt::Psbt, Address, OutPoint, Txid},
@@ -73,7 +74,7 @@ pub trait Daemon: Debug {
         &self,
         address: Address<address::NetworkUnchecked>,
         feerate_vb: u64,
-        sequence: Option<u16>,
+        sequence: Option<model::RecoveryTimelock>,
     ) -> Result<Psbt, DaemonError>;
     fn list_txs(&self, txid: &[Txid]) -> Result<model::ListTransactionsResult, DaemonError>;
     fn get_labels(
@@ -81,6 +82,7 @@ pub trait Daemon: Debug {
         labels: &HashSet<LabelItem>,
     ) -> Result<HashMap<String, String>, DaemonError>;
     fn update_labels(&self, labels: &HashMap<LabelItem, String>) -> Result<(), DaemonError>;
+    fn get_backup(&self) -> Result<WalletBackup, DaemonError>;
 
     fn list_spend_transactions(&self) -> Result<Vec<model::SpendTx>, DaemonError> {
         let info = self.get_info()?;
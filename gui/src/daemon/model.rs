@@ -6,7 +6,7 @@ pub use liana::{
         ListCoinsEntry, ListCoinsResult, ListSpendEntry, ListSpendResult, ListTransactionsResult,
         TransactionInfo,
     },
-    descriptors::{PartialSpendInfo, PathSpendInfo},
+    descriptors::{PartialSpendInfo, PathSpendInfo, RecoveryTimelock},
     miniscript::bitcoin::{
         bip32::Fingerprint, psbt::Psbt, Address, Amount, Network, OutPoint, Transaction, Txid,
     },
@@ -14,15 +14,32 @@ pub use liana::{
 
 pub type Coin = ListCoinsEntry;
 
-pub fn remaining_sequence(coin: &Coin, blockheight: u32, timelock: u16) -> u32 {
+/// The number of blocks remaining before the coin is spendable through the recovery path with
+/// this timelock. For time-based timelocks, this is an estimation assuming 10 minutes blocks.
+pub fn remaining_sequence(coin: &Coin, blockheight: u32, timelock: RecoveryTimelock) -> u32 {
+    let timelock = match timelock {
+        // An absolute timelock doesn't depend on the coin's confirmation.
+        RecoveryTimelock::AbsoluteHeight(height) => return height.saturating_sub(blockheight),
+        RecoveryTimelock::AbsoluteTime(timestamp) => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as u32)
+                .unwrap_or(0);
+            let remaining_secs = timestamp.saturating_sub(now);
+            return remaining_secs / 600 + if remaining_secs % 600 > 0 { 1 } else { 0 };
+        }
+        tl => tl
+            .estimated_blocks()
+            .expect("Always set for relative timelocks"),
+    };
     if let Some(coin_blockheight) = coin.block_height {
-        if blockheight > coin_blockheight as u32 + timelock as u32 {
+        if blockheight > coin_blockheight as u32 + timelock {
             0
         } else {
-            coin_blockheight as u32 + timelock as u32 - blockheight
+            coin_blockheight as u32 + timelock - blockheight
         }
     } else {
-        timelock as u32
+        timelock
     }
 }
 
@@ -64,7 +81,9 @@ impl SpendTx {
         let (change_amount, spend_amount) = psbt.unsigned_tx.output.iter().enumerate().fold(
             (Amount::from_sat(0), Amount::from_sat(0)),
             |(change, spend), (i, output)| {
-                if !psbt.outputs[i].bip32_derivation.is_empty() {
+                if !psbt.outputs[i].bip32_derivation.is_empty()
+                    || !psbt.outputs[i].tap_key_origins.is_empty()
+                {
                     change_indexes.push(i);
                     (change + Amount::from_sat(output.value), spend)
                 } else {
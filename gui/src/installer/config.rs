@@ -21,6 +21,8 @@ impl TryFrom<Context> for LianaConfig {
             data_dir: Some(ctx.data_dir),
             bitcoin_config: ctx.bitcoin_config,
             bitcoind_config: ctx.bitcoind_config,
+            signer_policies: Vec::new(),
+            signer_command: None,
         })
     }
 }
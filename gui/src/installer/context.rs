@@ -13,6 +13,7 @@ use crate::{
 };
 use async_hwi::DeviceKind;
 use liana::{
+    backup::WalletBackup,
     config::Config,
     config::{BitcoinConfig, BitcoindConfig},
     descriptors::LianaDescriptor,
@@ -33,9 +34,13 @@ pub struct Context {
     // In case a user entered a mnemonic,
     // we dont want to override the generated signer with it.
     pub recovered_signer: Option<Arc<Signer>>,
+    // If set, the mnemonics are stored encrypted with this passphrase.
+    pub mnemonic_passphrase: Option<String>,
     pub bitcoind_is_external: bool,
     pub internal_bitcoind_config: Option<InternalBitcoindConfig>,
     pub internal_bitcoind: Option<Bitcoind>,
+    // In case the wallet was imported from a backup, the metadata to restore.
+    pub backup: Option<WalletBackup>,
 }
 
 impl Context {
@@ -52,14 +57,16 @@ impl Context {
             data_dir,
             hw_is_used: false,
             recovered_signer: None,
+            mnemonic_passphrase: None,
             bitcoind_is_external: true,
             internal_bitcoind_config: None,
             internal_bitcoind: None,
+            backup: None,
         }
     }
 
     pub fn extract_gui_settings(&self) -> Settings {
-        let hardware_wallets = self
+        let mut hardware_wallets: Vec<HardwareWalletConfig> = self
             .hws
             .iter()
             .filter_map(|(kind, fingerprint, token)| {
@@ -68,9 +75,28 @@ impl Context {
                     .map(|token| HardwareWalletConfig::new(kind, *fingerprint, token))
             })
             .collect();
+        // Keep the registrations from the backup for the devices which were not registered again.
+        if let Some(backup) = &self.backup {
+            for hw in &backup.hardware_wallets {
+                if !hardware_wallets
+                    .iter()
+                    .any(|cfg| cfg.fingerprint == hw.fingerprint)
+                {
+                    hardware_wallets.push(HardwareWalletConfig {
+                        kind: hw.kind.clone(),
+                        fingerprint: hw.fingerprint,
+                        token: hw.token.clone(),
+                    });
+                }
+            }
+        }
         Settings {
             wallets: vec![WalletSetting {
-                name: DEFAULT_WALLET_NAME.to_string(),
+                name: self
+                    .backup
+                    .as_ref()
+                    .and_then(|backup| backup.name.clone())
+                    .unwrap_or_else(|| DEFAULT_WALLET_NAME.to_string()),
                 descriptor_checksum: self
                     .descriptor
                     .as_ref()
@@ -82,6 +108,7 @@ impl Context {
                     .to_string(),
                 keys: self.keys.clone(),
                 hardware_wallets,
+                acknowledged_expired_coins: 0,
             }],
         }
     }
@@ -95,6 +122,8 @@ impl Context {
             data_dir: Some(self.data_dir.clone()),
             bitcoin_config: self.bitcoin_config.clone(),
             bitcoind_config: self.bitcoind_config.clone(),
+            signer_policies: Vec::new(),
+            signer_command: None,
         }
     }
 }
@@ -1,6 +1,9 @@
-use liana::miniscript::{
-    bitcoin::{bip32::Fingerprint, Network},
-    DescriptorPublicKey,
+use liana::{
+    descriptors::{PolicyTemplate, RegistrationFormat},
+    miniscript::{
+        bitcoin::{bip32::Fingerprint, Network},
+        DescriptorPublicKey,
+    },
 };
 use std::path::PathBuf;
 
@@ -33,8 +36,14 @@ pub enum Message {
     ImportXpub(usize, Result<DescriptorPublicKey, Error>),
     ConnectedHardwareWallets(Vec<HardwareWallet>),
     WalletRegistered(Result<(Fingerprint, Option<[u8; 32]>), Error>),
+    ExportRegistrationFile(RegistrationFormat),
+    RegistrationFileExported(Result<PathBuf, Error>),
     MnemonicWord(usize, String),
+    MnemonicWordCount(usize),
+    Bip39Passphrase(String),
     ImportMnemonic(bool),
+    MnemonicPassphrase(String),
+    MnemonicPassphraseConfirmation(String),
 }
 
 #[derive(Debug, Clone)]
@@ -64,6 +73,10 @@ pub enum InternalBitcoindMsg {
 #[derive(Debug, Clone)]
 pub enum DefineDescriptor {
     ImportDescriptor(String),
+    DecryptionKeyEdited(String),
+    DecryptWithDevices,
+    BackupDecrypted(Result<String, Error>),
+    Template(PolicyTemplate),
     PrimaryPath(DefinePath),
     RecoveryPath(usize, DefinePath),
     AddRecoveryPath,
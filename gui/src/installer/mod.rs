@@ -5,7 +5,7 @@ mod step;
 mod view;
 
 use iced::{clipboard, Command, Subscription};
-use liana::miniscript::bitcoin;
+use liana::{backup::WalletBackup, miniscript::bitcoin};
 use liana_ui::widget::Element;
 use tracing::{error, info, warn};
 
@@ -236,10 +236,21 @@ impl Installer {
     }
 }
 
-pub fn daemon_check(cfg: liana::config::Config) -> Result<(), Error> {
+pub fn daemon_check(
+    cfg: liana::config::Config,
+    backup: Option<&WalletBackup>,
+) -> Result<(), Error> {
     // Start Daemon to check correctness of installation
     match liana::DaemonHandle::start_default(cfg) {
         Ok(daemon) => {
+            // If the wallet is imported from a backup, restore its labels and history. Don't
+            // fail the installation if it isn't possible (for instance if bitcoind is still
+            // syncing), the user can still start a rescan later on.
+            if let Some(backup) = backup {
+                if let Err(e) = daemon.control.restore_backup(backup) {
+                    warn!("Failed to restore the wallet backup: {}", e);
+                }
+            }
             daemon.shutdown();
             Ok(())
         }
@@ -259,7 +270,7 @@ pub async fn install(ctx: Context, signer: Arc<Mutex<Signer>>) -> Result<PathBuf
         .map_err(|e| Error::Unexpected(format!("Failed to canonicalize datadir path: {}", e)))?;
     cfg.data_dir = Some(data_dir.clone());
 
-    daemon_check(cfg.clone())?;
+    daemon_check(cfg.clone(), ctx.backup.as_ref())?;
 
     info!("daemon checked");
 
@@ -290,6 +301,7 @@ pub async fn install(ctx: Context, signer: Arc<Mutex<Signer>>) -> Result<PathBuf
             .store(
                 &cfg.data_dir().expect("Already checked"),
                 cfg.bitcoin_config.network,
+                ctx.mnemonic_passphrase.as_deref(),
             )
             .map_err(|e| Error::Unexpected(format!("Failed to store mnemonic: {}", e)))?;
 
@@ -301,6 +313,7 @@ pub async fn install(ctx: Context, signer: Arc<Mutex<Signer>>) -> Result<PathBuf
             .store(
                 &cfg.data_dir().expect("Already checked"),
                 cfg.bitcoin_config.network,
+                ctx.mnemonic_passphrase.as_deref(),
             )
             .map_err(|e| Error::Unexpected(format!("Failed to store mnemonic: {}", e)))?;
 
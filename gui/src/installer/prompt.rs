@@ -4,8 +4,13 @@ pub const DEFINE_DESCRIPTOR_PRIMARY_PATH_TOOLTIP: &str =
     "Set key(s) that can be used to spend coins immediately, with no time restriction.";
 pub const DEFINE_DESCRIPTOR_RECOVERY_PATH_TOOLTIP: &str =
     "Set key(s) that can be used to spend coins after a defined period of time.\n Different sets of keys can be set to become available at different times.";
+pub const DEFINE_DESCRIPTOR_TEMPLATES_TOOLTIP: &str =
+    "Set the spending paths of a common setup. Keys must still be set, and everything can be edited afterward.";
 pub const DEFINE_DESCRIPTOR_FINGERPRINT_TOOLTIP: &str =
     "The alias is applied on all the keys derived from the same seed";
 pub const REGISTER_DESCRIPTOR_HELP: &str = "To be used with the wallet, a signing device needs the descriptor. If the descriptor contains one or more keys imported from an external signing device, the descriptor must be registered on it. Registration confirms that the device is able to handle the policy. Registration on a device is not a substitute for backing up the descriptor.";
 pub const MNEMONIC_HELP: &str = "A hot key generated on this computer was used for creating this wallet. It needs to be backed up. \n Keep it in a safe place. Never share it with anyone.";
+pub const MNEMONIC_PASSPHRASE_HELP: &str = "The passphrase will be asked for every time the hot key signs a transaction. It is not part of the backup: if it is lost, the hot key can still be recovered from the mnemonic.";
+pub const BIP39_PASSPHRASE_HELP: &str = "Only if the seed is protected by a BIP39 passphrase, sometimes called the '25th word'. A wrong passphrase derives a different key. The mnemonic will be stored encrypted with this passphrase.";
 pub const RECOVER_MNEMONIC_HELP: &str = "If you were using a hot key (a key stored on the computer) in your wallet, you will need to recover it from mnemonics to be able to sign transactions again. Otherwise you can directly go the next step.";
+pub const BACKUP_ENCRYPTED_DESCRIPTOR_MESSAGE: &str = "The descriptor can also be backed up encrypted. Only the holder of one of its keys can decrypt it, but it still reveals that you are using a Liana wallet. It can be imported in place of the descriptor, and decrypted by giving any of the keys.";
@@ -6,7 +6,11 @@ use std::sync::{Arc, Mutex};
 use iced::Command;
 use liana::miniscript::bitcoin::bip32::ExtendedPubKey;
 use liana::{
-    descriptors::{LianaDescriptor, LianaPolicy, PathInfo},
+    backup::WalletBackup,
+    descriptors::{
+        registration_file, EncryptedBackup, LianaDescriptor, LianaPolicy, PathInfo, PolicyTemplate,
+        RecoveryTimelock, RegistrationFormat,
+    },
     miniscript::{
         bitcoin::{
             bip32::{ChildNumber, DerivationPath, Fingerprint},
@@ -29,6 +33,7 @@ use crate::{
     app::settings::KeySetting,
     hw::{list_unregistered_hardware_wallets, HardwareWallet},
     installer::{
+        create_and_write_file,
         message::{self, Message},
         step::{Context, Step},
         view, Error,
@@ -114,6 +119,27 @@ impl Setup {
         }
     }
 
+    // Reset the spending paths to the layout of this template. The keys must still be set by
+    // the user, but the ones already imported are kept.
+    fn apply_template(&mut self, template: PolicyTemplate) {
+        let path = |n_keys: usize, threshold: usize, sequence: u16| RecoveryPath {
+            keys: vec![None; n_keys],
+            threshold,
+            sequence,
+            duplicate_sequence: false,
+        };
+        let (n_spending_keys, recovery_paths) = match template {
+            PolicyTemplate::SimpleInheritance => (1, vec![path(1, 1, 52_560)]),
+            PolicyTemplate::ExpandingMultisig => (2, vec![path(3, 2, 52_560)]),
+            PolicyTemplate::DecayingMultisig => (3, vec![path(3, 2, 26_280), path(3, 1, 52_560)]),
+            PolicyTemplate::ThirdPartyRecovery => (1, vec![path(2, 2, 52_560)]),
+        };
+        self.spending_keys = vec![None; n_spending_keys];
+        self.spending_threshold = n_spending_keys;
+        self.recovery_paths = recovery_paths;
+        self.check_for_duplicate();
+    }
+
     fn valid(&self) -> bool {
         !self.spending_keys.is_empty()
             && !self.spending_keys.iter().any(|k| k.is_none())
@@ -217,6 +243,9 @@ impl Step for DefineDescriptor {
                 self.modal = None;
             }
             Message::Network(network) => self.set_network(network),
+            Message::DefineDescriptor(message::DefineDescriptor::Template(template)) => {
+                self.setup_mut().apply_template(template);
+            }
             Message::DefineDescriptor(message::DefineDescriptor::AddRecoveryPath) => {
                 self.setup_mut().recovery_paths.push(RecoveryPath::new());
             }
@@ -453,7 +482,7 @@ impl Step for DefineDescriptor {
                 PathInfo::Multi(path.threshold, recovery_keys)
             };
 
-            recovery_paths.insert(path.sequence, recovery_keys);
+            recovery_paths.insert(RecoveryTimelock::Blocks(path.sequence), recovery_keys);
         }
 
         if !self.network_valid || spending_keys.is_empty() {
@@ -1236,6 +1265,10 @@ pub struct ImportDescriptor {
     change_network: bool,
     data_dir: Option<PathBuf>,
     imported_descriptor: form::Value<String>,
+    // In case the user imported an encrypted backup, it must be decrypted with one of the keys.
+    encrypted_backup: Option<EncryptedBackup>,
+    decryption_key: form::Value<String>,
+    processing: bool,
     error: Option<String>,
 }
 
@@ -1247,20 +1280,89 @@ impl ImportDescriptor {
             network_valid: true,
             data_dir: None,
             imported_descriptor: form::Value::default(),
+            encrypted_backup: None,
+            decryption_key: form::Value::default(),
+            processing: false,
             error: None,
         }
     }
 
-    fn check_descriptor(&mut self) {
-        if !self.imported_descriptor.value.is_empty() {
-            if let Ok(desc) = LianaDescriptor::from_str(&self.imported_descriptor.value) {
+    // Decrypt the encrypted backup with the given xpub, with or without origin.
+    fn decrypt_with_key(&mut self) {
+        let key = self.decryption_key.value.trim();
+        let xpub = match DescriptorPublicKey::from_str(key) {
+            Ok(DescriptorPublicKey::XPub(xpub)) => xpub.xkey,
+            Ok(DescriptorPublicKey::MultiXPub(xpub)) => xpub.xkey,
+            _ => {
+                self.decryption_key.valid = key.is_empty();
+                return;
+            }
+        };
+        let decrypted = self
+            .encrypted_backup
+            .as_ref()
+            .map(|backup| backup.decrypt_with_xpub(&xpub));
+        match decrypted {
+            Some(Ok(content)) => self.backup_decrypted(content),
+            Some(Err(_)) => self.decryption_key.valid = false,
+            None => {}
+        }
+    }
+
+    fn backup_decrypted(&mut self, content: Vec<u8>) {
+        match String::from_utf8(content) {
+            Ok(content) => {
+                self.encrypted_backup = None;
+                self.decryption_key = form::Value::default();
+                self.imported_descriptor.value = content;
+                self.check_descriptor();
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    // The user may either paste a descriptor or a wallet backup.
+    fn parse_imported(&self) -> Result<(LianaDescriptor, Option<WalletBackup>), String> {
+        let value = self.imported_descriptor.value.trim();
+        if value.starts_with('{') {
+            let backup = WalletBackup::from_json(value).map_err(|e| e.to_string())?;
+            if backup.network != self.network {
+                return Err(format!("The backup is for the {} network.", backup.network));
+            }
+            Ok((backup.descriptor.clone(), Some(backup)))
+        } else {
+            LianaDescriptor::from_str(value)
+                .map(|desc| (desc, None))
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    fn check_descriptor(&mut self) -> Option<(LianaDescriptor, Option<WalletBackup>)> {
+        if self.imported_descriptor.value.is_empty() {
+            return None;
+        }
+        self.error = None;
+        match self.parse_imported() {
+            Ok((desc, backup)) => {
                 if self.network == Network::Bitcoin {
                     self.imported_descriptor.valid = desc.all_xpubs_net_is(self.network);
                 } else {
                     self.imported_descriptor.valid = desc.all_xpubs_net_is(Network::Testnet);
                 }
-            } else {
+                if self.imported_descriptor.valid {
+                    Some((desc, backup))
+                } else {
+                    None
+                }
+            }
+            Err(e) => {
                 self.imported_descriptor.valid = false;
+                // Only report errors when reading a backup, the form already warns about an
+                // invalid descriptor.
+                if self.imported_descriptor.value.trim().starts_with('{') {
+                    self.error = Some(e);
+                }
+                None
             }
         }
     }
@@ -1276,11 +1378,39 @@ impl Step for ImportDescriptor {
                 let mut network_datadir = self.data_dir.clone().unwrap();
                 network_datadir.push(self.network.to_string());
                 self.network_valid = !network_datadir.exists();
-                self.check_descriptor();
+                if self.encrypted_backup.is_none() {
+                    self.check_descriptor();
+                }
             }
             Message::DefineDescriptor(message::DefineDescriptor::ImportDescriptor(desc)) => {
+                self.encrypted_backup = EncryptedBackup::from_str(&desc).ok();
                 self.imported_descriptor.value = desc;
-                self.check_descriptor();
+                if self.encrypted_backup.is_some() {
+                    self.imported_descriptor.valid = true;
+                    self.error = None;
+                } else {
+                    self.check_descriptor();
+                }
+            }
+            Message::DefineDescriptor(message::DefineDescriptor::DecryptionKeyEdited(key)) => {
+                self.decryption_key.value = key;
+                self.decrypt_with_key();
+            }
+            Message::DefineDescriptor(message::DefineDescriptor::DecryptWithDevices) => {
+                if let Some(backup) = &self.encrypted_backup {
+                    self.processing = true;
+                    self.error = None;
+                    return Command::perform(decrypt_with_devices(backup.clone()), |res| {
+                        Message::DefineDescriptor(message::DefineDescriptor::BackupDecrypted(res))
+                    });
+                }
+            }
+            Message::DefineDescriptor(message::DefineDescriptor::BackupDecrypted(res)) => {
+                self.processing = false;
+                match res {
+                    Ok(content) => self.backup_decrypted(content.into_bytes()),
+                    Err(e) => self.error = Some(e.to_string()),
+                }
             }
             _ => {}
         };
@@ -1300,23 +1430,20 @@ impl Step for ImportDescriptor {
         // Set to true in order to force the registration process to be shown to user.
         ctx.hw_is_used = true;
         // descriptor forms for import or creation cannot be both empty or filled.
-        if !self.imported_descriptor.value.is_empty() {
-            if let Ok(desc) = LianaDescriptor::from_str(&self.imported_descriptor.value) {
-                if self.network == Network::Bitcoin {
-                    self.imported_descriptor.valid = desc.all_xpubs_net_is(self.network);
-                } else {
-                    self.imported_descriptor.valid = desc.all_xpubs_net_is(Network::Testnet);
-                }
-                if self.imported_descriptor.valid {
-                    ctx.descriptor = Some(desc);
-                    true
-                } else {
-                    false
-                }
-            } else {
-                self.imported_descriptor.valid = false;
-                false
+        if let Some((desc, backup)) = self.check_descriptor() {
+            if let Some(backup) = &backup {
+                ctx.keys = backup
+                    .keys
+                    .iter()
+                    .map(|key| KeySetting {
+                        name: key.name.clone(),
+                        master_fingerprint: key.master_fingerprint,
+                    })
+                    .collect();
             }
+            ctx.descriptor = Some(desc);
+            ctx.backup = backup;
+            true
         } else {
             false
         }
@@ -1329,6 +1456,9 @@ impl Step for ImportDescriptor {
             self.network,
             self.network_valid,
             &self.imported_descriptor,
+            self.encrypted_backup
+                .as_ref()
+                .map(|_| (&self.decryption_key, self.processing)),
             self.error.as_ref(),
         )
     }
@@ -1340,14 +1470,43 @@ impl From<ImportDescriptor> for Box<dyn Step> {
     }
 }
 
+/// Try to decrypt the backup with the xpubs of the connected signing devices, at each of the
+/// derivation paths used in the descriptor.
+async fn decrypt_with_devices(backup: EncryptedBackup) -> Result<String, Error> {
+    for hw in list_unregistered_hardware_wallets().await {
+        if let HardwareWallet::Supported { device, .. } = hw {
+            for path in backup.derivation_paths() {
+                let xpub = match device.get_extended_pubkey(path).await {
+                    Ok(xpub) => xpub,
+                    Err(e) => {
+                        tracing::debug!("Error getting xpub at '{}': {}", path, e);
+                        continue;
+                    }
+                };
+                if let Ok(content) = backup.decrypt_with_xpub(&xpub) {
+                    return String::from_utf8(content)
+                        .map_err(|e| Error::Unexpected(e.to_string()));
+                }
+            }
+        }
+    }
+    Err(Error::Unexpected(
+        "None of the connected signing devices can decrypt this backup.".to_string(),
+    ))
+}
+
 pub struct RegisterDescriptor {
     descriptor: Option<LianaDescriptor>,
+    network: Network,
+    network_datadir: PathBuf,
     keys_aliases: HashMap<Fingerprint, String>,
     processing: bool,
     chosen_hw: Option<usize>,
     hws: Vec<HardwareWallet>,
     hmacs: Vec<(Fingerprint, DeviceKind, Option<[u8; 32]>)>,
     registered: HashSet<Fingerprint>,
+    /// The registration files written for air-gapped signing devices.
+    exported: Vec<PathBuf>,
     error: Option<Error>,
     done: bool,
     /// Whether this step is part of the descriptor creation process. This is used to detect when
@@ -1362,12 +1521,15 @@ impl RegisterDescriptor {
         Self {
             created_desc,
             descriptor: Default::default(),
+            network: Network::Bitcoin,
+            network_datadir: Default::default(),
             keys_aliases: Default::default(),
             processing: Default::default(),
             chosen_hw: Default::default(),
             hws: Default::default(),
             hmacs: Default::default(),
             registered: Default::default(),
+            exported: Default::default(),
             error: Default::default(),
             done: Default::default(),
         }
@@ -1385,6 +1547,8 @@ impl RegisterDescriptor {
 impl Step for RegisterDescriptor {
     fn load_context(&mut self, ctx: &Context) {
         self.descriptor = ctx.descriptor.clone();
+        self.network = ctx.bitcoin_config.network;
+        self.network_datadir = ctx.data_dir.join(ctx.bitcoin_config.network.to_string());
         let mut map = HashMap::new();
         for key in ctx.keys.iter().filter(|k| !k.name.is_empty()) {
             map.insert(key.master_fingerprint, key.name.clone());
@@ -1429,6 +1593,26 @@ impl Step for RegisterDescriptor {
                     Err(e) => self.error = Some(e),
                 }
             }
+            Message::ExportRegistrationFile(format) => {
+                self.error = None;
+                return Command::perform(
+                    export_registration_file(
+                        self.network_datadir.clone(),
+                        self.descriptor.clone().unwrap(),
+                        self.network,
+                        format,
+                    ),
+                    Message::RegistrationFileExported,
+                );
+            }
+            Message::RegistrationFileExported(res) => match res {
+                Ok(path) => {
+                    if !self.exported.contains(&path) {
+                        self.exported.push(path);
+                    }
+                }
+                Err(e) => self.error = Some(e),
+            },
             Message::ConnectedHardwareWallets(hws) => {
                 self.hws = hws;
             }
@@ -1465,6 +1649,7 @@ impl Step for RegisterDescriptor {
             desc.to_string(),
             &self.hws,
             &self.registered,
+            &self.exported,
             self.error.as_ref(),
             self.processing,
             self.chosen_hw,
@@ -1474,6 +1659,25 @@ impl Step for RegisterDescriptor {
     }
 }
 
+// Write the file to register the descriptor on air-gapped signing devices in the data directory.
+async fn export_registration_file(
+    network_datadir: PathBuf,
+    descriptor: LianaDescriptor,
+    network: Network,
+    format: RegistrationFormat,
+) -> Result<PathBuf, Error> {
+    let content = registration_file(&descriptor, "Liana", network, format)
+        .map_err(|e| Error::Unexpected(e.to_string()))?;
+    std::fs::create_dir_all(&network_datadir)
+        .map_err(|e| Error::CannotCreateDatadir(e.to_string()))?;
+    let file_name = format!(
+        "registration_{}.{}",
+        format.to_string().to_lowercase(),
+        format.extension()
+    );
+    create_and_write_file(network_datadir, &file_name, content.as_bytes())
+}
+
 async fn register_wallet(
     hw: std::sync::Arc<dyn async_hwi::HWI + Send + Sync>,
     fingerprint: Fingerprint,
@@ -1496,6 +1700,8 @@ impl From<RegisterDescriptor> for Box<dyn Step> {
 pub struct BackupDescriptor {
     done: bool,
     descriptor: Option<LianaDescriptor>,
+    // The descriptor encrypted to its own keys, safer to store online.
+    encrypted_descriptor: Option<String>,
 }
 
 impl Step for BackupDescriptor {
@@ -1507,10 +1713,21 @@ impl Step for BackupDescriptor {
     }
     fn load_context(&mut self, ctx: &Context) {
         self.descriptor = ctx.descriptor.clone();
+        self.encrypted_descriptor = self.descriptor.as_ref().and_then(|desc| {
+            EncryptedBackup::encrypt_descriptor(desc)
+                .map(|backup| backup.to_string())
+                .map_err(|e| tracing::error!("Failed to encrypt the descriptor: {}", e))
+                .ok()
+        });
     }
     fn view(&self, progress: (usize, usize)) -> Element<Message> {
         let desc = self.descriptor.as_ref().unwrap();
-        view::backup_descriptor(progress, desc.to_string(), self.done)
+        view::backup_descriptor(
+            progress,
+            desc.to_string(),
+            self.encrypted_descriptor.clone(),
+            self.done,
+        )
     }
 }
 
@@ -4,7 +4,7 @@ use std::sync::{Arc, Mutex};
 use iced::Command;
 use liana::{bip39, signer::HotSigner};
 
-use liana_ui::widget::Element;
+use liana_ui::{component::form, widget::Element};
 
 use crate::{
     installer::{context::Context, message::Message, step::Step, view},
@@ -12,9 +12,12 @@ use crate::{
 };
 
 pub struct BackupMnemonic {
-    words: [&'static str; 12],
+    words: Vec<&'static str>,
     done: bool,
     signer: Arc<Mutex<Signer>>,
+    // Optional passphrase to encrypt the mnemonic on disk.
+    passphrase: form::Value<String>,
+    passphrase_confirmation: form::Value<String>,
 }
 
 impl BackupMnemonic {
@@ -24,6 +27,8 @@ impl BackupMnemonic {
             done: false,
             words,
             signer,
+            passphrase: form::Value::default(),
+            passphrase_confirmation: form::Value::default(),
         }
     }
 }
@@ -36,11 +41,32 @@ impl From<BackupMnemonic> for Box<dyn Step> {
 
 impl Step for BackupMnemonic {
     fn update(&mut self, message: Message) -> Command<Message> {
-        if let Message::UserActionDone(done) = message {
-            self.done = done;
+        match message {
+            Message::UserActionDone(done) => self.done = done,
+            Message::MnemonicPassphrase(passphrase) => {
+                self.passphrase.value = passphrase;
+                self.passphrase_confirmation.valid = true;
+            }
+            Message::MnemonicPassphraseConfirmation(passphrase) => {
+                self.passphrase_confirmation.valid = passphrase == self.passphrase.value;
+                self.passphrase_confirmation.value = passphrase;
+            }
+            _ => {}
         }
         Command::none()
     }
+    fn apply(&mut self, ctx: &mut Context) -> bool {
+        if self.passphrase.value != self.passphrase_confirmation.value {
+            self.passphrase_confirmation.valid = false;
+            return false;
+        }
+        ctx.mnemonic_passphrase = if self.passphrase.value.is_empty() {
+            None
+        } else {
+            Some(self.passphrase.value.clone())
+        };
+        true
+    }
     fn skip(&self, ctx: &Context) -> bool {
         if let Some(descriptor) = &ctx.descriptor {
             !descriptor
@@ -51,13 +77,21 @@ impl Step for BackupMnemonic {
         }
     }
     fn view(&self, progress: (usize, usize)) -> Element<Message> {
-        view::backup_mnemonic(progress, &self.words, self.done)
+        view::backup_mnemonic(
+            progress,
+            &self.words,
+            &self.passphrase,
+            &self.passphrase_confirmation,
+            self.done,
+        )
     }
 }
 
 pub struct RecoverMnemonic {
     language: bip39::Language,
-    words: [(String, bool); 12],
+    words: Vec<(String, bool)>,
+    // The optional BIP39 passphrase protecting the seed.
+    passphrase: form::Value<String>,
     current: usize,
     suggestions: Vec<String>,
     error: Option<String>,
@@ -69,7 +103,8 @@ impl Default for RecoverMnemonic {
     fn default() -> Self {
         Self {
             language: bip39::Language::English,
-            words: Default::default(),
+            words: vec![Default::default(); 12],
+            passphrase: form::Value::default(),
             current: 0,
             suggestions: Vec::new(),
             error: None,
@@ -107,6 +142,16 @@ impl Step for RecoverMnemonic {
                     *word = value;
                 }
             }
+            Message::MnemonicWordCount(count) => {
+                self.words.resize(count, Default::default());
+                self.current = self.current.min(count - 1);
+                self.suggestions = Vec::new();
+                self.error = None;
+            }
+            Message::Bip39Passphrase(passphrase) => {
+                self.passphrase.value = passphrase;
+                self.error = None;
+            }
             Message::ImportMnemonic(recover) => self.recover = recover,
             Message::Skip => {
                 self.skip = true;
@@ -130,7 +175,11 @@ impl Step for RecoverMnemonic {
             .filter_map(|(s, valid)| if *valid { Some(s.clone()) } else { None })
             .collect();
 
-        let seed = match HotSigner::from_str(ctx.bitcoin_config.network, &words.join(" ")) {
+        let seed = match HotSigner::from_str_with_passphrase(
+            ctx.bitcoin_config.network,
+            &words.join(" "),
+            &self.passphrase.value,
+        ) {
             Ok(seed) => seed,
             Err(e) => {
                 self.error = Some(e.to_string());
@@ -159,6 +208,13 @@ impl Step for RecoverMnemonic {
             }
         }
 
+        // A mnemonic protected by a BIP39 passphrase is never stored in plaintext. It is encrypted
+        // using this same passphrase, which will be asked for when using the signer.
+        ctx.mnemonic_passphrase = if self.passphrase.value.is_empty() {
+            None
+        } else {
+            Some(self.passphrase.value.clone())
+        };
         ctx.recovered_signer = Some(Arc::new(signer));
         true
     }
@@ -166,6 +222,7 @@ impl Step for RecoverMnemonic {
         view::recover_mnemonic(
             progress,
             &self.words,
+            &self.passphrase,
             self.current,
             &self.suggestions,
             self.recover,
@@ -7,7 +7,10 @@ use async_hwi::DeviceKind;
 use std::path::PathBuf;
 use std::{collections::HashSet, str::FromStr};
 
-use liana::miniscript::bitcoin::{self, bip32::Fingerprint};
+use liana::{
+    descriptors::{PolicyTemplate, RegistrationFormat},
+    miniscript::bitcoin::{self, bip32::Fingerprint},
+};
 use liana_ui::{
     color,
     component::{
@@ -203,6 +206,30 @@ pub fn define_descriptor<'a>(
             Some(text("A data directory already exists for this network").style(color::RED))
         });
 
+    let col_templates = Column::new()
+        .spacing(10)
+        .push(
+            Row::new()
+                .spacing(10)
+                .push(text("Start from a template:").bold())
+                .push(tooltip(prompt::DEFINE_DESCRIPTOR_TEMPLATES_TOOLTIP)),
+        )
+        .push(
+            Row::with_children(
+                PolicyTemplate::ALL
+                    .iter()
+                    .map(|template| {
+                        button::secondary(None, template_title(template))
+                            .on_press(Message::DefineDescriptor(
+                                message::DefineDescriptor::Template(*template),
+                            ))
+                            .into()
+                    })
+                    .collect(),
+            )
+            .spacing(10),
+        );
+
     let col_spending_keys = Column::new()
         .push(
             Row::new()
@@ -266,6 +293,7 @@ pub fn define_descriptor<'a>(
                 Column::new()
                     .width(Length::Fill)
                     .push(col_network)
+                    .push(col_templates)
                     .push(
                         Column::new()
                             .spacing(25)
@@ -363,6 +391,9 @@ pub fn import_descriptor<'a>(
     network: bitcoin::Network,
     network_valid: bool,
     imported_descriptor: &form::Value<String>,
+    // If the imported value is an encrypted backup, the key to decrypt it and whether we are
+    // trying to decrypt it with the connected signing devices.
+    decryption: Option<(&form::Value<String>, bool)>,
     error: Option<&String>,
 ) -> Element<'a, Message> {
     let row_network = Row::new()
@@ -386,7 +417,7 @@ pub fn import_descriptor<'a>(
             Some(text("A data directory already exists for this network").style(color::RED))
         });
     let col_descriptor = Column::new()
-        .push(text("Descriptor:").bold())
+        .push(text("Descriptor, receive and change descriptors, or wallet backup:").bold())
         .push(
             form::Form::new_trimmed("Descriptor", imported_descriptor, |msg| {
                 Message::DefineDescriptor(message::DefineDescriptor::ImportDescriptor(msg))
@@ -396,6 +427,28 @@ pub fn import_descriptor<'a>(
             .padding(10),
         )
         .spacing(10);
+    let col_decryption = decryption.map(|(key, processing)| {
+        Column::new()
+            .push(text("This backup is encrypted. Enter one of the xpubs of the wallet, or decrypt it with a connected signing device:").bold())
+            .push(
+                form::Form::new_trimmed("Xpub", key, |msg| {
+                    Message::DefineDescriptor(message::DefineDescriptor::DecryptionKeyEdited(msg))
+                })
+                .warning("This key cannot decrypt the backup.")
+                .size(20)
+                .padding(10),
+            )
+            .push(if processing {
+                button::secondary(None, "Decrypting...").width(Length::Fixed(200.0))
+            } else {
+                button::secondary(None, "Use a signing device")
+                    .on_press(Message::DefineDescriptor(
+                        message::DefineDescriptor::DecryptWithDevices,
+                    ))
+                    .width(Length::Fixed(200.0))
+            })
+            .spacing(10)
+    });
     layout(
         progress,
         "Import the wallet",
@@ -408,10 +461,14 @@ pub fn import_descriptor<'a>(
                     } else {
                         None
                     })
-                    .push(col_descriptor),
+                    .push(col_descriptor)
+                    .push_maybe(col_decryption),
             )
             .push(
-                if imported_descriptor.value.is_empty() || !imported_descriptor.valid {
+                if imported_descriptor.value.is_empty()
+                    || !imported_descriptor.valid
+                    || decryption.is_some()
+                {
                     button::primary(None, "Next").width(Length::Fixed(200.0))
                 } else {
                     button::primary(None, "Next")
@@ -419,7 +476,7 @@ pub fn import_descriptor<'a>(
                         .on_press(Message::Next)
                 },
             )
-            .push_maybe(error.map(|e| card::error("Invalid descriptor", e.to_string())))
+            .push_maybe(error.map(|e| card::error("Invalid backup", e.to_string())))
             .spacing(50),
         true,
         Some(Message::Previous),
@@ -640,6 +697,7 @@ pub fn register_descriptor<'a>(
     descriptor: String,
     hws: &'a [HardwareWallet],
     registered: &HashSet<bitcoin::bip32::Fingerprint>,
+    exported: &[PathBuf],
     error: Option<&Error>,
     processing: bool,
     chosen_hw: Option<usize>,
@@ -708,6 +766,30 @@ pub fn register_descriptor<'a>(
                     )
                     .width(Length::Fill),
             )
+            .push(
+                Column::new()
+                    .spacing(10)
+                    .push(text("For air-gapped signing devices, export a registration file:").bold())
+                    .push(
+                        Row::new()
+                            .spacing(10)
+                            .push(button::secondary(None, "Coldcard").on_press(
+                                Message::ExportRegistrationFile(RegistrationFormat::Coldcard),
+                            ))
+                            .push(button::secondary(None, "Jade").on_press(
+                                Message::ExportRegistrationFile(RegistrationFormat::Jade),
+                            ))
+                            .push(button::secondary(None, "BSMS").on_press(
+                                Message::ExportRegistrationFile(RegistrationFormat::Bsms),
+                            )),
+                    )
+                    .push(exported.iter().fold(Column::new().spacing(5), |col, path| {
+                        col.push(
+                            text(format!("Registration file written to {}", path.display()))
+                                .small(),
+                        )
+                    })),
+            )
             .push_maybe(created_desc.then_some(checkbox(
                 "I have registered the descriptor on my device(s)",
                 done,
@@ -729,6 +811,7 @@ pub fn register_descriptor<'a>(
 pub fn backup_descriptor<'a>(
     progress: (usize, usize),
     descriptor: String,
+    encrypted_descriptor: Option<String>,
     done: bool,
 ) -> Element<'a, Message> {
     layout(
@@ -776,6 +859,24 @@ pub fn backup_descriptor<'a>(
                     .spacing(10)
                     .max_width(1000),
             ))
+            .push_maybe(encrypted_descriptor.map(|encrypted| {
+                card::simple(
+                    Column::new()
+                        .push(text("The encrypted descriptor:").small().bold())
+                        .push(text(prompt::BACKUP_ENCRYPTED_DESCRIPTOR_MESSAGE).small())
+                        .push(
+                            Row::new().push(Column::new().width(Length::Fill)).push(
+                                button::secondary(
+                                    Some(icon::clipboard_icon()),
+                                    "Copy encrypted descriptor",
+                                )
+                                .on_press(Message::Clibpboard(encrypted)),
+                            ),
+                        )
+                        .spacing(10)
+                        .max_width(1000),
+                )
+            }))
             .push(checkbox(
                 "I have backed up my descriptor",
                 done,
@@ -1431,6 +1532,15 @@ pub fn edit_key_modal<'a>(
         .into()
 }
 
+fn template_title(template: &PolicyTemplate) -> &'static str {
+    match template {
+        PolicyTemplate::SimpleInheritance => "Simple inheritance",
+        PolicyTemplate::ExpandingMultisig => "Expanding multisig",
+        PolicyTemplate::DecayingMultisig => "Decaying multisig",
+        PolicyTemplate::ThirdPartyRecovery => "Third-party recovery",
+    }
+}
+
 /// returns y,m,d,h,m
 fn duration_from_sequence(sequence: u16) -> (u32, u32, u32, u32, u32) {
     let mut n_minutes = sequence as u32 * 10;
@@ -1593,7 +1703,9 @@ pub fn key_list_view<'a>(
 
 pub fn backup_mnemonic<'a>(
     progress: (usize, usize),
-    words: &'a [&'static str; 12],
+    words: &'a [&'static str],
+    passphrase: &form::Value<String>,
+    passphrase_confirmation: &form::Value<String>,
     done: bool,
 ) -> Element<'a, Message> {
     layout(
@@ -1617,6 +1729,30 @@ pub fn backup_mnemonic<'a>(
                         )
                     }),
             )
+            .push(
+                Column::new()
+                    .spacing(10)
+                    .push(text("Encrypt the mnemonic on this computer (optional):").bold())
+                    .push(text(prompt::MNEMONIC_PASSPHRASE_HELP).small())
+                    .push(
+                        form::Form::new("Passphrase", passphrase, Message::MnemonicPassphrase)
+                            .password()
+                            .size(20)
+                            .padding(10),
+                    )
+                    .push(
+                        form::Form::new(
+                            "Confirm passphrase",
+                            passphrase_confirmation,
+                            Message::MnemonicPassphraseConfirmation,
+                        )
+                        .password()
+                        .warning("Passphrases do not match")
+                        .size(20)
+                        .padding(10),
+                    )
+                    .max_width(500),
+            )
             .push(checkbox(
                 "I have backed up my mnemonic",
                 done,
@@ -1637,7 +1773,8 @@ pub fn backup_mnemonic<'a>(
 
 pub fn recover_mnemonic<'a>(
     progress: (usize, usize),
-    words: &'a [(String, bool); 12],
+    words: &'a [(String, bool)],
+    passphrase: &form::Value<String>,
     current: usize,
     suggestions: &'a Vec<String>,
     recover: bool,
@@ -1670,31 +1807,77 @@ pub fn recover_mnemonic<'a>(
                             // Fixed height in order to not move words list
                             .height(Length::Fixed(50.0)),
                         )
-                        .push(words.iter().enumerate().fold(
-                            Column::new().spacing(5),
-                            |acc, (i, (word, valid))| {
-                                acc.push(
-                                    Row::new()
-                                        .spacing(10)
-                                        .align_items(Alignment::Center)
-                                        .push(
-                                            Container::new(text(format!("#{}", i + 1)).small())
-                                                .width(Length::Fixed(50.0)),
-                                        )
-                                        .push(
-                                            Container::new(TextInput::new("", word).on_input(
-                                                move |msg| Message::MnemonicWord(i, msg),
-                                            ))
-                                            .width(Length::Fixed(100.0)),
+                        .push([(12, "12 words"), (24, "24 words")].iter().fold(
+                            Row::new().spacing(10),
+                            |row, (count, label)| {
+                                row.push(if *count == words.len() {
+                                    button::primary(None, *label)
+                                } else {
+                                    button::secondary(None, *label)
+                                        .on_press(Message::MnemonicWordCount(*count))
+                                })
+                            },
+                        ))
+                        .push(Space::with_height(Length::Fixed(20.0)))
+                        // Words are displayed by columns of 12.
+                        .push(words.chunks(12).enumerate().fold(
+                            Row::new().spacing(50),
+                            |row, (chunk_index, chunk)| {
+                                row.push(chunk.iter().enumerate().fold(
+                                    Column::new().spacing(5),
+                                    |acc, (i, (word, valid))| {
+                                        let i = chunk_index * 12 + i;
+                                        acc.push(
+                                            Row::new()
+                                                .spacing(10)
+                                                .align_items(Alignment::Center)
+                                                .push(
+                                                    Container::new(
+                                                        text(format!("#{}", i + 1)).small(),
+                                                    )
+                                                    .width(Length::Fixed(50.0)),
+                                                )
+                                                .push(
+                                                    Container::new(
+                                                        TextInput::new("", word).on_input(
+                                                            move |msg| {
+                                                                Message::MnemonicWord(i, msg)
+                                                            },
+                                                        ),
+                                                    )
+                                                    .width(Length::Fixed(100.0)),
+                                                )
+                                                .push_maybe(if *valid {
+                                                    Some(
+                                                        icon::circle_check_icon()
+                                                            .style(color::GREEN),
+                                                    )
+                                                } else {
+                                                    None
+                                                }),
                                         )
-                                        .push_maybe(if *valid {
-                                            Some(icon::circle_check_icon().style(color::GREEN))
-                                        } else {
-                                            None
-                                        }),
-                                )
+                                    },
+                                ))
                             },
                         ))
+                        .push(Space::with_height(Length::Fixed(20.0)))
+                        .push(
+                            Column::new()
+                                .spacing(10)
+                                .push(text("BIP39 passphrase (optional):").bold())
+                                .push(text(prompt::BIP39_PASSPHRASE_HELP).small())
+                                .push(
+                                    form::Form::new(
+                                        "Passphrase",
+                                        passphrase,
+                                        Message::Bip39Passphrase,
+                                    )
+                                    .password()
+                                    .size(20)
+                                    .padding(10),
+                                )
+                                .max_width(500),
+                        )
                         .push(Space::with_height(Length::Fixed(50.0)))
                         .push_maybe(error.map(|e| card::invalid(text(e).style(color::RED)))),
                 )
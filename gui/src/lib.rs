@@ -7,6 +7,7 @@ pub mod installer;
 pub mod launcher;
 pub mod loader;
 pub mod logger;
+pub mod qr;
 pub mod signer;
 pub mod utils;
 
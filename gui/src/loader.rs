@@ -318,17 +318,19 @@ pub async fn load_application(
 > {
     let coins = daemon.list_coins().map(|res| res.coins)?;
     let spend_txs = daemon.list_spend_transactions()?;
+    let wallet =
+        Wallet::new(info.descriptors.main).load_settings(&gui_config, &datadir_path, network)?;
+
     let cache = Cache {
         network: info.network,
         blockheight: info.block_height,
         coins,
         spend_txs,
+        expired_coins: info.expired_coins,
+        acknowledged_expired_coins: wallet.acknowledged_expired_coins,
         ..Default::default()
     };
 
-    let wallet =
-        Wallet::new(info.descriptors.main).load_settings(&gui_config, &datadir_path, network)?;
-
     Ok((Arc::new(wallet), cache, daemon, internal_bitcoind))
 }
 
@@ -0,0 +1,399 @@
+//! Better Bitcoin QR (BBQr)
+//!
+//! A BBQr part is an 8 characters header (`B$`, the encoding, the file type, the number of parts
+//! and the index of this part, both as 2 base36 digits) followed by a chunk of the encoded data.
+//! We always encode in base32, but can decode hex and zlib-compressed base32 parts too.
+
+use std::{collections::BTreeMap, error, fmt, io::Read};
+
+use flate2::read::DeflateDecoder;
+
+// The maximum number of parts, as it's encoded as 2 base36 digits.
+const MAX_PARTS: usize = 36 * 36 - 1;
+
+// Don't decompress a payload larger than this.
+const MAX_DECOMPRESSED_LEN: usize = 10_000_000;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const BASE36_ALPHABET: &[u8; 36] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// The type of the file transmitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Psbt,
+    Transaction,
+    Json,
+    Cbor,
+    UnicodeText,
+}
+
+impl FileType {
+    fn code(&self) -> char {
+        match self {
+            Self::Psbt => 'P',
+            Self::Transaction => 'T',
+            Self::Json => 'J',
+            Self::Cbor => 'C',
+            Self::UnicodeText => 'U',
+        }
+    }
+
+    fn from_code(c: char) -> Option<Self> {
+        match c {
+            'P' => Some(Self::Psbt),
+            'T' => Some(Self::Transaction),
+            'J' => Some(Self::Json),
+            'C' => Some(Self::Cbor),
+            'U' => Some(Self::UnicodeText),
+            _ => None,
+        }
+    }
+}
+
+/// The encoding of the data in the parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Hex,
+    Base32,
+    /// Raw deflate (with a 1KiB window), then base32.
+    Zlib,
+}
+
+impl Encoding {
+    fn from_code(c: char) -> Option<Self> {
+        match c {
+            'H' => Some(Self::Hex),
+            '2' => Some(Self::Base32),
+            'Z' => Some(Self::Zlib),
+            _ => None,
+        }
+    }
+
+    fn is_valid_char(&self, c: u8) -> bool {
+        match self {
+            Self::Hex => c.is_ascii_digit() || (b'A'..=b'F').contains(&c),
+            Self::Base32 | Self::Zlib => BASE32_ALPHABET.contains(&c),
+        }
+    }
+
+    // The number of characters encoding a whole number of bytes.
+    fn chunk_len(&self) -> usize {
+        match self {
+            Self::Hex => 2,
+            Self::Base32 | Self::Zlib => 8,
+        }
+    }
+}
+
+/// An error related to decoding BBQr parts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BbqrError {
+    /// The header of the part is invalid.
+    InvalidHeader(String),
+    /// The data of the part isn't valid for its encoding.
+    InvalidData,
+    /// The part is inconsistent with the previously received ones.
+    InconsistentPart,
+    /// The compressed data could not be decompressed.
+    Decompression,
+}
+
+impl fmt::Display for BbqrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader(h) => write!(f, "Invalid BBQr header '{}'.", h),
+            Self::InvalidData => write!(f, "Invalid data in BBQr part."),
+            Self::InconsistentPart => write!(
+                f,
+                "BBQr part inconsistent with the previously received parts."
+            ),
+            Self::Decompression => write!(f, "Failed to decompress BBQr data."),
+        }
+    }
+}
+
+impl error::Error for BbqrError {}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut s = String::with_capacity(super::div_ceil(data.len() * 8, 5));
+    let (mut buffer, mut bits) = (0u16, 0);
+    for byte in data {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            s.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        s.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    s
+}
+
+fn base32_decode(s: &str) -> Result<Vec<u8>, BbqrError> {
+    let mut data = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u16, 0);
+    for c in s.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c)
+            .ok_or(BbqrError::InvalidData)?;
+        buffer = (buffer << 5) | value as u16;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(data)
+}
+
+// Decompress the raw deflate data, up to MAX_DECOMPRESSED_LEN bytes.
+fn inflate(data: &[u8]) -> Result<Vec<u8>, BbqrError> {
+    let mut decompressed = Vec::new();
+    DeflateDecoder::new(data)
+        .take(MAX_DECOMPRESSED_LEN as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|_| BbqrError::Decompression)?;
+    if decompressed.len() > MAX_DECOMPRESSED_LEN {
+        return Err(BbqrError::Decompression);
+    }
+    Ok(decompressed)
+}
+
+fn hex_decode(s: &str) -> Result<Vec<u8>, BbqrError> {
+    let pairs = s.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(BbqrError::InvalidData);
+    }
+    pairs
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(BbqrError::InvalidData)
+        })
+        .collect()
+}
+
+fn base36_encode(n: usize) -> String {
+    [
+        BASE36_ALPHABET[n / 36] as char,
+        BASE36_ALPHABET[n % 36] as char,
+    ]
+    .iter()
+    .collect()
+}
+
+fn base36_decode(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 36).ok()
+}
+
+/// Encode this data as a sequence of BBQr parts, in base32. Each part contains at most
+/// `max_part_len` characters of data, in addition to the header. The data is split in as few
+/// parts as possible, of about equal length.
+pub fn encode(data: &[u8], file_type: FileType, max_part_len: usize) -> Vec<String> {
+    let encoding = Encoding::Base32;
+    let encoded = base32_encode(data);
+    let chunk_len = encoding.chunk_len();
+
+    // Each part but the last must contain a whole number of bytes.
+    let max_part_len = (max_part_len / chunk_len).max(1) * chunk_len;
+    let count = super::div_ceil(encoded.len(), max_part_len).clamp(1, MAX_PARTS);
+    let part_len = super::div_ceil(encoded.len(), count);
+    let part_len = super::div_ceil(part_len, chunk_len).max(1) * chunk_len;
+    let mut chunks: Vec<&str> = (0..encoded.len())
+        .step_by(part_len)
+        .map(|i| &encoded[i..(i + part_len).min(encoded.len())])
+        .collect();
+    if chunks.is_empty() {
+        chunks.push("");
+    }
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            format!(
+                "B$2{}{}{}{}",
+                file_type.code(),
+                base36_encode(chunks.len()),
+                base36_encode(i),
+                chunk
+            )
+        })
+        .collect()
+}
+
+// The information in the header of a part, which must be the same for all parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PartsInfo {
+    encoding: Encoding,
+    file_type: FileType,
+    count: usize,
+}
+
+/// Reassemble the data from BBQr parts, received in any order.
+#[derive(Debug, Default)]
+pub struct BbqrDecoder {
+    info: Option<PartsInfo>,
+    parts: BTreeMap<usize, String>,
+    data: Option<Vec<u8>>,
+}
+
+impl BbqrDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The type of the file being decoded, if a part was received already.
+    pub fn file_type(&self) -> Option<FileType> {
+        self.info.map(|info| info.file_type)
+    }
+
+    /// The reassembled data, once all parts were received.
+    pub fn data(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+
+    /// The number of parts received and the total number of parts.
+    pub fn progress(&self) -> (usize, usize) {
+        (
+            self.parts.len(),
+            self.info.map(|info| info.count).unwrap_or(0),
+        )
+    }
+
+    /// Process a BBQr part. Parts received after the data was reassembled are ignored.
+    pub fn receive(&mut self, part: &str) -> Result<(), BbqrError> {
+        let part = part.trim();
+        let invalid_header = || BbqrError::InvalidHeader(part.chars().take(8).collect());
+        let header = part.get(..8).ok_or_else(invalid_header)?;
+        if !header.is_ascii() || !header.starts_with("B$") {
+            return Err(invalid_header());
+        }
+        let mut codes = header[2..4].chars();
+        let encoding = codes
+            .next()
+            .and_then(Encoding::from_code)
+            .ok_or_else(invalid_header)?;
+        let file_type = codes
+            .next()
+            .and_then(FileType::from_code)
+            .ok_or_else(invalid_header)?;
+        let count = base36_decode(&header[4..6]).ok_or_else(invalid_header)?;
+        let index = base36_decode(&header[6..8]).ok_or_else(invalid_header)?;
+        if count == 0 || index >= count {
+            return Err(invalid_header());
+        }
+        let info = PartsInfo {
+            encoding,
+            file_type,
+            count,
+        };
+        if self.info.map(|i| i != info).unwrap_or(false) {
+            return Err(BbqrError::InconsistentPart);
+        }
+        self.info = Some(info);
+        if self.data.is_some() {
+            return Ok(());
+        }
+
+        // All parts but the last must contain a whole number of bytes.
+        let chunk = &part[8..];
+        if (index + 1 < count
+            && !chunk
+                .as_bytes()
+                .chunks_exact(encoding.chunk_len())
+                .remainder()
+                .is_empty())
+            || !chunk.bytes().all(|c| encoding.is_valid_char(c))
+        {
+            return Err(BbqrError::InvalidData);
+        }
+        self.parts.insert(index, chunk.to_string());
+
+        if self.parts.len() == count {
+            let encoded: String = self.parts.values().map(|s| s.as_str()).collect();
+            self.data = Some(match encoding {
+                Encoding::Hex => hex_decode(&encoded)?,
+                Encoding::Base32 => base32_decode(&encoded)?,
+                Encoding::Zlib => inflate(&base32_decode(&encoded)?)?,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bbqr_encoding() {
+        // Single part data, in all the encodings.
+        let mut decoder = BbqrDecoder::new();
+        decoder.receive("B$2U0100JRUWC3TBEE").unwrap();
+        assert_eq!(decoder.data(), Some(&b"Liana!"[..]));
+        assert_eq!(decoder.file_type(), Some(FileType::UnicodeText));
+        assert_eq!(
+            encode(b"Liana!", FileType::UnicodeText, 100),
+            vec!["B$2U0100JRUWC3TBEE"]
+        );
+        let mut decoder = BbqrDecoder::new();
+        decoder.receive("B$HU01004C69616E6121").unwrap();
+        assert_eq!(decoder.data(), Some(&b"Liana!"[..]));
+
+        // Compressed data, split in two parts (generated with Python's zlib).
+        let data = "Liana is a simple Bitcoin wallet with a recovery path. ".repeat(4);
+        let mut decoder = BbqrDecoder::new();
+        decoder
+            .receive("B$ZU0201JEWVCKGPFTEQASQWUUTOPF5FCZKSUFBESZSOQKPYBQCW2AA")
+            .unwrap();
+        assert_eq!(decoder.progress(), (1, 2));
+        assert!(decoder.data().is_none());
+        decoder
+            .receive("B$ZU02006PEUZTCLKTECYVSIKQUM5TBNZBEVK4GKFRE45T6MKMUE7TGJ")
+            .unwrap();
+        assert_eq!(decoder.data(), Some(data.as_bytes()));
+
+        // Large data is split in parts of about equal size, each with a whole number of bytes.
+        let data: Vec<u8> = (0..1000).map(|i| (i % 256) as u8).collect();
+        let parts = encode(&data, FileType::Psbt, 500);
+        assert_eq!(parts.len(), 4);
+        for part in &parts[..3] {
+            assert!(part.starts_with("B$2P04"));
+            assert_eq!((part.len() - 8) % 8, 0);
+            assert!(part.len() - 8 <= 500);
+        }
+        let mut decoder = BbqrDecoder::new();
+        for part in parts.iter().rev() {
+            decoder.receive(part).unwrap();
+        }
+        assert_eq!(decoder.file_type(), Some(FileType::Psbt));
+        assert_eq!(decoder.data(), Some(&data[..]));
+
+        // Invalid or inconsistent parts are refused.
+        let mut decoder = BbqrDecoder::new();
+        decoder.receive(&parts[0]).unwrap();
+        assert_eq!(
+            decoder.receive("B$2U0100JRUWC3TBEE"),
+            Err(BbqrError::InconsistentPart)
+        );
+        assert!(matches!(
+            decoder.receive("B$2P0404AAAA"),
+            Err(BbqrError::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            decoder.receive("ur:crypto-psbt/aeadaolazmjendeoti"),
+            Err(BbqrError::InvalidHeader(_))
+        ));
+        assert_eq!(decoder.receive("B$2P0401AAA"), Err(BbqrError::InvalidData));
+        assert_eq!(
+            decoder.receive("B$2P0401AAAAAAA1"),
+            Err(BbqrError::InvalidData)
+        );
+    }
+}
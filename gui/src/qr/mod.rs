@@ -0,0 +1,220 @@
+//! Animated QR codes module
+//!
+//! Helpers to exchange PSBTs with air-gapped signing devices through sequences of QR codes. We
+//! support the two formats used by these devices: UR (`crypto-psbt`) and BBQr. A PSBT is encoded as
+//! a sequence of frames to be displayed in a loop, and the frames scanned from a device can be
+//! received in any order until the PSBT is reassembled.
+
+pub mod bbqr;
+pub mod ur;
+
+use std::{error, fmt};
+
+use liana::miniscript::bitcoin::psbt::Psbt;
+
+/// The format of the frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QrFormat {
+    Ur,
+    Bbqr,
+}
+
+/// An error related to decoding a PSBT from QR code frames.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QrError {
+    Ur(ur::UrError),
+    Bbqr(bbqr::BbqrError),
+    /// The frame is neither a UR nor a BBQr part.
+    UnknownFormat,
+    /// The frames don't contain a PSBT.
+    NotPsbt,
+    InvalidPsbt(String),
+    /// Not enough frames were given to reassemble the PSBT.
+    Incomplete(usize, usize),
+}
+
+impl fmt::Display for QrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ur(e) => write!(f, "{}", e),
+            Self::Bbqr(e) => write!(f, "{}", e),
+            Self::UnknownFormat => write!(f, "Not a UR or BBQr QR code."),
+            Self::NotPsbt => write!(f, "The QR code does not contain a PSBT."),
+            Self::InvalidPsbt(e) => write!(f, "Invalid PSBT: {}", e),
+            Self::Incomplete(received, total) => write!(
+                f,
+                "Incomplete PSBT: only {} out of {} parts were received.",
+                received, total
+            ),
+        }
+    }
+}
+
+impl error::Error for QrError {}
+
+// The number of parts needed to split `len` elements in parts of `part_len` elements. The method
+// of the standard library isn't available with our MSRV.
+fn div_ceil(len: usize, part_len: usize) -> usize {
+    len.checked_sub(1).map_or(0, |len| len / part_len + 1)
+}
+
+/// Encode this PSBT as a sequence of frames in this format. Each frame holds about
+/// `max_fragment_len` bytes of the PSBT.
+pub fn encode_psbt(psbt: &Psbt, format: QrFormat, max_fragment_len: usize) -> Vec<String> {
+    let psbt = psbt.serialize();
+    match format {
+        QrFormat::Ur => ur::encode(
+            ur::PSBT_UR_TYPE,
+            &ur::cbor_encode_bytes(&psbt),
+            max_fragment_len,
+        ),
+        // A byte takes 8/5 characters in base32.
+        QrFormat::Bbqr => bbqr::encode(&psbt, bbqr::FileType::Psbt, max_fragment_len * 8 / 5),
+    }
+}
+
+#[derive(Debug)]
+enum Decoder {
+    Ur(ur::UrDecoder),
+    Bbqr(bbqr::BbqrDecoder),
+}
+
+/// Reassemble a PSBT from the QR code frames scanned from a signing device, in either format.
+#[derive(Debug, Default)]
+pub struct PsbtQrDecoder {
+    decoder: Option<Decoder>,
+}
+
+impl PsbtQrDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of parts received and the total number of parts.
+    pub fn progress(&self) -> (usize, usize) {
+        match &self.decoder {
+            Some(Decoder::Ur(decoder)) => decoder.progress(),
+            Some(Decoder::Bbqr(decoder)) => decoder.progress(),
+            None => (0, 0),
+        }
+    }
+
+    /// Process a scanned frame. Returns the PSBT once all the needed frames were received. The
+    /// format is detected from the first frame.
+    pub fn receive(&mut self, frame: &str) -> Result<Option<Psbt>, QrError> {
+        let frame = frame.trim();
+        if self.decoder.is_none() {
+            self.decoder = if frame.to_lowercase().starts_with("ur:") {
+                Some(Decoder::Ur(ur::UrDecoder::new()))
+            } else if frame.starts_with("B$") {
+                Some(Decoder::Bbqr(bbqr::BbqrDecoder::new()))
+            } else {
+                return Err(QrError::UnknownFormat);
+            };
+        }
+
+        let psbt_bytes = match self.decoder.as_mut().expect("Just set") {
+            Decoder::Ur(decoder) => {
+                decoder.receive(frame).map_err(QrError::Ur)?;
+                match decoder.ur_type() {
+                    Some(ur::PSBT_UR_TYPE) | Some(ur::PSBT_UR_TYPE_V2) => {}
+                    _ => return Err(QrError::NotPsbt),
+                }
+                match decoder.message() {
+                    Some(message) => ur::cbor_decode_bytes(message).map_err(QrError::Ur)?,
+                    None => return Ok(None),
+                }
+            }
+            Decoder::Bbqr(decoder) => {
+                decoder.receive(frame).map_err(QrError::Bbqr)?;
+                if decoder.file_type() != Some(bbqr::FileType::Psbt) {
+                    return Err(QrError::NotPsbt);
+                }
+                match decoder.data() {
+                    Some(data) => data.to_vec(),
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        // Signing devices may use either version of PSBTs.
+        liana::psbt::deserialize(&psbt_bytes)
+            .map(Some)
+            .map_err(|e| QrError::InvalidPsbt(e.to_string()))
+    }
+}
+
+/// Decode a PSBT from these QR code frames, for instance pasted as text one per line. Empty
+/// lines are ignored.
+pub fn decode_psbt<'a>(frames: impl IntoIterator<Item = &'a str>) -> Result<Psbt, QrError> {
+    let mut decoder = PsbtQrDecoder::new();
+    for frame in frames.into_iter().filter(|f| !f.trim().is_empty()) {
+        if let Some(psbt) = decoder.receive(frame)? {
+            return Ok(psbt);
+        }
+    }
+    let (received, total) = decoder.progress();
+    Err(QrError::Incomplete(received, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn psbt_qr_roundtrip() {
+        let psbt = Psbt::from_str("cHNidP8BAHECAAAAAUSHuliRtuCX1S6JxRuDRqDCKkWfKmWL5sV9ukZ/wzvfAAAAAAD9////AogTAAAAAAAAFgAUIxe7UY6LJ6y5mFBoWTOoVispDmdwFwAAAAAAABYAFKqO83TK+t/KdpAt21z2HGC7/Z2FAAAAAAABASsQJwAAAAAAACIAIIIySQjGCTeyx/rKUQx8qobjhJeNCiVCliBJPdyRX6XKAQVBIQI2cqWpc9UAW2gZt2WkKjvi8KoMCui00pRlL6wG32uKDKxzZHapFNYASzIYkEdH9bJz6nnqUG3uBB8kiK1asmgiBgI2cqWpc9UAW2gZt2WkKjvi8KoMCui00pRlL6wG32uKDAz1rML9AAAAAG8AAAAiBgMLcbOxsfLe6+3r1UcjQo77HY0As8OKE4l37yj0/qhIyQyKZPKpAAAAAG8AAAAAAAA=").unwrap();
+
+        for format in [QrFormat::Ur, QrFormat::Bbqr].iter() {
+            // Small PSBTs fit in a single frame.
+            let frames = encode_psbt(&psbt, *format, 1_000);
+            assert_eq!(frames.len(), 1);
+            assert_eq!(
+                decode_psbt(frames.iter().map(|f| f.as_str())),
+                Ok(psbt.clone())
+            );
+
+            // Otherwise they are split into frames to be received in any order. Frames scanned
+            // as uppercase (QR alphanumeric mode) are accepted too.
+            let frames = encode_psbt(&psbt, *format, 50);
+            assert!(frames.len() > 3);
+            let mut decoder = PsbtQrDecoder::new();
+            for frame in frames.iter().rev().skip(1) {
+                assert_eq!(decoder.receive(&frame.to_uppercase()), Ok(None));
+            }
+            assert_eq!(decoder.progress(), (frames.len() - 1, frames.len()));
+            assert_eq!(
+                decoder.receive(&frames[frames.len() - 1]),
+                Ok(Some(psbt.clone()))
+            );
+
+            // All the frames are needed.
+            let text = frames[1..].join("\n");
+            assert!(matches!(
+                decode_psbt(text.lines()),
+                Err(QrError::Incomplete(..))
+            ));
+        }
+
+        // Frames must be of a known format, and contain a PSBT.
+        assert_eq!(
+            PsbtQrDecoder::new().receive("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"),
+            Err(QrError::UnknownFormat)
+        );
+        assert_eq!(
+            PsbtQrDecoder::new().receive("B$2U0100JRUWC3TBEE"),
+            Err(QrError::NotPsbt)
+        );
+        let bytes_ur = ur::encode("bytes", &ur::cbor_encode_bytes(b"Liana"), 100);
+        assert_eq!(
+            PsbtQrDecoder::new().receive(&bytes_ur[0]),
+            Err(QrError::NotPsbt)
+        );
+        let invalid_psbt = ur::encode(ur::PSBT_UR_TYPE, &ur::cbor_encode_bytes(b"Liana"), 100);
+        assert!(matches!(
+            PsbtQrDecoder::new().receive(&invalid_psbt[0]),
+            Err(QrError::InvalidPsbt(_))
+        ));
+    }
+}
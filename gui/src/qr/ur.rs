@@ -0,0 +1,756 @@
+//! Uniform Resources (BCR-2020-005)
+//!
+//! A UR is a CBOR payload of a given type encoded with bytewords (BCR-2020-012). Larger payloads
+//! are split into parts using a fountain code, so that a receiver can reconstruct the payload from
+//! any sufficient set of parts. We only ever emit the fragments as is ("simple" parts), but we can
+//! decode the XOR of several fragments ("mixed" parts) some encoders emit after the simple ones.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    convert::{TryFrom, TryInto},
+    error, fmt, iter,
+};
+
+use liana::miniscript::bitcoin::hashes::{sha256, Hash};
+
+/// The UR type of a PSBT, as registered in BCR-2020-006.
+pub const PSBT_UR_TYPE: &str = "crypto-psbt";
+
+/// The newer UR type of a PSBT, without the "crypto-" prefix.
+pub const PSBT_UR_TYPE_V2: &str = "psbt";
+
+// The minimum length of a fragment, unless the message is shorter.
+const MIN_FRAGMENT_LEN: usize = 10;
+
+// Bounds on the multi-part URs we accept to decode, as the cost of reassembling a message grows
+// quadratically with the number of parts.
+const MAX_MESSAGE_LEN: usize = 1 << 20;
+const MAX_SEQ_LEN: usize = 10_000;
+
+// The bytewords, indexed by the value of the byte they encode. The minimal encoding of a byte is
+// the first and last letters of its word.
+const BYTEWORDS: [&str; 256] = [
+    "able", "acid", "also", "apex", "aqua", "arch", "atom", "aunt", "away", "axis", "back", "bald",
+    "barn", "belt", "beta", "bias", "blue", "body", "brag", "brew", "bulb", "buzz", "calm", "cash",
+    "cats", "chef", "city", "claw", "code", "cola", "cook", "cost", "crux", "curl", "cusp", "cyan",
+    "dark", "data", "days", "deli", "dice", "diet", "door", "down", "draw", "drop", "drum", "dull",
+    "duty", "each", "easy", "echo", "edge", "epic", "even", "exam", "exit", "eyes", "fact", "fair",
+    "fern", "figs", "film", "fish", "fizz", "flap", "flew", "flux", "foxy", "free", "frog", "fuel",
+    "fund", "gala", "game", "gear", "gems", "gift", "girl", "glow", "good", "gray", "grim", "guru",
+    "gush", "gyro", "half", "hang", "hard", "hawk", "heat", "help", "high", "hill", "holy", "hope",
+    "horn", "huts", "iced", "idea", "idle", "inch", "inky", "into", "iris", "iron", "item", "jade",
+    "jazz", "join", "jolt", "jowl", "judo", "jugs", "jump", "junk", "jury", "keep", "keno", "kept",
+    "keys", "kick", "kiln", "king", "kite", "kiwi", "knob", "lamb", "lava", "lazy", "leaf", "legs",
+    "liar", "limp", "lion", "list", "logo", "loud", "love", "luau", "luck", "lung", "main", "many",
+    "math", "maze", "memo", "menu", "meow", "mild", "mint", "miss", "monk", "nail", "navy", "need",
+    "news", "next", "noon", "note", "numb", "obey", "oboe", "omit", "onyx", "open", "oval", "owls",
+    "paid", "part", "peck", "play", "plus", "poem", "pool", "pose", "puff", "puma", "purr", "quad",
+    "quiz", "race", "ramp", "real", "redo", "rich", "road", "rock", "roof", "ruby", "ruin", "runs",
+    "rust", "safe", "saga", "scar", "sets", "silk", "skew", "slot", "soap", "solo", "song", "stub",
+    "surf", "swan", "taco", "task", "taxi", "tent", "tied", "time", "tiny", "toil", "tomb", "toys",
+    "trip", "tuna", "twin", "ugly", "undo", "unit", "urge", "user", "vast", "very", "veto", "vial",
+    "vibe", "view", "visa", "void", "vows", "wall", "wand", "warm", "wasp", "wave", "waxy", "webs",
+    "what", "when", "whiz", "wolf", "work", "yank", "yawn", "yell", "yoga", "yurt", "zaps", "zero",
+    "zest", "zinc", "zone", "zoom",
+];
+
+/// An error related to decoding a UR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrError {
+    /// The string doesn't start with the "ur:" scheme.
+    InvalidScheme,
+    /// The type of the UR is invalid, or differs between the parts.
+    InvalidType(String),
+    /// The sequence number of a part is invalid.
+    InvalidSequence(String),
+    /// The bytewords are invalid.
+    InvalidBytewords,
+    /// The checksum of the bytewords or of the reassembled message doesn't match.
+    ChecksumMismatch,
+    /// The CBOR payload is invalid.
+    InvalidCbor,
+    /// The part is inconsistent with the previously received ones.
+    InconsistentPart,
+}
+
+impl fmt::Display for UrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidScheme => write!(f, "Not a UR: must start with 'ur:'."),
+            Self::InvalidType(t) => write!(f, "Invalid or unexpected UR type '{}'.", t),
+            Self::InvalidSequence(s) => write!(f, "Invalid UR sequence '{}'.", s),
+            Self::InvalidBytewords => write!(f, "Invalid bytewords."),
+            Self::ChecksumMismatch => write!(f, "UR checksum mismatch."),
+            Self::InvalidCbor => write!(f, "Invalid CBOR in UR."),
+            Self::InconsistentPart => {
+                write!(
+                    f,
+                    "UR part inconsistent with the previously received parts."
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for UrError {}
+
+/// The CRC32 (ISO-HDLC) checksum of these bytes, as used by URs.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Encode these bytes as minimal bytewords, followed by their checksum.
+pub fn bytewords_encode(data: &[u8]) -> String {
+    let mut s = String::with_capacity((data.len() + 4) * 2);
+    for byte in data.iter().chain(crc32(data).to_be_bytes().iter()) {
+        let word = BYTEWORDS[*byte as usize].as_bytes();
+        s.push(word[0] as char);
+        s.push(word[3] as char);
+    }
+    s
+}
+
+/// Decode minimal bytewords and check their checksum.
+pub fn bytewords_decode(s: &str) -> Result<Vec<u8>, UrError> {
+    let s = s.as_bytes();
+    let pairs = s.chunks_exact(2);
+    if !pairs.remainder().is_empty() || s.len() < 2 * 4 {
+        return Err(UrError::InvalidBytewords);
+    }
+    let mut data = pairs
+        .map(|pair| {
+            BYTEWORDS
+                .iter()
+                .position(|word| {
+                    let word = word.as_bytes();
+                    word[0] == pair[0] && word[3] == pair[1]
+                })
+                .map(|i| i as u8)
+                .ok_or(UrError::InvalidBytewords)
+        })
+        .collect::<Result<Vec<u8>, UrError>>()?;
+    let checksum = data.split_off(data.len() - 4);
+    if crc32(&data).to_be_bytes()[..] != checksum[..] {
+        return Err(UrError::ChecksumMismatch);
+    }
+    Ok(data)
+}
+
+// Write the head of a CBOR data item of this major type with this argument.
+fn cbor_head(major: u8, arg: u64, buf: &mut Vec<u8>) {
+    let major = major << 5;
+    if arg < 24 {
+        buf.push(major | arg as u8);
+    } else if arg <= u8::MAX as u64 {
+        buf.push(major | 24);
+        buf.push(arg as u8);
+    } else if arg <= u16::MAX as u64 {
+        buf.push(major | 25);
+        buf.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= u32::MAX as u64 {
+        buf.push(major | 26);
+        buf.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        buf.push(major | 27);
+        buf.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+// Read the head of a CBOR data item at this position, returning its major type and argument.
+fn cbor_read_head(data: &[u8], pos: &mut usize) -> Result<(u8, u64), UrError> {
+    let initial = *data.get(*pos).ok_or(UrError::InvalidCbor)?;
+    *pos += 1;
+    let (major, info) = (initial >> 5, initial & 0x1f);
+    let len = match info {
+        0..=23 => return Ok((major, u64::from(info))),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return Err(UrError::InvalidCbor),
+    };
+    let bytes = data.get(*pos..*pos + len).ok_or(UrError::InvalidCbor)?;
+    *pos += len;
+    Ok((
+        major,
+        bytes.iter().fold(0, |acc, b| (acc << 8) | u64::from(*b)),
+    ))
+}
+
+// Read a CBOR unsigned integer at this position.
+fn cbor_read_uint(data: &[u8], pos: &mut usize) -> Result<u64, UrError> {
+    match cbor_read_head(data, pos)? {
+        (0, value) => Ok(value),
+        _ => Err(UrError::InvalidCbor),
+    }
+}
+
+// Read a CBOR byte string at this position.
+fn cbor_read_bytes<'a>(data: &'a [u8], pos: &mut usize) -> Result<&'a [u8], UrError> {
+    match cbor_read_head(data, pos)? {
+        (2, len) => {
+            let end = usize::try_from(len)
+                .ok()
+                .and_then(|len| pos.checked_add(len))
+                .ok_or(UrError::InvalidCbor)?;
+            let bytes = data.get(*pos..end).ok_or(UrError::InvalidCbor)?;
+            *pos = end;
+            Ok(bytes)
+        }
+        _ => Err(UrError::InvalidCbor),
+    }
+}
+
+/// Encode these bytes as a CBOR byte string, the payload of a `crypto-psbt` UR.
+pub fn cbor_encode_bytes(data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(data.len() + 9);
+    cbor_head(2, data.len() as u64, &mut buf);
+    buf.extend_from_slice(data);
+    buf
+}
+
+/// Decode a CBOR byte string, the payload of a `crypto-psbt` UR. It may be tagged.
+pub fn cbor_decode_bytes(data: &[u8]) -> Result<Vec<u8>, UrError> {
+    let mut pos = 0;
+    let mut start = 0;
+    while let (6, _) = cbor_read_head(data, &mut pos)? {
+        start = pos;
+    }
+    pos = start;
+    let bytes = cbor_read_bytes(data, &mut pos)?;
+    if pos != data.len() {
+        return Err(UrError::InvalidCbor);
+    }
+    Ok(bytes.to_vec())
+}
+
+// The Xoshiro256** PRNG, seeded as in the reference implementation of the fountain code.
+struct Xoshiro256 {
+    s: [u64; 4],
+}
+
+impl Xoshiro256 {
+    fn from_seed(seed: &[u8]) -> Self {
+        let hash = sha256::Hash::hash(seed);
+        let mut s = [0; 4];
+        for (i, chunk) in hash.as_byte_array().chunks(8).enumerate() {
+            s[i] = u64::from_be_bytes(chunk.try_into().expect("8 bytes chunks"));
+        }
+        Self { s }
+    }
+
+    fn next(&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.s[1] << 17;
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+        result
+    }
+
+    fn next_double(&mut self) -> f64 {
+        self.next() as f64 / (u64::MAX as f64 + 1.0)
+    }
+
+    fn next_int(&mut self, low: usize, high: usize) -> usize {
+        (self.next_double() * (high - low + 1) as f64) as usize + low
+    }
+}
+
+// Choose the degree of a mixed part, with a probability inversely proportional to it. This is a
+// Vose alias sampler of the weights, as in the reference implementation.
+fn choose_degree(seq_len: usize, rng: &mut Xoshiro256) -> usize {
+    let weights: Vec<f64> = (1..=seq_len).map(|i| 1.0 / i as f64).collect();
+    let sum: f64 = weights.iter().sum();
+    let mut probs: Vec<f64> = weights.iter().map(|w| w * seq_len as f64 / sum).collect();
+    let (mut small, mut large) = (Vec::new(), Vec::new());
+    for i in (0..seq_len).rev() {
+        if probs[i] < 1.0 {
+            small.push(i);
+        } else {
+            large.push(i);
+        }
+    }
+    let mut alias_probs = vec![0.0; seq_len];
+    let mut aliases = vec![0; seq_len];
+    while !small.is_empty() && !large.is_empty() {
+        let (a, g) = (small.pop().unwrap(), large.pop().unwrap());
+        alias_probs[a] = probs[a];
+        aliases[a] = g;
+        probs[g] += probs[a] - 1.0;
+        if probs[g] < 1.0 {
+            small.push(g);
+        } else {
+            large.push(g);
+        }
+    }
+    for i in large.into_iter().chain(small) {
+        alias_probs[i] = 1.0;
+    }
+
+    let (r1, r2) = (rng.next_double(), rng.next_double());
+    let i = (seq_len as f64 * r1) as usize;
+    let index = if r2 < alias_probs[i] { i } else { aliases[i] };
+    index + 1
+}
+
+// The indexes of the fragments mixed in the part with this sequence number.
+fn choose_fragments(seq_num: u32, seq_len: usize, checksum: u32) -> BTreeSet<usize> {
+    if seq_num as usize <= seq_len {
+        return iter::once(seq_num as usize - 1).collect();
+    }
+
+    let mut seed = seq_num.to_be_bytes().to_vec();
+    seed.extend_from_slice(&checksum.to_be_bytes());
+    let mut rng = Xoshiro256::from_seed(&seed);
+    let degree = choose_degree(seq_len, &mut rng);
+    let mut remaining: Vec<usize> = (0..seq_len).collect();
+    let mut shuffled = Vec::with_capacity(seq_len);
+    while !remaining.is_empty() {
+        let index = rng.next_int(0, remaining.len() - 1);
+        shuffled.push(remaining.remove(index));
+    }
+    shuffled.into_iter().take(degree).collect()
+}
+
+// The length of the fragments for a message of this size, so that they are of about equal size.
+fn fragment_len(message_len: usize, max_fragment_len: usize) -> usize {
+    let max_count = (message_len / MIN_FRAGMENT_LEN).max(1);
+    (1..=max_count)
+        .map(|count| super::div_ceil(message_len, count))
+        .find(|len| *len <= max_fragment_len)
+        .unwrap_or_else(|| super::div_ceil(message_len, max_count))
+}
+
+fn xor_into(a: &mut [u8], b: &[u8]) {
+    for (x, y) in a.iter_mut().zip(b.iter()) {
+        *x ^= y;
+    }
+}
+
+/// Encode this CBOR payload of the given type as a sequence of URs. Each part contains a fragment
+/// of at most `max_fragment_len` bytes of the payload. If it fits in a single fragment, it's
+/// encoded as a single-part UR.
+pub fn encode(ur_type: &str, message: &[u8], max_fragment_len: usize) -> Vec<String> {
+    if message.len() <= max_fragment_len {
+        return vec![format!("ur:{}/{}", ur_type, bytewords_encode(message))];
+    }
+
+    let frag_len = fragment_len(message.len(), max_fragment_len);
+    let seq_len = super::div_ceil(message.len(), frag_len);
+    (1..=seq_len as u32)
+        .map(|seq_num| encode_part(ur_type, message, frag_len, seq_num))
+        .collect()
+}
+
+// Encode the part with this sequence number of a multi-part UR for this payload.
+fn encode_part(ur_type: &str, message: &[u8], frag_len: usize, seq_num: u32) -> String {
+    let seq_len = super::div_ceil(message.len(), frag_len);
+    let checksum = crc32(message);
+    let mut padded = message.to_vec();
+    padded.resize(seq_len * frag_len, 0);
+    let mut fragment = vec![0; frag_len];
+    for index in choose_fragments(seq_num, seq_len, checksum) {
+        xor_into(
+            &mut fragment,
+            &padded[index * frag_len..(index + 1) * frag_len],
+        );
+    }
+
+    let mut part = Vec::with_capacity(frag_len + 32);
+    cbor_head(4, 5, &mut part);
+    cbor_head(0, u64::from(seq_num), &mut part);
+    cbor_head(0, seq_len as u64, &mut part);
+    cbor_head(0, message.len() as u64, &mut part);
+    cbor_head(0, u64::from(checksum), &mut part);
+    cbor_head(2, frag_len as u64, &mut part);
+    part.extend_from_slice(&fragment);
+    format!(
+        "ur:{}/{}-{}/{}",
+        ur_type,
+        seq_num,
+        seq_len,
+        bytewords_encode(&part)
+    )
+}
+
+// The parameters of a multi-part UR, which must be the same for all parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PartsInfo {
+    seq_len: usize,
+    message_len: usize,
+    checksum: u32,
+    fragment_len: usize,
+}
+
+/// Reassemble a payload from the parts of a UR, received in any order.
+#[derive(Debug, Default)]
+pub struct UrDecoder {
+    ur_type: Option<String>,
+    info: Option<PartsInfo>,
+    received: BTreeSet<u32>,
+    simple: HashMap<usize, Vec<u8>>,
+    mixed: Vec<(BTreeSet<usize>, Vec<u8>)>,
+    message: Option<Vec<u8>>,
+}
+
+impl UrDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The type of the UR being decoded, if a part was received already.
+    pub fn ur_type(&self) -> Option<&str> {
+        self.ur_type.as_deref()
+    }
+
+    /// The reassembled payload, once enough parts were received.
+    pub fn message(&self) -> Option<&[u8]> {
+        self.message.as_deref()
+    }
+
+    /// The number of fragments we know of and the total number of fragments. For a single-part
+    /// UR, one fragment.
+    pub fn progress(&self) -> (usize, usize) {
+        match (&self.message, &self.info) {
+            (Some(_), Some(info)) => (info.seq_len, info.seq_len),
+            (Some(_), None) => (1, 1),
+            (None, Some(info)) => (self.simple.len(), info.seq_len),
+            (None, None) => (0, 0),
+        }
+    }
+
+    /// Process a part of the UR. Parts received after the payload was reassembled are ignored.
+    pub fn receive(&mut self, part: &str) -> Result<(), UrError> {
+        let part = part.trim().to_lowercase();
+        let rest = part.strip_prefix("ur:").ok_or(UrError::InvalidScheme)?;
+        let components: Vec<&str> = rest.split('/').collect();
+        let (ur_type, seq, body) = match components.as_slice() {
+            [ur_type, body] => (*ur_type, None, *body),
+            [ur_type, seq, body] => (*ur_type, Some(*seq), *body),
+            _ => return Err(UrError::InvalidSequence(rest.to_string())),
+        };
+        if ur_type.is_empty()
+            || !ur_type
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            || self.ur_type.as_ref().map(|t| t != ur_type).unwrap_or(false)
+        {
+            return Err(UrError::InvalidType(ur_type.to_string()));
+        }
+        if self.message.is_some() {
+            return Ok(());
+        }
+        let data = bytewords_decode(body)?;
+
+        let seq = match seq {
+            Some(seq) => seq,
+            None => {
+                if self.info.is_some() {
+                    return Err(UrError::InconsistentPart);
+                }
+                self.ur_type = Some(ur_type.to_string());
+                self.message = Some(data);
+                return Ok(());
+            }
+        };
+        let (seq_num, path_seq_len) = parse_seq(seq)?;
+
+        // Parse the part and check it's consistent with the previous ones.
+        let mut pos = 0;
+        if cbor_read_head(&data, &mut pos)? != (4, 5) {
+            return Err(UrError::InvalidCbor);
+        }
+        let part_seq_num = cbor_read_uint(&data, &mut pos)?;
+        let seq_len = cbor_read_uint(&data, &mut pos)?;
+        let message_len = cbor_read_uint(&data, &mut pos)?;
+        let checksum = cbor_read_uint(&data, &mut pos)?;
+        let fragment = cbor_read_bytes(&data, &mut pos)?;
+        if pos != data.len() || part_seq_num != u64::from(seq_num) {
+            return Err(UrError::InvalidCbor);
+        }
+        let info = PartsInfo {
+            seq_len: seq_len.try_into().map_err(|_| UrError::InvalidCbor)?,
+            message_len: message_len.try_into().map_err(|_| UrError::InvalidCbor)?,
+            checksum: checksum.try_into().map_err(|_| UrError::InvalidCbor)?,
+            fragment_len: fragment.len(),
+        };
+        if info.fragment_len == 0
+            || info.message_len == 0
+            || info.message_len > MAX_MESSAGE_LEN
+            || info.seq_len > MAX_SEQ_LEN
+            || info.seq_len != super::div_ceil(info.message_len, info.fragment_len)
+            || u64::from(path_seq_len) != seq_len
+            || self.info.map(|i| i != info).unwrap_or(false)
+        {
+            return Err(UrError::InconsistentPart);
+        }
+        self.ur_type = Some(ur_type.to_string());
+        self.info = Some(info);
+        if !self.received.insert(seq_num) {
+            return Ok(());
+        }
+
+        let indexes = choose_fragments(seq_num, info.seq_len, info.checksum);
+        self.process_part(indexes, fragment.to_vec());
+
+        // Reassemble the message once we know all the fragments.
+        if self.simple.len() == info.seq_len {
+            let mut message = Vec::with_capacity(info.seq_len * info.fragment_len);
+            for i in 0..info.seq_len {
+                message.extend_from_slice(&self.simple[&i]);
+            }
+            message.truncate(info.message_len);
+            if crc32(&message) != info.checksum {
+                return Err(UrError::ChecksumMismatch);
+            }
+            self.message = Some(message);
+        }
+
+        Ok(())
+    }
+
+    // Reduce a part using the fragments we know of, and reduce the mixed parts we have using it
+    // if it turns out to be a simple one.
+    fn process_part(&mut self, indexes: BTreeSet<usize>, fragment: Vec<u8>) {
+        let mut queue = vec![(indexes, fragment)];
+        while let Some((mut indexes, mut fragment)) = queue.pop() {
+            for (index, known) in &self.simple {
+                if indexes.remove(index) {
+                    xor_into(&mut fragment, known);
+                }
+            }
+            for (mixed_indexes, mixed_fragment) in &self.mixed {
+                if mixed_indexes.is_subset(&indexes) {
+                    indexes = indexes.difference(mixed_indexes).cloned().collect();
+                    xor_into(&mut fragment, mixed_fragment);
+                }
+            }
+
+            match indexes.len() {
+                0 => {}
+                1 => {
+                    let index = *indexes.iter().next().expect("Length is 1");
+                    // The mixed parts containing this fragment can now be reduced.
+                    let (reducible, mixed) = self
+                        .mixed
+                        .drain(..)
+                        .partition(|(mixed_indexes, _)| mixed_indexes.contains(&index));
+                    self.mixed = mixed;
+                    queue.extend(reducible);
+                    self.simple.insert(index, fragment);
+                }
+                _ => {
+                    if !self.mixed.iter().any(|(i, _)| i == &indexes) {
+                        self.mixed.push((indexes, fragment));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Parse the sequence number and the sequence length of a part from its path component.
+fn parse_seq(seq: &str) -> Result<(u32, u32), UrError> {
+    let invalid = || UrError::InvalidSequence(seq.to_string());
+    let mut nums = seq.splitn(2, '-');
+    let seq_num: u32 = nums
+        .next()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(invalid)?;
+    let seq_len: u32 = nums
+        .next()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(invalid)?;
+    if seq_num == 0 || seq_len == 0 {
+        return Err(invalid());
+    }
+    Ok((seq_num, seq_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A message generated by the PRNG from this seed, as in the reference test vectors.
+    fn make_message(len: usize, seed: &str) -> Vec<u8> {
+        let mut rng = Xoshiro256::from_seed(seed.as_bytes());
+        (0..len).map(|_| rng.next_int(0, 255) as u8).collect()
+    }
+
+    #[test]
+    fn bytewords() {
+        // From the test vectors of BCR-2020-012.
+        let data = [0, 1, 2, 128, 255];
+        assert_eq!(bytewords_encode(&data), "aeadaolazmjendeoti");
+        assert_eq!(bytewords_decode("aeadaolazmjendeoti").unwrap(), data);
+        assert_eq!(
+            bytewords_decode("aeadaolazmjendeoxx"),
+            Err(UrError::InvalidBytewords)
+        );
+        assert_eq!(
+            bytewords_decode("aeadaolazmjendeoto"),
+            Err(UrError::ChecksumMismatch)
+        );
+        assert_eq!(bytewords_decode("aea"), Err(UrError::InvalidBytewords));
+    }
+
+    #[test]
+    fn fountain_prng() {
+        // From the test vectors of the reference implementation.
+        let mut rng = Xoshiro256::from_seed(b"Wolf");
+        let values: Vec<u64> = (0..10).map(|_| rng.next() % 100).collect();
+        assert_eq!(values, vec![42, 81, 85, 8, 82, 84, 76, 73, 70, 88]);
+        let degrees: Vec<usize> = (1..=12)
+            .map(|i| {
+                choose_degree(
+                    11,
+                    &mut Xoshiro256::from_seed(format!("Wolf-{}", i).as_bytes()),
+                )
+            })
+            .collect();
+        assert_eq!(degrees, vec![11, 3, 6, 5, 2, 1, 2, 11, 1, 3, 9, 10]);
+
+        let message = make_message(1024, "Wolf");
+        let checksum = crc32(&message);
+        let fragments: Vec<Vec<usize>> = (12..=20)
+            .map(|seq_num| {
+                choose_fragments(seq_num, 11, checksum)
+                    .into_iter()
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            fragments,
+            vec![
+                vec![9],
+                vec![2, 5, 6, 8, 9, 10],
+                vec![8],
+                vec![1, 5],
+                vec![1],
+                vec![0, 2, 4, 5, 8, 10],
+                vec![5],
+                vec![2],
+                vec![2]
+            ]
+        );
+    }
+
+    #[test]
+    fn multipart_ur() {
+        // From the test vectors of the reference implementation.
+        let message = cbor_encode_bytes(&make_message(256, "Wolf"));
+        let parts = encode("bytes", &message, 30);
+        assert_eq!(parts.len(), 9);
+        assert_eq!(parts[0], "ur:bytes/1-9/lpadascfadaxcywenbpljkhdcahkadaemejtswhhylkepmykhhtsytsnoyoyaxaedsuttydmmhhpktpmsrjtdkgslpgh");
+        assert_eq!(parts[1], "ur:bytes/2-9/lpaoascfadaxcywenbpljkhdcagwdpfnsboxgwlbaawzuefywkdplrsrjynbvygabwjldapfcsgmghhkhstlrdcxaefz");
+        assert_eq!(parts[2], "ur:bytes/3-9/lpaxascfadaxcywenbpljkhdcahelbknlkuejnbadmssfhfrdpsbiegecpasvssovlgeykssjykklronvsjksopdzmol");
+
+        // The parts can be received in any order, and more than once.
+        let mut decoder = UrDecoder::new();
+        for part in parts.iter().rev().chain(parts.iter()).skip(1) {
+            assert!(decoder.message().is_none());
+            decoder.receive(part).unwrap();
+        }
+        assert_eq!(decoder.progress(), (9, 9));
+        assert_eq!(decoder.ur_type(), Some("bytes"));
+        assert_eq!(decoder.message(), Some(&message[..]));
+        assert_eq!(
+            cbor_decode_bytes(&message).unwrap(),
+            make_message(256, "Wolf")
+        );
+
+        // A mixed part (here the XOR of the first and last fragments) can be used in place of
+        // one of them.
+        assert_eq!(
+            choose_fragments(19, 9, crc32(&message)),
+            [0, 8].iter().cloned().collect()
+        );
+        let mixed = encode_part("bytes", &message, 29, 19);
+        let mut decoder = UrDecoder::new();
+        decoder.receive(&mixed).unwrap();
+        for part in &parts[..8] {
+            decoder.receive(part).unwrap();
+        }
+        assert_eq!(decoder.message(), Some(&message[..]));
+        let mut decoder = UrDecoder::new();
+        for part in parts[1..].iter().chain(iter::once(&mixed)) {
+            decoder.receive(part).unwrap();
+        }
+        assert_eq!(decoder.message(), Some(&message[..]));
+
+        // Parts from another UR, or with another type, are refused.
+        let other_parts = encode("bytes", &cbor_encode_bytes(&make_message(300, "Wolf")), 30);
+        let mut decoder = UrDecoder::new();
+        decoder.receive(&parts[0].to_uppercase()).unwrap();
+        assert_eq!(decoder.progress(), (1, 9));
+        assert_eq!(
+            decoder.receive(&other_parts[1]),
+            Err(UrError::InconsistentPart)
+        );
+        assert!(matches!(
+            decoder.receive(&parts[1].replace("bytes", "crypto-psbt")),
+            Err(UrError::InvalidType(_))
+        ));
+        assert_eq!(
+            decoder.receive("bytes/1-9/lpad"),
+            Err(UrError::InvalidScheme)
+        );
+
+        // The sequence length must be the one of the message for this fragment length, and be
+        // the same as in the path. Overly large messages are refused.
+        let craft_part = |path_seq_len: u64, seq_len: u64, message_len: u64, frag_len: usize| {
+            let mut part = Vec::new();
+            cbor_head(4, 5, &mut part);
+            cbor_head(0, 1, &mut part);
+            cbor_head(0, seq_len, &mut part);
+            cbor_head(0, message_len, &mut part);
+            cbor_head(0, u64::from(crc32(&message)), &mut part);
+            cbor_head(2, frag_len as u64, &mut part);
+            part.extend_from_slice(&message[..frag_len]);
+            format!("ur:bytes/1-{}/{}", path_seq_len, bytewords_encode(&part))
+        };
+        assert_eq!(craft_part(9, 9, 259, 29), parts[0]);
+        for (path_seq_len, seq_len, message_len, frag_len) in [
+            (1_000_000, 1_000_000, 259, 29),
+            (9, 9, 259, 28),
+            (10, 9, 259, 29),
+            (10_001, 10_001, 10_001, 1),
+            (1, 1, (MAX_MESSAGE_LEN + 1) as u64, 29),
+        ] {
+            assert_eq!(
+                UrDecoder::new().receive(&craft_part(path_seq_len, seq_len, message_len, frag_len)),
+                Err(UrError::InconsistentPart)
+            );
+        }
+    }
+
+    #[test]
+    fn single_part_ur() {
+        let message = cbor_encode_bytes(b"Liana");
+        let parts = encode(PSBT_UR_TYPE, &message, 100);
+        assert_eq!(parts.len(), 1);
+        assert!(parts[0].starts_with("ur:crypto-psbt/"));
+        let mut decoder = UrDecoder::new();
+        decoder.receive(&parts[0]).unwrap();
+        assert_eq!(decoder.progress(), (1, 1));
+        assert_eq!(
+            cbor_decode_bytes(decoder.message().unwrap()).unwrap(),
+            b"Liana"
+        );
+    }
+}
@@ -1,16 +1,17 @@
 pub use liana::signer::SignerError;
 
 use liana::{
+    descriptors::LianaDescriptor,
     miniscript::bitcoin::{
         bip32::{DerivationPath, ExtendedPubKey, Fingerprint},
         psbt::Psbt,
         secp256k1, Network,
     },
-    signer::HotSigner,
+    signer::{EncryptedMnemonic, FeeBounds, HotSigner},
 };
 
 pub struct Signer {
-    curve: secp256k1::Secp256k1<secp256k1::SignOnly>,
+    curve: secp256k1::Secp256k1<secp256k1::All>,
     key: HotSigner,
     pub fingerprint: Fingerprint,
 }
@@ -23,7 +24,7 @@ impl std::fmt::Debug for Signer {
 
 impl Signer {
     pub fn new(key: HotSigner) -> Self {
-        let curve = secp256k1::Secp256k1::signing_only();
+        let curve = secp256k1::Secp256k1::new();
         let fingerprint = key.fingerprint(&curve);
         Self {
             key,
@@ -36,7 +37,7 @@ impl Signer {
         self.key.set_network(network)
     }
 
-    pub fn mnemonic(&self) -> [&'static str; 12] {
+    pub fn mnemonic(&self) -> Vec<&'static str> {
         self.key.words()
     }
 
@@ -56,11 +57,53 @@ impl Signer {
         self.key.sign_psbt(psbt, &self.curve)
     }
 
+    /// Sign the PSBT only if it is safe to sign for a participant in this descriptor.
+    pub fn sign_psbt_checked(
+        &self,
+        psbt: Psbt,
+        descriptor: &LianaDescriptor,
+    ) -> Result<Psbt, SignerError> {
+        self.key
+            .sign_psbt_checked(psbt, descriptor, &FeeBounds::default(), &self.curve)
+    }
+
+    /// Store the mnemonic, encrypted if a passphrase is given.
     pub fn store(
         &self,
         datadir_root: &std::path::Path,
         network: Network,
+        passphrase: Option<&str>,
     ) -> Result<(), SignerError> {
-        self.key.store(datadir_root, network, &self.curve)
+        if let Some(passphrase) = passphrase {
+            self.key
+                .store_encrypted(datadir_root, network, passphrase, &self.curve)
+        } else {
+            self.key.store(datadir_root, network, &self.curve)
+        }
+    }
+}
+
+/// A hot signer whose mnemonic is stored encrypted. It must be decrypted with its passphrase
+/// before signing.
+#[derive(Debug, Clone)]
+pub struct EncryptedSigner {
+    mnemonic: EncryptedMnemonic,
+    network: Network,
+}
+
+impl EncryptedSigner {
+    pub fn new(mnemonic: EncryptedMnemonic, network: Network) -> Self {
+        Self { mnemonic, network }
+    }
+
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.mnemonic.fingerprint()
+    }
+
+    /// The key material of the returned signer is erased once it is dropped.
+    pub fn decrypt(&self, passphrase: &str) -> Result<Signer, SignerError> {
+        let curve = secp256k1::Secp256k1::signing_only();
+        let key = self.mnemonic.decrypt(self.network, passphrase, &curve)?;
+        Ok(Signer::new(key))
     }
 }
@@ -79,6 +79,12 @@ where
         self.input = self.input.size(size);
         self
     }
+
+    /// Hides the value of the [`Form`], for passwords.
+    pub fn password(mut self) -> Self {
+        self.input = self.input.password();
+        self
+    }
 }
 
 impl<'a, Message: 'a + Clone> From<Form<'a, Message>> for Element<'a, Message> {
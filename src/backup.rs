@@ -0,0 +1,254 @@
+//! Backup module
+//!
+//! A versioned backup document for a Liana wallet. Backing up the descriptor alone is enough to
+//! recover the funds, but loses the metadata which makes the wallet usable: the keys aliases, the
+//! hardware wallets registrations, the labels and the date from which the chain must be scanned.
+
+use crate::descriptors::LianaDescriptor;
+
+use std::{collections::BTreeMap, error, fmt, str};
+
+use miniscript::bitcoin::{self, bip32};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// The version of the backup format produced by this version of Liana.
+pub const BACKUP_VERSION: u32 = 1;
+
+/// An error related to reading a wallet backup.
+#[derive(Debug)]
+pub enum BackupError {
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    /// The checksum of the backup does not match the one of the descriptor it contains.
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    /// The keys of the descriptor are not for the network of the backup.
+    NetworkMismatch(bitcoin::Network),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "Error parsing the backup: '{}'.", e),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported backup version '{}'. Latest supported version is '{}'.",
+                v, BACKUP_VERSION
+            ),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "Backup checksum '{}' does not match the descriptor checksum '{}'.",
+                expected, actual
+            ),
+            Self::NetworkMismatch(net) => write!(
+                f,
+                "The descriptor in the backup is not valid for network '{}'.",
+                net
+            ),
+        }
+    }
+}
+
+impl error::Error for BackupError {}
+
+/// An alias given to the key of a participant in the wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyAlias {
+    pub master_fingerprint: bip32::Fingerprint,
+    pub name: String,
+}
+
+/// The registration of the wallet descriptor on a hardware signing device.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareWalletRegistration {
+    /// The kind of device, for instance "ledger".
+    pub kind: String,
+    pub fingerprint: bip32::Fingerprint,
+    /// The hex-encoded token returned by the device upon registration (for instance the HMAC of
+    /// the wallet policy for a Ledger).
+    pub token: String,
+}
+
+/// A backup of a Liana wallet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletBackup {
+    /// The version of the backup format.
+    pub version: u32,
+    /// The name of the wallet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub descriptor: LianaDescriptor,
+    /// The checksum of the descriptor, to check the integrity of the backup.
+    pub checksum: String,
+    pub network: bitcoin::Network,
+    /// Timestamp of the creation of the wallet. When restoring, the chain is rescanned from this
+    /// date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub birthday: Option<u32>,
+    #[serde(default)]
+    pub keys: Vec<KeyAlias>,
+    #[serde(default)]
+    pub hardware_wallets: Vec<HardwareWalletRegistration>,
+    /// The labels of the wallet, by address, txid or outpoint.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+fn deser_fromstr<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: str::FromStr,
+    <T as str::FromStr>::Err: fmt::Display,
+{
+    let string = String::deserialize(deserializer)?;
+    T::from_str(&string).map_err(de::Error::custom)
+}
+
+fn ser_to_string<T: fmt::Display, S: Serializer>(field: T, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&field.to_string())
+}
+
+/// Get the checksum of this descriptor.
+pub fn descriptor_checksum(descriptor: &LianaDescriptor) -> String {
+    descriptor
+        .to_string()
+        .split_once('#')
+        .map(|(_, checksum)| checksum.to_string())
+        .expect("Descriptors are always displayed with a checksum")
+}
+
+impl WalletBackup {
+    /// A backup containing only the descriptor. Use the fields to add the wallet metadata.
+    pub fn new(descriptor: LianaDescriptor, network: bitcoin::Network) -> WalletBackup {
+        WalletBackup {
+            version: BACKUP_VERSION,
+            name: None,
+            checksum: descriptor_checksum(&descriptor),
+            descriptor,
+            network,
+            birthday: None,
+            keys: Vec::new(),
+            hardware_wallets: Vec::new(),
+            labels: BTreeMap::new(),
+        }
+    }
+
+    /// Read a backup from its JSON serialization, checking its integrity.
+    pub fn from_json(s: &str) -> Result<WalletBackup, BackupError> {
+        let backup: WalletBackup = serde_json::from_str(s).map_err(BackupError::Json)?;
+
+        if backup.version > BACKUP_VERSION {
+            return Err(BackupError::UnsupportedVersion(backup.version));
+        }
+
+        let checksum = descriptor_checksum(&backup.descriptor);
+        if backup.checksum != checksum {
+            return Err(BackupError::ChecksumMismatch {
+                expected: backup.checksum,
+                actual: checksum,
+            });
+        }
+
+        // All test networks use the same xpub version.
+        let xpubs_net = if backup.network == bitcoin::Network::Bitcoin {
+            bitcoin::Network::Bitcoin
+        } else {
+            bitcoin::Network::Testnet
+        };
+        if !backup.descriptor.all_xpubs_net_is(xpubs_net) {
+            return Err(BackupError::NetworkMismatch(backup.network));
+        }
+
+        Ok(backup)
+    }
+
+    /// Serialize this backup to JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serialization can't fail")
+    }
+
+    /// Get the keys aliases as a map from master fingerprint to name.
+    pub fn keys_aliases(&self) -> BTreeMap<bip32::Fingerprint, String> {
+        self.keys
+            .iter()
+            .map(|key| (key.master_fingerprint, key.name.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const DESC: &str = "wsh(andor(pk([abcdef01]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([abcdef01]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#2qj59a9y";
+
+    #[test]
+    fn wallet_backup_roundtrip() {
+        let desc = LianaDescriptor::from_str(DESC).unwrap();
+        let mut backup = WalletBackup::new(desc.clone(), bitcoin::Network::Testnet);
+        assert_eq!(backup.checksum, "2qj59a9y");
+        backup.name = Some("Liana".to_string());
+        backup.birthday = Some(1_690_000_000);
+        backup.keys.push(KeyAlias {
+            master_fingerprint: bip32::Fingerprint::from_str("abcdef01").unwrap(),
+            name: "Alice".to_string(),
+        });
+        backup.hardware_wallets.push(HardwareWalletRegistration {
+            kind: "ledger".to_string(),
+            fingerprint: bip32::Fingerprint::from_str("abcdef01").unwrap(),
+            token: "00".repeat(32),
+        });
+        backup.labels.insert(
+            "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7".to_string(),
+            "payment".to_string(),
+        );
+
+        let json = backup.to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["descriptor"], DESC);
+        assert_eq!(value["network"], "testnet");
+        assert_eq!(WalletBackup::from_json(&json).unwrap(), backup);
+        assert_eq!(
+            backup
+                .keys_aliases()
+                .get(&bip32::Fingerprint::from_str("abcdef01").unwrap()),
+            Some(&"Alice".to_string())
+        );
+
+        // A minimal backup only contains the descriptor.
+        let minimal = format!(
+            "{{\"version\":1,\"descriptor\":\"{}\",\"checksum\":\"2qj59a9y\",\"network\":\"testnet\"}}",
+            DESC
+        );
+        assert_eq!(
+            WalletBackup::from_json(&minimal).unwrap(),
+            WalletBackup::new(desc, bitcoin::Network::Testnet)
+        );
+
+        // Integrity checks.
+        let wrong_checksum =
+            minimal.replace("\"checksum\":\"2qj59a9y\"", "\"checksum\":\"2qj59a9z\"");
+        assert!(matches!(
+            WalletBackup::from_json(&wrong_checksum),
+            Err(BackupError::ChecksumMismatch { .. })
+        ));
+        let wrong_network = minimal.replace("testnet", "bitcoin");
+        assert!(matches!(
+            WalletBackup::from_json(&wrong_network),
+            Err(BackupError::NetworkMismatch(bitcoin::Network::Bitcoin))
+        ));
+        let future_version = minimal.replace("\"version\":1", "\"version\":2");
+        assert!(matches!(
+            WalletBackup::from_json(&future_version),
+            Err(BackupError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            WalletBackup::from_json("wsh(pk(xpub))"),
+            Err(BackupError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+//! BIP322 module
+//!
+//! Generic signed messages (BIP322) for the addresses of a Liana wallet. A signature is a virtual
+//! transaction spending a coin locked by the address, committing to the message. We create it as a
+//! PSBT so it can be signed by any of our signers like a regular Spend transaction.
+
+use crate::descriptors::DerivedSinglePathLianaDesc;
+
+use std::{error, fmt};
+
+use miniscript::{
+    bitcoin::{
+        self, base64,
+        blockdata::{locktime::absolute, opcodes},
+        consensus,
+        hashes::{sha256, Hash, HashEngine},
+        psbt::Psbt,
+        secp256k1, sighash,
+    },
+    psbt::PsbtExt,
+    Interpreter,
+};
+
+/// The tag of the hash of the message committed to in the virtual transaction.
+const MESSAGE_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// An error related to a BIP322 signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bip322Error {
+    /// The signature is neither a "full" nor a "simple" BIP322 signature.
+    InvalidEncoding,
+    /// The signature does not commit to this message for this address.
+    MessageMismatch,
+    /// The signature does not satisfy the Script of the address.
+    InvalidSignature(String),
+    /// The PSBT could not be finalized into a signature.
+    Finalization(String),
+}
+
+impl fmt::Display for Bip322Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidEncoding => write!(f, "Invalid BIP322 signature encoding."),
+            Self::MessageMismatch => {
+                write!(f, "The signature is not for this message and this address.")
+            }
+            Self::InvalidSignature(e) => write!(f, "Invalid signature: '{}'.", e),
+            Self::Finalization(e) => write!(f, "Failed to finalize the message PSBT: '{}'.", e),
+        }
+    }
+}
+
+impl error::Error for Bip322Error {}
+
+/// The tagged hash of the message, as committed to in the `to_spend` transaction.
+pub fn message_hash(message: &[u8]) -> sha256::Hash {
+    let tag_hash = sha256::Hash::hash(MESSAGE_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_byte_array());
+    engine.input(tag_hash.as_byte_array());
+    engine.input(message);
+    sha256::Hash::from_engine(engine)
+}
+
+/// The virtual transaction creating a coin locked by this Script, committing to the message.
+pub fn to_spend(script_pubkey: &bitcoin::Script, message: &[u8]) -> bitcoin::Transaction {
+    let script_sig = bitcoin::ScriptBuf::builder()
+        .push_opcode(opcodes::OP_0)
+        .push_slice(message_hash(message).to_byte_array())
+        .into_script();
+    bitcoin::Transaction {
+        version: 0,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![bitcoin::TxIn {
+            previous_output: bitcoin::OutPoint {
+                txid: bitcoin::Txid::all_zeros(),
+                vout: 0xFF_FF_FF_FF,
+            },
+            script_sig,
+            sequence: bitcoin::Sequence::ZERO,
+            witness: bitcoin::Witness::new(),
+        }],
+        output: vec![bitcoin::TxOut {
+            value: 0,
+            script_pubkey: script_pubkey.to_owned(),
+        }],
+    }
+}
+
+/// The (unsigned) virtual transaction spending the coin created by the `to_spend` transaction.
+pub fn to_sign(to_spend: &bitcoin::Transaction) -> bitcoin::Transaction {
+    bitcoin::Transaction {
+        version: 0,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![bitcoin::TxIn {
+            previous_output: bitcoin::OutPoint {
+                txid: to_spend.txid(),
+                vout: 0,
+            },
+            script_sig: bitcoin::ScriptBuf::new(),
+            sequence: bitcoin::Sequence::ZERO,
+            witness: bitcoin::Witness::new(),
+        }],
+        output: vec![bitcoin::TxOut {
+            value: 0,
+            script_pubkey: bitcoin::ScriptBuf::builder()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+        }],
+    }
+}
+
+/// Create the PSBT to be signed to prove control of the address of this derived descriptor. The
+/// signature is for the primary path, as the virtual transaction doesn't set any timelock.
+pub fn message_psbt(derived_desc: &DerivedSinglePathLianaDesc, message: &str) -> Psbt {
+    let to_spend = to_spend(&derived_desc.script_pubkey(), message.as_bytes());
+    let mut psbt =
+        Psbt::from_unsigned_tx(to_sign(&to_spend)).expect("The transaction is unsigned.");
+    psbt.inputs[0].witness_utxo = Some(to_spend.output[0].clone());
+    psbt.inputs[0].non_witness_utxo = Some(to_spend);
+    derived_desc.update_psbt_in(&mut psbt.inputs[0]);
+    psbt
+}
+
+/// Finalize a signed message PSBT into a BIP322 "full" signature, encoded as base64.
+pub fn signature_from_psbt<C: secp256k1::Verification>(
+    mut psbt: Psbt,
+    secp: &secp256k1::Secp256k1<C>,
+) -> Result<String, Bip322Error> {
+    psbt.finalize_mut(secp).map_err(|e| {
+        Bip322Error::Finalization(
+            e.into_iter()
+                .next()
+                .map(|e| e.to_string())
+                .unwrap_or_default(),
+        )
+    })?;
+    Ok(base64::encode(consensus::serialize(&psbt.extract_tx())))
+}
+
+/// Check this base64-encoded BIP322 signature, in the "full" or "simple" format, proves control
+/// of this address for this message.
+pub fn verify<C: secp256k1::Verification>(
+    address: &bitcoin::Address,
+    message: &str,
+    signature: &str,
+    secp: &secp256k1::Secp256k1<C>,
+) -> Result<(), Bip322Error> {
+    let sig_bytes = base64::decode(signature).map_err(|_| Bip322Error::InvalidEncoding)?;
+    let to_spend = to_spend(&address.script_pubkey(), message.as_bytes());
+    let expected_to_sign = to_sign(&to_spend);
+
+    // A "full" signature is the whole signed transaction, a "simple" one only its witness.
+    let to_sign = match consensus::deserialize::<bitcoin::Transaction>(&sig_bytes) {
+        Ok(tx) => tx,
+        Err(_) => {
+            let witness: bitcoin::Witness =
+                consensus::deserialize(&sig_bytes).map_err(|_| Bip322Error::InvalidEncoding)?;
+            let mut tx = expected_to_sign.clone();
+            tx.input[0].witness = witness;
+            tx
+        }
+    };
+    if to_sign.input.len() != 1
+        || to_sign.input[0].previous_output != expected_to_sign.input[0].previous_output
+        || to_sign.output != expected_to_sign.output
+    {
+        return Err(Bip322Error::MessageMismatch);
+    }
+
+    // Finally, run the Script of the address against the witness of the signature.
+    let txin = &to_sign.input[0];
+    let interpreter = Interpreter::from_txdata(
+        &to_spend.output[0].script_pubkey,
+        &txin.script_sig,
+        &txin.witness,
+        txin.sequence,
+        to_sign.lock_time,
+    )
+    .map_err(|e| Bip322Error::InvalidSignature(e.to_string()))?;
+    let prevouts = sighash::Prevouts::All(&to_spend.output);
+    for constraint in interpreter.iter(secp, &to_sign, 0, &prevouts) {
+        constraint.map_err(|e| Bip322Error::InvalidSignature(e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{descriptors::LianaDescriptor, signer::HotSigner};
+    use bitcoin::bip32;
+
+    use std::str::FromStr;
+
+    #[test]
+    fn bip322_test_vectors() {
+        // From the test vectors in BIP322.
+        assert_eq!(
+            message_hash(b"").to_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            message_hash(b"Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+
+        let addr = bitcoin::Address::from_str("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l")
+            .unwrap()
+            .assume_checked();
+        let to_spend_empty = to_spend(&addr.script_pubkey(), b"");
+        assert_eq!(
+            to_spend_empty.txid().to_string(),
+            "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7"
+        );
+        assert_eq!(
+            to_sign(&to_spend_empty).txid().to_string(),
+            "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6"
+        );
+        let to_spend_hello = to_spend(&addr.script_pubkey(), b"Hello World");
+        assert_eq!(
+            to_spend_hello.txid().to_string(),
+            "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b"
+        );
+        assert_eq!(
+            to_sign(&to_spend_hello).txid().to_string(),
+            "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf"
+        );
+
+        // A "simple" signature for a P2WPKH address.
+        let secp = secp256k1::Secp256k1::verification_only();
+        let sig = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        verify(&addr, "Hello World", sig, &secp).unwrap();
+        assert!(matches!(
+            verify(&addr, "Hello World!", sig, &secp),
+            Err(Bip322Error::InvalidSignature(_))
+        ));
+        assert_eq!(
+            verify(&addr, "Hello World", "not base64", &secp),
+            Err(Bip322Error::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn bip322_liana_address() {
+        let secp = secp256k1::Secp256k1::new();
+        let network = bitcoin::Network::Bitcoin;
+        let signer = HotSigner::generate(network).unwrap();
+        let fingerprint = signer.fingerprint(&secp);
+        let xpub = signer.xpub_at(&bip32::DerivationPath::master(), &secp);
+        let desc = LianaDescriptor::from_str(&format!("wsh(or_d(pk([{}]{}/<0;1>/*),and_v(v:pkh([aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000))))", fingerprint, xpub)).unwrap();
+        let derived_desc = desc
+            .receive_descriptor()
+            .derive(bip32::ChildNumber::from(42), &secp);
+        let addr = derived_desc.address(network);
+
+        // The unsigned PSBT can't be finalized.
+        let psbt = message_psbt(&derived_desc, "Liana");
+        assert!(matches!(
+            signature_from_psbt(psbt.clone(), &secp),
+            Err(Bip322Error::Finalization(_))
+        ));
+
+        // Once signed by the primary path key it's a valid signature for this message only.
+        let psbt = signer.sign_psbt(psbt, &secp).unwrap();
+        let sig = signature_from_psbt(psbt, &secp).unwrap();
+        verify(&addr, "Liana", &sig, &secp).unwrap();
+        assert!(verify(&addr, "Liana!", &sig, &secp).is_err());
+        let other_addr = desc
+            .receive_descriptor()
+            .derive(bip32::ChildNumber::from(43), &secp)
+            .address(network);
+        assert!(verify(&other_addr, "Liana", &sig, &secp).is_err());
+
+        // An unsigned virtual transaction isn't a valid signature.
+        let unsigned_sig = base64::encode(consensus::serialize(&to_sign(&to_spend(
+            &addr.script_pubkey(),
+            b"Liana",
+        ))));
+        assert!(verify(&addr, "Liana", &unsigned_sig, &secp).is_err());
+    }
+}
@@ -44,6 +44,8 @@ const BITCOIND_RETRY_LIMIT: usize = 60;
 
 // The minimum bitcoind version that can be used with lianad.
 const MIN_BITCOIND_VERSION: u64 = 240000;
+// Miniscript in Taproot descriptors is only supported since bitcoind v26.
+const MIN_TAPROOT_BITCOIND_VERSION: u64 = 260000;
 
 /// An error in the bitcoind interface.
 #[derive(Debug)]
@@ -56,6 +58,7 @@ pub enum BitcoindError {
     /// Error while managing wallet.
     Wallet(String /* watchonly wallet path */, WalletError),
     InvalidVersion(u64),
+    TaprootNotSupported(u64),
     NetworkMismatch(String /*config*/, String /*bitcoind*/),
     StartRescan,
 }
@@ -132,6 +135,13 @@ impl std::fmt::Display for BitcoindError {
                     v, MIN_BITCOIND_VERSION
                 )
             }
+            BitcoindError::TaprootNotSupported(v) => {
+                write!(
+                    f,
+                    "Bitcoind version '{}' does not support Taproot descriptors, minimum supported is '{}'.",
+                    v, MIN_TAPROOT_BITCOIND_VERSION
+                )
+            }
             BitcoindError::NetworkMismatch(conf_net, bitcoind_net) => {
                 write!(
                     f,
@@ -541,8 +551,8 @@ impl BitcoinD {
         Ok(())
     }
 
-    // Import the receive and change descriptors from the multipath descriptor to bitcoind.
-    fn import_descriptor(&self, desc: &LianaDescriptor) -> Option<String> {
+    /// Import the receive and change descriptors from the multipath descriptor to bitcoind.
+    pub fn import_descriptor(&self, desc: &LianaDescriptor) -> Option<String> {
         let descriptors = [desc.receive_descriptor(), desc.change_descriptor()]
             .iter()
             .map(|desc| {
@@ -679,12 +689,16 @@ impl BitcoinD {
     pub fn node_sanity_checks(
         &self,
         config_network: bitcoin::Network,
+        is_taproot: bool,
     ) -> Result<(), BitcoindError> {
         // Check the minimum supported bitcoind version
         let version = self.get_bitcoind_version();
         if version < MIN_BITCOIND_VERSION {
             return Err(BitcoindError::InvalidVersion(version));
         }
+        if is_taproot && version < MIN_TAPROOT_BITCOIND_VERSION {
+            return Err(BitcoindError::TaprootNotSupported(version));
+        }
 
         // Check bitcoind is running on the right network
         let bitcoind_net = self.get_network_bip70();
@@ -838,6 +852,36 @@ impl BitcoinD {
         .is_none()
     }
 
+    /// Get the output at this outpoint if it is unspent and confirmed in the best chain.
+    pub fn get_txout(&self, op: &bitcoin::OutPoint) -> Option<bitcoin::TxOut> {
+        // The result of gettxout is empty if the outpoint is spent or doesn't exist. Don't
+        // include the mempool: we want confirmed coins.
+        let res = self.make_node_request(
+            "gettxout",
+            &params!(
+                Json::String(op.txid.to_string()),
+                Json::Number(op.vout.into()),
+                Json::Bool(false)
+            ),
+        );
+        res.get("bestblock")?;
+        let value = res
+            .get("value")
+            .and_then(Json::as_f64)
+            .and_then(|a| bitcoin::Amount::from_btc(a).ok())
+            .expect("bitcoind won't give us a bad amount");
+        let script_pubkey = res
+            .get("scriptPubKey")
+            .and_then(|spk| spk.get("hex"))
+            .and_then(Json::as_str)
+            .and_then(|hex| bitcoin::ScriptBuf::from_hex(hex).ok())
+            .expect("bitcoind won't give us a bad scriptPubKey");
+        Some(bitcoin::TxOut {
+            value: value.to_sat(),
+            script_pubkey,
+        })
+    }
+
     /// So, bitcoind has no API for getting the transaction spending a wallet UTXO. Instead we are
     /// therefore using a rather convoluted way to get it the other way around, since the spending
     /// transaction is actually *part of the wallet transactions*.
@@ -1128,6 +1172,30 @@ impl BitcoinD {
         }
     }
 
+    /// Get the txid of the mempool transactions spending any of these outpoints.
+    pub fn mempool_spenders(&self, outpoints: &[bitcoin::OutPoint]) -> Vec<bitcoin::Txid> {
+        let prevouts: Vec<Json> = outpoints
+            .iter()
+            .map(|op| {
+                serde_json::json!({
+                    "txid": op.txid.to_string(),
+                    "vout": op.vout,
+                })
+            })
+            .collect();
+        self.make_node_request("gettxspendingprevout", &params!(Json::Array(prevouts)))
+            .as_array()
+            .expect("Always returns an array")
+            .iter()
+            .filter_map(|entry| {
+                entry
+                    .get("spendingtxid")
+                    .and_then(Json::as_str)
+                    .map(|txid| bitcoin::Txid::from_str(txid).expect("Must be a valid txid"))
+            })
+            .collect()
+    }
+
     /// Stop bitcoind.
     pub fn stop(&self) {
         self.make_node_request("stop", &[]);
@@ -38,6 +38,10 @@ impl fmt::Display for BlockChainTip {
     }
 }
 
+/// An unconfirmed coin whose creating transaction disappeared, along with the txid of the
+/// transaction that conflicted with it, if known.
+pub type ExpiredOutpoint = (bitcoin::OutPoint, Option<bitcoin::Txid>);
+
 /// Our Bitcoin backend.
 pub trait BitcoinInterface: Send {
     fn genesis_block(&self) -> BlockChainTip;
@@ -53,6 +57,10 @@ pub trait BitcoinInterface: Send {
     /// Get the timestamp set in the best block's header.
     fn tip_time(&self) -> u32;
 
+    /// Get the median time past (BIP113) of the block at this height in the best chain. Returns
+    /// `None` if there is no block at this height.
+    fn median_time_past(&self, height: i32) -> Option<u32>;
+
     /// Check whether this former tip is part of the current best chain.
     fn is_in_chain(&self, tip: &BlockChainTip) -> bool;
 
@@ -64,11 +72,12 @@ pub trait BitcoinInterface: Send {
     ) -> Vec<UTxO>;
 
     /// Get all coins that were confirmed, and at what height and time. Along with "expired"
-    /// unconfirmed coins (for instance whose creating transaction may have been replaced).
+    /// unconfirmed coins (for instance whose creating transaction may have been replaced), and
+    /// the txid of the transaction which conflicted with their creating transaction if known.
     fn confirmed_coins(
         &self,
         outpoints: &[bitcoin::OutPoint],
-    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<bitcoin::OutPoint>);
+    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<ExpiredOutpoint>);
 
     /// Get all coins that are being spent, and the spending txid.
     fn spending_coins(
@@ -96,6 +105,9 @@ pub trait BitcoinInterface: Send {
         timestamp: u32,
     ) -> Result<(), String>;
 
+    /// Start tracking the coins received on this descriptor from now on.
+    fn import_descriptor(&self, desc: &descriptors::LianaDescriptor) -> Result<(), String>;
+
     /// Rescan progress percentage. Between 0 and 1.
     fn rescan_progress(&self) -> Option<f64>;
 
@@ -108,6 +120,12 @@ pub trait BitcoinInterface: Send {
         &self,
         txid: &bitcoin::Txid,
     ) -> Option<(bitcoin::Transaction, Option<Block>)>;
+
+    /// Whether this transaction is currently in the mempool of the Bitcoin backend.
+    fn is_in_mempool(&self, txid: &bitcoin::Txid) -> bool;
+
+    /// Get the output at this outpoint, if it is confirmed and unspent in the best chain.
+    fn unspent_txout(&self, outpoint: &bitcoin::OutPoint) -> Option<bitcoin::TxOut>;
 }
 
 impl BitcoinInterface for d::BitcoinD {
@@ -173,7 +191,7 @@ impl BitcoinInterface for d::BitcoinD {
     fn confirmed_coins(
         &self,
         outpoints: &[bitcoin::OutPoint],
-    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<bitcoin::OutPoint>) {
+    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<ExpiredOutpoint>) {
         // The confirmed and expired coins to be returned.
         let mut confirmed = Vec::with_capacity(outpoints.len());
         let mut expired = Vec::new();
@@ -199,9 +217,19 @@ impl BitcoinInterface for d::BitcoinD {
                 continue;
             }
 
-            // If the transaction was dropped from the mempool, discard the coin.
+            // If the transaction was dropped from the mempool, discard the coin. Try to find out
+            // whether it was because of a conflicting transaction.
             if !self.is_in_mempool(&op.txid) {
-                expired.push(*op);
+                let conflicting_txid = res.conflicting_txs.first().copied().or_else(|| {
+                    let prevouts: Vec<bitcoin::OutPoint> = res
+                        .tx
+                        .input
+                        .iter()
+                        .map(|txin| txin.previous_output)
+                        .collect();
+                    self.mempool_spenders(&prevouts).into_iter().next()
+                });
+                expired.push((*op, conflicting_txid));
             }
         }
 
@@ -308,6 +336,13 @@ impl BitcoinInterface for d::BitcoinD {
             .map_err(|e| e.to_string())
     }
 
+    fn import_descriptor(&self, desc: &descriptors::LianaDescriptor) -> Result<(), String> {
+        match self.import_descriptor(desc) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
     fn rescan_progress(&self) -> Option<f64> {
         self.rescan_progress()
     }
@@ -321,12 +356,25 @@ impl BitcoinInterface for d::BitcoinD {
         self.get_block_stats(tip.hash).time
     }
 
+    fn median_time_past(&self, height: i32) -> Option<u32> {
+        let hash = self.get_block_hash(height)?;
+        Some(self.get_block_stats(hash).median_time_past)
+    }
+
     fn wallet_transaction(
         &self,
         txid: &bitcoin::Txid,
     ) -> Option<(bitcoin::Transaction, Option<Block>)> {
         self.get_transaction(txid).map(|res| (res.tx, res.block))
     }
+
+    fn is_in_mempool(&self, txid: &bitcoin::Txid) -> bool {
+        self.is_in_mempool(txid)
+    }
+
+    fn unspent_txout(&self, outpoint: &bitcoin::OutPoint) -> Option<bitcoin::TxOut> {
+        self.get_txout(outpoint)
+    }
 }
 
 // FIXME: do we need to repeat the entire trait implemenation? Isn't there a nicer way?
@@ -358,7 +406,7 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
     fn confirmed_coins(
         &self,
         outpoints: &[bitcoin::OutPoint],
-    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<bitcoin::OutPoint>) {
+    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<ExpiredOutpoint>) {
         self.lock().unwrap().confirmed_coins(outpoints)
     }
 
@@ -392,6 +440,10 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
         self.lock().unwrap().start_rescan(desc, timestamp)
     }
 
+    fn import_descriptor(&self, desc: &descriptors::LianaDescriptor) -> Result<(), String> {
+        self.lock().unwrap().import_descriptor(desc)
+    }
+
     fn rescan_progress(&self) -> Option<f64> {
         self.lock().unwrap().rescan_progress()
     }
@@ -404,12 +456,24 @@ impl BitcoinInterface for sync::Arc<sync::Mutex<dyn BitcoinInterface + 'static>>
         self.lock().unwrap().tip_time()
     }
 
+    fn median_time_past(&self, height: i32) -> Option<u32> {
+        self.lock().unwrap().median_time_past(height)
+    }
+
     fn wallet_transaction(
         &self,
         txid: &bitcoin::Txid,
     ) -> Option<(bitcoin::Transaction, Option<Block>)> {
         self.lock().unwrap().wallet_transaction(txid)
     }
+
+    fn is_in_mempool(&self, txid: &bitcoin::Txid) -> bool {
+        self.lock().unwrap().is_in_mempool(txid)
+    }
+
+    fn unspent_txout(&self, outpoint: &bitcoin::OutPoint) -> Option<bitcoin::TxOut> {
+        self.lock().unwrap().unspent_txout(outpoint)
+    }
 }
 
 // FIXME: We could avoid this type (and all the conversions entailing allocations) if bitcoind
@@ -1,10 +1,12 @@
 use crate::{
-    bitcoin::{BitcoinInterface, BlockChainTip, UTxO},
-    database::{Coin, DatabaseConnection, DatabaseInterface},
+    bitcoin::{BitcoinInterface, BlockChainTip, ExpiredOutpoint, UTxO},
+    database::{Coin, DatabaseConnection, DatabaseInterface, SpendTxStatus},
     descriptors,
 };
 
 use std::{
+    collections::HashSet,
+    convert::TryInto,
     sync::{self, atomic},
     thread, time,
 };
@@ -15,9 +17,10 @@ use miniscript::bitcoin::{self, secp256k1};
 struct UpdatedCoins {
     pub received: Vec<Coin>,
     pub confirmed: Vec<(bitcoin::OutPoint, i32, u32)>,
-    pub expired: Vec<bitcoin::OutPoint>,
+    pub expired: Vec<ExpiredOutpoint>,
     pub spending: Vec<(bitcoin::OutPoint, bitcoin::Txid)>,
     pub spent: Vec<(bitcoin::OutPoint, bitcoin::Txid, i32, u32)>,
+    pub txs: Vec<bitcoin::Transaction>,
 }
 
 // Update the state of our coins. There may be new unspent, and existing ones may become confirmed
@@ -76,6 +79,7 @@ fn update_coins(
                     block_info: None,
                     spend_txid: None,
                     spend_block: None,
+                    previous_descriptor_id: None,
                 };
                 received.push(coin);
             }
@@ -136,22 +140,108 @@ fn update_coins(
         .map(|coin| (coin.outpoint, coin.spend_txid.expect("Coin is spending")))
         .chain(spending.iter().cloned())
         .collect();
-    let spent = bit
+    let spent: Vec<(bitcoin::OutPoint, bitcoin::Txid, i32, u32)> = bit
         .spent_coins(spending_coins.as_slice())
         .into_iter()
         .map(|(oupoint, txid, block)| (oupoint, txid, block.height, block.time))
         .collect();
     log::debug!("Newly spent coins: {:?}", spent);
 
+    // Fetch the transactions that created or spent our coins, if we don't have them already. On new
+    // coin events, also retry those we couldn't fetch at a previous poll. We don't retry at every
+    // poll as the backend may never return some of them.
+    let has_coin_events =
+        !received.is_empty() || !confirmed.is_empty() || !spending.is_empty() || !spent.is_empty();
+    let missing_txids = if has_coin_events {
+        db_conn.missing_txids()
+    } else {
+        Vec::new()
+    };
+    let txids: Vec<bitcoin::Txid> = received
+        .iter()
+        .map(|coin| coin.outpoint.txid)
+        .chain(spending.iter().map(|(_, txid)| *txid))
+        .chain(spent.iter().map(|(_, txid, _, _)| *txid))
+        .chain(missing_txids)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let known_txs = db_conn.list_wallet_transactions(&txids);
+    let txs = txids
+        .iter()
+        .filter(|txid| !known_txs.contains_key(*txid))
+        .filter_map(|txid| match bit.wallet_transaction(txid) {
+            Some((tx, _)) => Some(tx),
+            None => {
+                log::error!("Could not get wallet transaction '{}'", txid);
+                None
+            }
+        })
+        .collect();
+
     UpdatedCoins {
         received,
         confirmed,
         expired,
         spending,
         spent,
+        txs,
     }
 }
 
+// Update what happened to the Spend transactions we created. This is mostly inferred from the
+// state of the coins they spend, but we also check whether those that were broadcast and not yet
+// confirmed are still in the mempool.
+fn update_spend_txs(bit: &impl BitcoinInterface, db_conn: &mut Box<dyn DatabaseConnection>) {
+    let now: u32 = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .expect("System clock went backward the epoch?")
+        .as_secs()
+        .try_into()
+        .expect("Is this the year 2106 yet? Misconfigured system clock.");
+
+    let mut updated = Vec::new();
+    for (psbt, _, status) in db_conn.list_spend() {
+        let txid = psbt.unsigned_tx.txid();
+        let outpoints: Vec<bitcoin::OutPoint> = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect();
+        let coins = db_conn.coins_by_outpoints(&outpoints);
+
+        let mut new_status = SpendTxStatus {
+            broadcast_at: status.broadcast_at,
+            ..SpendTxStatus::default()
+        };
+        if let Some(coin) = coins.values().find(|c| c.spend_txid == Some(txid)) {
+            new_status.confirmation = coin.spend_block;
+            new_status.in_mempool = coin.spend_block.is_none() && bit.is_in_mempool(&txid);
+        } else if let Some(conflicting_txid) = coins.values().find_map(|c| c.spend_txid) {
+            new_status.conflicting_txid = Some(conflicting_txid);
+        } else if status.broadcast_at.is_some() {
+            new_status.in_mempool = bit.is_in_mempool(&txid);
+        }
+        if new_status.broadcast_at.is_none()
+            && (new_status.in_mempool || new_status.confirmation.is_some())
+        {
+            new_status.broadcast_at = Some(now);
+        }
+
+        if new_status != status {
+            log::debug!(
+                "New status for Spend transaction '{}': {:?}",
+                txid,
+                new_status
+            );
+            updated.push((txid, new_status));
+        }
+    }
+
+    db_conn.update_spend_status(&updated);
+}
+
 #[derive(Debug, Clone, Copy)]
 enum TipUpdate {
     // The best block is still the same as in the previous poll.
@@ -234,8 +324,9 @@ fn updates(
     // The chain tip did not change since we started our updates. Record them and the latest tip.
     // Having the tip in database means that, as far as the chain is concerned, we've got all
     // updates up to this block. But not more.
+    db_conn.new_txs(&updated_coins.txs);
     db_conn.new_unspent_coins(&updated_coins.received);
-    db_conn.remove_coins(&updated_coins.expired);
+    db_conn.expire_coins(&updated_coins.expired);
     db_conn.confirm_coins(&updated_coins.confirmed);
     db_conn.spend_coins(&updated_coins.spending);
     db_conn.confirm_spend(&updated_coins.spent);
@@ -243,6 +334,7 @@ fn updates(
         db_conn.update_tip(&latest_tip);
         log::debug!("New tip: '{}'", latest_tip);
     }
+    update_spend_txs(bit, &mut db_conn);
 
     log::debug!("Updates done.");
 }
@@ -5,29 +5,38 @@
 mod utils;
 
 use crate::{
+    backup::WalletBackup,
+    bip322,
     bitcoin::BitcoinInterface,
-    database::{Coin, DatabaseInterface},
-    descriptors, DaemonControl, VERSION,
+    config::SignerPolicy,
+    database::{Coin, DatabaseInterface, ExpiredCoin, WalletTransaction},
+    descriptors,
+    external_signer::{ExternalSigner, ExternalSignerError, SignerDescriptors, SignerDevice},
+    reserves,
+    signer::{check_psbt, FeeBounds, HotSigner, SignerError},
+    DaemonControl, VERSION,
 };
 
-pub use crate::database::{CoinStatus, LabelItem};
+pub use crate::database::{CoinStatus, CoinsOrderBy, CoinsQuery, LabelItem};
 
 use utils::{
-    deser_addr_assume_checked, deser_amount_from_sats, deser_fromstr, deser_hex, ser_amount,
-    ser_hex, ser_to_string,
+    deser_addr_assume_checked, deser_amount_from_sats, deser_fromstr, deser_hex,
+    deser_optional_amount_from_sats, deser_signed_amount_from_sats, ser_amount, ser_hex,
+    ser_optional_amount, ser_signed_amount, ser_to_string,
 };
 
 use std::{
-    collections::{hash_map, BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     convert::TryInto,
-    fmt,
+    fmt, io, iter, time,
 };
 
 use miniscript::{
     bitcoin::{
-        self, address,
+        self, address, bip32,
         locktime::absolute,
         psbt::{Input as PsbtIn, Output as PsbtOut, PartiallySignedTransaction as Psbt},
+        secp256k1,
     },
     psbt::PsbtExt,
 };
@@ -43,6 +52,11 @@ const MAX_FEE: u64 = bitcoin::blockdata::constants::COIN_VALUE;
 // Assume that paying more than 1000sat/vb in feerate is a bug.
 const MAX_FEERATE: u64 = 1_000;
 
+// The maximum number of transactions to migrate to a new descriptor. Each of them pays to a
+// different address, which must be within the look-ahead window of the new descriptor for the
+// coins to be detected once we switch to it.
+const MAX_MIGRATION_TXS: usize = 100;
+
 // Timestamp in the header of the genesis block. Used for sanity checks.
 const MAINNET_GENESIS_TIME: u32 = 1231006505;
 
@@ -62,7 +76,7 @@ pub enum CommandError {
     ),
     InsaneFees(InsaneFeeInfo),
     FetchingTransaction(bitcoin::OutPoint),
-    SanityCheckFailure(Psbt),
+    SanityCheckFailure(Box<Psbt>),
     UnknownSpend(bitcoin::Txid),
     // FIXME: when upgrading Miniscript put the actual error there
     SpendFinalization(String),
@@ -72,6 +86,31 @@ pub enum CommandError {
     /// An error that might occur in the racy rescan triggering logic.
     RescanTrigger(String),
     RecoveryNotAvailable,
+    MigratedCoin(bitcoin::OutPoint),
+    MigrationToSameDescriptor,
+    NoCoinToMigrate,
+    InvalidMigrationBatchSize(usize),
+    TooManyMigrationTransactions(usize),
+    DescriptorImport(String),
+    BackupDescriptorMismatch(Box<descriptors::LianaDescriptor>),
+    BackupNetworkMismatch(bitcoin::Network),
+    InvalidBackupLabel(String),
+    /// None of the hot signers in the data directory can sign for the descriptor on its own.
+    NoHotSigner,
+    /// The hot signer with this fingerprint is only stored encrypted, it can't be used to sign.
+    EncryptedHotSigner(bip32::Fingerprint),
+    HotSignerStorage(String),
+    HotSigning(String),
+    SigningPolicyViolation(bip32::Fingerprint, String),
+    UnknownAddress(bitcoin::Address),
+    Bip322(bip322::Bip322Error),
+    NoConfirmedCoin,
+    ProofOfReserves(reserves::ReservesError),
+    /// No external signer command was configured.
+    NoExternalSigner,
+    ExternalSigner(ExternalSignerError),
+    /// The signer with this fingerprint has no key in our descriptor.
+    ForeignSigner(bip32::Fingerprint),
 }
 
 impl fmt::Display for CommandError {
@@ -136,6 +175,64 @@ impl fmt::Display for CommandError {
                 f,
                 "No coin currently spendable through this timelocked recovery path."
            ),
+            Self::MigratedCoin(op) => write!(
+                f,
+                "Coin at '{}' was received on a descriptor we migrated away from.",
+                op
+            ),
+            Self::MigrationToSameDescriptor => {
+                write!(f, "The new descriptor is the same as the current one.")
+            }
+            Self::NoCoinToMigrate => write!(f, "No confirmed coin to migrate."),
+            Self::InvalidMigrationBatchSize(size) => {
+                write!(f, "Invalid number of coins per migration transaction: {}.", size)
+            }
+            Self::TooManyMigrationTransactions(n) => write!(
+                f,
+                "The migration would need {} transactions, the maximum is {}. Use larger batches.",
+                n, MAX_MIGRATION_TXS
+            ),
+            Self::DescriptorImport(e) => {
+                write!(f, "Error while importing the new descriptor: '{}'.", e)
+            }
+            Self::BackupDescriptorMismatch(desc) => write!(
+                f,
+                "The backup is for descriptor '{}', not the descriptor of this wallet.",
+                desc
+            ),
+            Self::BackupNetworkMismatch(net) => {
+                write!(f, "The backup is for network '{}', not the network of this wallet.", net)
+            }
+            Self::InvalidBackupLabel(item) => write!(
+                f,
+                "Invalid labelled item '{}' in backup: must be an address, a txid or an outpoint.",
+                item
+            ),
+            Self::NoHotSigner => write!(
+                f,
+                "No hot signer in the data directory is part of the descriptor and has a spending policy."
+            ),
+            Self::EncryptedHotSigner(fg) => write!(
+                f,
+                "Hot signer '{}' is encrypted. Only unencrypted hot signers can be used to sign.",
+                fg
+            ),
+            Self::HotSignerStorage(e) => write!(f, "Error loading the hot signers: '{}'.", e),
+            Self::HotSigning(e) => write!(f, "Error signing with a hot signer: '{}'.", e),
+            Self::SigningPolicyViolation(fg, e) => write!(
+                f,
+                "Spending policy of hot signer '{}' forbids signing this transaction: {}.",
+                fg, e
+            ),
+            Self::UnknownAddress(addr) => write!(f, "Address '{}' is not ours.", addr),
+            Self::Bip322(e) => write!(f, "BIP322 error: {}", e),
+            Self::NoConfirmedCoin => write!(f, "No confirmed coin to prove ownership of."),
+            Self::ProofOfReserves(e) => write!(f, "Invalid proof of reserves: {}", e),
+            Self::NoExternalSigner => write!(f, "No external signer command configured."),
+            Self::ExternalSigner(e) => write!(f, "External signer: {}", e),
+            Self::ForeignSigner(fg) => {
+                write!(f, "Signer '{}' has no key in the descriptor.", fg)
+            }
         }
     }
 }
@@ -175,20 +272,20 @@ fn sanity_check_psbt(
         || psbt.outputs.len() != tx.output.len()
         || tx.output.is_empty()
     {
-        return Err(CommandError::SanityCheckFailure(psbt.clone()));
+        return Err(CommandError::SanityCheckFailure(Box::new(psbt.clone())));
     }
 
     // Compute the transaction input value, checking all PSBT inputs have the derivation
     // index set for signing devices to recognize them as ours.
     let mut value_in = 0;
     for psbtin in psbt.inputs.iter() {
-        if psbtin.bip32_derivation.is_empty() {
-            return Err(CommandError::SanityCheckFailure(psbt.clone()));
+        if psbtin.bip32_derivation.is_empty() && psbtin.tap_key_origins.is_empty() {
+            return Err(CommandError::SanityCheckFailure(Box::new(psbt.clone())));
         }
         value_in += psbtin
             .witness_utxo
             .as_ref()
-            .ok_or_else(|| CommandError::SanityCheckFailure(psbt.clone()))?
+            .ok_or_else(|| CommandError::SanityCheckFailure(Box::new(psbt.clone())))?
             .value;
     }
 
@@ -215,7 +312,7 @@ fn sanity_check_psbt(
     // Check for dust outputs
     for txo in psbt.unsigned_tx.output.iter() {
         if txo.value < txo.script_pubkey.dust_value().to_sat() {
-            return Err(CommandError::SanityCheckFailure(psbt.clone()));
+            return Err(CommandError::SanityCheckFailure(Box::new(psbt.clone())));
         }
     }
 
@@ -238,6 +335,21 @@ impl DaemonControl {
         desc.derive(coin.derivation_index, &self.secp)
     }
 
+    // Get the descriptor this coin was received on, given the descriptors we migrated away from.
+    fn coin_descriptor<'a>(
+        &'a self,
+        previous_descriptor_id: Option<i64>,
+        previous_descriptors: &'a HashMap<i64, descriptors::LianaDescriptor>,
+    ) -> &'a descriptors::LianaDescriptor {
+        previous_descriptor_id
+            .map(|id| {
+                previous_descriptors
+                    .get(&id)
+                    .expect("Previous descriptors are never removed")
+            })
+            .unwrap_or(&self.config.main_descriptor)
+    }
+
     // Check whether this address is valid for the network we are operating on.
     fn validate_address(
         &self,
@@ -248,6 +360,71 @@ impl DaemonControl {
         addr.require_network(self.config.bitcoin_config.network)
             .map_err(CommandError::Address)
     }
+
+    // The fingerprints of all the keys in our descriptor.
+    fn descriptor_fingerprints(&self) -> HashSet<bip32::Fingerprint> {
+        let desc_policy = self.config.main_descriptor.policy();
+        iter::once(desc_policy.primary_path())
+            .chain(desc_policy.recovery_paths().values())
+            .flat_map(|path_info| path_info.thresh_origins().1.into_keys())
+            .collect()
+    }
+
+    // Fill-in the inputs of a sweep transaction spending all the given coins with the given
+    // nSequence, and set the value of its single output for it to pay the given feerate.
+    fn fill_sweep_psbt<'a>(
+        &self,
+        psbt: &mut Psbt,
+        coins: impl IntoIterator<Item = &'a Coin>,
+        spent_txs: &HashMap<bitcoin::Txid, WalletTransaction>,
+        sequence: bitcoin::Sequence,
+        feerate_vb: u64,
+    ) -> Result<(), CommandError> {
+        // Record the value that is fed to the transaction to compute the fees afterward.
+        let mut in_value = bitcoin::Amount::from_sat(0);
+        let txin_sat_vb = self.config.main_descriptor.max_sat_vbytes();
+        let mut sat_vb = 1; // Start at 1 for the segwit marker size, rounded up.
+        for coin in coins {
+            in_value += coin.amount;
+            psbt.unsigned_tx.input.push(bitcoin::TxIn {
+                previous_output: coin.outpoint,
+                sequence,
+                // TODO: once we move to Taproot, anti-fee-sniping using nSequence
+                ..bitcoin::TxIn::default()
+            });
+
+            // Get the transaction that created this coin
+            let spent_tx = spent_txs
+                .get(&coin.outpoint.txid)
+                .ok_or(CommandError::FetchingTransaction(coin.outpoint))?;
+
+            let coin_desc = self.derived_desc(coin);
+            sat_vb += txin_sat_vb;
+            let mut psbt_in = PsbtIn {
+                witness_utxo: Some(bitcoin::TxOut {
+                    value: coin.amount.to_sat(),
+                    script_pubkey: coin_desc.script_pubkey(),
+                }),
+                non_witness_utxo: Some(spent_tx.transaction.clone()),
+                ..PsbtIn::default()
+            };
+            coin_desc.update_psbt_in(&mut psbt_in);
+            psbt.inputs.push(psbt_in);
+        }
+
+        // Compute the value of the single output based on the requested feerate.
+        let tx_vbytes = (psbt.unsigned_tx.vsize() + sat_vb) as u64;
+        let absolute_fee = tx_vbytes
+            .checked_mul(feerate_vb)
+            .map(bitcoin::Amount::from_sat)
+            .ok_or(CommandError::InvalidFeerate(feerate_vb))?;
+        let output_value = in_value
+            .checked_sub(absolute_fee)
+            .ok_or(CommandError::InsufficientFunds(in_value, None, feerate_vb))?;
+        psbt.unsigned_tx.output[0].value = output_value.to_sat();
+
+        Ok(())
+    }
 }
 
 impl DaemonControl {
@@ -259,6 +436,7 @@ impl DaemonControl {
         let rescan_progress = db_conn
             .rescan_timestamp()
             .map(|_| self.bitcoin.rescan_progress().unwrap_or(1.0));
+        let expired_coins = db_conn.list_expired_coins().len();
         GetInfoResult {
             version: VERSION.to_string(),
             network: self.config.bitcoin_config.network,
@@ -268,6 +446,7 @@ impl DaemonControl {
                 main: self.config.main_descriptor.clone(),
             },
             rescan_progress,
+            expired_coins,
         }
     }
 
@@ -289,16 +468,19 @@ impl DaemonControl {
         GetAddressResult::new(address)
     }
 
-    /// Get a list of all known coins, optionally by status and/or outpoint.
+    /// Get a list of all known coins, optionally by status and/or outpoint. The list may be
+    /// further filtered, ordered and paginated using the given query.
     pub fn list_coins(
         &self,
         statuses: &[CoinStatus],
         outpoints: &[bitcoin::OutPoint],
+        query: &CoinsQuery,
     ) -> ListCoinsResult {
         let mut db_conn = self.db.connection();
+        let previous_descs = db_conn.previous_descriptors();
         let coins: Vec<ListCoinsEntry> = db_conn
-            .coins(statuses, outpoints)
-            .into_values()
+            .query_coins(statuses, outpoints, query)
+            .into_iter()
             .map(|coin| {
                 let Coin {
                     amount,
@@ -307,15 +489,23 @@ impl DaemonControl {
                     spend_txid,
                     spend_block,
                     is_immature,
-                    ..
+                    derivation_index,
+                    is_change,
+                    previous_descriptor_id,
                 } = coin;
                 let spend_info = spend_txid.map(|txid| LCSpendInfo {
                     txid,
                     height: spend_block.map(|b| b.height),
                 });
                 let block_height = block_info.map(|b| b.height);
-                let address = self
-                    .derived_desc(&coin)
+                let coin_desc = self.coin_descriptor(previous_descriptor_id, &previous_descs);
+                let desc = if is_change {
+                    coin_desc.change_descriptor()
+                } else {
+                    coin_desc.receive_descriptor()
+                };
+                let address = desc
+                    .derive(derivation_index, &self.secp)
                     .address(self.config.bitcoin_config.network);
                 ListCoinsEntry {
                     address,
@@ -324,12 +514,81 @@ impl DaemonControl {
                     block_height,
                     spend_info,
                     is_immature,
+                    derivation_index,
+                    is_change,
                 }
             })
             .collect();
         ListCoinsResult { coins }
     }
 
+    /// Get the unconfirmed coins which disappeared, most recently expired first.
+    pub fn list_expired_coins(&self) -> ListExpiredCoinsResult {
+        let mut db_conn = self.db.connection();
+        let previous_descs = db_conn.previous_descriptors();
+        let coins = db_conn
+            .list_expired_coins()
+            .into_iter()
+            .map(|coin| {
+                let ExpiredCoin {
+                    outpoint,
+                    amount,
+                    derivation_index,
+                    is_change,
+                    expired_at,
+                    conflicting_txid,
+                    previous_descriptor_id,
+                } = coin;
+                let coin_desc = self.coin_descriptor(previous_descriptor_id, &previous_descs);
+                let desc = if is_change {
+                    coin_desc.change_descriptor()
+                } else {
+                    coin_desc.receive_descriptor()
+                };
+                let address = desc
+                    .derive(derivation_index, &self.secp)
+                    .address(self.config.bitcoin_config.network);
+                ListExpiredCoinsEntry {
+                    amount,
+                    outpoint,
+                    address,
+                    derivation_index,
+                    is_change,
+                    expired_at,
+                    conflicting_txid,
+                }
+            })
+            .collect();
+        ListExpiredCoinsResult { coins }
+    }
+
+    /// Get a description of the spending policy of our descriptor, and when its recovery paths
+    /// become available for a coin confirmed at the given height (defaults to the current block
+    /// height). Keys are named after the given aliases for their master fingerprint.
+    pub fn get_policy(
+        &self,
+        aliases: &HashMap<bip32::Fingerprint, String>,
+        coin_height: Option<u32>,
+    ) -> GetPolicyResult {
+        let coin_height = coin_height.unwrap_or_else(|| {
+            let mut db_conn = self.db.connection();
+            db_conn
+                .chain_tip()
+                .map(|tip| {
+                    tip.height
+                        .try_into()
+                        .expect("Must fit, it's a block height")
+                })
+                .unwrap_or(0)
+        });
+        let desc = &self.config.main_descriptor;
+        GetPolicyResult {
+            policy: desc.description(aliases),
+            coin_height,
+            timeline: desc.timeline(coin_height),
+        }
+    }
+
     pub fn create_spend(
         &self,
         destinations: &HashMap<bitcoin::Address<bitcoin::address::NetworkUnchecked>, u64>,
@@ -355,8 +614,13 @@ impl DaemonControl {
         let mut sat_vb = 0;
         let mut txins = Vec::with_capacity(coins_outpoints.len());
         let mut psbt_ins = Vec::with_capacity(coins_outpoints.len());
-        let mut spent_txs = HashMap::with_capacity(coins_outpoints.len());
         let coins = db_conn.coins_by_outpoints(coins_outpoints);
+        let spent_txs = db_conn.list_wallet_transactions(
+            &coins_outpoints
+                .iter()
+                .map(|op| op.txid)
+                .collect::<Vec<bitcoin::Txid>>(),
+        );
         for op in coins_outpoints {
             // Get the coin from our in-DB unspent txos
             let coin = coins.get(op).ok_or(CommandError::UnknownOutpoint(*op))?;
@@ -366,16 +630,15 @@ impl DaemonControl {
             if coin.is_immature {
                 return Err(CommandError::ImmatureCoinbase(*op));
             }
-
-            // Fetch the transaction that created it if necessary
-            if !spent_txs.contains_key(op) {
-                let tx = self
-                    .bitcoin
-                    .wallet_transaction(&op.txid)
-                    .ok_or(CommandError::FetchingTransaction(*op))?;
-                spent_txs.insert(*op, tx.0);
+            if coin.previous_descriptor_id.is_some() {
+                return Err(CommandError::MigratedCoin(*op));
             }
 
+            // Get the transaction that created it
+            let spent_tx = spent_txs
+                .get(&op.txid)
+                .ok_or(CommandError::FetchingTransaction(*op))?;
+
             in_value += coin.amount;
             txins.push(bitcoin::TxIn {
                 previous_output: *op,
@@ -387,20 +650,18 @@ impl DaemonControl {
             // Populate the PSBT input with the information needed by signers.
             let coin_desc = self.derived_desc(coin);
             sat_vb += txin_sat_vb;
-            let witness_script = Some(coin_desc.witness_script());
             let witness_utxo = Some(bitcoin::TxOut {
                 value: coin.amount.to_sat(),
                 script_pubkey: coin_desc.script_pubkey(),
             });
-            let non_witness_utxo = spent_txs.get(op).cloned();
-            let bip32_derivation = coin_desc.bip32_derivations();
-            psbt_ins.push(PsbtIn {
-                witness_script,
+            let non_witness_utxo = Some(spent_tx.transaction.clone());
+            let mut psbt_in = PsbtIn {
                 witness_utxo,
-                bip32_derivation,
                 non_witness_utxo,
                 ..PsbtIn::default()
-            });
+            };
+            coin_desc.update_psbt_in(&mut psbt_in);
+            psbt_ins.push(psbt_in);
         }
 
         // Add the destinations outputs to the transaction and PSBT. At the same time record the
@@ -421,21 +682,17 @@ impl DaemonControl {
             });
             // If it's an address of ours, signal it as change to signing devices by adding the
             // BIP32 derivation path to the PSBT output.
-            let bip32_derivation =
-                if let Some((index, is_change)) = db_conn.derivation_index_by_address(&address) {
-                    let desc = if is_change {
-                        self.config.main_descriptor.change_descriptor()
-                    } else {
-                        self.config.main_descriptor.receive_descriptor()
-                    };
-                    desc.derive(index, &self.secp).bip32_derivations()
+            let mut psbt_out = PsbtOut::default();
+            if let Some((index, is_change)) = db_conn.derivation_index_by_address(&address) {
+                let desc = if is_change {
+                    self.config.main_descriptor.change_descriptor()
                 } else {
-                    Default::default()
+                    self.config.main_descriptor.receive_descriptor()
                 };
-            psbt_outs.push(PsbtOut {
-                bip32_derivation,
-                ..PsbtOut::default()
-            });
+                desc.derive(index, &self.secp)
+                    .update_psbt_out(&mut psbt_out);
+            }
+            psbt_outs.push(psbt_out);
         }
         assert_eq!(txouts.is_empty(), is_self_send);
 
@@ -506,10 +763,9 @@ impl DaemonControl {
                     // TODO: shuffle once we have Taproot
                     change_txo.value = change_amount.to_sat();
                     tx.output.push(change_txo);
-                    psbt_outs.push(PsbtOut {
-                        bip32_derivation: change_desc.bip32_derivations(),
-                        ..PsbtOut::default()
-                    });
+                    let mut psbt_out = PsbtOut::default();
+                    change_desc.update_psbt_out(&mut psbt_out);
+                    psbt_outs.push(psbt_out);
                 } else if is_self_send {
                     return Err(CommandError::InsufficientFunds(in_value, None, feerate_vb));
                 }
@@ -585,6 +841,244 @@ impl DaemonControl {
         Ok(())
     }
 
+    /// Sign a stored Spend transaction with the hot signers from the data directory which are part
+    /// of our descriptor and were given a spending policy in the configuration. The transaction
+    /// must be allowed by the policy of each of these signers. The signatures are merged into the
+    /// stored PSBT.
+    pub fn sign_spend(&self, txid: &bitcoin::Txid) -> Result<SignSpendResult, CommandError> {
+        // The amount signed for by each signer is checked against its daily limit, then only
+        // recorded once the signed PSBT is stored. Don't let another signing request in between.
+        let _signing_guard = self.signing_lock.lock().unwrap();
+        let mut db_conn = self.db.connection();
+        let mut psbt = db_conn
+            .spend_tx(txid)
+            .ok_or(CommandError::UnknownSpend(*txid))?;
+        let descriptor = &self.config.main_descriptor;
+        let secp = secp256k1::Secp256k1::new();
+
+        // Load the hot signers which can sign for our descriptor and are allowed to do so.
+        let datadir = self
+            .config
+            .data_dir()
+            .expect("Didn't fail at startup, must not now");
+        let signers = match HotSigner::from_datadir(&datadir, self.config.bitcoin_config.network) {
+            Ok(signers) => signers,
+            Err(SignerError::MnemonicStorage(e)) if e.kind() == io::ErrorKind::NotFound => {
+                Vec::new()
+            }
+            Err(e) => return Err(CommandError::HotSignerStorage(e.to_string())),
+        };
+        let desc_fingerprints = self.descriptor_fingerprints();
+        let signer_policy = |fingerprint: bip32::Fingerprint| {
+            if !desc_fingerprints.contains(&fingerprint) {
+                return None;
+            }
+            self.config
+                .signer_policies
+                .iter()
+                .find(|policy| policy.fingerprint == fingerprint)
+        };
+        let signers: Vec<(HotSigner, &SignerPolicy)> = signers
+            .into_iter()
+            .filter_map(|signer| {
+                let policy = signer_policy(signer.fingerprint(&secp))?;
+                Some((signer, policy))
+            })
+            .collect();
+
+        // We can't decrypt the encrypted hot signers. Don't silently ignore those which would
+        // otherwise have been used.
+        let encrypted =
+            match HotSigner::encrypted_from_datadir(&datadir, self.config.bitcoin_config.network) {
+                Ok(encrypted) => encrypted,
+                Err(SignerError::MnemonicStorage(e)) if e.kind() == io::ErrorKind::NotFound => {
+                    Vec::new()
+                }
+                Err(e) => return Err(CommandError::HotSignerStorage(e.to_string())),
+            };
+        for fingerprint in encrypted.iter().map(|enc| enc.fingerprint()) {
+            let has_plaintext = signers
+                .iter()
+                .any(|(signer, _)| signer.fingerprint(&secp) == fingerprint);
+            if signer_policy(fingerprint).is_none() || has_plaintext {
+                continue;
+            }
+            if signers.is_empty() {
+                return Err(CommandError::EncryptedHotSigner(fingerprint));
+            }
+            log::warn!(
+                "Not signing Spend '{}' with hot signer '{}': it is encrypted.",
+                txid,
+                fingerprint
+            );
+        }
+        if signers.is_empty() {
+            return Err(CommandError::NoHotSigner);
+        }
+
+        // Never sign a transaction spending coins which aren't ours or sending change to an
+        // address which isn't. The outputs without derivation information are then the
+        // external ones.
+        check_psbt(&psbt, descriptor, &FeeBounds::default(), &secp)
+            .map_err(|e| CommandError::HotSigning(e.to_string()))?;
+        let external_outputs: Vec<(usize, &bitcoin::TxOut)> = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .zip(psbt.outputs.iter())
+            .enumerate()
+            .filter(|(_, (_, psbt_out))| {
+                psbt_out.bip32_derivation.is_empty() && psbt_out.tap_key_origins.is_empty()
+            })
+            .map(|(i, (txo, _))| (i, txo))
+            .collect();
+        let external_amount =
+            bitcoin::Amount::from_sat(external_outputs.iter().map(|(_, txo)| txo.value).sum());
+
+        // Now make sure the policy of each signer allows for this transaction.
+        let now: u32 = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .expect("Current time must be after the UNIX epoch.")
+            .as_secs()
+            .try_into()
+            .expect("Must fit in a u32 until 2106.");
+        let day_ago = now.saturating_sub(24 * 60 * 60);
+        for (_, policy) in &signers {
+            if let Some(destinations) = &policy.allowed_destinations {
+                for (i, txo) in &external_outputs {
+                    if !destinations
+                        .iter()
+                        .any(|addr| addr.payload.script_pubkey() == txo.script_pubkey)
+                    {
+                        return Err(CommandError::SigningPolicyViolation(
+                            policy.fingerprint,
+                            format!("output at index {} is not an allowed destination", i),
+                        ));
+                    }
+                }
+            }
+            if let Some(daily_limit) = policy.daily_limit {
+                let total_amount = db_conn.signed_spends_amount(policy.fingerprint, day_ago, txid)
+                    + external_amount;
+                if total_amount.to_sat() > daily_limit {
+                    return Err(CommandError::SigningPolicyViolation(
+                        policy.fingerprint,
+                        format!(
+                            "it would bring the amount sent over the last 24 hours to {} sats, \
+                             above the daily limit of {} sats",
+                            total_amount.to_sat(),
+                            daily_limit
+                        ),
+                    ));
+                }
+            }
+        }
+
+        // Finally sign, merge the signatures into the stored PSBT and account for the signed
+        // amount.
+        let mut signed_by = Vec::with_capacity(signers.len());
+        for (signer, policy) in &signers {
+            psbt = signer
+                .sign_psbt(psbt, &secp)
+                .map_err(|e| CommandError::HotSigning(e.to_string()))?;
+            signed_by.push(policy.fingerprint);
+        }
+        self.update_spend(psbt.clone())?;
+        for fingerprint in &signed_by {
+            db_conn.record_signed_spend(*fingerprint, txid, external_amount);
+        }
+
+        Ok(SignSpendResult {
+            psbt,
+            signers: signed_by,
+        })
+    }
+
+    // The external signer interface, if one was configured.
+    fn external_signer(&self) -> Result<ExternalSigner, CommandError> {
+        self.config
+            .signer_command
+            .clone()
+            .map(|command| ExternalSigner::new(command, self.config.bitcoin_config.network))
+            .ok_or(CommandError::NoExternalSigner)
+    }
+
+    /// List the signing devices available through the external signer command.
+    pub fn enumerate_signers(&self) -> Result<EnumerateSignersResult, CommandError> {
+        let signers = self
+            .external_signer()?
+            .enumerate()
+            .map_err(CommandError::ExternalSigner)?;
+        Ok(EnumerateSignersResult { signers })
+    }
+
+    /// Get the descriptors exposed by this signing device for this account, through the external
+    /// signer command.
+    pub fn get_signer_descriptors(
+        &self,
+        fingerprint: &bip32::Fingerprint,
+        account: u32,
+    ) -> Result<SignerDescriptors, CommandError> {
+        self.external_signer()?
+            .get_descriptors(fingerprint, account)
+            .map_err(CommandError::ExternalSigner)
+    }
+
+    /// Register our descriptor on this signing device, through the external signer command.
+    /// Returns the proof of registration for devices which need it when signing.
+    pub fn register_descriptor(
+        &self,
+        fingerprint: &bip32::Fingerprint,
+    ) -> Result<RegisterDescriptorResult, CommandError> {
+        if !self.descriptor_fingerprints().contains(fingerprint) {
+            return Err(CommandError::ForeignSigner(*fingerprint));
+        }
+        let hmac = self
+            .external_signer()?
+            .register(
+                fingerprint,
+                &self.config.main_descriptor.to_string(),
+                "Liana",
+            )
+            .map_err(CommandError::ExternalSigner)?;
+        Ok(RegisterDescriptorResult { hmac })
+    }
+
+    /// Have this signing device sign the stored Spend transaction with this txid, through the
+    /// external signer command. The signatures are merged into the stored PSBT.
+    pub fn sign_spend_external(
+        &self,
+        txid: &bitcoin::Txid,
+        fingerprint: &bip32::Fingerprint,
+    ) -> Result<SignSpendResult, CommandError> {
+        let mut db_conn = self.db.connection();
+        let psbt = db_conn
+            .spend_tx(txid)
+            .ok_or(CommandError::UnknownSpend(*txid))?;
+        if !self.descriptor_fingerprints().contains(fingerprint) {
+            return Err(CommandError::ForeignSigner(*fingerprint));
+        }
+
+        let signed_psbt = self
+            .external_signer()?
+            .sign_psbt(fingerprint, &psbt)
+            .map_err(CommandError::ExternalSigner)?;
+        // Don't store another transaction as a new Spend.
+        if signed_psbt.unsigned_tx.txid() != *txid {
+            return Err(CommandError::ExternalSigner(
+                ExternalSignerError::InvalidResponse(
+                    "the signed PSBT is for a different transaction".to_string(),
+                ),
+            ));
+        }
+        self.update_spend(signed_psbt.clone())?;
+
+        Ok(SignSpendResult {
+            psbt: signed_psbt,
+            signers: vec![*fingerprint],
+        })
+    }
+
     pub fn update_labels(&self, items: &HashMap<LabelItem, String>) {
         let mut db_conn = self.db.connection();
         db_conn.update_labels(items);
@@ -597,12 +1091,84 @@ impl DaemonControl {
         }
     }
 
+    /// Get a backup of the wallet. It contains the descriptor, the wallet creation date and the
+    /// labels, but not the keys aliases or hardware wallets registrations which are not known to
+    /// the daemon.
+    pub fn get_backup(&self) -> WalletBackup {
+        let mut db_conn = self.db.connection();
+        let mut backup = WalletBackup::new(
+            self.config.main_descriptor.clone(),
+            self.config.bitcoin_config.network,
+        );
+        backup.birthday = Some(db_conn.timestamp());
+        backup.labels = db_conn.all_labels().into_iter().collect();
+        backup
+    }
+
+    /// Restore the labels of the wallet from this backup, and rescan the chain from the wallet
+    /// birthday if it predates the creation of this wallet.
+    pub fn restore_backup(&self, backup: &WalletBackup) -> Result<(), CommandError> {
+        if backup.descriptor != self.config.main_descriptor {
+            return Err(CommandError::BackupDescriptorMismatch(Box::new(
+                backup.descriptor.clone(),
+            )));
+        }
+        if backup.network != self.config.bitcoin_config.network {
+            return Err(CommandError::BackupNetworkMismatch(backup.network));
+        }
+
+        let mut labels = HashMap::with_capacity(backup.labels.len());
+        for (item, value) in backup.labels.iter() {
+            let item = LabelItem::from_str(item, self.config.bitcoin_config.network)
+                .ok_or_else(|| CommandError::InvalidBackupLabel(item.clone()))?;
+            labels.insert(item, value.clone());
+        }
+        self.update_labels(&labels);
+
+        let timestamp = self.db.connection().timestamp();
+        if let Some(birthday) = backup.birthday.filter(|b| *b < timestamp) {
+            self.start_rescan(birthday)?;
+        }
+
+        Ok(())
+    }
+
     pub fn list_spend(&self) -> ListSpendResult {
         let mut db_conn = self.db.connection();
-        let spend_txs = db_conn
-            .list_spend()
+        let db_spends = db_conn.list_spend();
+        let spend_txids: HashSet<bitcoin::Txid> = db_spends
+            .iter()
+            .map(|(psbt, _, _)| psbt.unsigned_tx.txid())
+            .collect();
+        let spend_txs = db_spends
             .into_iter()
-            .map(|(psbt, updated_at)| ListSpendEntry { psbt, updated_at })
+            .map(|(psbt, updated_at, status)| {
+                let state = if status.confirmation.is_some() {
+                    SpendTxState::Confirmed
+                } else if let Some(txid) = status.conflicting_txid {
+                    // If the conflicting transaction is one we created it's a replacement.
+                    if spend_txids.contains(&txid) {
+                        SpendTxState::Replaced
+                    } else {
+                        SpendTxState::Conflicted
+                    }
+                } else if status.in_mempool {
+                    SpendTxState::Mempool
+                } else if status.broadcast_at.is_some() {
+                    SpendTxState::Dropped
+                } else {
+                    SpendTxState::Unbroadcast
+                };
+                ListSpendEntry {
+                    psbt,
+                    updated_at,
+                    status: state,
+                    broadcast_at: status.broadcast_at,
+                    confirmation_height: status.confirmation.map(|b| b.height),
+                    confirmation_time: status.confirmation.map(|b| b.time),
+                    conflicting_txid: status.conflicting_txid,
+                }
+            })
             .collect();
         ListSpendResult { spend_txs }
     }
@@ -635,7 +1201,148 @@ impl DaemonControl {
         let final_tx = spend_psbt.extract_tx();
         self.bitcoin
             .broadcast_tx(&final_tx)
-            .map_err(CommandError::TxBroadcast)
+            .map_err(CommandError::TxBroadcast)?;
+        db_conn.mark_spend_broadcast(txid);
+
+        Ok(())
+    }
+
+    /// Create the PSBT to be signed to prove control of one of our addresses with a BIP322
+    /// signature of this message. If a PSBT signed for this message is given instead, finalize it
+    /// into the BIP322 signature once it has enough signatures.
+    pub fn sign_message(
+        &self,
+        address: bitcoin::Address<address::NetworkUnchecked>,
+        message: &str,
+        psbt: Option<Psbt>,
+    ) -> Result<SignMessageResult, CommandError> {
+        let mut db_conn = self.db.connection();
+        let address = self.validate_address(address)?;
+        let (index, is_change) = db_conn
+            .derivation_index_by_address(&address)
+            .ok_or_else(|| CommandError::UnknownAddress(address.clone()))?;
+        let desc = if is_change {
+            self.config.main_descriptor.change_descriptor()
+        } else {
+            self.config.main_descriptor.receive_descriptor()
+        };
+        let message_psbt = bip322::message_psbt(&desc.derive(index, &self.secp), message);
+
+        let psbt = match psbt {
+            Some(psbt) => {
+                if psbt.unsigned_tx != message_psbt.unsigned_tx {
+                    return Err(CommandError::Bip322(bip322::Bip322Error::MessageMismatch));
+                }
+                psbt
+            }
+            None => message_psbt,
+        };
+        let signature = bip322::signature_from_psbt(psbt.clone(), &self.secp).ok();
+
+        Ok(SignMessageResult { psbt, signature })
+    }
+
+    /// Check a BIP322 signature of this message for this address. The address needs not be ours.
+    pub fn verify_message(
+        &self,
+        address: bitcoin::Address<address::NetworkUnchecked>,
+        message: &str,
+        signature: &str,
+    ) -> Result<VerifyMessageResult, CommandError> {
+        let address = self.validate_address(address)?;
+        let valid = match bip322::verify(&address, message, signature, &self.secp) {
+            Ok(()) => true,
+            Err(e) => {
+                log::debug!("Invalid BIP322 signature for address '{}': {}", address, e);
+                false
+            }
+        };
+
+        Ok(VerifyMessageResult { valid })
+    }
+
+    /// Create a proof of reserves (BIP127) for these coins, committing to this message. If no coin
+    /// is given, the proof is for all our confirmed unspent coins. It must be signed like a Spend
+    /// transaction.
+    pub fn create_proof_of_reserves(
+        &self,
+        message: &str,
+        coins_outpoints: &[bitcoin::OutPoint],
+    ) -> Result<CreateProofOfReservesResult, CommandError> {
+        let mut db_conn = self.db.connection();
+
+        let coins = if coins_outpoints.is_empty() {
+            let coins: Vec<Coin> = db_conn
+                .coins(&[CoinStatus::Confirmed], &[])
+                .into_values()
+                .filter(|coin| coin.previous_descriptor_id.is_none())
+                .collect();
+            if coins.is_empty() {
+                return Err(CommandError::NoConfirmedCoin);
+            }
+            coins
+        } else {
+            let db_coins = db_conn.coins_by_outpoints(coins_outpoints);
+            let mut coins = Vec::with_capacity(coins_outpoints.len());
+            for op in coins_outpoints {
+                let coin = db_coins.get(op).ok_or(CommandError::UnknownOutpoint(*op))?;
+                if coin.is_spent() {
+                    return Err(CommandError::AlreadySpent(*op));
+                }
+                if coin.previous_descriptor_id.is_some() {
+                    return Err(CommandError::MigratedCoin(*op));
+                }
+                coins.push(*coin);
+            }
+            coins
+        };
+
+        // Populate the PSBT inputs with the information needed by signers, like for a Spend.
+        let spent_txs = db_conn.list_wallet_transactions(
+            &coins
+                .iter()
+                .map(|coin| coin.outpoint.txid)
+                .collect::<Vec<bitcoin::Txid>>(),
+        );
+        let mut psbt_ins = Vec::with_capacity(coins.len());
+        for coin in &coins {
+            let spent_tx = spent_txs
+                .get(&coin.outpoint.txid)
+                .ok_or(CommandError::FetchingTransaction(coin.outpoint))?;
+            let coin_desc = self.derived_desc(coin);
+            let mut psbt_in = PsbtIn {
+                witness_utxo: Some(bitcoin::TxOut {
+                    value: coin.amount.to_sat(),
+                    script_pubkey: coin_desc.script_pubkey(),
+                }),
+                non_witness_utxo: Some(spent_tx.transaction.clone()),
+                ..PsbtIn::default()
+            };
+            coin_desc.update_psbt_in(&mut psbt_in);
+            psbt_ins.push((coin.outpoint, psbt_in));
+        }
+
+        Ok(CreateProofOfReservesResult {
+            psbt: reserves::proof_psbt(message, psbt_ins),
+        })
+    }
+
+    /// Check a signed proof of reserves commits to this message and only spends coins which are
+    /// confirmed and unspent in the best chain. Returns the total value of these coins.
+    pub fn verify_proof_of_reserves(
+        &self,
+        message: &str,
+        psbt: &Psbt,
+    ) -> Result<VerifyProofOfReservesResult, CommandError> {
+        let amount = reserves::verify_proof(
+            psbt,
+            message,
+            |op| self.bitcoin.unspent_txout(op),
+            &self.secp,
+        )
+        .map_err(CommandError::ProofOfReserves)?;
+
+        Ok(VerifyProofOfReservesResult { amount })
     }
 
     /// Trigger a rescan of the block chain for transactions involving our main descriptor between
@@ -671,68 +1378,170 @@ impl DaemonControl {
     ) -> ListTransactionsResult {
         let mut db_conn = self.db.connection();
         let txids = db_conn.list_txids(start, end, limit);
-        let transactions = txids
-            .iter()
-            .filter_map(|txid| {
-                // TODO: batch those calls to the Bitcoin backend
-                // so it can in turn optimize its queries.
-                self.bitcoin
-                    .wallet_transaction(txid)
-                    .map(|(tx, block)| TransactionInfo {
-                        tx,
-                        height: block.map(|b| b.height),
-                        time: block.map(|b| b.time),
-                    })
-            })
-            .collect();
-        ListTransactionsResult { transactions }
+        self.list_transactions(&txids)
     }
 
     /// list_transactions retrieves the transactions with the given txids.
     pub fn list_transactions(&self, txids: &[bitcoin::Txid]) -> ListTransactionsResult {
+        let mut db_conn = self.db.connection();
+        let wallet_txs = db_conn.list_wallet_transactions(txids);
         let transactions = txids
             .iter()
             .filter_map(|txid| {
-                // TODO: batch those calls to the Bitcoin backend
-                // so it can in turn optimize its queries.
-                self.bitcoin
-                    .wallet_transaction(txid)
-                    .map(|(tx, block)| TransactionInfo {
-                        tx,
-                        height: block.map(|b| b.height),
-                        time: block.map(|b| b.time),
-                    })
+                wallet_txs.get(txid).map(|wallet_tx| TransactionInfo {
+                    tx: wallet_tx.transaction.clone(),
+                    height: wallet_tx.block_info.map(|b| b.height),
+                    time: wallet_tx.block_info.map(|b| b.time),
+                })
             })
             .collect();
         ListTransactionsResult { transactions }
     }
 
-    /// Create a transaction that sweeps all coins for which a timelocked recovery path is
-    /// currently available to a provided address with the provided feerate.
-    ///
-    /// The `timelock` parameter can be used to specify which recovery path to use. By default,
-    /// we'll use the first recovery path available.
-    ///
-    /// Note that not all coins may be spendable through a single recovery path at the same time.
-    pub fn create_recovery(
-        &self,
-        address: bitcoin::Address<address::NetworkUnchecked>,
-        feerate_vb: u64,
-        timelock: Option<u16>,
-    ) -> Result<CreateRecoveryResult, CommandError> {
-        if feerate_vb < 1 {
-            return Err(CommandError::InvalidFeerate(feerate_vb));
-        }
-        let address = self.validate_address(address)?;
+    /// Get a page of the wallet history: all the transactions that created or spent one of our
+    /// coins, along with their direction, net amount, fee and confirmation status. Unconfirmed
+    /// transactions come first, then the most recently confirmed ones.
+    pub fn list_history(&self, limit: u64, offset: u64) -> ListHistoryResult {
         let mut db_conn = self.db.connection();
-
-        // The transaction template. We'll fill-in the inputs afterward.
-        let mut psbt = Psbt {
-            unsigned_tx: bitcoin::Transaction {
-                version: 2,
-                lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO), // TODO: anti-fee sniping
-                input: Vec::new(),
-                output: vec![bitcoin::TxOut {
+        let txids = db_conn.list_history_txids(limit, offset);
+        let coins = db_conn.coins_by_txids(&txids);
+        // We may not have stored some of the transactions yet, if we couldn't fetch them when
+        // polling. Query the Bitcoin backend for those.
+        let mut txs: HashMap<bitcoin::Txid, bitcoin::Transaction> = db_conn
+            .list_wallet_transactions(&txids)
+            .into_iter()
+            .map(|(txid, wallet_tx)| (txid, wallet_tx.transaction))
+            .collect();
+        for txid in txids.iter() {
+            if !txs.contains_key(txid) {
+                if let Some((tx, _)) = self.bitcoin.wallet_transaction(txid) {
+                    txs.insert(*txid, tx);
+                }
+            }
+        }
+        let labels = db_conn.labels(
+            &txids
+                .iter()
+                .map(|txid| LabelItem::from(*txid))
+                .chain(coins.keys().map(|op| LabelItem::from(*op)))
+                .collect(),
+        );
+        let recovery_timelocks: Vec<descriptors::RecoveryTimelock> = self
+            .config
+            .main_descriptor
+            .policy()
+            .recovery_paths()
+            .keys()
+            .copied()
+            .collect();
+
+        let transactions = txids
+            .into_iter()
+            .map(|txid| {
+                let tx = txs.get(&txid);
+                let received: Vec<&Coin> =
+                    coins.values().filter(|c| c.outpoint.txid == txid).collect();
+                let spent: Vec<&Coin> = coins
+                    .values()
+                    .filter(|c| c.spend_txid == Some(txid))
+                    .collect();
+                let received_value: bitcoin::Amount = received.iter().map(|c| c.amount).sum();
+                let spent_value: bitcoin::Amount = spent.iter().map(|c| c.amount).sum();
+
+                // A transaction spending our coins through one of the recovery paths must have
+                // set the relative timelock in the nSequence of the inputs, or the absolute
+                // timelock in its nLockTime. If we don't have the transaction at all, we can only
+                // tell whether it spent any of our coins.
+                let is_recovery = matches!(tx, Some(tx) if tx.input.iter().any(|txin| {
+                    spent.iter().any(|c| c.outpoint == txin.previous_output)
+                        && recovery_timelocks
+                            .iter()
+                            .any(|tl| tl.is_satisfied_by(txin.sequence, tx.lock_time))
+                }));
+                let direction = if spent.is_empty() {
+                    TxDirection::Incoming
+                } else if is_recovery {
+                    TxDirection::Recovery
+                } else if matches!(tx, Some(tx) if received.len() == tx.output.len()) {
+                    TxDirection::SelfSend
+                } else {
+                    TxDirection::Outgoing
+                };
+
+                // We can only compute the fee if we know the value of all the inputs.
+                let fee = tx.and_then(|tx| {
+                    if spent.len() == tx.input.len() {
+                        let out_value: u64 = tx.output.iter().map(|txo| txo.value).sum();
+                        spent_value.checked_sub(bitcoin::Amount::from_sat(out_value))
+                    } else {
+                        None
+                    }
+                });
+                let feerate = tx
+                    .zip(fee)
+                    .map(|(tx, fee)| fee.to_sat() as f64 / tx.vsize() as f64);
+
+                let block = received
+                    .iter()
+                    .find_map(|c| c.block_info)
+                    .or_else(|| spent.iter().find_map(|c| c.spend_block));
+
+                let tx_labels = labels
+                    .iter()
+                    .filter(|(item, _)| {
+                        **item == txid.to_string()
+                            || received
+                                .iter()
+                                .chain(spent.iter())
+                                .any(|c| **item == c.outpoint.to_string())
+                    })
+                    .map(|(item, label)| (item.clone(), label.clone()))
+                    .collect();
+
+                HistoryEntry {
+                    txid,
+                    direction,
+                    amount: received_value.to_signed().expect("Must fit")
+                        - spent_value.to_signed().expect("Must fit"),
+                    fee,
+                    feerate,
+                    height: block.map(|b| b.height),
+                    time: block.map(|b| b.time),
+                    labels: tx_labels,
+                }
+            })
+            .collect();
+        ListHistoryResult { transactions }
+    }
+
+    /// Create a transaction that sweeps all coins for which a timelocked recovery path is
+    /// currently available to a provided address with the provided feerate.
+    ///
+    /// The `timelock` parameter can be used to specify which recovery path to use. By default,
+    /// we'll use the first recovery path available. Time-based timelocks are measured from the
+    /// median time past of the block preceding the one which confirmed the coin (BIP68). For an
+    /// absolute timelock, all confirmed coins are swept once the chain reached it.
+    ///
+    /// Note that not all coins may be spendable through a single recovery path at the same time.
+    pub fn create_recovery(
+        &self,
+        address: bitcoin::Address<address::NetworkUnchecked>,
+        feerate_vb: u64,
+        timelock: Option<descriptors::RecoveryTimelock>,
+    ) -> Result<CreateRecoveryResult, CommandError> {
+        if !(1..=MAX_FEERATE).contains(&feerate_vb) {
+            return Err(CommandError::InvalidFeerate(feerate_vb));
+        }
+        let address = self.validate_address(address)?;
+        let mut db_conn = self.db.connection();
+
+        // The transaction template. We'll fill-in the inputs afterward.
+        let mut psbt = Psbt {
+            unsigned_tx: bitcoin::Transaction {
+                version: 2,
+                lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO), // TODO: anti-fee sniping
+                input: Vec::new(),
+                output: vec![bitcoin::TxOut {
                     script_pubkey: address.script_pubkey(),
                     value: 0xFF_FF_FF_FF,
                 }],
@@ -748,77 +1557,161 @@ impl DaemonControl {
         // Query the coins that we can spend through the specified recovery path (if no recovery
         // path specified, use the first available one) from the database.
         let current_height = self.bitcoin.chain_tip().height;
-        let timelock =
-            timelock.unwrap_or_else(|| self.config.main_descriptor.first_timelock_value());
-        let height_delta: i32 = timelock.try_into().expect("Must fit, it's a u16");
+        let timelock = timelock.unwrap_or_else(|| self.config.main_descriptor.first_timelock());
+        let current_mtp = match timelock {
+            descriptors::RecoveryTimelock::Time(_)
+            | descriptors::RecoveryTimelock::AbsoluteTime(_) => {
+                self.bitcoin.median_time_past(current_height)
+            }
+            descriptors::RecoveryTimelock::Blocks(_)
+            | descriptors::RecoveryTimelock::AbsoluteHeight(_) => None,
+        };
+        // An absolute timelock must be set in the nLockTime of the transaction. Note it is only
+        // final if strictly below the height of the next block, or the median time past of the
+        // current tip.
+        psbt.unsigned_tx.lock_time = timelock.lock_time();
         let sweepable_coins = db_conn
             .coins(&[CoinStatus::Unconfirmed, CoinStatus::Confirmed], &[])
             .into_values()
             .filter(|c| {
                 // We are interested in coins available at the *next* block
-                c.block_info
-                    .map(|b| current_height + 1 >= b.height + height_delta)
-                    .unwrap_or(false)
-            });
+                c.previous_descriptor_id.is_none()
+                    && c.block_info
+                        .map(|b| match timelock {
+                            descriptors::RecoveryTimelock::Blocks(n) => {
+                                current_height + 1 >= b.height + n as i32
+                            }
+                            descriptors::RecoveryTimelock::Time(n) => current_mtp
+                                .zip(self.bitcoin.median_time_past(b.height - 1))
+                                .map(|(cur_mtp, coin_mtp)| cur_mtp >= coin_mtp + n as u32 * 512)
+                                .unwrap_or(false),
+                            descriptors::RecoveryTimelock::AbsoluteHeight(h) => {
+                                current_height + 1 > h as i32
+                            }
+                            descriptors::RecoveryTimelock::AbsoluteTime(t) => {
+                                current_mtp.map(|mtp| mtp > t).unwrap_or(false)
+                            }
+                        })
+                        .unwrap_or(false)
+            })
+            .collect::<Vec<Coin>>();
+        let spent_txs = db_conn.list_wallet_transactions(
+            &sweepable_coins
+                .iter()
+                .map(|c| c.outpoint.txid)
+                .collect::<Vec<bitcoin::Txid>>(),
+        );
 
-        // Fill-in the transaction inputs and PSBT inputs information. Record the value
-        // that is fed to the transaction while doing so, to compute the fees afterward.
-        let mut in_value = bitcoin::Amount::from_sat(0);
-        let txin_sat_vb = self.config.main_descriptor.max_sat_vbytes();
-        let mut sat_vb = 1; // Start at 1 for the segwit marker size, rounded up.
-        let mut spent_txs = HashMap::new();
-        for coin in sweepable_coins {
-            in_value += coin.amount;
-            psbt.unsigned_tx.input.push(bitcoin::TxIn {
-                previous_output: coin.outpoint,
-                sequence: bitcoin::Sequence::from_height(timelock),
-                // TODO: once we move to Taproot, anti-fee-sniping using nSequence
-                ..bitcoin::TxIn::default()
-            });
+        // The sweepable_coins iterator may have been empty.
+        if sweepable_coins.is_empty() {
+            return Err(CommandError::RecoveryNotAvailable);
+        }
+        self.fill_sweep_psbt(
+            &mut psbt,
+            &sweepable_coins,
+            &spent_txs,
+            timelock.sequence(),
+            feerate_vb,
+        )?;
 
-            // Fetch the transaction that created this coin if necessary
-            if let hash_map::Entry::Vacant(e) = spent_txs.entry(coin.outpoint) {
-                let tx = self
-                    .bitcoin
-                    .wallet_transaction(&coin.outpoint.txid)
-                    .ok_or(CommandError::FetchingTransaction(coin.outpoint))?;
-                e.insert(tx.0);
-            }
+        sanity_check_psbt(&self.config.main_descriptor, &psbt)?;
 
-            let coin_desc = self.derived_desc(&coin);
-            sat_vb += txin_sat_vb;
-            let witness_script = Some(coin_desc.witness_script());
-            let witness_utxo = Some(bitcoin::TxOut {
-                value: coin.amount.to_sat(),
-                script_pubkey: coin_desc.script_pubkey(),
-            });
-            let non_witness_utxo = spent_txs.get(&coin.outpoint).cloned();
-            let bip32_derivation = coin_desc.bip32_derivations();
-            psbt.inputs.push(PsbtIn {
-                witness_script,
-                witness_utxo,
-                non_witness_utxo,
-                bip32_derivation,
-                ..PsbtIn::default()
-            });
+        Ok(CreateRecoveryResult { psbt })
+    }
+
+    /// Create the transactions sweeping all our confirmed coins to the given new descriptor
+    /// through the primary path, in batches of at most `batch_size` coins. If no batch size is
+    /// given, all the coins are swept in a single transaction.
+    ///
+    /// The new descriptor is imported on the Bitcoin backend and recorded as the one we are
+    /// migrating to. Once all the coins are swept, restarting with this new descriptor in the
+    /// configuration switches the wallet to it. The history and labels of the current descriptor
+    /// are kept.
+    pub fn create_migration(
+        &self,
+        new_descriptor: &descriptors::LianaDescriptor,
+        feerate_vb: u64,
+        batch_size: Option<usize>,
+    ) -> Result<CreateMigrationResult, CommandError> {
+        if !(1..=MAX_FEERATE).contains(&feerate_vb) {
+            return Err(CommandError::InvalidFeerate(feerate_vb));
+        }
+        if new_descriptor == &self.config.main_descriptor {
+            return Err(CommandError::MigrationToSameDescriptor);
         }
+        let mut db_conn = self.db.connection();
 
-        // The sweepable_coins iterator may have been empty.
-        if psbt.unsigned_tx.input.is_empty() {
-            return Err(CommandError::RecoveryNotAvailable);
+        // Sweep the coins in the order they were confirmed.
+        let mut coins = db_conn
+            .coins(&[CoinStatus::Confirmed], &[])
+            .into_values()
+            .filter(|c| !c.is_immature && c.previous_descriptor_id.is_none())
+            .collect::<Vec<Coin>>();
+        if coins.is_empty() {
+            return Err(CommandError::NoCoinToMigrate);
+        }
+        coins.sort_by_key(|c| (c.block_info.map(|b| b.height), c.outpoint));
+        let batch_size = batch_size.unwrap_or(coins.len());
+        if batch_size == 0 {
+            return Err(CommandError::InvalidMigrationBatchSize(batch_size));
         }
+        let batches: Vec<&[Coin]> = coins.chunks(batch_size).collect();
+        if batches.len() > MAX_MIGRATION_TXS {
+            return Err(CommandError::TooManyMigrationTransactions(batches.len()));
+        }
+        let spent_txs = db_conn.list_wallet_transactions(
+            &coins
+                .iter()
+                .map(|c| c.outpoint.txid)
+                .collect::<Vec<bitcoin::Txid>>(),
+        );
 
-        // Compute the value of the single output based on the requested feerate.
-        let tx_vbytes = (psbt.unsigned_tx.vsize() + sat_vb) as u64;
-        let absolute_fee = bitcoin::Amount::from_sat(tx_vbytes.checked_mul(feerate_vb).unwrap());
-        let output_value = in_value
-            .checked_sub(absolute_fee)
-            .ok_or(CommandError::InsufficientFunds(in_value, None, feerate_vb))?;
-        psbt.unsigned_tx.output[0].value = output_value.to_sat();
+        // Each transaction pays to a different change address of the new descriptor. These are
+        // internal transfers, and the derivation indexes start from 0 once we switch to it.
+        let new_change_desc = new_descriptor.change_descriptor();
+        let mut transactions = Vec::with_capacity(batches.len());
+        for (i, batch) in batches.into_iter().enumerate() {
+            let index = bip32::ChildNumber::from(i as u32);
+            let address = new_change_desc
+                .derive(index, &self.secp)
+                .address(self.config.bitcoin_config.network);
+            let mut psbt = Psbt {
+                unsigned_tx: bitcoin::Transaction {
+                    version: 2,
+                    lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO), // TODO: anti-fee sniping
+                    input: Vec::with_capacity(batch.len()),
+                    output: vec![bitcoin::TxOut {
+                        script_pubkey: address.script_pubkey(),
+                        value: 0xFF_FF_FF_FF,
+                    }],
+                },
+                version: 0,
+                xpub: BTreeMap::new(),
+                proprietary: BTreeMap::new(),
+                unknown: BTreeMap::new(),
+                inputs: Vec::with_capacity(batch.len()),
+                outputs: vec![PsbtOut::default()],
+            };
 
-        sanity_check_psbt(&self.config.main_descriptor, &psbt)?;
+            self.fill_sweep_psbt(
+                &mut psbt,
+                batch,
+                &spent_txs,
+                bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME,
+                feerate_vb,
+            )?;
 
-        Ok(CreateRecoveryResult { psbt })
+            sanity_check_psbt(&self.config.main_descriptor, &psbt)?;
+            transactions.push(MigrationTransaction { psbt, address });
+        }
+
+        // Start tracking the new descriptor before any of the sweep transactions gets broadcast.
+        self.bitcoin
+            .import_descriptor(new_descriptor)
+            .map_err(CommandError::DescriptorImport)?;
+        db_conn.set_migration_descriptor(new_descriptor);
+
+        Ok(CreateMigrationResult { transactions })
     }
 }
 
@@ -837,6 +1730,8 @@ pub struct GetInfoResult {
     pub descriptors: GetInfoDescriptors,
     /// The progress as a percentage (between 0 and 1) of an ongoing rescan if there is any
     pub rescan_progress: Option<f64>,
+    /// The number of unconfirmed coins which disappeared so far. See `listexpiredcoins`.
+    pub expired_coins: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -885,6 +1780,10 @@ pub struct ListCoinsEntry {
     pub spend_info: Option<LCSpendInfo>,
     /// Whether this coin was created by a coinbase transaction that is still immature.
     pub is_immature: bool,
+    /// The derivation index of the address of this coin.
+    pub derivation_index: bip32::ChildNumber,
+    /// Whether this coin was received on a change address.
+    pub is_change: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -892,17 +1791,125 @@ pub struct ListCoinsResult {
     pub coins: Vec<ListCoinsEntry>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListExpiredCoinsEntry {
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub amount: bitcoin::Amount,
+    pub outpoint: bitcoin::OutPoint,
+    #[serde(
+        serialize_with = "ser_to_string",
+        deserialize_with = "deser_addr_assume_checked"
+    )]
+    pub address: bitcoin::Address,
+    /// The derivation index of the address of this coin.
+    pub derivation_index: bip32::ChildNumber,
+    /// Whether this coin was received on a change address.
+    pub is_change: bool,
+    /// When the coin was noticed to have disappeared.
+    pub expired_at: u32,
+    /// The transaction which conflicted with the one that created this coin, if known.
+    pub conflicting_txid: Option<bitcoin::Txid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListExpiredCoinsResult {
+    pub coins: Vec<ListExpiredCoinsEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetPolicyResult {
+    pub policy: descriptors::PolicyDescription,
+    /// The height of the coin the timeline was computed for.
+    pub coin_height: u32,
+    /// When each recovery path becomes available, in the same order as in the policy.
+    pub timeline: Vec<descriptors::TimelineEntry>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct CreateSpendResult {
     #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
     pub psbt: Psbt,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignSpendResult {
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub psbt: Psbt,
+    /// The fingerprints of the hot signers which signed the transaction.
+    pub signers: Vec<bip32::Fingerprint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EnumerateSignersResult {
+    pub signers: Vec<SignerDevice>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RegisterDescriptorResult {
+    /// The proof of registration, for the devices which return one.
+    pub hmac: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SignMessageResult {
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub psbt: Psbt,
+    /// The base64-encoded BIP322 signature, once the PSBT is signed.
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VerifyMessageResult {
+    pub valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreateProofOfReservesResult {
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub psbt: Psbt,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VerifyProofOfReservesResult {
+    /// The total value of the coins the proof is for.
+    #[serde(
+        serialize_with = "ser_amount",
+        deserialize_with = "deser_amount_from_sats"
+    )]
+    pub amount: bitcoin::Amount,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListSpendEntry {
     #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
     pub psbt: Psbt,
     pub updated_at: Option<u32>,
+    pub status: SpendTxState,
+    pub broadcast_at: Option<u32>,
+    pub confirmation_height: Option<i32>,
+    pub confirmation_time: Option<u32>,
+    pub conflicting_txid: Option<bitcoin::Txid>,
+}
+
+/// What happened to a Spend transaction since it was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendTxState {
+    /// The transaction was never seen broadcast.
+    Unbroadcast,
+    /// The transaction is unconfirmed, in the mempool.
+    Mempool,
+    /// The transaction was included in a block.
+    Confirmed,
+    /// Another Spend transaction we created, spending some of the same coins, was seen instead.
+    Replaced,
+    /// A transaction we did not create, spending some of the same coins, was seen instead.
+    Conflicted,
+    /// The transaction was broadcast but isn't in the mempool anymore.
+    Dropped,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -923,24 +1930,91 @@ pub struct TransactionInfo {
     pub time: Option<u32>,
 }
 
+/// The direction of a transaction, from the point of view of the wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxDirection {
+    /// The transaction does not spend any of our coins.
+    Incoming,
+    /// The transaction spends some of our coins through the primary path to at least one
+    /// external address.
+    Outgoing,
+    /// The transaction spends some of our coins through the primary path and all its outputs
+    /// are ours.
+    SelfSend,
+    /// The transaction spends some of our coins through one of the recovery paths.
+    Recovery,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub txid: bitcoin::Txid,
+    pub direction: TxDirection,
+    /// The value received minus the value spent by this transaction. Includes the fee if we paid
+    /// it.
+    #[serde(
+        serialize_with = "ser_signed_amount",
+        deserialize_with = "deser_signed_amount_from_sats"
+    )]
+    pub amount: bitcoin::SignedAmount,
+    /// The fee paid by this transaction, if we know the value of all its inputs.
+    #[serde(
+        serialize_with = "ser_optional_amount",
+        deserialize_with = "deser_optional_amount_from_sats"
+    )]
+    pub fee: Option<bitcoin::Amount>,
+    /// The feerate of this transaction in sats/vb, if we know its fee.
+    pub feerate: Option<f64>,
+    /// The block height this transaction was confirmed at.
+    pub height: Option<i32>,
+    /// The time of the block this transaction was confirmed in.
+    pub time: Option<u32>,
+    /// The labels of this transaction and of the coins it created or spent.
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListHistoryResult {
+    pub transactions: Vec<HistoryEntry>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct CreateRecoveryResult {
     #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
     pub psbt: Psbt,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MigrationTransaction {
+    #[serde(serialize_with = "ser_to_string", deserialize_with = "deser_fromstr")]
+    pub psbt: Psbt,
+    /// The address of the new descriptor this transaction sweeps the coins to.
+    #[serde(
+        serialize_with = "ser_to_string",
+        deserialize_with = "deser_addr_assume_checked"
+    )]
+    pub address: bitcoin::Address,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreateMigrationResult {
+    pub transactions: Vec<MigrationTransaction>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{bitcoin::Block, database::BlockInfo, testutils::*};
+    use crate::{database::BlockInfo, testutils::*};
 
     use bitcoin::{
         bip32::{self, ChildNumber},
         blockdata::transaction::{TxIn, TxOut},
+        hashes::Hash,
         locktime::absolute,
         OutPoint, ScriptBuf, Sequence, Transaction, Txid, Witness,
     };
-    use std::str::FromStr;
+    use miniscript::descriptor::DescriptorPublicKey;
+    use std::{fs, str::FromStr};
 
     #[test]
     fn getinfo() {
@@ -950,6 +2024,104 @@ mod tests {
         ms.shutdown();
     }
 
+    #[test]
+    fn getpolicy() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        // Both keys have the same master fingerprint in the test descriptor.
+        let aliases: HashMap<_, _> = [(
+            bip32::Fingerprint::from_str("aabbccdd").unwrap(),
+            "Alice".to_string(),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+        let res = control.get_policy(&aliases, Some(100));
+        assert_eq!(res.coin_height, 100);
+        assert_eq!(res.policy.primary_path.threshold, 1);
+        assert_eq!(
+            res.policy.primary_path.keys[0].alias.as_deref(),
+            Some("Alice")
+        );
+        assert_eq!(res.policy.recovery_paths.len(), 1);
+        assert_eq!(res.policy.recovery_paths[0].blocks, Some(10_000));
+        assert_eq!(res.timeline.len(), 1);
+        assert_eq!(res.timeline[0].height, Some(10_100));
+
+        // Without aliases nor height, it uses the current tip.
+        let res = control.get_policy(&HashMap::new(), None);
+        let tip_height = control.db.connection().chain_tip().unwrap().height;
+        assert_eq!(res.coin_height as i32, tip_height);
+        assert_eq!(res.policy.primary_path.keys[0].alias, None);
+        assert_eq!(res.timeline[0].height, Some(res.coin_height + 10_000));
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn backup() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        let backup = control.get_backup();
+        assert_eq!(backup.descriptor, control.config.main_descriptor);
+        assert_eq!(backup.network, bitcoin::Network::Bitcoin);
+        assert_eq!(backup.birthday, Some(1_690_000_000));
+        assert!(backup.labels.is_empty());
+
+        let txid =
+            Txid::from_str("0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7")
+                .unwrap();
+        let labels: HashMap<LabelItem, String> = [(LabelItem::Txid(txid), "payment".to_string())]
+            .iter()
+            .cloned()
+            .collect();
+        control.update_labels(&labels);
+        let backup = control.get_backup();
+        assert_eq!(
+            backup.labels.get(&txid.to_string()),
+            Some(&"payment".to_string())
+        );
+        ms.shutdown();
+
+        // The labels are restored on a fresh wallet.
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+        control.restore_backup(&backup).unwrap();
+        let items: HashSet<LabelItem> = labels.keys().cloned().collect();
+        assert_eq!(
+            control.get_labels(&items).labels.get(&txid.to_string()),
+            Some(&"payment".to_string())
+        );
+
+        // The backup must be for this wallet.
+        let mut wrong_backup = backup.clone();
+        wrong_backup.network = bitcoin::Network::Testnet;
+        assert!(matches!(
+            control.restore_backup(&wrong_backup),
+            Err(CommandError::BackupNetworkMismatch(
+                bitcoin::Network::Testnet
+            ))
+        ));
+        let mut wrong_backup = backup.clone();
+        wrong_backup.descriptor = descriptors::LianaDescriptor::from_str("wsh(or_d(pk([aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh([aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*),older(52560))))").unwrap();
+        assert!(matches!(
+            control.restore_backup(&wrong_backup),
+            Err(CommandError::BackupDescriptorMismatch(_))
+        ));
+        let mut wrong_backup = backup;
+        wrong_backup
+            .labels
+            .insert("not an item".to_string(), "label".to_string());
+        assert!(matches!(
+            control.restore_backup(&wrong_backup),
+            Err(CommandError::InvalidBackupLabel(_))
+        ));
+
+        ms.shutdown();
+    }
+
     #[test]
     fn getnewaddress() {
         let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
@@ -974,24 +2146,16 @@ mod tests {
 
     #[test]
     fn create_spend() {
-        let dummy_op = bitcoin::OutPoint::from_str(
-            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
-        )
-        .unwrap();
-        let mut dummy_bitcoind = DummyBitcoind::new();
-        dummy_bitcoind.txs.insert(
-            dummy_op.txid,
-            (
-                bitcoin::Transaction {
-                    version: 2,
-                    lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
-                    input: vec![],
-                    output: vec![],
-                },
-                None,
-            ),
-        );
-        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let dummy_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![],
+            output: vec![],
+        };
+        let dummy_op = bitcoin::OutPoint::new(dummy_tx.txid(), 0);
+        let mut db = DummyDatabase::new();
+        db.insert_txs(vec![dummy_tx]);
+        let ms = DummyLiana::new(DummyBitcoind::new(), db);
         let control = &ms.handle.control;
 
         // Arguments sanity checking
@@ -1028,6 +2192,7 @@ mod tests {
             is_change: false,
             spend_txid: None,
             spend_block: None,
+            previous_descriptor_id: None,
         }]);
         let res = control.create_spend(&destinations, &[dummy_op], 1).unwrap();
         assert!(res.psbt.inputs[0].non_witness_utxo.is_some());
@@ -1134,6 +2299,7 @@ mod tests {
             is_change: false,
             spend_txid: None,
             spend_block: None,
+            previous_descriptor_id: None,
         }]);
         assert_eq!(
             control.create_spend(&destinations, &[dummy_op_dup], 1_001),
@@ -1156,6 +2322,7 @@ mod tests {
             is_change: false,
             spend_txid: None,
             spend_block: None,
+            previous_descriptor_id: None,
         }]);
         assert_eq!(
             control.create_spend(&destinations, &[imma_op], 1_001),
@@ -1165,28 +2332,271 @@ mod tests {
         ms.shutdown();
     }
 
+    #[test]
+    fn create_recovery() {
+        let dummy_txs: Vec<bitcoin::Transaction> = (0..4)
+            .map(|i| bitcoin::Transaction {
+                version: 2,
+                lock_time: absolute::LockTime::from_height(i).unwrap(),
+                input: vec![],
+                output: vec![],
+            })
+            .collect();
+        let mut db = DummyDatabase::new();
+        db.insert_txs(dummy_txs.clone());
+        // Coins confirmed at heights 95, 85 and 75, and an unconfirmed one. The tip is at height
+        // 100, and there is a block every 10 minutes.
+        db.insert_coins(
+            dummy_txs
+                .iter()
+                .enumerate()
+                .map(|(i, tx)| Coin {
+                    outpoint: bitcoin::OutPoint::new(tx.txid(), 0),
+                    is_immature: false,
+                    block_info: if i < 3 {
+                        Some(BlockInfo {
+                            height: 95 - 10 * i as i32,
+                            time: 1_700_000_000,
+                        })
+                    } else {
+                        None
+                    },
+                    amount: bitcoin::Amount::from_sat(100_000),
+                    derivation_index: bip32::ChildNumber::from(i as u32),
+                    is_change: false,
+                    spend_txid: None,
+                    spend_block: None,
+                    previous_descriptor_id: None,
+                })
+                .collect(),
+        );
+        // A recovery path after 20 blocks, and one after 12 * 512 seconds (about 10 blocks).
+        let owner_key = descriptors::PathInfo::Single(DescriptorPublicKey::from_str("[aabbccdd]xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*").unwrap());
+        let heir_key = descriptors::PathInfo::Single(DescriptorPublicKey::from_str("[aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*").unwrap());
+        let lawyer_key = descriptors::PathInfo::Single(DescriptorPublicKey::from_str("[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*").unwrap());
+        let policy = descriptors::LianaPolicy::new(
+            owner_key,
+            [
+                (descriptors::RecoveryTimelock::Blocks(20), heir_key),
+                (descriptors::RecoveryTimelock::Time(12), lawyer_key),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap();
+        let desc = descriptors::LianaDescriptor::new(policy);
+        let ms = DummyLiana::new_with_config(DummyBitcoind::new(), db, desc, |_| {});
+        let control = &ms.handle.control;
+        let dummy_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let swept = |res: CreateRecoveryResult| -> Vec<bitcoin::Txid> {
+            res.psbt
+                .unsigned_tx
+                .input
+                .iter()
+                .map(|txin| txin.previous_output.txid)
+                .collect()
+        };
+
+        // The time-based path is available for the coins whose confirmation block's parent has
+        // a median time past at least 12 * 512 seconds older than the tip's.
+        let res = control
+            .create_recovery(
+                dummy_addr.clone(),
+                1,
+                Some(descriptors::RecoveryTimelock::Time(12)),
+            )
+            .unwrap();
+        assert!(res
+            .psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .all(|txin| txin.sequence == bitcoin::Sequence::from_512_second_intervals(12)));
+        let mut txids = swept(res);
+        txids.sort();
+        let mut expected = vec![dummy_txs[1].txid(), dummy_txs[2].txid()];
+        expected.sort();
+        assert_eq!(txids, expected);
+
+        // It is the first one to be available, so it is used by default.
+        let res = control
+            .create_recovery(dummy_addr.clone(), 1, None)
+            .unwrap();
+        assert_eq!(res.psbt.unsigned_tx.input.len(), 2);
+
+        // The block-based path is only available for the oldest coin.
+        let res = control
+            .create_recovery(
+                dummy_addr.clone(),
+                1,
+                Some(descriptors::RecoveryTimelock::Blocks(20)),
+            )
+            .unwrap();
+        assert_eq!(
+            res.psbt.unsigned_tx.input[0].sequence,
+            bitcoin::Sequence::from_height(20)
+        );
+        assert_eq!(swept(res), vec![dummy_txs[2].txid()]);
+
+        // The feerate must be sane.
+        assert_eq!(
+            control.create_recovery(dummy_addr.clone(), 0, None),
+            Err(CommandError::InvalidFeerate(0))
+        );
+        assert_eq!(
+            control.create_recovery(dummy_addr.clone(), u64::MAX, None),
+            Err(CommandError::InvalidFeerate(u64::MAX))
+        );
+
+        // No coin is old enough for a longer time-based timelock.
+        assert_eq!(
+            control.create_recovery(
+                dummy_addr,
+                1,
+                Some(descriptors::RecoveryTimelock::Time(100))
+            ),
+            Err(CommandError::RecoveryNotAvailable)
+        );
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn create_migration() {
+        let dummy_txs: Vec<bitcoin::Transaction> = (0..4)
+            .map(|i| bitcoin::Transaction {
+                version: 2,
+                lock_time: absolute::LockTime::from_height(i).unwrap(),
+                input: vec![],
+                output: vec![],
+            })
+            .collect();
+        let mut db = DummyDatabase::new();
+        db.insert_txs(dummy_txs.clone());
+        // Three confirmed coins and an unconfirmed one, which won't be swept.
+        db.insert_coins(
+            dummy_txs
+                .iter()
+                .enumerate()
+                .map(|(i, tx)| Coin {
+                    outpoint: bitcoin::OutPoint::new(tx.txid(), 0),
+                    is_immature: false,
+                    block_info: if i < 3 {
+                        Some(BlockInfo {
+                            height: 3 - i as i32,
+                            time: 1_700_000_000,
+                        })
+                    } else {
+                        None
+                    },
+                    amount: bitcoin::Amount::from_sat(100_000),
+                    derivation_index: bip32::ChildNumber::from(i as u32),
+                    is_change: false,
+                    spend_txid: None,
+                    spend_block: None,
+                    previous_descriptor_id: None,
+                })
+                .collect(),
+        );
+        let ms = DummyLiana::new(DummyBitcoind::new(), db);
+        let control = &ms.handle.control;
+        let new_desc = descriptors::LianaDescriptor::from_str("wsh(or_d(pk([aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh([aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*),older(52560))))").unwrap();
+
+        // Arguments sanity checking
+        assert_eq!(
+            control.create_migration(&new_desc, 0, None),
+            Err(CommandError::InvalidFeerate(0))
+        );
+        assert_eq!(
+            control.create_migration(&new_desc, 1_001, None),
+            Err(CommandError::InvalidFeerate(1_001))
+        );
+        assert_eq!(
+            control.create_migration(&new_desc, u64::MAX, None),
+            Err(CommandError::InvalidFeerate(u64::MAX))
+        );
+        assert_eq!(
+            control.create_migration(&control.config.main_descriptor, 1, None),
+            Err(CommandError::MigrationToSameDescriptor)
+        );
+        assert_eq!(
+            control.create_migration(&new_desc, 1, Some(0)),
+            Err(CommandError::InvalidMigrationBatchSize(0))
+        );
+
+        // By default all the confirmed coins are swept in a single transaction, to the first
+        // change address of the new descriptor.
+        let new_addr = |index: u32| {
+            new_desc
+                .change_descriptor()
+                .derive(index.into(), &control.secp)
+                .address(bitcoin::Network::Bitcoin)
+        };
+        let res = control.create_migration(&new_desc, 1, None).unwrap();
+        assert_eq!(res.transactions.len(), 1);
+        let tx = &res.transactions[0].psbt.unsigned_tx;
+        assert_eq!(res.transactions[0].address, new_addr(0));
+        assert_eq!(tx.input.len(), 3);
+        assert!(tx
+            .input
+            .iter()
+            .all(|txin| txin.previous_output.txid != dummy_txs[3].txid()));
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, new_addr(0).script_pubkey());
+        assert!(tx.output[0].value < 300_000 && tx.output[0].value > 299_000);
+
+        // They may be split in batches, in the order they were confirmed.
+        let res = control.create_migration(&new_desc, 1, Some(2)).unwrap();
+        assert_eq!(res.transactions.len(), 2);
+        let first_tx = &res.transactions[0].psbt.unsigned_tx;
+        assert_eq!(
+            first_tx
+                .input
+                .iter()
+                .map(|txin| txin.previous_output.txid)
+                .collect::<Vec<_>>(),
+            [dummy_txs[2].txid(), dummy_txs[1].txid()]
+        );
+        let second_tx = &res.transactions[1].psbt.unsigned_tx;
+        assert_eq!(second_tx.input.len(), 1);
+        assert_eq!(second_tx.input[0].previous_output.txid, dummy_txs[0].txid());
+        assert_eq!(
+            second_tx.output[0].script_pubkey,
+            new_addr(1).script_pubkey()
+        );
+
+        // We need enough funds to pay for the fees.
+        assert_eq!(
+            control.create_migration(&new_desc, 1_000, None),
+            Err(CommandError::InsufficientFunds(
+                bitcoin::Amount::from_sat(300_000),
+                None,
+                1_000
+            ))
+        );
+
+        ms.shutdown();
+    }
+
     #[test]
     fn update_spend() {
-        let dummy_op_a = bitcoin::OutPoint::from_str(
-            "3753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:0",
-        )
-        .unwrap();
-        let dummy_op_b = bitcoin::OutPoint::from_str(
-            "4753a1d74c0af8dd0a0f3b763c14faf3bd9ed03cbdf33337a074fb0e9f6c7810:1",
-        )
-        .unwrap();
-        let mut dummy_bitcoind = DummyBitcoind::new();
-        let dummy_tx = bitcoin::Transaction {
+        let dummy_tx_a = bitcoin::Transaction {
             version: 2,
             lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
             input: vec![],
             output: vec![],
         };
-        dummy_bitcoind
-            .txs
-            .insert(dummy_op_a.txid, (dummy_tx.clone(), None));
-        dummy_bitcoind.txs.insert(dummy_op_b.txid, (dummy_tx, None));
-        let ms = DummyLiana::new(dummy_bitcoind, DummyDatabase::new());
+        let dummy_tx_b = bitcoin::Transaction {
+            version: 1,
+            ..dummy_tx_a.clone()
+        };
+        let dummy_op_a = bitcoin::OutPoint::new(dummy_tx_a.txid(), 0);
+        let dummy_op_b = bitcoin::OutPoint::new(dummy_tx_b.txid(), 1);
+        let mut db = DummyDatabase::new();
+        db.insert_txs(vec![dummy_tx_a, dummy_tx_b]);
+        let ms = DummyLiana::new(DummyBitcoind::new(), db);
         let control = &ms.handle.control;
         let mut db_conn = control.db().lock().unwrap().connection();
 
@@ -1201,6 +2611,7 @@ mod tests {
                 is_change: false,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             },
             Coin {
                 outpoint: dummy_op_b,
@@ -1211,6 +2622,7 @@ mod tests {
                 is_change: false,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             },
         ]);
 
@@ -1290,6 +2702,458 @@ mod tests {
         ms.shutdown();
     }
 
+    #[test]
+    fn sign_spend() {
+        let secp = secp256k1::Secp256k1::new();
+        let network = bitcoin::Network::Bitcoin;
+
+        // A descriptor with a hot signer as the primary path.
+        let signer = HotSigner::generate(network).unwrap();
+        let fingerprint = signer.fingerprint(&secp);
+        let xpub = signer.xpub_at(&bip32::DerivationPath::master(), &secp);
+        let owner_key = descriptors::PathInfo::Single(
+            DescriptorPublicKey::from_str(&format!("[{}]{}/<0;1>/*", fingerprint, xpub)).unwrap(),
+        );
+        let heir_key = descriptors::PathInfo::Single(DescriptorPublicKey::from_str("[aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*").unwrap());
+        let policy = descriptors::LianaPolicy::new(
+            owner_key,
+            [(descriptors::RecoveryTimelock::Blocks(10_000), heir_key)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .unwrap();
+        let desc = descriptors::LianaDescriptor::new(policy);
+
+        // Its policy only allows to send up to 30k sats a day to a single address.
+        let allowed_addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let other_addr =
+            bitcoin::Address::from_str("bc1qvklensptw5lk7d470ds60pcpsr0psdpgyvwepv").unwrap();
+        let signer_policies = vec![SignerPolicy {
+            fingerprint,
+            daily_limit: Some(30_000),
+            allowed_destinations: Some(vec![allowed_addr.clone()]),
+        }];
+
+        // The transaction creating our two coins, at receive indexes 13 and 14.
+        let dummy_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![],
+            output: [13, 14]
+                .iter()
+                .map(|index: &u32| bitcoin::TxOut {
+                    value: 100_000,
+                    script_pubkey: desc
+                        .receive_descriptor()
+                        .derive((*index).into(), &secp)
+                        .script_pubkey(),
+                })
+                .collect(),
+        };
+        let (dummy_op_a, dummy_op_b) = (
+            bitcoin::OutPoint::new(dummy_tx.txid(), 0),
+            bitcoin::OutPoint::new(dummy_tx.txid(), 1),
+        );
+        let mut db = DummyDatabase::new();
+        db.insert_txs(vec![dummy_tx]);
+        let ms = DummyLiana::new_with_config(DummyBitcoind::new(), db, desc, |config| {
+            config.signer_policies = signer_policies
+        });
+        let control = &ms.handle.control;
+        let mut db_conn = control.db().lock().unwrap().connection();
+        db_conn.new_unspent_coins(
+            &[(dummy_op_a, 13), (dummy_op_b, 14)]
+                .iter()
+                .map(|(outpoint, index)| Coin {
+                    outpoint: *outpoint,
+                    is_immature: false,
+                    block_info: None,
+                    amount: bitcoin::Amount::from_sat(100_000),
+                    derivation_index: bip32::ChildNumber::from(*index),
+                    is_change: false,
+                    spend_txid: None,
+                    spend_block: None,
+                    previous_descriptor_id: None,
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        // Create and store a Spend transaction.
+        let create_spend = |addr: &bitcoin::Address<address::NetworkUnchecked>, value, outpoint| {
+            let destinations = [(addr.clone(), value)].iter().cloned().collect();
+            let psbt = control
+                .create_spend(&destinations, &[outpoint], 2)
+                .unwrap()
+                .psbt;
+            control.update_spend(psbt.clone()).unwrap();
+            psbt.unsigned_tx.txid()
+        };
+
+        // We can't sign an unknown spend, nor without a hot signer.
+        let txid = create_spend(&allowed_addr, 20_000, dummy_op_a);
+        let unknown_txid = bitcoin::Txid::from_slice(&[0; 32][..]).unwrap();
+        assert_eq!(
+            control.sign_spend(&unknown_txid),
+            Err(CommandError::UnknownSpend(unknown_txid))
+        );
+        assert_eq!(control.sign_spend(&txid), Err(CommandError::NoHotSigner));
+
+        // An encrypted hot signer can't be used to sign.
+        signer
+            .store_encrypted(
+                &control.config.data_dir().unwrap(),
+                network,
+                "passphrase",
+                &secp,
+            )
+            .unwrap();
+        assert_eq!(
+            control.sign_spend(&txid),
+            Err(CommandError::EncryptedHotSigner(signer.fingerprint(&secp)))
+        );
+
+        // Once the signer is stored in the datadir, it'll sign the stored PSBT.
+        signer
+            .store(&control.config.data_dir().unwrap(), network, &secp)
+            .unwrap();
+        let res = control.sign_spend(&txid).unwrap();
+        assert_eq!(res.signers, vec![fingerprint]);
+        assert_eq!(res.psbt.inputs[0].partial_sigs.len(), 1);
+        let db_psbt = db_conn.spend_tx(&txid).unwrap();
+        assert_eq!(
+            db_psbt.inputs[0].partial_sigs,
+            res.psbt.inputs[0].partial_sigs
+        );
+
+        // Signing the same transaction again doesn't count twice toward the daily limit.
+        control.sign_spend(&txid).unwrap();
+        assert_eq!(
+            db_conn.signed_spends_amount(fingerprint, 0, &unknown_txid),
+            bitcoin::Amount::from_sat(20_000)
+        );
+
+        // It won't sign for a transaction paying to another address.
+        let txid = create_spend(&other_addr, 5_000, dummy_op_b);
+        assert!(matches!(
+            control.sign_spend(&txid),
+            Err(CommandError::SigningPolicyViolation(fg, _)) if fg == fingerprint
+        ));
+
+        // Nor for a transaction which would exceed the daily limit.
+        let txid = create_spend(&allowed_addr, 15_000, dummy_op_b);
+        assert!(matches!(
+            control.sign_spend(&txid),
+            Err(CommandError::SigningPolicyViolation(fg, _)) if fg == fingerprint
+        ));
+
+        // But it will for one within the limit. Concurrent requests can't get around the limit:
+        // only one of two transactions within the limit, but above it together, gets signed.
+        let txids = [
+            create_spend(&allowed_addr, 10_000, dummy_op_b),
+            create_spend(&allowed_addr, 9_000, dummy_op_b),
+        ];
+        let results: Vec<_> = txids
+            .iter()
+            .map(|txid| {
+                let (control, txid) = (control.clone(), *txid);
+                std::thread::spawn(move || control.sign_spend(&txid).ok())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        assert_eq!(results.iter().filter(|res| res.is_some()).count(), 1);
+        let res = results.into_iter().flatten().next().unwrap();
+        assert_eq!(res.psbt.inputs[0].partial_sigs.len(), 1);
+        assert!(
+            db_conn.signed_spends_amount(fingerprint, 0, &unknown_txid)
+                <= bitcoin::Amount::from_sat(30_000)
+        );
+
+        ms.shutdown();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sign_spend_external() {
+        let secp = secp256k1::Secp256k1::new();
+        let network = bitcoin::Network::Bitcoin;
+
+        // The key of the primary path is on a signing device, which we emulate with a hot signer.
+        let device = HotSigner::generate(network).unwrap();
+        let fingerprint = device.fingerprint(&secp);
+        let xpub = device.xpub_at(&bip32::DerivationPath::master(), &secp);
+        let desc = descriptors::LianaDescriptor::from_str(&format!("wsh(or_d(pk([{}]{}/<0;1>/*),and_v(v:pkh([aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000))))", fingerprint, xpub)).unwrap();
+
+        // The fake signer command returns the response prepared in its directory.
+        let signer_dir = tmp_dir();
+        let script = format!(
+            r#"
+case "$*" in
+    *enumerate*)
+        echo '[{{"fingerprint": "{}", "type": "fake"}}]'
+        ;;
+    *register*)
+        echo '{{"hmac": "deadbeef"}}'
+        ;;
+    *)
+        cat "$(dirname "$0")/response.json"
+        ;;
+esac
+"#,
+            fingerprint
+        );
+        let signer_path = fake_signer(&signer_dir, &script);
+        let set_response = |psbt: &Psbt| {
+            fs::write(
+                signer_dir.join("response.json"),
+                serde_json::json!({ "psbt": psbt.to_string() }).to_string(),
+            )
+            .unwrap()
+        };
+
+        // We can't use signing devices without a signer command.
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        assert_eq!(
+            ms.handle.control.enumerate_signers(),
+            Err(CommandError::NoExternalSigner)
+        );
+        ms.shutdown();
+
+        let dummy_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![],
+            output: vec![],
+        };
+        let dummy_op = bitcoin::OutPoint::new(dummy_tx.txid(), 0);
+        let mut db = DummyDatabase::new();
+        db.insert_txs(vec![dummy_tx]);
+        db.insert_coins(vec![Coin {
+            outpoint: dummy_op,
+            is_immature: false,
+            block_info: None,
+            amount: bitcoin::Amount::from_sat(100_000),
+            derivation_index: bip32::ChildNumber::from(13),
+            is_change: false,
+            spend_txid: None,
+            spend_block: None,
+            previous_descriptor_id: None,
+        }]);
+        let ms = DummyLiana::new_with_config(DummyBitcoind::new(), db, desc, |config| {
+            config.signer_command = Some(vec![signer_path.to_str().unwrap().to_string()])
+        });
+        let control = &ms.handle.control;
+        assert_eq!(
+            control.enumerate_signers().unwrap().signers,
+            vec![SignerDevice {
+                fingerprint,
+                device_type: Some("fake".to_string()),
+                model: None,
+            }]
+        );
+
+        // We can register the descriptor on the device, but not on one with no key in it.
+        let foreign_fg = bip32::Fingerprint::from([0, 1, 2, 3]);
+        assert_eq!(
+            control.register_descriptor(&foreign_fg),
+            Err(CommandError::ForeignSigner(foreign_fg))
+        );
+        assert_eq!(
+            control.register_descriptor(&fingerprint).unwrap().hmac,
+            Some("deadbeef".to_string())
+        );
+
+        // Create and store a Spend, have the device sign it. The signatures are stored.
+        let addr =
+            bitcoin::Address::from_str("bc1qnsexk3gnuyayu92fc3tczvc7k62u22a22ua2kv").unwrap();
+        let destinations = [(addr, 20_000)].iter().cloned().collect();
+        let psbt = control
+            .create_spend(&destinations, &[dummy_op], 2)
+            .unwrap()
+            .psbt;
+        let txid = psbt.unsigned_tx.txid();
+        assert_eq!(
+            control.sign_spend_external(&txid, &fingerprint),
+            Err(CommandError::UnknownSpend(txid))
+        );
+        control.update_spend(psbt.clone()).unwrap();
+        assert_eq!(
+            control.sign_spend_external(&txid, &foreign_fg),
+            Err(CommandError::ForeignSigner(foreign_fg))
+        );
+        let signed_psbt = device.sign_psbt(psbt.clone(), &secp).unwrap();
+        set_response(&signed_psbt);
+        let res = control.sign_spend_external(&txid, &fingerprint).unwrap();
+        assert_eq!(res.signers, vec![fingerprint]);
+        assert_eq!(res.psbt.inputs[0].partial_sigs.len(), 1);
+        let mut db_conn = control.db().lock().unwrap().connection();
+        assert_eq!(
+            db_conn.spend_tx(&txid).unwrap().inputs[0].partial_sigs,
+            signed_psbt.inputs[0].partial_sigs
+        );
+
+        // A response for another transaction is refused.
+        let mut other_psbt = psbt;
+        other_psbt.unsigned_tx.output[0].value += 1;
+        set_response(&other_psbt);
+        assert!(matches!(
+            control.sign_spend_external(&txid, &fingerprint),
+            Err(CommandError::ExternalSigner(
+                ExternalSignerError::InvalidResponse(_)
+            ))
+        ));
+
+        ms.shutdown();
+        fs::remove_dir_all(&signer_dir).unwrap();
+    }
+
+    #[test]
+    fn sign_verify_message() {
+        let ms = DummyLiana::new(DummyBitcoind::new(), DummyDatabase::new());
+        let control = &ms.handle.control;
+
+        // We can only sign for our own addresses on our network.
+        let addr =
+            bitcoin::Address::from_str("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l").unwrap();
+        assert_eq!(
+            control.sign_message(addr.clone(), "Hello World", None),
+            Err(CommandError::UnknownAddress(addr.clone().assume_checked()))
+        );
+        let testnet_addr =
+            bitcoin::Address::from_str("tb1qar0srrr7xfkvy5l643lydnw9re59gtzzy00gkn").unwrap();
+        assert!(matches!(
+            control.sign_message(testnet_addr, "Hello World", None),
+            Err(CommandError::Address(_))
+        ));
+
+        // But we can verify a signature for any address.
+        let sig = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert!(
+            control
+                .verify_message(addr.clone(), "Hello World", sig)
+                .unwrap()
+                .valid
+        );
+        assert!(!control.verify_message(addr, "Hello", sig).unwrap().valid);
+
+        ms.shutdown();
+    }
+
+    #[test]
+    fn proof_of_reserves() {
+        let secp = secp256k1::Secp256k1::new();
+        let network = bitcoin::Network::Bitcoin;
+        let signer = HotSigner::generate(network).unwrap();
+        let fingerprint = signer.fingerprint(&secp);
+        let xpub = signer.xpub_at(&bip32::DerivationPath::master(), &secp);
+        let desc = descriptors::LianaDescriptor::from_str(&format!("wsh(or_d(pk([{}]{}/<0;1>/*),and_v(v:pkh([aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000))))", fingerprint, xpub)).unwrap();
+
+        // Two confirmed coins and an unconfirmed one, which the proof won't include by default.
+        let deposit_txs: Vec<bitcoin::Transaction> = (0..3)
+            .map(|i| bitcoin::Transaction {
+                version: 2,
+                lock_time: absolute::LockTime::from_height(i).unwrap(),
+                input: vec![],
+                output: vec![TxOut {
+                    value: 100_000,
+                    script_pubkey: desc
+                        .receive_descriptor()
+                        .derive(ChildNumber::from(i), &secp)
+                        .script_pubkey(),
+                }],
+            })
+            .collect();
+        let coins: Vec<Coin> = deposit_txs
+            .iter()
+            .enumerate()
+            .map(|(i, tx)| Coin {
+                outpoint: OutPoint::new(tx.txid(), 0),
+                is_immature: false,
+                block_info: if i < 2 {
+                    Some(BlockInfo { height: 1, time: 1 })
+                } else {
+                    None
+                },
+                amount: bitcoin::Amount::from_sat(100_000),
+                derivation_index: ChildNumber::from(i as u32),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                previous_descriptor_id: None,
+            })
+            .collect();
+        let mut db = DummyDatabase::new();
+        db.insert_txs(deposit_txs.clone());
+        db.insert_coins(coins.clone());
+        let mut bitcoind = DummyBitcoind::new();
+        for (i, tx) in deposit_txs.iter().enumerate() {
+            let block = if i < 2 {
+                Some(crate::bitcoin::Block {
+                    hash: bitcoin::BlockHash::all_zeros(),
+                    height: 1,
+                    time: 1,
+                })
+            } else {
+                None
+            };
+            bitcoind.txs.insert(tx.txid(), (tx.clone(), block));
+        }
+        let ms = DummyLiana::new_with_config(bitcoind, db, desc, |_| {});
+        let control = &ms.handle.control;
+
+        // By default the proof is for all our confirmed coins.
+        let message = "Liana reserves";
+        let psbt = control.create_proof_of_reserves(message, &[]).unwrap().psbt;
+        assert_eq!(psbt.unsigned_tx.input.len(), 3);
+        assert_eq!(psbt.unsigned_tx.output[0].value, 200_000);
+
+        // It's only valid once signed, and for this message.
+        assert!(matches!(
+            control.verify_proof_of_reserves(message, &psbt),
+            Err(CommandError::ProofOfReserves(
+                reserves::ReservesError::Finalization(..)
+            ))
+        ));
+        let psbt = signer.sign_psbt(psbt, &secp).unwrap();
+        assert_eq!(
+            control
+                .verify_proof_of_reserves(message, &psbt)
+                .unwrap()
+                .amount,
+            bitcoin::Amount::from_sat(200_000)
+        );
+        assert_eq!(
+            control.verify_proof_of_reserves("Other message", &psbt),
+            Err(CommandError::ProofOfReserves(
+                reserves::ReservesError::MessageMismatch
+            ))
+        );
+
+        // We can select the coins, but a proof for an unconfirmed coin won't be valid.
+        let unknown_op = OutPoint::new(Txid::all_zeros(), 0);
+        assert_eq!(
+            control.create_proof_of_reserves(message, &[unknown_op]),
+            Err(CommandError::UnknownOutpoint(unknown_op))
+        );
+        let psbt = control
+            .create_proof_of_reserves(message, &[coins[2].outpoint])
+            .unwrap()
+            .psbt;
+        assert_eq!(psbt.unsigned_tx.input[1].previous_output, coins[2].outpoint);
+        let psbt = signer.sign_psbt(psbt, &secp).unwrap();
+        assert_eq!(
+            control.verify_proof_of_reserves(message, &psbt),
+            Err(CommandError::ProofOfReserves(
+                reserves::ReservesError::UnknownCoin(coins[2].outpoint)
+            ))
+        );
+
+        ms.shutdown();
+    }
+
     #[test]
     fn list_confirmed_transactions() {
         let outpoint = OutPoint::new(
@@ -1382,6 +3246,7 @@ mod tests {
                 derivation_index: ChildNumber::from(0),
                 amount: bitcoin::Amount::from_sat(100_000_000),
                 spend_txid: Some(spend_tx.txid()),
+                previous_descriptor_id: None,
             },
             // Deposit 2
             Coin {
@@ -1396,6 +3261,7 @@ mod tests {
                 derivation_index: ChildNumber::from(1),
                 amount: bitcoin::Amount::from_sat(2000),
                 spend_txid: None,
+                previous_descriptor_id: None,
             },
             // This coin is a change output.
             Coin {
@@ -1407,6 +3273,7 @@ mod tests {
                 derivation_index: ChildNumber::from(2),
                 amount: bitcoin::Amount::from_sat(100_000_000 - 4000 - 1000),
                 spend_txid: None,
+                previous_descriptor_id: None,
             },
             // Deposit 3
             Coin {
@@ -1421,68 +3288,18 @@ mod tests {
                 derivation_index: ChildNumber::from(3),
                 amount: bitcoin::Amount::from_sat(3000),
                 spend_txid: None,
+                previous_descriptor_id: None,
             },
         ]);
 
-        let mut btc = DummyBitcoind::new();
-        btc.txs.insert(
-            deposit1.txid(),
-            (
-                deposit1.clone(),
-                Some(Block {
-                    hash: bitcoin::BlockHash::from_str(
-                        "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
-                    )
-                    .unwrap(),
-                    time: 1,
-                    height: 1,
-                }),
-            ),
-        );
-        btc.txs.insert(
-            deposit2.txid(),
-            (
-                deposit2.clone(),
-                Some(Block {
-                    hash: bitcoin::BlockHash::from_str(
-                        "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
-                    )
-                    .unwrap(),
-                    time: 2,
-                    height: 2,
-                }),
-            ),
-        );
-        btc.txs.insert(
-            spend_tx.txid(),
-            (
-                spend_tx.clone(),
-                Some(Block {
-                    hash: bitcoin::BlockHash::from_str(
-                        "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
-                    )
-                    .unwrap(),
-                    time: 3,
-                    height: 3,
-                }),
-            ),
-        );
-        btc.txs.insert(
-            deposit3.txid(),
-            (
-                deposit3.clone(),
-                Some(Block {
-                    hash: bitcoin::BlockHash::from_str(
-                        "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
-                    )
-                    .unwrap(),
-                    time: 4,
-                    height: 4,
-                }),
-            ),
-        );
+        db.insert_txs(vec![
+            deposit1.clone(),
+            deposit2.clone(),
+            spend_tx.clone(),
+            deposit3.clone(),
+        ]);
 
-        let ms = DummyLiana::new(btc, db);
+        let ms = DummyLiana::new(DummyBitcoind::new(), db);
 
         let control = &ms.handle.control;
 
@@ -1570,51 +3387,10 @@ mod tests {
             }],
         };
 
-        let mut btc = DummyBitcoind::new();
-        btc.txs.insert(
-            tx1.txid(),
-            (
-                tx1.clone(),
-                Some(Block {
-                    hash: bitcoin::BlockHash::from_str(
-                        "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
-                    )
-                    .unwrap(),
-                    time: 1,
-                    height: 1,
-                }),
-            ),
-        );
-        btc.txs.insert(
-            tx2.txid(),
-            (
-                tx2.clone(),
-                Some(Block {
-                    hash: bitcoin::BlockHash::from_str(
-                        "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
-                    )
-                    .unwrap(),
-                    time: 2,
-                    height: 2,
-                }),
-            ),
-        );
-        btc.txs.insert(
-            tx3.txid(),
-            (
-                tx3.clone(),
-                Some(Block {
-                    hash: bitcoin::BlockHash::from_str(
-                        "0000000000000000000326b8fca8d3f820647c97ea33ef722096b3c7b2c8ee94",
-                    )
-                    .unwrap(),
-                    time: 4,
-                    height: 4,
-                }),
-            ),
-        );
+        let mut db = DummyDatabase::new();
+        db.insert_txs(vec![tx1.clone(), tx2.clone(), tx3.clone()]);
 
-        let ms = DummyLiana::new(btc, DummyDatabase::new());
+        let ms = DummyLiana::new(DummyBitcoind::new(), db);
 
         let control = &ms.handle.control;
 
@@ -1638,4 +3414,193 @@ mod tests {
 
         ms.shutdown();
     }
+
+    #[test]
+    fn list_history() {
+        let dummy_txin = |previous_output, sequence| TxIn {
+            witness: Witness::new(),
+            previous_output,
+            script_sig: ScriptBuf::new(),
+            sequence,
+        };
+        let dummy_txout = |value| TxOut {
+            script_pubkey: ScriptBuf::new(),
+            value,
+        };
+        let dummy_tx = |input, output| Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input,
+            output,
+        };
+        let external_op = OutPoint::new(
+            Txid::from_str("617eab1fc0b03ee7f82ba70166725291783461f1a0e7975eaf8b5f8f674234f3")
+                .unwrap(),
+            0,
+        );
+
+        // Two deposits, a payment with change, a self-send of the change and a recovery of the
+        // second deposit.
+        let deposit_a = dummy_tx(
+            vec![dummy_txin(external_op, Sequence::MAX)],
+            vec![dummy_txout(100_000_000)],
+        );
+        let deposit_b = dummy_tx(
+            vec![dummy_txin(external_op, Sequence::MAX)],
+            vec![dummy_txout(50_000), dummy_txout(2_000_000)],
+        );
+        let payment = dummy_tx(
+            vec![dummy_txin(
+                OutPoint::new(deposit_a.txid(), 0),
+                Sequence::ENABLE_RBF_NO_LOCKTIME,
+            )],
+            vec![dummy_txout(4_000), dummy_txout(99_995_000)],
+        );
+        let self_send = dummy_tx(
+            vec![dummy_txin(
+                OutPoint::new(payment.txid(), 1),
+                Sequence::ENABLE_RBF_NO_LOCKTIME,
+            )],
+            vec![dummy_txout(99_994_000)],
+        );
+        let recovery = dummy_tx(
+            vec![dummy_txin(
+                OutPoint::new(deposit_b.txid(), 1),
+                Sequence::from_height(10_000),
+            )],
+            vec![dummy_txout(1_999_000)],
+        );
+
+        let coin = |outpoint,
+                    amount,
+                    is_change,
+                    block_time: Option<u32>,
+                    spend: Option<(Txid, Option<u32>)>| Coin {
+            outpoint,
+            is_immature: false,
+            block_info: block_time.map(|time| BlockInfo {
+                height: time as i32,
+                time,
+            }),
+            amount: bitcoin::Amount::from_sat(amount),
+            derivation_index: ChildNumber::from(0),
+            is_change,
+            spend_txid: spend.map(|(txid, _)| txid),
+            spend_block: spend.and_then(|(_, time)| {
+                time.map(|time| BlockInfo {
+                    height: time as i32,
+                    time,
+                })
+            }),
+            previous_descriptor_id: None,
+        };
+        let mut db = DummyDatabase::new();
+        db.insert_coins(vec![
+            coin(
+                OutPoint::new(deposit_a.txid(), 0),
+                100_000_000,
+                false,
+                Some(1),
+                Some((payment.txid(), Some(3))),
+            ),
+            coin(
+                OutPoint::new(deposit_b.txid(), 1),
+                2_000_000,
+                false,
+                Some(2),
+                Some((recovery.txid(), Some(4))),
+            ),
+            coin(
+                OutPoint::new(payment.txid(), 1),
+                99_995_000,
+                true,
+                Some(3),
+                Some((self_send.txid(), None)),
+            ),
+            coin(
+                OutPoint::new(self_send.txid(), 0),
+                99_994_000,
+                true,
+                None,
+                None,
+            ),
+        ]);
+        // We didn't store the self-send yet, but the Bitcoin backend has it. Neither know about the
+        // first deposit.
+        db.insert_txs(vec![deposit_b.clone(), payment.clone(), recovery.clone()]);
+        let mut bitcoind = DummyBitcoind::new();
+        bitcoind
+            .txs
+            .insert(self_send.txid(), (self_send.clone(), None));
+
+        let ms = DummyLiana::new(bitcoind, db);
+        let control = &ms.handle.control;
+        control.update_labels(
+            &vec![(LabelItem::from(payment.txid()), "Rent".to_string())]
+                .into_iter()
+                .collect(),
+        );
+
+        // Unconfirmed transactions come first, then the most recent ones.
+        let history = control.list_history(10, 0).transactions;
+        assert_eq!(
+            history.iter().map(|e| e.txid).collect::<Vec<_>>(),
+            vec![
+                self_send.txid(),
+                recovery.txid(),
+                payment.txid(),
+                deposit_b.txid(),
+                deposit_a.txid()
+            ]
+        );
+
+        let (self_send_entry, recovery_entry, payment_entry, deposit_b_entry, deposit_a_entry) = (
+            &history[0],
+            &history[1],
+            &history[2],
+            &history[3],
+            &history[4],
+        );
+        assert_eq!(self_send_entry.direction, TxDirection::SelfSend);
+        assert_eq!(self_send_entry.amount.to_sat(), -1_000);
+        assert_eq!(self_send_entry.fee, Some(bitcoin::Amount::from_sat(1_000)));
+        assert_eq!(
+            self_send_entry.feerate,
+            Some(1_000.0 / self_send.vsize() as f64)
+        );
+        assert!(self_send_entry.height.is_none() && self_send_entry.time.is_none());
+
+        assert_eq!(recovery_entry.direction, TxDirection::Recovery);
+        assert_eq!(recovery_entry.amount.to_sat(), -2_000_000);
+        assert_eq!(recovery_entry.fee, Some(bitcoin::Amount::from_sat(1_000)));
+        assert_eq!(recovery_entry.height, Some(4));
+
+        assert_eq!(payment_entry.direction, TxDirection::Outgoing);
+        assert_eq!(payment_entry.amount.to_sat(), -5_000);
+        assert_eq!(payment_entry.fee, Some(bitcoin::Amount::from_sat(1_000)));
+        assert_eq!(
+            payment_entry.labels.get(&payment.txid().to_string()),
+            Some(&"Rent".to_string())
+        );
+
+        // We don't know the value of the input of a deposit, so we can't tell its fee.
+        assert_eq!(deposit_b_entry.direction, TxDirection::Incoming);
+        assert_eq!(deposit_b_entry.amount.to_sat(), 2_000_000);
+        assert!(deposit_b_entry.fee.is_none() && deposit_b_entry.feerate.is_none());
+        assert_eq!(deposit_b_entry.time, Some(2));
+
+        // A transaction we couldn't get is still part of the history.
+        assert_eq!(deposit_a_entry.direction, TxDirection::Incoming);
+        assert!(deposit_a_entry.fee.is_none() && deposit_a_entry.feerate.is_none());
+
+        // We can paginate through the history.
+        let history = control.list_history(2, 1).transactions;
+        assert_eq!(
+            history.iter().map(|e| e.txid).collect::<Vec<_>>(),
+            vec![recovery.txid(), payment.txid()]
+        );
+        assert!(control.list_history(2, 5).transactions.is_empty());
+
+        ms.shutdown();
+    }
 }
@@ -2,7 +2,7 @@ use crate::descriptors::LianaDescriptor;
 
 use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
 
-use miniscript::bitcoin::Network;
+use miniscript::bitcoin::{self, bip32, Network};
 
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
@@ -70,6 +70,21 @@ pub struct BitcoinConfig {
     pub poll_interval_secs: Duration,
 }
 
+/// Restrictions on the spends a hot signer stored in the data directory may sign without
+/// human interaction (see the `signspend` command).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SignerPolicy {
+    /// The master key fingerprint of the hot signer this policy applies to
+    pub fingerprint: bip32::Fingerprint,
+    /// The maximum amount, in satoshis, this signer may send to external addresses over the last
+    /// 24 hours
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_limit: Option<u64>,
+    /// If set, the only addresses this signer may send coins to (besides our own change)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_destinations: Option<Vec<bitcoin::Address<bitcoin::address::NetworkUnchecked>>>,
+}
+
 /// Static informations we require to operate
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
@@ -96,6 +111,15 @@ pub struct Config {
     pub bitcoin_config: BitcoinConfig,
     /// Settings specific to bitcoind as the Bitcoin interface
     pub bitcoind_config: Option<BitcoindConfig>,
+    /// The spending policies of the hot signers allowed to sign automatically. A hot signer
+    /// without a policy is never used by the daemon.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signer_policies: Vec<SignerPolicy>,
+    /// A command implementing the external signer protocol (see `doc/API.md`), used to talk to the
+    /// signing devices. Given as
+    /// the program followed by its arguments, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signer_command: Option<Vec<String>>,
 }
 
 impl Config {
@@ -209,6 +233,19 @@ impl Config {
             )));
         }
 
+        // Check the network of the addresses the hot signers are allowed to send to
+        for policy in &self.signer_policies {
+            let destinations = policy.allowed_destinations.iter().flatten();
+            for addr in destinations {
+                if !addr.is_valid_for_network(self.bitcoin_config.network) {
+                    return Err(ConfigError::Unexpected(format!(
+                        "Allowed destination '{:?}' of signer '{}' is not for network {}",
+                        addr, policy.fingerprint, self.bitcoin_config.network
+                    )));
+                }
+            }
+        }
+
         // TODO: check the semantics of the main descriptor
 
         Ok(())
@@ -259,6 +296,57 @@ mod tests {
         #[cfg(unix)] // On non-UNIX there is no 'daemon' member.
         assert_eq!(toml_str, serialized);
 
+        // A valid config with spending policies for hot signers and a signer command
+        let toml_str = r#"
+            log_level = "debug"
+            signer_command = ["python3", "/path/to/my signer.py"]
+            main_descriptor = "wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs"
+
+            [bitcoin_config]
+            network = "bitcoin"
+
+            [[signer_policies]]
+            fingerprint = "aabbccdd"
+            daily_limit = 1000000
+            allowed_destinations = ["bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"]
+
+            [[signer_policies]]
+            fingerprint = "11223344"
+            "#.trim_start().replace("            ", "");
+        let config = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        assert_eq!(config.signer_policies.len(), 2);
+        assert_eq!(
+            config.signer_command,
+            Some(vec![
+                "python3".to_string(),
+                "/path/to/my signer.py".to_string()
+            ])
+        );
+        assert_eq!(config.signer_policies[0].daily_limit, Some(1_000_000));
+        assert_eq!(
+            config.signer_policies[0]
+                .allowed_destinations
+                .as_ref()
+                .map(|addrs| addrs.len()),
+            Some(1)
+        );
+        assert!(config.signer_policies[1].daily_limit.is_none());
+        assert!(config.signer_policies[1].allowed_destinations.is_none());
+
+        // The main descriptor may be given as a pair of receive and change descriptors with
+        // single-path keys.
+        let toml_str = r#"
+            main_descriptor = """
+            wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/0/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/0/*)))
+            wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/1/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/1/*)))
+            """
+
+            [bitcoin_config]
+            network = "bitcoin"
+            "#.trim_start().replace("            ", "");
+        let config = toml::from_str::<Config>(&toml_str).expect("Deserializing toml_str");
+        assert_eq!(config.main_descriptor.to_string(), "wsh(andor(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(10000),pk([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))#dw4ulnrs");
+
         // Invalid desc checksum
         let toml_str = r#"
             daemon = false
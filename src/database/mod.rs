@@ -7,9 +7,10 @@ pub mod sqlite;
 use crate::{
     bitcoin::BlockChainTip,
     database::sqlite::{
-        schema::{DbBlockInfo, DbCoin, DbTip},
+        schema::{DbBlockInfo, DbCoin, DbExpiredCoin, DbTip, DbWalletTransaction},
         SqliteConn, SqliteDb,
     },
+    descriptors::LianaDescriptor,
 };
 
 use std::{
@@ -69,6 +70,9 @@ pub trait DatabaseConnection {
         secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
     );
 
+    /// Get the timestamp at which the wallet was created, or was rescanned from.
+    fn timestamp(&mut self) -> u32;
+
     /// Get the timestamp at which to start rescaning from, if any.
     fn rescan_timestamp(&mut self) -> Option<u32>;
 
@@ -78,6 +82,12 @@ pub trait DatabaseConnection {
     /// Mark the rescan as complete.
     fn complete_rescan(&mut self);
 
+    /// Record the descriptor we are migrating the funds of the wallet to.
+    fn set_migration_descriptor(&mut self, descriptor: &LianaDescriptor);
+
+    /// Get the descriptors we migrated away from, by identifier.
+    fn previous_descriptors(&mut self) -> HashMap<i64, LianaDescriptor>;
+
     /// Get the derivation index for this address, as well as whether this address is change.
     fn derivation_index_by_address(
         &mut self,
@@ -91,14 +101,28 @@ pub trait DatabaseConnection {
         outpoints: &[bitcoin::OutPoint],
     ) -> HashMap<bitcoin::OutPoint, Coin>;
 
+    /// Get coins by status and/or outpoint, further filtered, ordered and paginated according to
+    /// the given query.
+    fn query_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        query: &CoinsQuery,
+    ) -> Vec<Coin>;
+
     /// List coins that are being spent and whose spending transaction is still unconfirmed.
     fn list_spending_coins(&mut self) -> HashMap<bitcoin::OutPoint, Coin>;
 
     /// Store new UTxOs. Coins must not already be in database.
     fn new_unspent_coins(&mut self, coins: &[Coin]);
 
-    /// Remove some UTxOs from the database.
-    fn remove_coins(&mut self, coins: &[bitcoin::OutPoint]);
+    /// Remove some unconfirmed UTxOs from the database whose creating transaction disappeared,
+    /// along with the txid of the transaction it conflicted with, if known. A record of these
+    /// coins is kept.
+    fn expire_coins(&mut self, coins: &[(bitcoin::OutPoint, Option<bitcoin::Txid>)]);
+
+    /// Get all the coins that expired, most recently expired first.
+    fn list_expired_coins(&mut self) -> Vec<ExpiredCoin>;
 
     /// Mark a set of coins as being confirmed at a specified height and block time.
     /// NOTE: if the coin comes from an immature coinbase transaction, this will mark it as mature.
@@ -123,21 +147,70 @@ pub trait DatabaseConnection {
     /// Insert a new Spend transaction or replace an existing one.
     fn store_spend(&mut self, psbt: &Psbt);
 
-    /// List all existing Spend transactions, along with an optional last update timestamp.
-    fn list_spend(&mut self) -> Vec<(Psbt, Option<u32>)>;
+    /// List all existing Spend transactions, along with an optional last update timestamp and
+    /// what happened to them since they were created.
+    fn list_spend(&mut self) -> Vec<(Psbt, Option<u32>, SpendTxStatus)>;
+
+    /// Record that a Spend transaction was just broadcast.
+    fn mark_spend_broadcast(&mut self, txid: &bitcoin::Txid);
+
+    /// Update the status of a set of Spend transactions. A broadcast time already set is kept.
+    fn update_spend_status(&mut self, statuses: &[(bitcoin::Txid, SpendTxStatus)]);
 
     /// Delete a Spend transaction from database.
     fn delete_spend(&mut self, txid: &bitcoin::Txid);
 
+    /// Record that a Spend transaction sending this amount to external addresses was signed by
+    /// one of our hot signers.
+    fn record_signed_spend(
+        &mut self,
+        fingerprint: bip32::Fingerprint,
+        txid: &bitcoin::Txid,
+        amount: bitcoin::Amount,
+    );
+
+    /// The total amount sent to external addresses by the Spend transactions signed by this hot
+    /// signer since the given timestamp, not counting the given transaction.
+    fn signed_spends_amount(
+        &mut self,
+        fingerprint: bip32::Fingerprint,
+        since: u32,
+        exclude_txid: &bitcoin::Txid,
+    ) -> bitcoin::Amount;
+
     fn update_labels(&mut self, items: &HashMap<LabelItem, String>);
 
     fn labels(&mut self, labels: &HashSet<LabelItem>) -> HashMap<String, String>;
 
+    /// Get all the labels of the wallet.
+    fn all_labels(&mut self) -> HashMap<String, String>;
+
     /// Mark the given tip as the new best seen block. Update stored data accordingly.
     fn rollback_tip(&mut self, new_tip: &BlockChainTip);
 
     /// Retrieve a limited list of txids that where deposited or spent between the start and end timestamps (inclusive bounds)
     fn list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid>;
+
+    /// Retrieve a page of the txids of all the transactions that created or spent one of our
+    /// coins. Unconfirmed transactions come first, then the most recently confirmed.
+    fn list_history_txids(&mut self, limit: u64, offset: u64) -> Vec<bitcoin::Txid>;
+
+    /// Get all the coins created or spent by any of the given transactions.
+    fn coins_by_txids(&mut self, txids: &[bitcoin::Txid]) -> HashMap<bitcoin::OutPoint, Coin>;
+
+    /// Store transactions that created or spent our coins. Transactions already stored are
+    /// ignored.
+    fn new_txs(&mut self, txs: &[bitcoin::Transaction]);
+
+    /// Get the stored transactions with the given txids, along with their confirmation info.
+    fn list_wallet_transactions(
+        &mut self,
+        txids: &[bitcoin::Txid],
+    ) -> HashMap<bitcoin::Txid, WalletTransaction>;
+
+    /// Get the txids of the transactions that created or spent one of our coins but aren't
+    /// stored.
+    fn missing_txids(&mut self) -> Vec<bitcoin::Txid>;
 }
 
 impl DatabaseConnection for SqliteConn {
@@ -184,6 +257,10 @@ impl DatabaseConnection for SqliteConn {
         self.set_derivation_index(index, true, secp)
     }
 
+    fn timestamp(&mut self) -> u32 {
+        self.db_wallet().timestamp
+    }
+
     fn rescan_timestamp(&mut self) -> Option<u32> {
         self.db_wallet().rescan_timestamp
     }
@@ -196,6 +273,17 @@ impl DatabaseConnection for SqliteConn {
         self.complete_wallet_rescan()
     }
 
+    fn set_migration_descriptor(&mut self, descriptor: &LianaDescriptor) {
+        self.set_migration_descriptor(descriptor)
+    }
+
+    fn previous_descriptors(&mut self) -> HashMap<i64, LianaDescriptor> {
+        self.db_previous_descriptors()
+            .into_iter()
+            .map(|db_desc| (db_desc.id, db_desc.descriptor))
+            .collect()
+    }
+
     fn coins(
         &mut self,
         statuses: &[CoinStatus],
@@ -207,6 +295,18 @@ impl DatabaseConnection for SqliteConn {
             .collect()
     }
 
+    fn query_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        query: &CoinsQuery,
+    ) -> Vec<Coin> {
+        self.query_coins(statuses, outpoints, query)
+            .into_iter()
+            .map(Coin::from)
+            .collect()
+    }
+
     fn list_spending_coins(&mut self) -> HashMap<bitcoin::OutPoint, Coin> {
         self.list_spending_coins()
             .into_iter()
@@ -218,8 +318,15 @@ impl DatabaseConnection for SqliteConn {
         self.new_unspent_coins(coins)
     }
 
-    fn remove_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
-        self.remove_coins(outpoints)
+    fn expire_coins(&mut self, coins: &[(bitcoin::OutPoint, Option<bitcoin::Txid>)]) {
+        self.expire_coins(coins)
+    }
+
+    fn list_expired_coins(&mut self) -> Vec<ExpiredCoin> {
+        self.db_expired_coins()
+            .into_iter()
+            .map(ExpiredCoin::from)
+            .collect()
     }
 
     fn confirm_coins<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, i32, u32)]) {
@@ -264,17 +371,51 @@ impl DatabaseConnection for SqliteConn {
         self.store_spend(psbt)
     }
 
-    fn list_spend(&mut self) -> Vec<(Psbt, Option<u32>)> {
+    fn list_spend(&mut self) -> Vec<(Psbt, Option<u32>, SpendTxStatus)> {
         self.list_spend()
             .into_iter()
-            .map(|db_spend| (db_spend.psbt, db_spend.updated_at))
+            .map(|db_spend| {
+                let status = SpendTxStatus {
+                    broadcast_at: db_spend.broadcast_at,
+                    in_mempool: db_spend.in_mempool,
+                    confirmation: db_spend.confirmation.map(BlockInfo::from),
+                    conflicting_txid: db_spend.conflicting_txid,
+                };
+                (db_spend.psbt, db_spend.updated_at, status)
+            })
             .collect()
     }
 
+    fn mark_spend_broadcast(&mut self, txid: &bitcoin::Txid) {
+        self.mark_spend_broadcast(txid)
+    }
+
+    fn update_spend_status(&mut self, statuses: &[(bitcoin::Txid, SpendTxStatus)]) {
+        self.update_spend_status(statuses)
+    }
+
     fn delete_spend(&mut self, txid: &bitcoin::Txid) {
         self.delete_spend(txid)
     }
 
+    fn record_signed_spend(
+        &mut self,
+        fingerprint: bip32::Fingerprint,
+        txid: &bitcoin::Txid,
+        amount: bitcoin::Amount,
+    ) {
+        self.record_signed_spend(fingerprint, txid, amount)
+    }
+
+    fn signed_spends_amount(
+        &mut self,
+        fingerprint: bip32::Fingerprint,
+        since: u32,
+        exclude_txid: &bitcoin::Txid,
+    ) -> bitcoin::Amount {
+        self.signed_spends_amount(fingerprint, since, exclude_txid)
+    }
+
     fn update_labels(&mut self, items: &HashMap<LabelItem, String>) {
         self.update_labels(items)
     }
@@ -284,6 +425,11 @@ impl DatabaseConnection for SqliteConn {
         HashMap::from_iter(labels.into_iter().map(|label| (label.item, label.value)))
     }
 
+    fn all_labels(&mut self) -> HashMap<String, String> {
+        let labels = self.db_all_labels();
+        HashMap::from_iter(labels.into_iter().map(|label| (label.item, label.value)))
+    }
+
     fn rollback_tip(&mut self, new_tip: &BlockChainTip) {
         self.rollback_tip(new_tip)
     }
@@ -291,6 +437,35 @@ impl DatabaseConnection for SqliteConn {
     fn list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid> {
         self.db_list_txids(start, end, limit)
     }
+
+    fn list_history_txids(&mut self, limit: u64, offset: u64) -> Vec<bitcoin::Txid> {
+        self.db_list_history_txids(limit, offset)
+    }
+
+    fn coins_by_txids(&mut self, txids: &[bitcoin::Txid]) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.db_coins_by_txids(txids)
+            .into_iter()
+            .map(|db_coin| (db_coin.outpoint, db_coin.into()))
+            .collect()
+    }
+
+    fn new_txs(&mut self, txs: &[bitcoin::Transaction]) {
+        self.new_txs(txs)
+    }
+
+    fn list_wallet_transactions(
+        &mut self,
+        txids: &[bitcoin::Txid],
+    ) -> HashMap<bitcoin::Txid, WalletTransaction> {
+        self.list_wallet_transactions(txids)
+            .into_iter()
+            .map(|db_tx| (db_tx.transaction.txid(), db_tx.into()))
+            .collect()
+    }
+
+    fn missing_txids(&mut self) -> Vec<bitcoin::Txid> {
+        self.db_missing_txids()
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -318,6 +493,9 @@ pub struct Coin {
     pub is_change: bool,
     pub spend_txid: Option<bitcoin::Txid>,
     pub spend_block: Option<BlockInfo>,
+    /// Set if this coin was received on a descriptor we since migrated away from. See
+    /// [`DatabaseConnection::previous_descriptors`].
+    pub previous_descriptor_id: Option<i64>,
 }
 
 impl std::convert::From<DbCoin> for Coin {
@@ -331,6 +509,7 @@ impl std::convert::From<DbCoin> for Coin {
             is_change,
             spend_txid,
             spend_block,
+            previous_descriptor_id,
             ..
         } = db_coin;
         Coin {
@@ -342,6 +521,7 @@ impl std::convert::From<DbCoin> for Coin {
             is_change,
             spend_txid,
             spend_block: spend_block.map(BlockInfo::from),
+            previous_descriptor_id,
         }
     }
 }
@@ -356,6 +536,75 @@ impl Coin {
     }
 }
 
+/// An unconfirmed coin which disappeared, for instance because its creating transaction was
+/// replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExpiredCoin {
+    pub outpoint: bitcoin::OutPoint,
+    pub amount: bitcoin::Amount,
+    pub derivation_index: bip32::ChildNumber,
+    pub is_change: bool,
+    /// When we noticed the coin disappeared.
+    pub expired_at: u32,
+    /// The transaction which conflicted with the one that created this coin, if known.
+    pub conflicting_txid: Option<bitcoin::Txid>,
+    /// Set if this coin was received on a descriptor we since migrated away from.
+    pub previous_descriptor_id: Option<i64>,
+}
+
+impl From<DbExpiredCoin> for ExpiredCoin {
+    fn from(db_coin: DbExpiredCoin) -> ExpiredCoin {
+        let DbExpiredCoin {
+            outpoint,
+            amount,
+            derivation_index,
+            is_change,
+            expired_at,
+            conflicting_txid,
+            previous_descriptor_id,
+            ..
+        } = db_coin;
+        ExpiredCoin {
+            outpoint,
+            amount,
+            derivation_index,
+            is_change,
+            expired_at,
+            conflicting_txid,
+            previous_descriptor_id,
+        }
+    }
+}
+
+/// A transaction that created or spent one of our coins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletTransaction {
+    pub transaction: bitcoin::Transaction,
+    pub block_info: Option<BlockInfo>,
+}
+
+impl From<DbWalletTransaction> for WalletTransaction {
+    fn from(db_tx: DbWalletTransaction) -> WalletTransaction {
+        WalletTransaction {
+            transaction: db_tx.transaction,
+            block_info: db_tx.block_info.map(BlockInfo::from),
+        }
+    }
+}
+
+/// What happened to a Spend transaction since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpendTxStatus {
+    /// When the transaction was broadcast, or first seen as such.
+    pub broadcast_at: Option<u32>,
+    /// Whether the transaction was in the mempool at the last poll.
+    pub in_mempool: bool,
+    /// The block in which the transaction was confirmed, if any.
+    pub confirmation: Option<BlockInfo>,
+    /// Another transaction spending some of the same coins, which was seen in its stead.
+    pub conflicting_txid: Option<bitcoin::Txid>,
+}
+
 /// Possible (mutually exclusive) status of a coin.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CoinStatus {
@@ -381,6 +630,52 @@ impl CoinStatus {
     }
 }
 
+/// The coin field by which to order a list of coins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoinsOrderBy {
+    Amount,
+    BlockHeight,
+    DerivationIndex,
+}
+
+impl CoinsOrderBy {
+    pub fn from_arg(s: &str) -> Option<CoinsOrderBy> {
+        match s {
+            "amount" => Some(CoinsOrderBy::Amount),
+            "block_height" => Some(CoinsOrderBy::BlockHeight),
+            "derivation_index" => Some(CoinsOrderBy::DerivationIndex),
+            _ => None,
+        }
+    }
+}
+
+/// Criteria to filter, order and paginate a list of coins on top of their status and outpoint.
+///
+/// All the filters are cumulative. The default query does not filter anything out and returns the
+/// coins in the order they were first seen.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoinsQuery {
+    /// Only return coins worth at least this amount.
+    pub min_amount: Option<bitcoin::Amount>,
+    /// Only return coins worth at most this amount.
+    pub max_amount: Option<bitcoin::Amount>,
+    /// Only return change (or only non-change) coins.
+    pub is_change: Option<bool>,
+    /// Only return coins whose outpoint, txid or address has a label containing this string
+    /// (case-insensitive).
+    pub label: Option<String>,
+    /// Only return coins confirmed at this height or above.
+    pub min_height: Option<i32>,
+    /// Only return coins confirmed at this height or below.
+    pub max_height: Option<i32>,
+    /// Order the coins by this field, in descending order if the boolean is set.
+    pub order_by: Option<(CoinsOrderBy, bool)>,
+    /// The maximum number of coins to return.
+    pub limit: Option<u64>,
+    /// The number of coins to skip before starting to return them.
+    pub offset: u64,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum LabelItem {
     Address(bitcoin::Address),
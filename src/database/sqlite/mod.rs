@@ -15,15 +15,15 @@ use crate::{
     database::{
         sqlite::{
             schema::{
-                DbAddress, DbCoin, DbLabel, DbLabelledKind, DbSpendTransaction, DbTip, DbWallet,
-                SCHEMA,
+                DbAddress, DbCoin, DbExpiredCoin, DbLabel, DbLabelledKind, DbPreviousDescriptor,
+                DbSpendTransaction, DbTip, DbWallet, DbWalletTransaction, SCHEMA,
             },
             utils::{
                 create_fresh_db, curr_timestamp, db_exec, db_query, db_tx_query, db_version,
                 maybe_apply_migration, LOOK_AHEAD_LIMIT,
             },
         },
-        Coin, CoinStatus, LabelItem,
+        Coin, CoinStatus, CoinsOrderBy, CoinsQuery, LabelItem, SpendTxStatus,
     },
     descriptors::LianaDescriptor,
 };
@@ -31,8 +31,9 @@ use crate::{
 use std::{
     cmp,
     collections::{HashMap, HashSet},
-    convert::TryInto,
+    convert::{TryFrom, TryInto},
     fmt, io, path,
+    str::FromStr,
 };
 
 use miniscript::bitcoin::{
@@ -43,7 +44,7 @@ use miniscript::bitcoin::{
     secp256k1,
 };
 
-const DB_VERSION: i64 = 3;
+const DB_VERSION: i64 = 8;
 
 #[derive(Debug)]
 pub enum SqliteDbError {
@@ -52,6 +53,7 @@ pub enum SqliteDbError {
     UnsupportedVersion(i64),
     InvalidNetwork(bitcoin::Network),
     DescriptorMismatch(Box<LianaDescriptor>),
+    UnsweptCoins(Vec<bitcoin::OutPoint>),
     Rusqlite(rusqlite::Error),
 }
 
@@ -73,6 +75,15 @@ impl std::fmt::Display for SqliteDbError {
             SqliteDbError::DescriptorMismatch(desc) => {
                 write!(f, "Database descriptor mismatch: '{}'.", desc)
             }
+            SqliteDbError::UnsweptCoins(outpoints) => write!(
+                f,
+                "Cannot switch to the new descriptor, some coins of the current one were not swept: '{}'.",
+                outpoints
+                    .iter()
+                    .map(|op| op.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             SqliteDbError::Rusqlite(e) => write!(f, "SQLite error: '{}'", e),
         }
     }
@@ -110,6 +121,26 @@ impl fmt::Display for FrontwardHexTxid {
     }
 }
 
+// The condition for a coin to be at one of the given outpoints.
+// SELECT * FROM coins WHERE (txid, vout) IN ((txidA, voutA), (txidB, voutB));
+fn outpoints_condition(outpoints: &[bitcoin::OutPoint]) -> String {
+    let mut cond = "(txid, vout) IN (VALUES ".to_string();
+    for (i, outpoint) in outpoints.iter().enumerate() {
+        // NOTE: SQLite doesn't know Satoshi decided txids would be displayed as little-endian
+        // hex.
+        cond += &format!(
+            "(x'{}', {})",
+            FrontwardHexTxid(outpoint.txid),
+            outpoint.vout
+        );
+        if i != outpoints.len() - 1 {
+            cond += ", ";
+        }
+    }
+    cond += ")";
+    cond
+}
+
 #[derive(Debug, Clone)]
 pub struct FreshDbOptions {
     pub(self) bitcoind_network: bitcoin::Network,
@@ -197,6 +228,39 @@ impl SqliteDb {
 
         Ok(())
     }
+
+    /// Switch to the given main descriptor if it's the one we are migrating the funds to.
+    ///
+    /// All the coins of the current main descriptor must have been swept. They are kept, along
+    /// with their labels, and linked to the current main descriptor which gets recorded as a
+    /// previous descriptor. Returns whether the switch was performed.
+    pub fn maybe_switch_descriptor(
+        &self,
+        main_descriptor: &LianaDescriptor,
+        secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    ) -> Result<bool, SqliteDbError> {
+        let mut conn = self.connection()?;
+
+        let db_wallet = conn.db_wallet();
+        if &db_wallet.main_descriptor == main_descriptor
+            || db_wallet.migration_descriptor.as_ref() != Some(main_descriptor)
+        {
+            return Ok(false);
+        }
+
+        let unswept_coins: Vec<bitcoin::OutPoint> = conn
+            .coins(&[CoinStatus::Unconfirmed, CoinStatus::Confirmed], &[])
+            .into_iter()
+            .filter(|c| c.previous_descriptor_id.is_none())
+            .map(|c| c.outpoint)
+            .collect();
+        if !unswept_coins.is_empty() {
+            return Err(SqliteDbError::UnsweptCoins(unswept_coins));
+        }
+
+        conn.switch_descriptor(secp);
+        Ok(true)
+    }
 }
 
 // We only support single wallet. The id of the wallet row is always 1.
@@ -320,6 +384,99 @@ impl SqliteConn {
         .expect("Database must be available")
     }
 
+    /// Record the descriptor we are migrating the funds of the wallet to. The start time of the
+    /// migration is only updated if the descriptor changes.
+    pub fn set_migration_descriptor(&mut self, descriptor: &LianaDescriptor) {
+        let timestamp = curr_timestamp();
+        db_exec(&mut self.conn, |db_tx| {
+            db_tx
+                .execute(
+                    "UPDATE wallets SET \
+                     migration_timestamp = CASE WHEN migration_descriptor IS ?1 THEN migration_timestamp ELSE ?2 END, \
+                     migration_descriptor = ?1",
+                    rusqlite::params![descriptor.to_string(), timestamp],
+                )
+                .map(|_| ())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Switch the main descriptor of the wallet to the one we are migrating to.
+    ///
+    /// The current main descriptor is recorded as a previous descriptor, along with all the coins
+    /// that were received on it. The derivation indexes are reset and a rescan is set up from
+    /// the start of the migration, in order to pick up the coins received on the new descriptor
+    /// since then.
+    pub fn switch_descriptor(&mut self, secp: &secp256k1::Secp256k1<secp256k1::VerifyOnly>) {
+        let network = self.db_tip().network;
+        let replaced_at = curr_timestamp();
+
+        db_exec(&mut self.conn, |db_tx| {
+            let db_wallet: DbWallet =
+                db_tx_query(db_tx, "SELECT * FROM wallets", rusqlite::params![], |row| {
+                    row.try_into()
+                })?
+                .pop()
+                .expect("There is always a row in the wallet table");
+            let new_descriptor = db_wallet
+                .migration_descriptor
+                .expect("Must only be called when migrating");
+            let migration_timestamp = db_wallet
+                .migration_timestamp
+                .expect("Always set along with the migration descriptor");
+            let rescan_timestamp = db_wallet
+                .rescan_timestamp
+                .map(|t| cmp::min(t, migration_timestamp))
+                .unwrap_or(migration_timestamp);
+
+            db_tx.execute(
+                "INSERT INTO previous_descriptors (wallet_id, descriptor, replaced_at) VALUES (?1, ?2, ?3)",
+                rusqlite::params![db_wallet.id, db_wallet.main_descriptor.to_string(), replaced_at],
+            )?;
+            let previous_descriptor_id = db_tx.last_insert_rowid();
+            db_tx.execute(
+                "UPDATE coins SET previous_descriptor_id = ?1 WHERE previous_descriptor_id IS NULL",
+                rusqlite::params![previous_descriptor_id],
+            )?;
+            db_tx.execute(
+                "UPDATE expired_coins SET previous_descriptor_id = ?1 WHERE previous_descriptor_id IS NULL",
+                rusqlite::params![previous_descriptor_id],
+            )?;
+            db_tx.execute(
+                "UPDATE wallets SET main_descriptor = ?1, deposit_derivation_index = 0, change_derivation_index = 0, \
+                 rescan_timestamp = ?2, migration_descriptor = NULL, migration_timestamp = NULL",
+                rusqlite::params![new_descriptor.to_string(), rescan_timestamp],
+            )?;
+
+            // Replace the address->deriv_index mapping by the one of the new descriptor.
+            db_tx.execute("DELETE FROM addresses", rusqlite::params![])?;
+            let receive_desc = new_descriptor.receive_descriptor();
+            let change_desc = new_descriptor.change_descriptor();
+            for index in 0..LOOK_AHEAD_LIMIT {
+                let receive_addr = receive_desc.derive(index.into(), secp).address(network);
+                let change_addr = change_desc.derive(index.into(), secp).address(network);
+                db_tx.execute(
+                    "INSERT INTO addresses (receive_address, change_address, derivation_index) VALUES (?1, ?2, ?3)",
+                    rusqlite::params![receive_addr.to_string(), change_addr.to_string(), index],
+                )?;
+            }
+
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Get the descriptors this wallet used before migrating to its current main descriptor.
+    pub fn db_previous_descriptors(&mut self) -> Vec<DbPreviousDescriptor> {
+        db_query(
+            &mut self.conn,
+            "SELECT * FROM previous_descriptors",
+            rusqlite::params![],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
     pub fn set_wallet_rescan_timestamp(&mut self, timestamp: u32) {
         db_exec(&mut self.conn, |db_tx| {
             // NOTE: this will need to be updated if we ever implement multi-wallet support
@@ -363,6 +520,19 @@ impl SqliteConn {
         statuses: &[CoinStatus],
         outpoints: &[bitcoin::OutPoint],
     ) -> Vec<DbCoin> {
+        self.query_coins(statuses, outpoints, &CoinsQuery::default())
+    }
+
+    /// Get the coins from DB, optionally filtered by coin status and/or outpoint, and further
+    /// filtered, ordered and paginated according to the given query.
+    pub fn query_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        query: &CoinsQuery,
+    ) -> Vec<DbCoin> {
+        let mut conditions = Vec::new();
+
         let status_condition = statuses
             .iter()
             .map(|c| {
@@ -384,40 +554,144 @@ impl SqliteConn {
             })
             .collect::<Vec<String>>()
             .join(" OR ");
-        // SELECT * FROM coins WHERE (txid, vout) IN ((txidA, voutA), (txidB, voutB));
-        let op_condition = if !outpoints.is_empty() {
-            let mut cond = "(txid, vout) IN (VALUES ".to_string();
-            for (i, outpoint) in outpoints.iter().enumerate() {
-                // NOTE: SQLite doesn't know Satoshi decided txids would be displayed as little-endian
-                // hex.
-                cond += &format!(
-                    "(x'{}', {})",
-                    FrontwardHexTxid(outpoint.txid),
-                    outpoint.vout
-                );
-                if i != outpoints.len() - 1 {
-                    cond += ", ";
-                }
-            }
-            cond += ")";
-            cond
-        } else {
+        if !status_condition.is_empty() {
+            conditions.push(status_condition);
+        }
+
+        if !outpoints.is_empty() {
+            conditions.push(outpoints_condition(outpoints));
+        }
+
+        if let Some(min_amount) = query.min_amount {
+            conditions.push(format!("amount_sat >= {}", min_amount.to_sat()));
+        }
+        if let Some(max_amount) = query.max_amount {
+            conditions.push(format!("amount_sat <= {}", max_amount.to_sat()));
+        }
+        if let Some(is_change) = query.is_change {
+            conditions.push(format!("is_change = {}", is_change as i64));
+        }
+        // NOTE: unconfirmed coins have a NULL block height, which never compares true.
+        if let Some(min_height) = query.min_height {
+            conditions.push(format!("blockheight >= {}", min_height));
+        }
+        if let Some(max_height) = query.max_height {
+            conditions.push(format!("blockheight <= {}", max_height));
+        }
+
+        // The label value is user-provided, so bind it as a parameter instead of formatting it
+        // into the query.
+        let mut params: Vec<&dyn rusqlite::ToSql> = Vec::new();
+        if let Some(label) = query.label.as_ref() {
+            params.push(label);
+            conditions.push(self.label_condition(label));
+        }
+
+        let where_clause = if conditions.is_empty() {
             String::new()
-        };
-        let where_clause = if !status_condition.is_empty() && !op_condition.is_empty() {
-            format!(" WHERE ({}) AND ({})", status_condition, op_condition)
-        } else if status_condition.is_empty() && !op_condition.is_empty() {
-            format!(" WHERE {}", op_condition)
-        } else if !status_condition.is_empty() && op_condition.is_empty() {
-            format!(" WHERE {}", status_condition)
         } else {
+            format!(
+                " WHERE {}",
+                conditions
+                    .iter()
+                    .map(|c| format!("({})", c))
+                    .collect::<Vec<String>>()
+                    .join(" AND ")
+            )
+        };
+        let order_clause = match query.order_by {
+            Some((order_by, descending)) => format!(
+                " ORDER BY {} {}, id",
+                match order_by {
+                    CoinsOrderBy::Amount => "amount_sat",
+                    CoinsOrderBy::BlockHeight => "blockheight",
+                    CoinsOrderBy::DerivationIndex => "derivation_index",
+                },
+                if descending { "DESC" } else { "ASC" }
+            ),
+            None => " ORDER BY id".to_string(),
+        };
+        // SQLite only accepts an OFFSET clause along with a LIMIT one. A negative limit means no
+        // limit. SQLite integers are signed 64 bits, larger values are saturated.
+        let limit = query
+            .limit
+            .map(|l| i64::try_from(l).unwrap_or(i64::MAX))
+            .unwrap_or(-1);
+        let offset = i64::try_from(query.offset).unwrap_or(i64::MAX);
+        let limit_clause = if query.limit.is_none() && query.offset == 0 {
             String::new()
+        } else {
+            params.push(&limit);
+            params.push(&offset);
+            format!(" LIMIT ?{} OFFSET ?{}", params.len() - 1, params.len())
         };
-        let query = format!("SELECT * FROM coins{}", where_clause);
-        db_query(&mut self.conn, &query, rusqlite::params![], |row| {
-            row.try_into()
-        })
-        .expect("Db must not fail")
+
+        let query = format!(
+            "SELECT * FROM coins{}{}{}",
+            where_clause, order_clause, limit_clause
+        );
+        db_query(&mut self.conn, &query, &params[..], |row| row.try_into())
+            .expect("Db must not fail")
+    }
+
+    // The condition for a coin to have a label (on its outpoint, its txid or its address)
+    // containing the first parameter of the query.
+    // Addresses are stored as strings in both the labels and addresses tables, so we can look up
+    // address labels directly. Txids and outpoints are stored in the labels table using their
+    // string representation (with the txid in reverse byte order) but as raw bytes in the coins
+    // table. Therefore we first query the matching txid and outpoint labels and filter on the
+    // parsed items.
+    fn label_condition(&mut self, label: &str) -> String {
+        let items = db_query(
+            &mut self.conn,
+            "SELECT item, item_kind FROM labels \
+             WHERE item_kind IN (?2, ?3) AND instr(lower(value), lower(?1)) > 0",
+            rusqlite::params![
+                label,
+                DbLabelledKind::OutPoint as i64,
+                DbLabelledKind::Txid as i64
+            ],
+            |row| {
+                let item: String = row.get(0)?;
+                let kind: i64 = row.get(1)?;
+                Ok((item, DbLabelledKind::from(kind)))
+            },
+        )
+        .expect("Db must not fail");
+        let mut labelled_outpoints = Vec::new();
+        let mut labelled_txids = Vec::new();
+        for (item, kind) in items {
+            match kind {
+                DbLabelledKind::OutPoint => labelled_outpoints.push(
+                    bitcoin::OutPoint::from_str(&item).expect("We only store valid outpoints"),
+                ),
+                DbLabelledKind::Txid => labelled_txids
+                    .push(bitcoin::Txid::from_str(&item).expect("We only store valid txids")),
+                DbLabelledKind::Address => unreachable!("Not queried"),
+            }
+        }
+
+        let mut condition = format!(
+            "EXISTS (SELECT 1 FROM labels INNER JOIN addresses ON labels.item = \
+             CASE WHEN coins.is_change THEN addresses.change_address ELSE addresses.receive_address END \
+             WHERE addresses.derivation_index = coins.derivation_index \
+             AND labels.item_kind = {} AND instr(lower(labels.value), lower(?1)) > 0)",
+            DbLabelledKind::Address as i64
+        );
+        if !labelled_outpoints.is_empty() {
+            condition += &format!(" OR {}", outpoints_condition(&labelled_outpoints));
+        }
+        if !labelled_txids.is_empty() {
+            condition += &format!(
+                " OR txid IN ({})",
+                labelled_txids
+                    .iter()
+                    .map(|txid| format!("x'{}'", FrontwardHexTxid(*txid)))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            );
+        }
+        condition
     }
 
     /// List coins that are being spent and whose spending transaction is still unconfirmed.
@@ -451,10 +725,23 @@ impl SqliteConn {
         .expect("Database must be available")
     }
 
-    /// Remove a set of coins from the database.
-    pub fn remove_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
+    /// Remove a set of coins from the database, keeping a record of their expiration along with
+    /// the txid of the transaction that conflicted with their creating transaction, if known.
+    pub fn expire_coins(&mut self, coins: &[(bitcoin::OutPoint, Option<bitcoin::Txid>)]) {
+        let expired_at = curr_timestamp();
         db_exec(&mut self.conn, |db_tx| {
-            for outpoint in outpoints {
+            for (outpoint, conflicting_txid) in coins {
+                db_tx.execute(
+                    "INSERT INTO expired_coins (wallet_id, txid, vout, amount_sat, derivation_index, is_change, expired_at, conflicting_txid, previous_descriptor_id) \
+                     SELECT wallet_id, txid, vout, amount_sat, derivation_index, is_change, ?3, ?4, previous_descriptor_id \
+                     FROM coins WHERE txid = ?1 AND vout = ?2",
+                    rusqlite::params![
+                        outpoint.txid[..].to_vec(),
+                        outpoint.vout,
+                        expired_at,
+                        conflicting_txid.map(|txid| txid[..].to_vec()),
+                    ],
+                )?;
                 db_tx.execute(
                     "DELETE FROM coins WHERE txid = ?1 AND vout = ?2",
                     rusqlite::params![outpoint.txid[..].to_vec(), outpoint.vout,],
@@ -466,6 +753,17 @@ impl SqliteConn {
         .expect("Database must be available")
     }
 
+    /// Get all the coins that expired, most recently expired first.
+    pub fn db_expired_coins(&mut self) -> Vec<DbExpiredCoin> {
+        db_query(
+            &mut self.conn,
+            "SELECT * FROM expired_coins ORDER BY expired_at DESC, id DESC",
+            rusqlite::params![],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
     /// Mark a set of coins as confirmed.
     ///
     /// NOTE: this will also mark the coin as mature if it originates from an immature coinbase
@@ -585,6 +883,45 @@ impl SqliteConn {
         .expect("Db must not fail")
     }
 
+    /// Record that this Spend transaction was just broadcast.
+    pub fn mark_spend_broadcast(&mut self, txid: &bitcoin::Txid) {
+        db_exec(&mut self.conn, |db_tx| {
+            db_tx.execute(
+                "UPDATE spend_transactions SET broadcast_at = ?1, in_mempool = 1 WHERE txid = ?2",
+                rusqlite::params![curr_timestamp(), txid[..].to_vec()],
+            )?;
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
+    /// Update the status of a set of Spend transactions. A broadcast time that was already set
+    /// is never overwritten.
+    pub fn update_spend_status<'a>(
+        &mut self,
+        statuses: impl IntoIterator<Item = &'a (bitcoin::Txid, SpendTxStatus)>,
+    ) {
+        db_exec(&mut self.conn, |db_tx| {
+            for (txid, status) in statuses {
+                db_tx.execute(
+                    "UPDATE spend_transactions SET broadcast_at = COALESCE(broadcast_at, ?1), \
+                     in_mempool = ?2, confirmation_height = ?3, confirmation_time = ?4, \
+                     conflicting_txid = ?5 WHERE txid = ?6",
+                    rusqlite::params![
+                        status.broadcast_at,
+                        status.in_mempool,
+                        status.confirmation.map(|b| b.height),
+                        status.confirmation.map(|b| b.time),
+                        status.conflicting_txid.map(|txid| txid[..].to_vec()),
+                        txid[..].to_vec(),
+                    ],
+                )?;
+            }
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
     pub fn update_labels(&mut self, items: &HashMap<LabelItem, String>) {
         db_exec(&mut self.conn, |db_tx| {
             for (labelled, kind, value) in items
@@ -622,6 +959,16 @@ impl SqliteConn {
         .expect("Db must not fail")
     }
 
+    pub fn db_all_labels(&mut self) -> Vec<DbLabel> {
+        db_query(
+            &mut self.conn,
+            "SELECT * FROM labels",
+            rusqlite::params![],
+            |row| row.try_into(),
+        )
+        .expect("Db must not fail")
+    }
+
     /// Retrieves a limited and ordered list of transactions ids that happened during the given
     /// range.
     pub fn db_list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid> {
@@ -654,6 +1001,123 @@ impl SqliteConn {
         .expect("Db must not fail")
     }
 
+    /// Retrieves a page of the txids of all the transactions that created or spent one of our
+    /// coins. Unconfirmed transactions are ordered first, then by decreasing block time.
+    pub fn db_list_history_txids(&mut self, limit: u64, offset: u64) -> Vec<bitcoin::Txid> {
+        // SQLite integers are signed 64 bits, larger values are saturated.
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let offset = i64::try_from(offset).unwrap_or(i64::MAX);
+        // Break ties between transactions confirmed in the same block (or unconfirmed) by
+        // ordering the most recently stored coins first.
+        db_query(
+            &mut self.conn,
+            "SELECT txid, MAX(date) AS date, MAX(id) AS id FROM ( \
+                SELECT txid, blocktime AS date, id FROM coins \
+                UNION ALL \
+                SELECT spend_txid AS txid, spend_block_time AS date, id FROM coins \
+                WHERE spend_txid IS NOT NULL \
+            ) \
+            GROUP BY txid \
+            ORDER BY date IS NULL DESC, date DESC, id DESC \
+            LIMIT (?1) OFFSET (?2)",
+            rusqlite::params![limit, offset],
+            |row| {
+                let txid: Vec<u8> = row.get(0)?;
+                let txid: bitcoin::Txid =
+                    encode::deserialize(&txid).expect("We only store valid txids");
+                Ok(txid)
+            },
+        )
+        .expect("Db must not fail")
+    }
+
+    /// Get all the coins that were either created or spent by any of these transactions.
+    pub fn db_coins_by_txids(&mut self, txids: &[bitcoin::Txid]) -> Vec<DbCoin> {
+        if txids.is_empty() {
+            return Vec::new();
+        }
+        let txids = txids
+            .iter()
+            .map(|txid| format!("x'{}'", FrontwardHexTxid(*txid)))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let query = format!(
+            "SELECT * FROM coins WHERE txid IN ({0}) OR spend_txid IN ({0})",
+            txids
+        );
+        db_query(&mut self.conn, &query, rusqlite::params![], |row| {
+            row.try_into()
+        })
+        .expect("Db must not fail")
+    }
+
+    /// Store transactions in database, ignoring those that were already stored.
+    pub fn new_txs<'a>(&mut self, txs: impl IntoIterator<Item = &'a bitcoin::Transaction>) {
+        db_exec(&mut self.conn, |db_tx| {
+            for tx in txs {
+                db_tx.execute(
+                    "INSERT INTO transactions (txid, tx) VALUES (?1, ?2) ON CONFLICT DO NOTHING",
+                    rusqlite::params![tx.txid()[..].to_vec(), encode::serialize(tx)],
+                )?;
+            }
+            Ok(())
+        })
+        .expect("Database must be available")
+    }
+
+    /// Get the stored transactions with these txids, along with their confirmation information.
+    /// Transactions that aren't stored are ignored.
+    pub fn list_wallet_transactions(
+        &mut self,
+        txids: &[bitcoin::Txid],
+    ) -> Vec<DbWalletTransaction> {
+        if txids.is_empty() {
+            return Vec::new();
+        }
+        let txids = txids
+            .iter()
+            .map(|txid| format!("x'{}'", FrontwardHexTxid(*txid)))
+            .collect::<Vec<String>>()
+            .join(", ");
+        // A transaction is confirmed in the same block as the coins it created, or as the spend
+        // of the coins it spent.
+        let query = format!(
+            "SELECT t.tx, \
+             COALESCE( \
+                (SELECT blockheight FROM coins WHERE txid = t.txid AND blockheight IS NOT NULL), \
+                (SELECT spend_block_height FROM coins WHERE spend_txid = t.txid AND spend_block_height IS NOT NULL) \
+             ), \
+             COALESCE( \
+                (SELECT blocktime FROM coins WHERE txid = t.txid AND blocktime IS NOT NULL), \
+                (SELECT spend_block_time FROM coins WHERE spend_txid = t.txid AND spend_block_time IS NOT NULL) \
+             ) \
+             FROM transactions AS t WHERE t.txid IN ({})",
+            txids
+        );
+        db_query(&mut self.conn, &query, rusqlite::params![], |row| {
+            row.try_into()
+        })
+        .expect("Db must not fail")
+    }
+
+    /// Get the txids of the transactions that created or spent one of our coins and that aren't
+    /// stored in the transactions table.
+    pub fn db_missing_txids(&mut self) -> Vec<bitcoin::Txid> {
+        db_query(
+            &mut self.conn,
+            "SELECT txid FROM coins WHERE txid NOT IN (SELECT txid FROM transactions) \
+             UNION \
+             SELECT spend_txid FROM coins WHERE spend_txid IS NOT NULL \
+             AND spend_txid NOT IN (SELECT txid FROM transactions)",
+            rusqlite::params![],
+            |row| {
+                let txid: Vec<u8> = row.get(0)?;
+                Ok(encode::deserialize(&txid).expect("We only store valid txids"))
+            },
+        )
+        .expect("Db must not fail")
+    }
+
     pub fn delete_spend(&mut self, txid: &bitcoin::Txid) {
         db_exec(&mut self.conn, |db_tx| {
             db_tx.execute(
@@ -665,6 +1129,51 @@ impl SqliteConn {
         .expect("Db must not fail");
     }
 
+    /// Record that a Spend transaction sending this amount to external addresses was signed by
+    /// the signer with this fingerprint. Signing the same transaction again is only recorded once.
+    pub fn record_signed_spend(
+        &mut self,
+        fingerprint: bip32::Fingerprint,
+        txid: &bitcoin::Txid,
+        amount: bitcoin::Amount,
+    ) {
+        db_exec(&mut self.conn, |db_tx| {
+            db_tx.execute(
+                "INSERT OR IGNORE INTO signed_spends (fingerprint, txid, amount_sat, signed_at)                  VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![
+                    fingerprint[..].to_vec(),
+                    txid[..].to_vec(),
+                    amount.to_sat(),
+                    curr_timestamp()
+                ],
+            )?;
+            Ok(())
+        })
+        .expect("Db must not fail");
+    }
+
+    /// The total amount sent to external addresses by the Spend transactions signed by the signer
+    /// with this fingerprint since the given timestamp, excluding the given transaction.
+    pub fn signed_spends_amount(
+        &mut self,
+        fingerprint: bip32::Fingerprint,
+        since: u32,
+        exclude_txid: &bitcoin::Txid,
+    ) -> bitcoin::Amount {
+        let amounts: Vec<u64> = db_query(
+            &mut self.conn,
+            "SELECT amount_sat FROM signed_spends              WHERE fingerprint = ?1 AND signed_at >= ?2 AND txid != ?3",
+            rusqlite::params![
+                fingerprint[..].to_vec(),
+                since,
+                exclude_txid[..].to_vec()
+            ],
+            |row| row.get(0),
+        )
+        .expect("Db must not fail");
+        bitcoin::Amount::from_sat(amounts.into_iter().sum())
+    }
+
     // TODO: mark coinbase deposits that were mature and became immature as such.
     /// Unconfirm all data that was marked as being confirmed *after* the given chain
     /// tip, and set it as our new best block seen.
@@ -880,6 +1389,56 @@ CREATE TABLE spend_transactions (
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn db_signed_spends() {
+        let (tmp_dir, _, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+            let fg_a = bip32::Fingerprint::from_str("abcdef01").unwrap();
+            let fg_b = bip32::Fingerprint::from_str("abcdef02").unwrap();
+            let txid_a = bitcoin::Txid::from_str(
+                "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7",
+            )
+            .unwrap();
+            let txid_b = bitcoin::Txid::from_str(
+                "5f8e1d7cd1c7ba4ca4bea4a32ff0e5e7ca2a8b6e45a0bfcc6c8d0c6bfd2e8a39",
+            )
+            .unwrap();
+            let now = curr_timestamp();
+            assert_eq!(
+                conn.signed_spends_amount(fg_a, 0, &txid_b),
+                bitcoin::Amount::ZERO
+            );
+
+            conn.record_signed_spend(fg_a, &txid_a, bitcoin::Amount::from_sat(10_000));
+            conn.record_signed_spend(fg_a, &txid_b, bitcoin::Amount::from_sat(20_000));
+            conn.record_signed_spend(fg_b, &txid_a, bitcoin::Amount::from_sat(10_000));
+            // Signing again the same transaction is not counted twice.
+            conn.record_signed_spend(fg_a, &txid_a, bitcoin::Amount::from_sat(10_000));
+
+            let unknown_txid = bitcoin::Txid::all_zeros();
+            assert_eq!(
+                conn.signed_spends_amount(fg_a, now, &unknown_txid),
+                bitcoin::Amount::from_sat(30_000)
+            );
+            assert_eq!(
+                conn.signed_spends_amount(fg_a, now, &txid_b),
+                bitcoin::Amount::from_sat(10_000)
+            );
+            assert_eq!(
+                conn.signed_spends_amount(fg_b, now, &unknown_txid),
+                bitcoin::Amount::from_sat(10_000)
+            );
+            assert_eq!(
+                conn.signed_spends_amount(fg_a, now + 10, &unknown_txid),
+                bitcoin::Amount::ZERO
+            );
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn db_labels_update() {
         let (tmp_dir, _, _, db) = dummy_db();
@@ -907,6 +1466,10 @@ CREATE TABLE spend_transactions (
 
             let db_labels = conn.db_labels(&items);
             assert_eq!(db_labels[0].value, "hello again");
+
+            let all_labels = conn.db_all_labels();
+            assert_eq!(all_labels.len(), 1);
+            assert_eq!(all_labels[0].value, "hello again");
         }
 
         fs::remove_dir_all(tmp_dir).unwrap();
@@ -936,6 +1499,7 @@ CREATE TABLE spend_transactions (
                 is_change: false,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             };
             conn.new_unspent_coins(&[coin_a]);
             // We can query by status and/or outpoint.
@@ -985,6 +1549,7 @@ CREATE TABLE spend_transactions (
                 is_change: true,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             };
             conn.new_unspent_coins(&[coin_b]);
             // Both coins are unconfirmed.
@@ -1122,6 +1687,7 @@ CREATE TABLE spend_transactions (
                 is_change: false,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             };
             let outpoint_d = bitcoin::OutPoint::from_str(
                 "61db3e276b095e5b05f1849dd6bfffb4e7e5ec1c4a4210099b98fce01571937a:43",
@@ -1136,6 +1702,7 @@ CREATE TABLE spend_transactions (
                 is_change: false,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             };
             conn.new_unspent_coins(&[coin_c, coin_d]);
 
@@ -1221,6 +1788,210 @@ CREATE TABLE spend_transactions (
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn db_coins_query() {
+        let (tmp_dir, options, secp, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+
+            // Create a few coins with different amounts, derivation indexes and confirmation
+            // heights.
+            let coins: Vec<Coin> = (0..5u8)
+                .map(|i| Coin {
+                    outpoint: bitcoin::OutPoint {
+                        txid: bitcoin::Txid::from_slice(&[i; 32][..]).unwrap(),
+                        vout: i.into(),
+                    },
+                    is_immature: false,
+                    block_info: None,
+                    amount: bitcoin::Amount::from_sat(10_000 * (5 - i as u64)),
+                    derivation_index: bip32::ChildNumber::from_normal_idx(i.into()).unwrap(),
+                    is_change: i % 2 == 1,
+                    spend_txid: None,
+                    spend_block: None,
+                    previous_descriptor_id: None,
+                })
+                .collect();
+            conn.new_unspent_coins(&coins);
+            conn.confirm_coins(&[
+                (coins[0].outpoint, 100, 1_000),
+                (coins[1].outpoint, 300, 3_000),
+                (coins[2].outpoint, 200, 2_000),
+            ]);
+            let outpoints = |res: Vec<DbCoin>| -> Vec<bitcoin::OutPoint> {
+                res.into_iter().map(|c| c.outpoint).collect()
+            };
+
+            // By default coins are returned in the order they were stored.
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &CoinsQuery::default())),
+                coins.iter().map(|c| c.outpoint).collect::<Vec<_>>()
+            );
+
+            // We can paginate through them.
+            let query = CoinsQuery {
+                limit: Some(2),
+                offset: 1,
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[1].outpoint, coins[2].outpoint]
+            );
+            let query = CoinsQuery {
+                offset: 3,
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[3].outpoint, coins[4].outpoint]
+            );
+            // Values out of SQLite's integer range don't make the query fail.
+            let query = CoinsQuery {
+                limit: Some(u64::MAX),
+                offset: 4,
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[4].outpoint]
+            );
+            let query = CoinsQuery {
+                offset: u64::MAX,
+                ..CoinsQuery::default()
+            };
+            assert!(conn.query_coins(&[], &[], &query).is_empty());
+
+            // We can order them by amount, height or derivation index.
+            let query = CoinsQuery {
+                order_by: Some((CoinsOrderBy::Amount, false)),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                coins.iter().rev().map(|c| c.outpoint).collect::<Vec<_>>()
+            );
+            let query = CoinsQuery {
+                order_by: Some((CoinsOrderBy::BlockHeight, true)),
+                limit: Some(3),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[1].outpoint, coins[2].outpoint, coins[0].outpoint]
+            );
+            let query = CoinsQuery {
+                order_by: Some((CoinsOrderBy::DerivationIndex, true)),
+                limit: Some(1),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[4].outpoint]
+            );
+
+            // We can filter them by amount range, change and height range. Unconfirmed coins are
+            // excluded when filtering by height.
+            let query = CoinsQuery {
+                min_amount: Some(bitcoin::Amount::from_sat(20_000)),
+                max_amount: Some(bitcoin::Amount::from_sat(40_000)),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[1].outpoint, coins[2].outpoint, coins[3].outpoint]
+            );
+            let query = CoinsQuery {
+                is_change: Some(true),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[1].outpoint, coins[3].outpoint]
+            );
+            let query = CoinsQuery {
+                min_height: Some(150),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[1].outpoint, coins[2].outpoint]
+            );
+            let query = CoinsQuery {
+                max_height: Some(250),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[CoinStatus::Confirmed], &[], &query)),
+                vec![coins[0].outpoint, coins[2].outpoint]
+            );
+
+            // The filters are cumulative with the status and outpoint ones.
+            let query = CoinsQuery {
+                is_change: Some(false),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(
+                    &[CoinStatus::Unconfirmed],
+                    &[coins[3].outpoint, coins[4].outpoint],
+                    &query
+                )),
+                vec![coins[4].outpoint]
+            );
+
+            // We can filter by a substring of the label of the coin's outpoint, txid or address.
+            let address = options
+                .main_descriptor
+                .change_descriptor()
+                .derive(coins[3].derivation_index, &secp)
+                .address(options.bitcoind_network);
+            let mut labels = HashMap::new();
+            labels.insert(LabelItem::OutPoint(coins[0].outpoint), "Salary".to_string());
+            labels.insert(
+                LabelItem::Txid(coins[2].outpoint.txid),
+                "salary".to_string(),
+            );
+            labels.insert(LabelItem::Address(address), "Old salary".to_string());
+            labels.insert(LabelItem::Txid(coins[4].outpoint.txid), "Rent".to_string());
+            conn.update_labels(&labels);
+            let query = CoinsQuery {
+                label: Some("SALARY".to_string()),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[0].outpoint, coins[2].outpoint, coins[3].outpoint]
+            );
+            let query = CoinsQuery {
+                label: Some("old".to_string()),
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[3].outpoint]
+            );
+            let query = CoinsQuery {
+                label: Some("salary".to_string()),
+                limit: Some(1),
+                offset: 1,
+                ..CoinsQuery::default()
+            };
+            assert_eq!(
+                outpoints(conn.query_coins(&[], &[], &query)),
+                vec![coins[2].outpoint]
+            );
+            let query = CoinsQuery {
+                label: Some("' OR 1=1 --".to_string()),
+                ..CoinsQuery::default()
+            };
+            assert!(conn.query_coins(&[], &[], &query).is_empty());
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn db_coins_update() {
         let (tmp_dir, _, _, db) = dummy_db();
@@ -1244,13 +2015,24 @@ CREATE TABLE spend_transactions (
                 is_change: false,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             };
             conn.new_unspent_coins(&[coin_a]);
             assert_eq!(conn.coins(&[], &[])[0].outpoint, coin_a.outpoint);
 
-            // We can also remove it. Say the unconfirmed tx that created it got replaced.
-            conn.remove_coins(&[coin_a.outpoint]);
+            // We can also remove it. Say the unconfirmed tx that created it got replaced. We keep
+            // a record of it.
+            assert!(conn.db_expired_coins().is_empty());
+            let conflicting_txid = bitcoin::Txid::from_slice(&[2; 32][..]).unwrap();
+            conn.expire_coins(&[(coin_a.outpoint, Some(conflicting_txid))]);
             assert!(conn.coins(&[], &[]).is_empty());
+            let expired_coins = conn.db_expired_coins();
+            assert_eq!(expired_coins.len(), 1);
+            assert_eq!(expired_coins[0].outpoint, coin_a.outpoint);
+            assert_eq!(expired_coins[0].amount, coin_a.amount);
+            assert_eq!(expired_coins[0].derivation_index, coin_a.derivation_index);
+            assert!(!expired_coins[0].is_change);
+            assert_eq!(expired_coins[0].conflicting_txid, Some(conflicting_txid));
 
             // Add it back for the rest of the test.
             conn.new_unspent_coins(&[coin_a]);
@@ -1289,6 +2071,7 @@ CREATE TABLE spend_transactions (
                 is_change: true,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             };
             conn.new_unspent_coins(&[coin_b]);
             let outpoints: HashSet<bitcoin::OutPoint> = conn
@@ -1407,6 +2190,7 @@ CREATE TABLE spend_transactions (
                 is_change: false, // Cannot be both a coinbase deposit and change.
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             };
             conn.new_unspent_coins(&[coin_imma]);
             let outpoints: HashSet<bitcoin::OutPoint> = conn
@@ -1572,6 +2356,7 @@ CREATE TABLE spend_transactions (
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    previous_descriptor_id: None,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1588,6 +2373,7 @@ CREATE TABLE spend_transactions (
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    previous_descriptor_id: None,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1612,6 +2398,7 @@ CREATE TABLE spend_transactions (
                         height: 101_199,
                         time: 1_231_678,
                     }),
+                    previous_descriptor_id: None,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1628,6 +2415,7 @@ CREATE TABLE spend_transactions (
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    previous_descriptor_id: None,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1652,6 +2440,7 @@ CREATE TABLE spend_transactions (
                         height: 101_105,
                         time: 1_201_678,
                     }),
+                    previous_descriptor_id: None,
                 },
             ];
             conn.new_unspent_coins(&coins);
@@ -1770,6 +2559,345 @@ CREATE TABLE spend_transactions (
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn db_descriptor_migration() {
+        let (tmp_dir, options, secp, db) = dummy_db();
+        let old_desc = options.main_descriptor;
+        let new_desc = LianaDescriptor::from_str("wsh(or_d(pk([aabbccdd]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),and_v(v:pkh([aabbccdd]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*),older(20000))))").unwrap();
+
+        {
+            let mut conn = db.connection().unwrap();
+
+            // A coin was received on the current descriptor, and labelled.
+            let outpoint = bitcoin::OutPoint::from_str(
+                "6f0dc85a369b44ca5a2c4bd8a4e3a5a4a0e3c4b0f6f5e6f1a2a6e3c4f8a3b1c2:1",
+            )
+            .unwrap();
+            conn.new_unspent_coins(&[Coin {
+                outpoint,
+                is_immature: false,
+                block_info: None,
+                amount: bitcoin::Amount::from_sat(98765),
+                derivation_index: bip32::ChildNumber::from(10),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                previous_descriptor_id: None,
+            }]);
+            let coin_label = LabelItem::OutPoint(outpoint);
+            conn.update_labels(
+                &[(coin_label.clone(), "savings".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            );
+            let old_addr = old_desc
+                .receive_descriptor()
+                .derive(0.into(), &secp)
+                .address(bitcoin::Network::Bitcoin);
+            assert!(conn.db_address(&old_addr).is_some());
+
+            // Switching to a descriptor we aren't migrating to is a no-op. The sanity checks will
+            // fail afterward.
+            assert!(!db.maybe_switch_descriptor(&new_desc, &secp).unwrap());
+            assert!(matches!(
+                db.sanity_check(bitcoin::Network::Bitcoin, &new_desc),
+                Err(SqliteDbError::DescriptorMismatch(..))
+            ));
+
+            // Once we record the migration, we can't switch until the coin is swept.
+            conn.set_migration_descriptor(&new_desc);
+            let db_wallet = conn.db_wallet();
+            assert_eq!(db_wallet.migration_descriptor.as_ref(), Some(&new_desc));
+            let migration_timestamp = db_wallet.migration_timestamp.unwrap();
+            assert!(matches!(
+                db.maybe_switch_descriptor(&new_desc, &secp),
+                Err(SqliteDbError::UnsweptCoins(ref ops)) if ops == &[outpoint]
+            ));
+
+            // Recording the same migration again doesn't change its start time.
+            conn.conn
+                .execute(
+                    "UPDATE wallets SET migration_timestamp = 42",
+                    rusqlite::params![],
+                )
+                .unwrap();
+            conn.set_migration_descriptor(&new_desc);
+            assert_eq!(conn.db_wallet().migration_timestamp, Some(42));
+            assert!(migration_timestamp > 42);
+
+            // Now sweep the coin and switch to the new descriptor.
+            let sweep_txid = bitcoin::Txid::from_str(
+                "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7",
+            )
+            .unwrap();
+            conn.spend_coins(&[(outpoint, sweep_txid)]);
+            assert!(db.maybe_switch_descriptor(&new_desc, &secp).unwrap());
+            db.sanity_check(bitcoin::Network::Bitcoin, &new_desc)
+                .unwrap();
+
+            // The wallet now uses the new descriptor, and will rescan since the start of the
+            // migration.
+            let db_wallet = conn.db_wallet();
+            assert_eq!(db_wallet.main_descriptor, new_desc);
+            assert!(db_wallet.migration_descriptor.is_none());
+            assert!(db_wallet.migration_timestamp.is_none());
+            assert_eq!(db_wallet.rescan_timestamp, Some(42));
+            assert_eq!(db_wallet.deposit_derivation_index, 0.into());
+            assert_eq!(db_wallet.change_derivation_index, 0.into());
+            assert!(conn.db_address(&old_addr).is_none());
+            let new_addr = new_desc
+                .change_descriptor()
+                .derive((LOOK_AHEAD_LIMIT - 1).into(), &secp)
+                .address(bitcoin::Network::Bitcoin);
+            assert!(conn.db_address(&new_addr).is_some());
+
+            // The coin and its label were kept, and linked to the former descriptor.
+            let prev_descs = conn.db_previous_descriptors();
+            assert_eq!(prev_descs.len(), 1);
+            assert_eq!(prev_descs[0].descriptor, old_desc);
+            let coin = conn.db_coins(&[outpoint]).pop().unwrap();
+            assert_eq!(coin.previous_descriptor_id, Some(prev_descs[0].id));
+            assert_eq!(coin.spend_txid, Some(sweep_txid));
+            let labels = conn.db_labels(&[coin_label].iter().cloned().collect());
+            assert_eq!(labels[0].value, "savings");
+
+            // We can't switch twice.
+            assert!(!db.maybe_switch_descriptor(&new_desc, &secp).unwrap());
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_list_history_txids() {
+        let (tmp_dir, _, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+            let txid = |i: u8| bitcoin::Txid::from_slice(&[i; 32][..]).unwrap();
+            assert!(conn.db_list_history_txids(10, 0).is_empty());
+
+            // Coin 1 was received by tx 1 at block 10 and spent by tx 4 at block 30. Coin 2 was
+            // received by tx 2 at block 20 and is being spent by the unconfirmed tx 5. Coin 3 was
+            // received by the unconfirmed tx 3, after coin 2.
+            let coins: Vec<Coin> = (1..4u8)
+                .map(|i| Coin {
+                    outpoint: bitcoin::OutPoint {
+                        txid: txid(i),
+                        vout: 0,
+                    },
+                    is_immature: false,
+                    block_info: None,
+                    amount: bitcoin::Amount::from_sat(10_000),
+                    derivation_index: bip32::ChildNumber::from_normal_idx(i.into()).unwrap(),
+                    is_change: false,
+                    spend_txid: None,
+                    spend_block: None,
+                    previous_descriptor_id: None,
+                })
+                .collect();
+            conn.new_unspent_coins(&coins);
+            conn.confirm_coins(&[
+                (coins[0].outpoint, 10, 1_000),
+                (coins[1].outpoint, 20, 2_000),
+            ]);
+            conn.spend_coins(&[(coins[0].outpoint, txid(4)), (coins[1].outpoint, txid(5))]);
+            conn.confirm_spend(&[(coins[0].outpoint, txid(4), 30, 3_000)]);
+
+            // Unconfirmed transactions come first, then the most recently confirmed.
+            assert_eq!(
+                conn.db_list_history_txids(10, 0),
+                vec![txid(3), txid(5), txid(4), txid(2), txid(1)]
+            );
+            assert_eq!(conn.db_list_history_txids(2, 2), vec![txid(4), txid(2)]);
+            assert!(conn.db_list_history_txids(10, 5).is_empty());
+
+            // Values out of SQLite's integer range are saturated.
+            assert_eq!(conn.db_list_history_txids(u64::MAX, 4), vec![txid(1)]);
+            assert!(conn.db_list_history_txids(10, u64::MAX).is_empty());
+
+            // We can get all the coins related to a set of transactions.
+            let related: HashSet<bitcoin::OutPoint> = conn
+                .db_coins_by_txids(&[txid(4), txid(2)])
+                .into_iter()
+                .map(|c| c.outpoint)
+                .collect();
+            assert_eq!(
+                related,
+                vec![coins[0].outpoint, coins[1].outpoint]
+                    .into_iter()
+                    .collect()
+            );
+            assert!(conn.db_coins_by_txids(&[]).is_empty());
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_wallet_transactions() {
+        let (tmp_dir, _, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+            let dummy_tx = |version| bitcoin::Transaction {
+                version,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: Vec::new(),
+                output: Vec::new(),
+            };
+            // A deposit, a transaction spending it and an unrelated transaction.
+            let (deposit, spend, other) = (dummy_tx(1), dummy_tx(2), dummy_tx(3));
+            assert!(conn
+                .list_wallet_transactions(&[deposit.txid(), spend.txid()])
+                .is_empty());
+
+            // Storing the same transaction twice is a no-op.
+            conn.new_txs(&[deposit.clone(), spend.clone()]);
+            conn.new_txs(std::slice::from_ref(&deposit));
+            let txs = conn.list_wallet_transactions(&[deposit.txid(), spend.txid(), other.txid()]);
+            assert_eq!(txs.len(), 2);
+            assert!(txs.iter().all(|tx| tx.block_info.is_none()));
+
+            // The confirmation info is the one of the coins the transactions created or spent.
+            let coin = Coin {
+                outpoint: bitcoin::OutPoint::new(deposit.txid(), 0),
+                is_immature: false,
+                block_info: None,
+                amount: bitcoin::Amount::from_sat(10_000),
+                derivation_index: bip32::ChildNumber::from_normal_idx(0).unwrap(),
+                is_change: false,
+                spend_txid: None,
+                spend_block: None,
+                previous_descriptor_id: None,
+            };
+            conn.new_unspent_coins(&[coin]);
+            conn.confirm_coins(&[(coin.outpoint, 10, 1_000)]);
+            conn.spend_coins(&[(coin.outpoint, spend.txid())]);
+            let txs = conn.list_wallet_transactions(&[deposit.txid(), spend.txid()]);
+            let deposit_info = txs.iter().find(|tx| tx.transaction == deposit).unwrap();
+            assert_eq!(
+                deposit_info.block_info,
+                Some(DbBlockInfo {
+                    height: 10,
+                    time: 1_000
+                })
+            );
+            let spend_info = txs.iter().find(|tx| tx.transaction == spend).unwrap();
+            assert!(spend_info.block_info.is_none());
+
+            conn.confirm_spend(&[(coin.outpoint, spend.txid(), 12, 1_200)]);
+            let txs = conn.list_wallet_transactions(&[spend.txid()]);
+            assert_eq!(
+                txs[0].block_info,
+                Some(DbBlockInfo {
+                    height: 12,
+                    time: 1_200
+                })
+            );
+
+            // The transactions of our coins that aren't stored are reported as missing.
+            assert!(conn.db_missing_txids().is_empty());
+            let other_coin = Coin {
+                outpoint: bitcoin::OutPoint::new(other.txid(), 0),
+                derivation_index: bip32::ChildNumber::from_normal_idx(1).unwrap(),
+                ..coin
+            };
+            let other_spend = dummy_tx(4);
+            conn.new_unspent_coins(&[other_coin]);
+            conn.spend_coins(&[(other_coin.outpoint, other_spend.txid())]);
+            let missing: HashSet<bitcoin::Txid> = conn.db_missing_txids().into_iter().collect();
+            assert_eq!(
+                missing,
+                vec![other.txid(), other_spend.txid()].into_iter().collect()
+            );
+            conn.new_txs(&[other]);
+            assert_eq!(conn.db_missing_txids(), vec![other_spend.txid()]);
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
+    #[test]
+    fn db_spend_status() {
+        let (tmp_dir, _, _, db) = dummy_db();
+
+        {
+            let mut conn = db.connection().unwrap();
+            let psbt = Psbt::from_unsigned_tx(bitcoin::Transaction {
+                version: 2,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: vec![bitcoin::TxIn::default()],
+                output: Vec::new(),
+            })
+            .unwrap();
+            let txid = psbt.unsigned_tx.txid();
+
+            // A freshly stored Spend has no status.
+            conn.store_spend(&psbt);
+            let db_spend = conn.db_spend(&txid).unwrap();
+            assert!(db_spend.broadcast_at.is_none());
+            assert!(!db_spend.in_mempool);
+            assert!(db_spend.confirmation.is_none());
+            assert!(db_spend.conflicting_txid.is_none());
+
+            // Once broadcast it's in the mempool.
+            conn.mark_spend_broadcast(&txid);
+            let db_spend = conn.db_spend(&txid).unwrap();
+            let broadcast_at = db_spend.broadcast_at.unwrap();
+            assert!(db_spend.in_mempool);
+
+            // Updating the status doesn't overwrite the broadcast time.
+            let conflicting_txid = bitcoin::Txid::from_slice(&[1; 32][..]).unwrap();
+            conn.update_spend_status(&[(
+                txid,
+                SpendTxStatus {
+                    broadcast_at: Some(broadcast_at + 10),
+                    in_mempool: false,
+                    confirmation: None,
+                    conflicting_txid: Some(conflicting_txid),
+                },
+            )]);
+            let db_spend = conn.db_spend(&txid).unwrap();
+            assert_eq!(db_spend.broadcast_at, Some(broadcast_at));
+            assert!(!db_spend.in_mempool);
+            assert_eq!(db_spend.conflicting_txid, Some(conflicting_txid));
+
+            // The conflicting transaction got reorged out and ours confirmed.
+            conn.update_spend_status(&[(
+                txid,
+                SpendTxStatus {
+                    broadcast_at: Some(broadcast_at),
+                    in_mempool: false,
+                    confirmation: Some(BlockInfo {
+                        height: 12,
+                        time: 1_200,
+                    }),
+                    conflicting_txid: None,
+                },
+            )]);
+            let db_spend = conn.db_spend(&txid).unwrap();
+            assert_eq!(
+                db_spend.confirmation,
+                Some(DbBlockInfo {
+                    height: 12,
+                    time: 1_200
+                })
+            );
+            assert!(db_spend.conflicting_txid.is_none());
+
+            // Updating the PSBT keeps the status.
+            conn.store_spend(&psbt);
+            assert_eq!(
+                conn.db_spend(&txid).unwrap().broadcast_at,
+                Some(broadcast_at)
+            );
+        }
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn sqlite_list_txids() {
         let (tmp_dir, _, _, db) = dummy_db();
@@ -1790,6 +2918,7 @@ CREATE TABLE spend_transactions (
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    previous_descriptor_id: None,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1806,6 +2935,7 @@ CREATE TABLE spend_transactions (
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    previous_descriptor_id: None,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1830,6 +2960,7 @@ CREATE TABLE spend_transactions (
                         height: 101_199,
                         time: 1_123_000,
                     }),
+                    previous_descriptor_id: None,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1846,6 +2977,7 @@ CREATE TABLE spend_transactions (
                     is_change: false,
                     spend_txid: None,
                     spend_block: None,
+                    previous_descriptor_id: None,
                 },
                 Coin {
                     outpoint: bitcoin::OutPoint::from_str(
@@ -1870,6 +3002,7 @@ CREATE TABLE spend_transactions (
                         height: 101_105,
                         time: 1_126_000,
                     }),
+                    previous_descriptor_id: None,
                 },
             ];
             conn.new_unspent_coins(&coins);
@@ -2003,25 +3136,30 @@ CREATE TABLE spend_transactions (
             .expect("Database must be available")
         }
 
-        // Store a couple coins before the migration.
+        // Store a couple coins before the migration. The transactions that created them will need
+        // to be fetched after migrating to the version storing the wallet transactions.
+        let tx_a = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        let tx_b = bitcoin::Transaction {
+            version: 1,
+            ..tx_a.clone()
+        };
         {
             let mut conn = rusqlite::Connection::open(&db_path).unwrap();
             store_coin_old(
                 &mut conn,
-                &bitcoin::OutPoint::from_str(
-                    "ed6c8f1af9325f84de521e785e7ddfd33dc28c9ada4d687dcd3850100bde54e9:5",
-                )
-                .unwrap(),
+                &bitcoin::OutPoint::new(tx_a.txid(), 5),
                 bitcoin::Amount::from_sat(14_000),
                 24.into(),
                 true,
             );
             store_coin_old(
                 &mut conn,
-                &bitcoin::OutPoint::from_str(
-                    "81b2f327d4c1fd67afd039374f8798fd9ff37932c6f5c221c1c569350eac5ac8:2",
-                )
-                .unwrap(),
+                &bitcoin::OutPoint::new(tx_b.txid(), 2),
                 bitcoin::Amount::from_sat(392_093_123),
                 24_567.into(),
                 false,
@@ -2033,6 +3171,16 @@ CREATE TABLE spend_transactions (
         maybe_apply_migration(&db_path).unwrap(); // Migrating twice will be a no-op.
         let db = SqliteDb::new(db_path, None, &secp).unwrap();
 
+        // The transactions of the existing coins are yet to be fetched.
+        {
+            let mut conn = db.connection().unwrap();
+            let mut missing = conn.db_missing_txids();
+            missing.sort();
+            let mut expected = vec![tx_a.txid(), tx_b.txid()];
+            expected.sort();
+            assert_eq!(missing, expected);
+        }
+
         // We should now be able to insert another PSBT, to query both, and the first PSBT must
         // have no associated timestamp.
         {
@@ -2067,6 +3215,7 @@ CREATE TABLE spend_transactions (
                 is_change: false,
                 spend_txid: None,
                 spend_block: None,
+                previous_descriptor_id: None,
             }]);
             let coins = conn.coins(&[], &[]);
             assert_eq!(coins.len(), 3);
@@ -2097,7 +3246,7 @@ CREATE TABLE spend_transactions (
         {
             let mut conn = db.connection().unwrap();
             let version = conn.db_version();
-            assert_eq!(version, 3);
+            assert_eq!(version, 8);
 
             let txid_str = "0c62a990d20d54429e70859292e82374ba6b1b951a3ab60f26bb65fee5724ff7";
             let txid = LabelItem::from_str(txid_str, bitcoin::Network::Bitcoin).unwrap();
@@ -25,6 +25,8 @@ CREATE TABLE tip (
  * information related to our descriptor(s) that occured after this date.
  * The optional 'rescan_timestamp' field is a the timestamp we need to rescan the chain
  * for events related to our descriptor(s) from.
+ * The optional 'migration_descriptor' field is the descriptor we are migrating the funds to, if
+ * any. The 'migration_timestamp' is the time at which the migration was started.
  */
 CREATE TABLE wallets (
     id INTEGER PRIMARY KEY NOT NULL,
@@ -32,7 +34,23 @@ CREATE TABLE wallets (
     main_descriptor TEXT NOT NULL,
     deposit_derivation_index INTEGER NOT NULL,
     change_derivation_index INTEGER NOT NULL,
-    rescan_timestamp INTEGER
+    rescan_timestamp INTEGER,
+    migration_descriptor TEXT,
+    migration_timestamp INTEGER
+);
+
+/* The descriptors this wallet used before migrating to its current main descriptor.
+ *
+ * The 'replaced_at' field is the time at which we switched away from this descriptor.
+ */
+CREATE TABLE previous_descriptors (
+    id INTEGER PRIMARY KEY NOT NULL,
+    wallet_id INTEGER NOT NULL,
+    descriptor TEXT UNIQUE NOT NULL,
+    replaced_at INTEGER NOT NULL,
+    FOREIGN KEY (wallet_id) REFERENCES wallets (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT
 );
 
 /* Our (U)TxOs.
@@ -43,6 +61,9 @@ CREATE TABLE wallets (
  * The 'is_immature' field is for coinbase deposits that are not yet buried under 100
  * blocks. Note coinbase deposits can't be change. They also technically can't be
  * unconfirmed but we keep them as such until they become mature.
+ *
+ * The 'previous_descriptor_id' field is only present for coins received on a descriptor we since
+ * migrated away from.
  */
 CREATE TABLE coins (
     id INTEGER PRIMARY KEY NOT NULL,
@@ -58,9 +79,13 @@ CREATE TABLE coins (
     spend_block_height INTEGER,
     spend_block_time INTEGER,
     is_immature BOOLEAN NOT NULL CHECK (is_immature IN (0,1)),
+    previous_descriptor_id INTEGER,
     CHECK (is_change IS 0 OR is_immature IS 0),
     UNIQUE (txid, vout),
     FOREIGN KEY (wallet_id) REFERENCES wallets (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT,
+    FOREIGN KEY (previous_descriptor_id) REFERENCES previous_descriptors (id)
         ON UPDATE RESTRICT
         ON DELETE RESTRICT
 );
@@ -74,12 +99,24 @@ CREATE TABLE addresses (
     derivation_index INTEGER NOT NULL UNIQUE
 );
 
-/* Transactions we created that spend some of our coins. */
+/* Transactions we created that spend some of our coins.
+ *
+ * The 'broadcast_at' field is the time at which the transaction was broadcast, or first seen as
+ * such. The 'in_mempool' field records whether it was in the mempool at the last poll.
+ * The 'confirmation_height' and 'confirmation_time' are only present once it was confirmed.
+ * The 'conflicting_txid' is only present if another transaction spending some of the same coins
+ * was seen in its stead.
+ */
 CREATE TABLE spend_transactions (
     id INTEGER PRIMARY KEY NOT NULL,
     psbt BLOB UNIQUE NOT NULL,
     txid BLOB UNIQUE NOT NULL,
-    updated_at INTEGER
+    updated_at INTEGER,
+    broadcast_at INTEGER,
+    in_mempool BOOLEAN NOT NULL DEFAULT 0 CHECK (in_mempool IN (0,1)),
+    confirmation_height INTEGER,
+    confirmation_time INTEGER,
+    conflicting_txid BLOB
 );
 
 /* Labels applied on addresses (0), outpoints (1), txids (2) */
@@ -90,6 +127,53 @@ CREATE TABLE labels (
     item TEXT UNIQUE NOT NULL,
     value TEXT NOT NULL
 );
+
+/* Unconfirmed coins which disappeared, for instance because the transaction that created them
+ * was replaced or conflicted. We keep them around for the user to be able to know about it.
+ *
+ * The 'expired_at' field is the time at which we noticed the coin disappeared. The
+ * 'conflicting_txid' is only present if we know of a transaction conflicting with the one that
+ * created the coin. The 'previous_descriptor_id' is set as for the 'coins' table.
+ */
+CREATE TABLE expired_coins (
+    id INTEGER PRIMARY KEY NOT NULL,
+    wallet_id INTEGER NOT NULL,
+    txid BLOB NOT NULL,
+    vout INTEGER NOT NULL,
+    amount_sat INTEGER NOT NULL,
+    derivation_index INTEGER NOT NULL,
+    is_change BOOLEAN NOT NULL CHECK (is_change IN (0,1)),
+    expired_at INTEGER NOT NULL,
+    conflicting_txid BLOB,
+    previous_descriptor_id INTEGER,
+    FOREIGN KEY (wallet_id) REFERENCES wallets (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT,
+    FOREIGN KEY (previous_descriptor_id) REFERENCES previous_descriptors (id)
+        ON UPDATE RESTRICT
+        ON DELETE RESTRICT
+);
+
+/* The transactions that created or spent our coins. */
+CREATE TABLE transactions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    txid BLOB UNIQUE NOT NULL,
+    tx BLOB UNIQUE NOT NULL
+);
+
+/* The Spend transactions signed by the daemon using one of its hot signers.
+ *
+ * The 'fingerprint' is the master fingerprint of the signer. The 'amount_sat' is the value sent
+ * to external addresses, used to enforce the spending limit of the signer.
+ */
+CREATE TABLE signed_spends (
+    id INTEGER PRIMARY KEY NOT NULL,
+    fingerprint BLOB NOT NULL,
+    txid BLOB NOT NULL,
+    amount_sat INTEGER NOT NULL,
+    signed_at INTEGER NOT NULL,
+    UNIQUE (fingerprint, txid)
+);
 ";
 
 /// A row in the "tip" table.
@@ -130,6 +214,8 @@ pub struct DbWallet {
     pub deposit_derivation_index: bip32::ChildNumber,
     pub change_derivation_index: bip32::ChildNumber,
     pub rescan_timestamp: Option<u32>,
+    pub migration_descriptor: Option<LianaDescriptor>,
+    pub migration_timestamp: Option<u32>,
 }
 
 impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
@@ -150,6 +236,13 @@ impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
 
         let rescan_timestamp = row.get(5)?;
 
+        let migration_desc_str: Option<String> = row.get(6)?;
+        let migration_descriptor = migration_desc_str.map(|s| {
+            LianaDescriptor::from_str(&s)
+                .expect("Insane database: can't parse migration descriptor")
+        });
+        let migration_timestamp = row.get(7)?;
+
         Ok(DbWallet {
             id,
             timestamp,
@@ -157,6 +250,39 @@ impl TryFrom<&rusqlite::Row<'_>> for DbWallet {
             deposit_derivation_index,
             change_derivation_index,
             rescan_timestamp,
+            migration_descriptor,
+            migration_timestamp,
+        })
+    }
+}
+
+/// A row in the "previous_descriptors" table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DbPreviousDescriptor {
+    pub id: i64,
+    pub wallet_id: i64,
+    pub descriptor: LianaDescriptor,
+    pub replaced_at: u32,
+}
+
+impl TryFrom<&rusqlite::Row<'_>> for DbPreviousDescriptor {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &rusqlite::Row) -> Result<Self, Self::Error> {
+        let id = row.get(0)?;
+        let wallet_id = row.get(1)?;
+
+        let desc_str: String = row.get(2)?;
+        let descriptor = LianaDescriptor::from_str(&desc_str)
+            .expect("Insane database: can't parse previous descriptor");
+
+        let replaced_at = row.get(3)?;
+
+        Ok(DbPreviousDescriptor {
+            id,
+            wallet_id,
+            descriptor,
+            replaced_at,
         })
     }
 }
@@ -180,6 +306,8 @@ pub struct DbCoin {
     pub is_change: bool,
     pub spend_txid: Option<bitcoin::Txid>,
     pub spend_block: Option<DbBlockInfo>,
+    /// Set if this coin was received on a descriptor we since migrated away from.
+    pub previous_descriptor_id: Option<i64>,
 }
 
 impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
@@ -223,6 +351,7 @@ impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
             !is_immature || !is_change,
             "A coin cannot be both created in a coinbase and be change"
         );
+        let previous_descriptor_id = row.get(13)?;
 
         Ok(DbCoin {
             id,
@@ -235,6 +364,7 @@ impl TryFrom<&rusqlite::Row<'_>> for DbCoin {
             is_change,
             spend_txid,
             spend_block,
+            previous_descriptor_id,
         })
     }
 }
@@ -277,6 +407,10 @@ pub struct DbSpendTransaction {
     pub psbt: Psbt,
     pub txid: bitcoin::Txid,
     pub updated_at: Option<u32>,
+    pub broadcast_at: Option<u32>,
+    pub in_mempool: bool,
+    pub confirmation: Option<DbBlockInfo>,
+    pub conflicting_txid: Option<bitcoin::Txid>,
 }
 
 impl TryFrom<&rusqlite::Row<'_>> for DbSpendTransaction {
@@ -293,12 +427,113 @@ impl TryFrom<&rusqlite::Row<'_>> for DbSpendTransaction {
         assert_eq!(txid, psbt.unsigned_tx.txid());
 
         let updated_at = row.get(3)?;
+        let broadcast_at = row.get(4)?;
+        let in_mempool = row.get(5)?;
+
+        let confirmation_height: Option<i32> = row.get(6)?;
+        let confirmation_time: Option<u32> = row.get(7)?;
+        assert_eq!(confirmation_height.is_none(), confirmation_time.is_none());
+        let confirmation = confirmation_height.map(|height| DbBlockInfo {
+            height,
+            time: confirmation_time.expect("Must be there if height is"),
+        });
+
+        let conflicting_txid: Option<Vec<u8>> = row.get(8)?;
+        let conflicting_txid = conflicting_txid
+            .map(|txid| encode::deserialize(&txid).expect("We only store valid txids"));
 
         Ok(DbSpendTransaction {
             id,
             psbt,
             txid,
             updated_at,
+            broadcast_at,
+            in_mempool,
+            confirmation,
+            conflicting_txid,
+        })
+    }
+}
+
+/// A row in the "expired_coins" table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbExpiredCoin {
+    pub id: i64,
+    pub wallet_id: i64,
+    pub outpoint: bitcoin::OutPoint,
+    pub amount: bitcoin::Amount,
+    pub derivation_index: bip32::ChildNumber,
+    pub is_change: bool,
+    pub expired_at: u32,
+    pub conflicting_txid: Option<bitcoin::Txid>,
+    pub previous_descriptor_id: Option<i64>,
+}
+
+impl TryFrom<&rusqlite::Row<'_>> for DbExpiredCoin {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &rusqlite::Row) -> Result<Self, Self::Error> {
+        let id = row.get(0)?;
+        let wallet_id = row.get(1)?;
+
+        let txid: Vec<u8> = row.get(2)?;
+        let txid: bitcoin::Txid = encode::deserialize(&txid).expect("We only store valid txids");
+        let vout = row.get(3)?;
+        let outpoint = bitcoin::OutPoint { txid, vout };
+
+        let amount = row.get(4)?;
+        let amount = bitcoin::Amount::from_sat(amount);
+        let der_idx: u32 = row.get(5)?;
+        let derivation_index = bip32::ChildNumber::from(der_idx);
+        let is_change: bool = row.get(6)?;
+        let expired_at = row.get(7)?;
+
+        let conflicting_txid: Option<Vec<u8>> = row.get(8)?;
+        let conflicting_txid = conflicting_txid
+            .map(|txid| encode::deserialize(&txid).expect("We only store valid txids"));
+        let previous_descriptor_id = row.get(9)?;
+
+        Ok(DbExpiredCoin {
+            id,
+            wallet_id,
+            outpoint,
+            amount,
+            derivation_index,
+            is_change,
+            expired_at,
+            conflicting_txid,
+            previous_descriptor_id,
+        })
+    }
+}
+
+/// A row in the "transactions" table, along with the block in which it was confirmed if any.
+/// The confirmation information is retrieved from the coins the transaction created or spent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbWalletTransaction {
+    pub transaction: bitcoin::Transaction,
+    pub block_info: Option<DbBlockInfo>,
+}
+
+impl TryFrom<&rusqlite::Row<'_>> for DbWalletTransaction {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &rusqlite::Row) -> Result<Self, Self::Error> {
+        let transaction: Vec<u8> = row.get(0)?;
+        let transaction: bitcoin::Transaction =
+            encode::deserialize(&transaction).expect("We only store valid transactions");
+
+        let block_height: Option<i32> = row.get(1)?;
+        let block_time: Option<u32> = row.get(2)?;
+        assert_eq!(block_height.is_none(), block_time.is_none());
+        let block_info = block_height.map(|height| DbBlockInfo {
+            height,
+            time: block_time.expect("Must be there if height is"),
+        });
+
+        Ok(DbWalletTransaction {
+            transaction,
+            block_info,
         })
     }
 }
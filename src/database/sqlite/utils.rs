@@ -194,6 +194,114 @@ fn migrate_v2_to_v3(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError
     Ok(())
 }
 
+// After Liana 2.0 we upgraded the schema to store the wallet transactions instead of querying
+// them from the Bitcoin backend every time. The transactions of the coins we already have are
+// fetched at startup, once the Bitcoin backend is set up.
+fn migrate_v3_to_v4(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute(
+            "CREATE TABLE transactions (id INTEGER PRIMARY KEY NOT NULL, txid BLOB UNIQUE NOT NULL, tx BLOB UNIQUE NOT NULL)",
+            rusqlite::params![],
+        )?;
+        tx.execute("UPDATE version SET version = 4", rusqlite::params![])?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// After Liana 2.0 we upgraded the schema to track what happens to the transactions we create
+// once they are broadcast. The status of existing ones will be set by the next poll.
+fn migrate_v4_to_v5(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute_batch(
+            "ALTER TABLE spend_transactions ADD COLUMN broadcast_at INTEGER;
+             ALTER TABLE spend_transactions ADD COLUMN in_mempool BOOLEAN NOT NULL DEFAULT 0 CHECK (in_mempool IN (0,1));
+             ALTER TABLE spend_transactions ADD COLUMN confirmation_height INTEGER;
+             ALTER TABLE spend_transactions ADD COLUMN confirmation_time INTEGER;
+             ALTER TABLE spend_transactions ADD COLUMN conflicting_txid BLOB;",
+        )?;
+        tx.execute("UPDATE version SET version = 5", rusqlite::params![])?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// We now keep track of the unconfirmed coins that disappeared instead of silently dropping them.
+fn migrate_v5_to_v6(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute(
+            "CREATE TABLE expired_coins (
+                id INTEGER PRIMARY KEY NOT NULL,
+                wallet_id INTEGER NOT NULL,
+                txid BLOB NOT NULL,
+                vout INTEGER NOT NULL,
+                amount_sat INTEGER NOT NULL,
+                derivation_index INTEGER NOT NULL,
+                is_change BOOLEAN NOT NULL CHECK (is_change IN (0,1)),
+                expired_at INTEGER NOT NULL,
+                conflicting_txid BLOB,
+                FOREIGN KEY (wallet_id) REFERENCES wallets (id)
+                    ON UPDATE RESTRICT
+                    ON DELETE RESTRICT
+            )",
+            rusqlite::params![],
+        )?;
+        tx.execute("UPDATE version SET version = 6", rusqlite::params![])?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// We now support migrating the funds of the wallet to a new descriptor, and keep track of the
+// descriptors we migrated away from.
+fn migrate_v6_to_v7(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute_batch(
+            "CREATE TABLE previous_descriptors (
+                id INTEGER PRIMARY KEY NOT NULL,
+                wallet_id INTEGER NOT NULL,
+                descriptor TEXT UNIQUE NOT NULL,
+                replaced_at INTEGER NOT NULL,
+                FOREIGN KEY (wallet_id) REFERENCES wallets (id)
+                    ON UPDATE RESTRICT
+                    ON DELETE RESTRICT
+            );
+            ALTER TABLE wallets ADD COLUMN migration_descriptor TEXT;
+            ALTER TABLE wallets ADD COLUMN migration_timestamp INTEGER;
+            ALTER TABLE coins ADD COLUMN previous_descriptor_id INTEGER REFERENCES previous_descriptors (id) ON UPDATE RESTRICT ON DELETE RESTRICT;
+            ALTER TABLE expired_coins ADD COLUMN previous_descriptor_id INTEGER REFERENCES previous_descriptors (id) ON UPDATE RESTRICT ON DELETE RESTRICT;
+            UPDATE version SET version = 7;",
+        )?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+// The daemon can now sign Spend transactions with its own hot signers, and keeps track of what it
+// signed.
+fn migrate_v7_to_v8(conn: &mut rusqlite::Connection) -> Result<(), SqliteDbError> {
+    db_exec(conn, |tx| {
+        tx.execute_batch(
+            "CREATE TABLE signed_spends (
+                id INTEGER PRIMARY KEY NOT NULL,
+                fingerprint BLOB NOT NULL,
+                txid BLOB NOT NULL,
+                amount_sat INTEGER NOT NULL,
+                signed_at INTEGER NOT NULL,
+                UNIQUE (fingerprint, txid)
+            );
+            UPDATE version SET version = 8;",
+        )?;
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
 /// Check the database version and if necessary apply the migrations to upgrade it to the current
 /// one.
 pub fn maybe_apply_migration(db_path: &path::Path) -> Result<(), SqliteDbError> {
@@ -222,6 +330,31 @@ pub fn maybe_apply_migration(db_path: &path::Path) -> Result<(), SqliteDbError>
                 migrate_v2_to_v3(&mut conn)?;
                 log::warn!("Migration from database version 2 to version 3 successful.");
             }
+            3 => {
+                log::warn!("Upgrading database from version 3 to version 4.");
+                migrate_v3_to_v4(&mut conn)?;
+                log::warn!("Migration from database version 3 to version 4 successful.");
+            }
+            4 => {
+                log::warn!("Upgrading database from version 4 to version 5.");
+                migrate_v4_to_v5(&mut conn)?;
+                log::warn!("Migration from database version 4 to version 5 successful.");
+            }
+            5 => {
+                log::warn!("Upgrading database from version 5 to version 6.");
+                migrate_v5_to_v6(&mut conn)?;
+                log::warn!("Migration from database version 5 to version 6 successful.");
+            }
+            6 => {
+                log::warn!("Upgrading database from version 6 to version 7.");
+                migrate_v6_to_v7(&mut conn)?;
+                log::warn!("Migration from database version 6 to version 7 successful.");
+            }
+            7 => {
+                log::warn!("Upgrading database from version 7 to version 8.");
+                migrate_v7_to_v8(&mut conn)?;
+                log::warn!("Migration from database version 7 to version 8 successful.");
+            }
             _ => return Err(SqliteDbError::UnsupportedVersion(version)),
         }
     }
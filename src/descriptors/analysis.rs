@@ -1,5 +1,9 @@
 use miniscript::{
-    bitcoin::{bip32, Sequence},
+    bitcoin::{
+        absolute, bip32,
+        hashes::{sha256, Hash, HashEngine},
+        secp256k1, Sequence,
+    },
     descriptor,
     policy::{compiler, Concrete as ConcretePolicy, Liftable, Semantic as SemanticPolicy},
     ScriptContext,
@@ -7,10 +11,19 @@ use miniscript::{
 
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
-    convert::TryFrom,
     error, fmt,
+    str::FromStr,
+    sync::Arc,
 };
 
+// The "H" point from BIP341, which has no known discrete logarithm. It is used as the internal key
+// of Taproot descriptors whose primary path isn't a single key, to make the key path unspendable.
+const NUMS_POINT: &str = "0250929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+// The bits of a relative timelock which have a consensus meaning (BIP68), besides the disable flag.
+const CSV_TYPE_FLAG: u32 = 1 << 22;
+const CSV_VALUE_MASK: u32 = 0x00_00_ff_ff;
+
 #[derive(Debug)]
 pub enum LianaPolicyError {
     MissingRecoveryPath,
@@ -25,6 +38,8 @@ pub enum LianaPolicyError {
     /// The spending policy is not a valid Miniscript policy: it may for instance be malleable, or
     /// overflow some limit.
     InvalidPolicy(compiler::CompilerError),
+    /// The spending paths could not be arranged in a valid Taproot tree.
+    InvalidTaprootTree(miniscript::Error),
 }
 
 impl std::fmt::Display for LianaPolicyError {
@@ -54,23 +69,74 @@ impl std::fmt::Display for LianaPolicyError {
                 "Descriptor is not compatible with a Liana spending policy."
             ),
             Self::InvalidPolicy(e) => write!(f, "Invalid Miniscript policy: {}", e),
+            Self::InvalidTaprootTree(e) => write!(f, "Invalid Taproot tree: {}", e),
         }
     }
 }
 
 impl error::Error for LianaPolicyError {}
 
-// Whether a Miniscript policy node represents a key check (or several of them).
-fn is_single_key_or_multisig(policy: &SemanticPolicy<descriptor::DescriptorPublicKey>) -> bool {
+// Whether a Miniscript policy node represents a key check (or several of them, possibly within
+// nested thresholds).
+fn is_key_checks(policy: &SemanticPolicy<descriptor::DescriptorPublicKey>) -> bool {
     match policy {
         SemanticPolicy::Key(..) => true,
-        SemanticPolicy::Threshold(_, subs) => {
-            subs.iter().all(|sub| matches!(sub, SemanticPolicy::Key(_)))
+        SemanticPolicy::Threshold(_, subs) => subs.iter().all(is_key_checks),
+        _ => false,
+    }
+}
+
+fn nums_public_key() -> secp256k1::PublicKey {
+    secp256k1::PublicKey::from_str(NUMS_POINT).expect("Valid point")
+}
+
+// Whether this key is an xpub for the NUMS point, which we use as an unspendable internal key.
+pub(super) fn is_unspendable_key(key: &descriptor::DescriptorPublicKey) -> bool {
+    match key {
+        descriptor::DescriptorPublicKey::MultiXPub(ref xpub) => {
+            xpub.xkey.public_key == nums_public_key()
         }
         _ => false,
     }
 }
 
+// Get a multipath xpub for the NUMS point, to be used as internal key of a Taproot descriptor when
+// the key path must not be spendable. The chain code is derived from the keys of the spending
+// paths so that the internal key isn't the same across Liana descriptors. This would otherwise
+// reveal the descriptor is a Liana descriptor when spending through a script path.
+fn unspendable_internal_key<'a>(
+    keys: impl Iterator<Item = &'a descriptor::DescriptorPublicKey>,
+) -> descriptor::DescriptorPublicKey {
+    let mut engine = sha256::Hash::engine();
+    let mut network = None;
+    for key in keys {
+        engine.input(key.to_string().as_bytes());
+        if let descriptor::DescriptorPublicKey::MultiXPub(ref xpub) = key {
+            network.get_or_insert(xpub.xkey.network);
+        }
+    }
+    let chain_code = sha256::Hash::from_engine(engine).to_byte_array();
+    let xkey = bip32::ExtendedPubKey {
+        network: network.expect("There is always at least one multipath xpub."),
+        depth: 0,
+        parent_fingerprint: Default::default(),
+        child_number: bip32::ChildNumber::from_normal_idx(0).expect("Zero is normal"),
+        public_key: nums_public_key(),
+        chain_code: chain_code.into(),
+    };
+    let derivation_paths = descriptor::DerivPaths::new(vec![
+        vec![bip32::ChildNumber::from_normal_idx(0).expect("Zero is normal")].into(),
+        vec![bip32::ChildNumber::from_normal_idx(1).expect("One is normal")].into(),
+    ])
+    .expect("Not empty");
+    descriptor::DescriptorPublicKey::MultiXPub(descriptor::DescriptorMultiXKey {
+        origin: None,
+        xkey,
+        derivation_paths,
+        wildcard: descriptor::Wildcard::Unhardened,
+    })
+}
+
 struct DescKeyChecker {
     keys_set: HashSet<(bip32::ExtendedPubKey, descriptor::DerivPaths)>,
 }
@@ -87,6 +153,7 @@ impl DescKeyChecker {
     ///  - Be multipath (to contain a step in the derivation path with multiple indexes)
     ///  - The multipath step to only contain two indexes. These can be any indexes, which is
     ///     useful for deriving multiple keys from the same xpub.
+    ///  - Only contain unhardened derivation steps, possibly many (eg `xpub/7/<0;1>/*`).
     ///  - Be 'signable' by an external signer (to contain an origin)
     ///
     /// This returns the origin fingerprint for this xpub, to make it possible for the caller to
@@ -109,11 +176,12 @@ impl DescKeyChecker {
             // without origin entirely.
             if let Some(ref origin) = xpub.origin {
                 let der_paths = xpub.derivation_paths.paths();
-                let first_der_path = der_paths.get(0).expect("Cannot be empty");
                 // We also rule out xpubs with hardened derivation steps (non-normalized xpubs).
                 let valid = xpub.wildcard == descriptor::Wildcard::Unhardened
                     && der_paths.len() == 2
-                    && first_der_path.into_iter().all(|step| step.is_normal());
+                    && der_paths
+                        .iter()
+                        .all(|path| path.into_iter().all(|step| step.is_normal()));
                 if valid {
                     return Ok(origin.0);
                 }
@@ -123,18 +191,193 @@ impl DescKeyChecker {
     }
 }
 
-// We require the locktime to:
-//  - not be disabled
-//  - be in number of blocks
-//  - be 'clean' / minimal, ie all bits without consensus meaning should be 0
-//
-// All this is achieved simply through asking for a 16-bit integer, since all the
-// above are signaled in leftmost bits.
-fn csv_check(csv_value: u32) -> Result<u16, LianaPolicyError> {
-    if csv_value > 0 {
-        u16::try_from(csv_value).map_err(|_| LianaPolicyError::InsaneTimelock(csv_value))
-    } else {
-        Err(LianaPolicyError::InsaneTimelock(csv_value))
+/// The timelock of a recovery path. It is either relative to the confirmation of the coin (BIP68,
+/// `older()`), expressed in number of blocks or in number of 512 seconds intervals, or absolute
+/// (`after()`), expressed as a block height or as a UNIX timestamp.
+///
+/// Relative timelocks are ordered by their expected duration, so the paths of a policy may be
+/// ordered consistently regardless of the unit of their timelock. Absolute timelocks come after
+/// all relative ones, heights first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecoveryTimelock {
+    /// A number of blocks.
+    Blocks(u16),
+    /// A number of 512 seconds intervals.
+    Time(u16),
+    /// A block height.
+    AbsoluteHeight(u32),
+    /// A UNIX timestamp, compared against the median time past of the chain (BIP113).
+    AbsoluteTime(u32),
+}
+
+impl RecoveryTimelock {
+    /// Create a timelock from a consensus-encoded relative timelock (as found in an `older()`
+    /// fragment or in an nSequence field).
+    ///
+    /// We require the locktime to:
+    ///  - not be disabled
+    ///  - be 'clean' / minimal, ie all bits without consensus meaning should be 0
+    ///  - be positive (Miniscript requires it not to be 0)
+    pub fn from_consensus(csv_value: u32) -> Result<RecoveryTimelock, LianaPolicyError> {
+        let value = (csv_value & CSV_VALUE_MASK) as u16;
+        if value == 0 || csv_value & !(CSV_TYPE_FLAG | CSV_VALUE_MASK) != 0 {
+            return Err(LianaPolicyError::InsaneTimelock(csv_value));
+        }
+        if csv_value & CSV_TYPE_FLAG != 0 {
+            Ok(RecoveryTimelock::Time(value))
+        } else {
+            Ok(RecoveryTimelock::Blocks(value))
+        }
+    }
+
+    /// Create a timelock from a consensus-encoded absolute timelock (as found in an `after()`
+    /// fragment or in an nLockTime field). Miniscript requires it not to be 0.
+    pub fn from_absolute_consensus(cltv_value: u32) -> Result<RecoveryTimelock, LianaPolicyError> {
+        match absolute::LockTime::from_consensus(cltv_value) {
+            absolute::LockTime::Blocks(h) if h.to_consensus_u32() > 0 => {
+                Ok(RecoveryTimelock::AbsoluteHeight(cltv_value))
+            }
+            absolute::LockTime::Seconds(_) => Ok(RecoveryTimelock::AbsoluteTime(cltv_value)),
+            _ => Err(LianaPolicyError::InsaneTimelock(cltv_value)),
+        }
+    }
+
+    // Whether the value of this timelock is sane: non-null and, for absolute timelocks, in the
+    // range of its unit.
+    fn is_sane(&self) -> bool {
+        match *self {
+            RecoveryTimelock::Blocks(n) | RecoveryTimelock::Time(n) => n > 0,
+            RecoveryTimelock::AbsoluteHeight(h) => h > 0 && h < absolute::LOCK_TIME_THRESHOLD,
+            RecoveryTimelock::AbsoluteTime(t) => t >= absolute::LOCK_TIME_THRESHOLD,
+        }
+    }
+
+    /// Whether this timelock is relative to the confirmation of the coin.
+    pub fn is_relative(&self) -> bool {
+        matches!(
+            self,
+            RecoveryTimelock::Blocks(_) | RecoveryTimelock::Time(_)
+        )
+    }
+
+    /// The nSequence value to use for spending through a path with this timelock. For absolute
+    /// timelocks, it is only required not to disable the nLockTime.
+    pub fn sequence(&self) -> Sequence {
+        match *self {
+            RecoveryTimelock::Blocks(n) => Sequence::from_height(n),
+            RecoveryTimelock::Time(n) => Sequence::from_512_second_intervals(n),
+            // Despite its name this enables nLockTime, only the relative timelock is disabled.
+            RecoveryTimelock::AbsoluteHeight(_) | RecoveryTimelock::AbsoluteTime(_) => {
+                Sequence::ENABLE_RBF_NO_LOCKTIME
+            }
+        }
+    }
+
+    /// The nLockTime value to use for spending through a path with this timelock.
+    pub fn lock_time(&self) -> absolute::LockTime {
+        match *self {
+            RecoveryTimelock::Blocks(_) | RecoveryTimelock::Time(_) => {
+                absolute::LockTime::Blocks(absolute::Height::ZERO)
+            }
+            RecoveryTimelock::AbsoluteHeight(n) | RecoveryTimelock::AbsoluteTime(n) => {
+                absolute::LockTime::from_consensus(n)
+            }
+        }
+    }
+
+    /// Whether an input with this nSequence value, in a transaction with this nLockTime value,
+    /// may spend through a path with this timelock.
+    pub fn is_satisfied_by(&self, sequence: Sequence, lock_time: absolute::LockTime) -> bool {
+        let value = sequence.0 & CSV_VALUE_MASK;
+        match *self {
+            RecoveryTimelock::Blocks(n) => sequence.is_height_locked() && value >= n as u32,
+            RecoveryTimelock::Time(n) => sequence.is_time_locked() && value >= n as u32,
+            RecoveryTimelock::AbsoluteHeight(n) => {
+                sequence.enables_absolute_lock_time()
+                    && lock_time.is_block_height()
+                    && lock_time.to_consensus_u32() >= n
+            }
+            RecoveryTimelock::AbsoluteTime(n) => {
+                sequence.enables_absolute_lock_time()
+                    && lock_time.is_block_time()
+                    && lock_time.to_consensus_u32() >= n
+            }
+        }
+    }
+
+    /// The value of this timelock in its own unit.
+    pub fn value(&self) -> u32 {
+        match *self {
+            RecoveryTimelock::Blocks(n) | RecoveryTimelock::Time(n) => n as u32,
+            RecoveryTimelock::AbsoluteHeight(n) | RecoveryTimelock::AbsoluteTime(n) => n,
+        }
+    }
+
+    /// The duration of this relative timelock in seconds. For timelocks expressed in blocks, this
+    /// is an estimation assuming 10 minutes blocks. Returns `None` for absolute timelocks.
+    pub fn estimated_seconds(&self) -> Option<u32> {
+        match *self {
+            RecoveryTimelock::Blocks(n) => Some(n as u32 * 600),
+            RecoveryTimelock::Time(n) => Some(n as u32 * 512),
+            RecoveryTimelock::AbsoluteHeight(_) | RecoveryTimelock::AbsoluteTime(_) => None,
+        }
+    }
+
+    /// The duration of this relative timelock in number of blocks. For timelocks expressed in
+    /// time, this is an estimation (rounded up) assuming 10 minutes blocks. Returns `None` for
+    /// absolute timelocks.
+    pub fn estimated_blocks(&self) -> Option<u32> {
+        match *self {
+            RecoveryTimelock::Blocks(n) => Some(n as u32),
+            RecoveryTimelock::Time(n) => {
+                let (blocks, rem) = (n as u32 * 512 / 600, n as u32 * 512 % 600);
+                if rem > 0 {
+                    Some(blocks + 1)
+                } else {
+                    Some(blocks)
+                }
+            }
+            RecoveryTimelock::AbsoluteHeight(_) | RecoveryTimelock::AbsoluteTime(_) => None,
+        }
+    }
+
+    fn into_policy(self) -> ConcretePolicy<descriptor::DescriptorPublicKey> {
+        if self.is_relative() {
+            ConcretePolicy::Older(self.sequence())
+        } else {
+            ConcretePolicy::After(self.lock_time().into())
+        }
+    }
+}
+
+impl Ord for RecoveryTimelock {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        // Relative timelocks are compared by duration, using the unit as a tie-breaker to keep
+        // the ordering total.
+        let sort_key = |tl: &Self| match *tl {
+            RecoveryTimelock::Blocks(_) => (0, tl.estimated_seconds().unwrap_or(0), 0),
+            RecoveryTimelock::Time(_) => (0, tl.estimated_seconds().unwrap_or(0), 1),
+            RecoveryTimelock::AbsoluteHeight(n) => (1, n, 0),
+            RecoveryTimelock::AbsoluteTime(n) => (2, n, 0),
+        };
+        sort_key(self).cmp(&sort_key(other))
+    }
+}
+
+impl PartialOrd for RecoveryTimelock {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for RecoveryTimelock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecoveryTimelock::Blocks(n) => write!(f, "{} blocks", n),
+            RecoveryTimelock::Time(n) => write!(f, "{} seconds", *n as u32 * 512),
+            RecoveryTimelock::AbsoluteHeight(n) => write!(f, "block height {}", n),
+            RecoveryTimelock::AbsoluteTime(n) => write!(f, "timestamp {}", n),
+        }
     }
 }
 
@@ -168,41 +411,58 @@ fn key_origins(
 pub enum PathInfo {
     Single(descriptor::DescriptorPublicKey),
     Multi(usize, Vec<descriptor::DescriptorPublicKey>),
+    /// A threshold of sub-paths, at least one of which isn't a single key. For instance "2 of
+    /// (CEO, CFO, 1 of 2 board members)".
+    Thresh(usize, Vec<PathInfo>),
 }
 
 impl PathInfo {
     /// Get the information about the primary spending path.
     /// Returns None if the policy does not describe the primary spending path of a Liana
-    /// descriptor (that is, a set of keys).
+    /// descriptor (that is, a set of keys, possibly within nested thresholds).
     pub fn from_primary_path(
         policy: SemanticPolicy<descriptor::DescriptorPublicKey>,
     ) -> Result<PathInfo, LianaPolicyError> {
         match policy {
             SemanticPolicy::Key(key) => Ok(PathInfo::Single(key)),
             SemanticPolicy::Threshold(k, subs) => {
-                let keys: Result<_, LianaPolicyError> = subs
+                let subs = subs
                     .into_iter()
-                    .map(|sub| match sub {
-                        SemanticPolicy::Key(key) => Ok(key),
-                        _ => Err(LianaPolicyError::IncompatibleDesc),
-                    })
-                    .collect();
-                Ok(PathInfo::Multi(k, keys?))
+                    .map(PathInfo::from_primary_path)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(PathInfo::from_thresh(k, subs))
             }
             _ => Err(LianaPolicyError::IncompatibleDesc),
         }
     }
 
+    // A threshold of sub-paths. Use the flat multisig representation if they are all single keys.
+    fn from_thresh(k: usize, subs: Vec<PathInfo>) -> PathInfo {
+        if subs.iter().all(|sub| matches!(sub, PathInfo::Single(_))) {
+            let keys = subs
+                .into_iter()
+                .filter_map(|sub| match sub {
+                    PathInfo::Single(key) => Some(key),
+                    _ => None,
+                })
+                .collect();
+            PathInfo::Multi(k, keys)
+        } else {
+            PathInfo::Thresh(k, subs)
+        }
+    }
+
     /// Get the information about the recovery spending path.
     /// Returns None if the policy does not describe the recovery spending path of a Liana
     /// descriptor (that is, a set of keys after a timelock).
     pub fn from_recovery_path(
         policy: SemanticPolicy<descriptor::DescriptorPublicKey>,
-    ) -> Result<(u16, PathInfo), LianaPolicyError> {
+    ) -> Result<(RecoveryTimelock, PathInfo), LianaPolicyError> {
         // The recovery spending path must always be a policy of type `thresh(2, older(x), thresh(n, key1,
         // key2, ..))`. In the special case n == 1, it is only `thresh(2, older(x), key)`. In the
         // special case n == len(keys) (i.e. it's an N-of-N multisig), it is normalized as
-        // `thresh(n+1, older(x), key1, key2, ...)`.
+        // `thresh(n+1, older(x), key1, key2, ...)`. The same goes with an absolute timelock
+        // `after(x)` in place of `older(x)`.
         let (k, subs) = match policy {
             SemanticPolicy::Threshold(k, subs) => (k, subs),
             _ => return Err(LianaPolicyError::IncompatibleDesc),
@@ -213,36 +473,49 @@ impl PathInfo {
             let tl_value = subs
                 .iter()
                 .find_map(|s| match s {
-                    SemanticPolicy::Older(val) => Some(csv_check(val.0)),
+                    SemanticPolicy::Older(val) => Some(RecoveryTimelock::from_consensus(val.0)),
+                    SemanticPolicy::After(val) => Some(RecoveryTimelock::from_absolute_consensus(
+                        val.to_consensus_u32(),
+                    )),
                     _ => None,
                 })
                 .ok_or(LianaPolicyError::IncompatibleDesc)??;
             let keys_sub = subs
                 .into_iter()
-                .find(is_single_key_or_multisig)
+                .find(is_key_checks)
                 .ok_or(LianaPolicyError::IncompatibleDesc)?;
             PathInfo::from_primary_path(keys_sub).map(|info| (tl_value, info))
         } else if k == subs.len() && subs.len() > 2 {
-            // The N-of-N case. All subs but the threshold must be keys (if one had been thresh()
-            // of keys it would have been normalized).
+            // The N-of-N case. All subs but the threshold must be keys, or nested thresholds of
+            // keys (if one had been an N-of-N thresh() it would have been normalized).
             let mut tl_value = None;
             let mut keys = Vec::with_capacity(subs.len());
             for sub in subs {
                 match sub {
-                    SemanticPolicy::Key(key) => keys.push(key),
+                    SemanticPolicy::Key(_) | SemanticPolicy::Threshold(..) => {
+                        keys.push(PathInfo::from_primary_path(sub)?)
+                    }
                     SemanticPolicy::Older(val) => {
                         if tl_value.is_some() {
                             return Err(LianaPolicyError::IncompatibleDesc);
                         }
-                        tl_value = Some(csv_check(val.0)?);
+                        tl_value = Some(RecoveryTimelock::from_consensus(val.0)?);
+                    }
+                    SemanticPolicy::After(val) => {
+                        if tl_value.is_some() {
+                            return Err(LianaPolicyError::IncompatibleDesc);
+                        }
+                        tl_value = Some(RecoveryTimelock::from_absolute_consensus(
+                            val.to_consensus_u32(),
+                        )?);
                     }
                     _ => return Err(LianaPolicyError::IncompatibleDesc),
                 }
             }
-            assert!(keys.len() > 1); // At least 3 subs, only one of which may be older().
+            assert!(keys.len() > 1); // At least 3 subs, only one of which may be a timelock.
             Ok((
                 tl_value.ok_or(LianaPolicyError::IncompatibleDesc)?,
-                PathInfo::Multi(k - 1, keys),
+                PathInfo::from_thresh(k - 1, keys),
             ))
         } else {
             // If there is less than 2 subs, there can't be both a timelock and keys. If the
@@ -251,6 +524,15 @@ impl PathInfo {
         }
     }
 
+    /// Get all the keys of this spending path, including those in nested thresholds.
+    pub fn keys(&self) -> Vec<&descriptor::DescriptorPublicKey> {
+        match self {
+            PathInfo::Single(key) => vec![key],
+            PathInfo::Multi(_, keys) => keys.iter().collect(),
+            PathInfo::Thresh(_, subs) => subs.iter().flat_map(|sub| sub.keys()).collect(),
+        }
+    }
+
     /// Add another available key to this `PathInfo`. Note this doesn't change the threshold.
     pub fn with_added_key(mut self, key: descriptor::DescriptorPublicKey) -> Self {
         match self {
@@ -259,6 +541,29 @@ impl PathInfo {
                 keys.push(key);
                 self
             }
+            Self::Thresh(_, ref mut subs) => {
+                subs.push(Self::Single(key));
+                self
+            }
+        }
+    }
+
+    // Check the thresholds of this path and its sub-paths are within bounds.
+    fn check_thresholds(&self) -> Result<(), LianaPolicyError> {
+        match self {
+            PathInfo::Single(_) => Ok(()),
+            PathInfo::Multi(k, keys) => {
+                if *k == 0 || *k > keys.len() {
+                    return Err(LianaPolicyError::InvalidMultiThresh(*k));
+                }
+                Ok(())
+            }
+            PathInfo::Thresh(k, subs) => {
+                if *k == 0 || *k > subs.len() {
+                    return Err(LianaPolicyError::InvalidMultiThresh(*k));
+                }
+                subs.iter().try_for_each(|sub| sub.check_thresholds())
+            }
         }
     }
 
@@ -267,6 +572,9 @@ impl PathInfo {
     /// mapping from a master extended key fingerprint, to a set of derivation paths. This is
     /// because we are using multipath descriptors. The derivation paths included the xpub's
     /// derivation path appended to the origin's derivation path (without the wildcard step).
+    ///
+    /// For a threshold of sub-paths, the returned threshold is the number of sub-paths required
+    /// and the set of keys contains the keys of all the sub-paths.
     pub fn thresh_origins(
         &self,
     ) -> (
@@ -280,7 +588,8 @@ impl PathInfo {
                 all_origins.insert(fg, der_path);
                 (1, all_origins)
             }
-            PathInfo::Multi(k, keys) => {
+            PathInfo::Multi(k, _) | PathInfo::Thresh(k, _) => {
+                let keys = self.keys();
                 let mut all_origins: HashMap<_, HashSet<_>> = HashMap::with_capacity(keys.len());
                 for key in keys {
                     let (fg, der_paths) =
@@ -298,10 +607,35 @@ impl PathInfo {
 
     /// Get the spend information for this descriptor based from the list of all pubkeys that
     /// signed the transaction.
+    ///
+    /// For a threshold of sub-paths, the signatures count is the number of sub-paths which have
+    /// enough signatures. The spend information of each sub-path is available in `sub_paths`.
     pub fn spend_info<'a>(
         &self,
-        all_pubkeys_signed: impl Iterator<Item = &'a (bip32::Fingerprint, bip32::DerivationPath)>,
+        all_pubkeys_signed: impl Iterator<Item = &'a (bip32::Fingerprint, bip32::DerivationPath)>
+            + Clone,
     ) -> PathSpendInfo {
+        if let PathInfo::Thresh(threshold, subs) = self {
+            let sub_paths: Vec<PathSpendInfo> = subs
+                .iter()
+                .map(|sub| sub.spend_info(all_pubkeys_signed.clone()))
+                .collect();
+            let sigs_count = sub_paths
+                .iter()
+                .filter(|sub| sub.sigs_count >= sub.threshold)
+                .count();
+            let mut signed_pubkeys = HashMap::new();
+            for (fg, count) in sub_paths.iter().flat_map(|sub| sub.signed_pubkeys.iter()) {
+                *signed_pubkeys.entry(*fg).or_insert(0) += count;
+            }
+            return PathSpendInfo {
+                threshold: *threshold,
+                sigs_count,
+                signed_pubkeys,
+                sub_paths,
+            };
+        }
+
         let mut signed_pubkeys = HashMap::new();
         let mut sigs_count = 0;
         let (threshold, origins) = self.thresh_origins();
@@ -338,6 +672,7 @@ impl PathInfo {
             threshold,
             sigs_count,
             signed_pubkeys,
+            sub_paths: Vec::new(),
         }
     }
 
@@ -349,6 +684,10 @@ impl PathInfo {
                 thresh,
                 keys.into_iter().map(ConcretePolicy::Key).collect(),
             ),
+            PathInfo::Thresh(thresh, subs) => ConcretePolicy::Threshold(
+                thresh,
+                subs.into_iter().map(PathInfo::into_ms_policy).collect(),
+            ),
         }
     }
 }
@@ -356,7 +695,9 @@ impl PathInfo {
 /// A Liana spending policy is one composed of at least two spending paths:
 ///     - A directly available path with any number of keys checks; or
 ///     - One or more recovery paths with any number of keys checks, behind increasing relative
-///     timelocks. No two recovery paths may have the same timelock.
+///     timelocks or absolute timelocks. No two recovery paths may have the same timelock.
+/// It may be expressed either as a P2WSH descriptor or as a Taproot descriptor. In the latter case
+/// each spending path is a tapleaf, except for a single-key primary path which is the key path.
 /// A Liana policy can be created from some settings (the primary and recovery keys, the
 /// timelock(s)) and be used to derive a descriptor. It can also be inferred from a descriptor and
 /// be used to retrieve the settings.
@@ -365,28 +706,41 @@ impl PathInfo {
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct LianaPolicy {
     pub(super) primary_path: PathInfo,
-    pub(super) recovery_paths: BTreeMap<u16, PathInfo>,
+    pub(super) recovery_paths: BTreeMap<RecoveryTimelock, PathInfo>,
+    pub(super) is_taproot: bool,
 }
 
 impl LianaPolicy {
-    /// Create a new Liana policy from a given configuration.
+    /// Create a new Liana policy from a given configuration, to be used in a P2WSH descriptor.
     pub fn new(
         primary_path: PathInfo,
-        recovery_paths: BTreeMap<u16, PathInfo>,
+        recovery_paths: BTreeMap<RecoveryTimelock, PathInfo>,
+    ) -> Result<LianaPolicy, LianaPolicyError> {
+        LianaPolicy::from_paths(primary_path, recovery_paths, false)
+    }
+
+    /// Create a new Liana policy from a given configuration, to be used in a Taproot descriptor.
+    pub fn new_taproot(
+        primary_path: PathInfo,
+        recovery_paths: BTreeMap<RecoveryTimelock, PathInfo>,
+    ) -> Result<LianaPolicy, LianaPolicyError> {
+        LianaPolicy::from_paths(primary_path, recovery_paths, true)
+    }
+
+    fn from_paths(
+        primary_path: PathInfo,
+        recovery_paths: BTreeMap<RecoveryTimelock, PathInfo>,
+        is_taproot: bool,
     ) -> Result<LianaPolicy, LianaPolicyError> {
         if recovery_paths.is_empty() {
             return Err(LianaPolicyError::MissingRecoveryPath);
         }
 
-        // We require the locktime to:
-        //  - not be disabled
-        //  - be in number of blocks
-        //  - be 'clean' / minimal, ie all bits without consensus meaning should be 0
-        //  - be positive (Miniscript requires it not to be 0)
-        //
-        // All this is achieved through asking for a 16-bit integer.
-        if recovery_paths.contains_key(&0) {
-            return Err(LianaPolicyError::InsaneTimelock(0));
+        // Miniscript requires the timelocks to be positive. Absolute timelocks must also be in the
+        // range of their unit. All the other requirements on the relative timelocks (see
+        // `RecoveryTimelock::from_consensus`) are enforced by the type.
+        if let Some(timelock) = recovery_paths.keys().find(|tl| !tl.is_sane()) {
+            return Err(LianaPolicyError::InsaneTimelock(timelock.value()));
         }
 
         // Check all keys are valid according to our standard (this checks all are multipath keys).
@@ -398,16 +752,18 @@ impl LianaPolicy {
             .chain(std::iter::once(&primary_path));
         let mut key_checker = DescKeyChecker::new();
         for path in spending_paths {
+            path.check_thresholds()?;
             match path {
                 PathInfo::Single(ref key) => {
                     let _ = key_checker.check(key)?;
                 }
-                PathInfo::Multi(_, ref keys) => {
+                PathInfo::Multi(..) | PathInfo::Thresh(..) => {
                     // Record the origins of the keys for this spending path. If any two keys share
                     // the same origin, they are from the same signer. We restrict using a signer
                     // more than once within a single spending path as it can lead to surprising
                     // behaviour. For details see:
                     // https://github.com/wizardsardine/liana/pull/706#issuecomment-1744705808
+                    let keys = path.keys();
                     let mut origin_fingerprints = HashSet::with_capacity(keys.len());
                     for key in keys {
                         let fg = key_checker.check(key)?;
@@ -426,32 +782,55 @@ impl LianaPolicy {
         let policy = LianaPolicy {
             primary_path,
             recovery_paths,
+            is_taproot,
         };
-        policy.clone().into_miniscript()?;
+        if is_taproot {
+            policy.clone().into_tr_descriptor()?;
+        } else {
+            policy.clone().into_miniscript()?;
+        }
         Ok(policy)
     }
 
     /// Create a Liana policy from a descriptor. This will check the descriptor is correctly formed
-    /// (P2WSH, multipath, ..) and has a valid Liana semantic.
+    /// (P2WSH or Taproot, multipath, ..) and has a valid Liana semantic.
     pub fn from_multipath_descriptor(
         desc: &descriptor::Descriptor<descriptor::DescriptorPublicKey>,
     ) -> Result<LianaPolicy, LianaPolicyError> {
-        // For now we only allow P2WSH descriptors.
-        let wsh_desc = match &desc {
-            descriptor::Descriptor::Wsh(desc) => desc,
-            _ => return Err(LianaPolicyError::IncompatibleDesc),
-        };
-
         // Lift a semantic policy out of this Miniscript and normalize it to make sure we compare
-        // apples to apples below.
-        let ms = match wsh_desc.as_inner() {
-            descriptor::WshInner::Ms(ms) => ms,
+        // apples to apples below. We only allow P2WSH and Taproot descriptors.
+        let (policy, is_taproot, spendable_internal_key) = match &desc {
+            descriptor::Descriptor::Wsh(wsh_desc) => {
+                let ms = match wsh_desc.as_inner() {
+                    descriptor::WshInner::Ms(ms) => ms,
+                    _ => return Err(LianaPolicyError::IncompatibleDesc),
+                };
+                let policy = ms.lift().expect("Lifting can't fail on a Miniscript");
+                (policy, false, false)
+            }
+            descriptor::Descriptor::Tr(tr_desc) => {
+                // The recovery paths are always in the tree. The primary path is either the
+                // internal key, or it's in the tree too and the internal key is unspendable.
+                let tree_policy = tr_desc
+                    .taptree()
+                    .as_ref()
+                    .ok_or(LianaPolicyError::IncompatibleDesc)?
+                    .lift()
+                    .expect("Lifting can't fail on a Miniscript");
+                let internal_key = tr_desc.internal_key();
+                if is_unspendable_key(internal_key) {
+                    (tree_policy, true, false)
+                } else {
+                    let policy = SemanticPolicy::Threshold(
+                        1,
+                        vec![SemanticPolicy::Key(internal_key.clone()), tree_policy],
+                    );
+                    (policy, true, true)
+                }
+            }
             _ => return Err(LianaPolicyError::IncompatibleDesc),
         };
-        let policy = ms
-            .lift()
-            .expect("Lifting can't fail on a Miniscript")
-            .normalized();
+        let policy = policy.normalized();
 
         // The policy must always be "1 of N spending paths" with at least an always-available
         // primary path with at least one key, and at least one timelocked recovery path with at
@@ -464,23 +843,15 @@ impl LianaPolicy {
 
         // Fetch all spending paths' semantic policies. The primary path is identified as the only
         // one that isn't timelocked.
-        let (mut primary_path, mut recovery_paths) = (None::<PathInfo>, BTreeMap::new());
+        let (mut primary_subs, mut recovery_paths) = (Vec::new(), BTreeMap::new());
         for sub in subs {
-            // This is a (multi)key check. It must be the primary path.
-            if is_single_key_or_multisig(&sub) {
+            // This is a (multi)key check. It must be (part of) the primary path.
+            if is_key_checks(&sub) {
                 // We only support a single primary path. But it may be that the primary path is a
-                // 1-of-N multisig. In this case the policy is normalized from `thresh(1, thresh(1,
+                // 1-of-N threshold. In this case the policy is normalized from `thresh(1, thresh(1,
                 // pk(A), pk(B)), thresh(2, older(42), pk(C)))` to `thresh(1, pk(A), pk(B),
                 // thresh(2, older(42), pk(C)))`.
-                if let Some(prim_path) = primary_path {
-                    if let SemanticPolicy::Key(key) = sub {
-                        primary_path = Some(prim_path.with_added_key(key));
-                    } else {
-                        return Err(LianaPolicyError::IncompatibleDesc);
-                    }
-                } else {
-                    primary_path = Some(PathInfo::from_primary_path(sub)?);
-                }
+                primary_subs.push(PathInfo::from_primary_path(sub)?);
             } else {
                 // If it's not a simple (multi)key check, it must be (one of) the timelocked
                 // recovery path(s).
@@ -494,8 +865,21 @@ impl LianaPolicy {
 
         // Use the constructor for sanity checking the keys and the Miniscript policy. Note this
         // makes sure the recovery paths mapping isn't empty, too.
-        let prim_path = primary_path.ok_or(LianaPolicyError::IncompatibleDesc)?;
-        LianaPolicy::new(prim_path, recovery_paths)
+        // If the internal key is spendable, it must be the whole primary path.
+        if spendable_internal_key && primary_subs.len() != 1 {
+            return Err(LianaPolicyError::IncompatibleDesc);
+        }
+        let prim_path = match primary_subs.len() {
+            0 => return Err(LianaPolicyError::IncompatibleDesc),
+            1 => primary_subs.pop().expect("Just checked the length"),
+            _ => PathInfo::from_thresh(1, primary_subs),
+        };
+        LianaPolicy::from_paths(prim_path, recovery_paths, is_taproot)
+    }
+
+    /// Whether this policy is expressed as a Taproot descriptor.
+    pub fn is_taproot(&self) -> bool {
+        self.is_taproot
     }
 
     pub fn primary_path(&self) -> &PathInfo {
@@ -504,7 +888,7 @@ impl LianaPolicy {
 
     /// Timelocks and path info of the recovery paths. Note we guarantee this mapping is never
     /// empty, as there is always at least one recovery path.
-    pub fn recovery_paths(&self) -> &BTreeMap<u16, PathInfo> {
+    pub fn recovery_paths(&self) -> &BTreeMap<RecoveryTimelock, PathInfo> {
         assert!(!self.recovery_paths.is_empty());
         &self.recovery_paths
     }
@@ -518,6 +902,7 @@ impl LianaPolicy {
         let LianaPolicy {
             primary_path,
             recovery_paths,
+            ..
         } = self;
 
         // Start with the primary spending path. We'll then or() all the recovery paths to it.
@@ -529,7 +914,7 @@ impl LianaPolicy {
             recovery_paths
                 .into_iter()
                 .fold(primary_keys, |tl_policy, (timelock, path_info)| {
-                    let timelock = ConcretePolicy::Older(Sequence::from_height(timelock));
+                    let timelock = timelock.into_policy();
                     let keys = path_info.into_ms_policy();
                     let recovery_branch = ConcretePolicy::And(vec![keys, timelock]);
                     // We assume the larger the timelock the less likely a branch would be used.
@@ -541,6 +926,57 @@ impl LianaPolicy {
             .map_err(LianaPolicyError::InvalidPolicy)
     }
 
+    fn into_tr_descriptor(
+        self,
+    ) -> Result<descriptor::Descriptor<descriptor::DescriptorPublicKey>, LianaPolicyError> {
+        // A single primary key is used as the internal key. Otherwise the primary path is a
+        // tapleaf like the recovery paths and the key path is made unspendable.
+        let internal_key = match self.primary_path {
+            PathInfo::Single(ref key) => key.clone(),
+            PathInfo::Multi(..) | PathInfo::Thresh(..) => unspendable_internal_key(
+                self.recovery_paths
+                    .values()
+                    .chain(std::iter::once(&self.primary_path))
+                    .flat_map(|path| path.keys()),
+            ),
+        };
+        let LianaPolicy {
+            primary_path,
+            recovery_paths,
+            ..
+        } = self;
+        let primary_leaf = match primary_path {
+            PathInfo::Single(..) => None,
+            path => Some(path.into_ms_policy()),
+        };
+
+        // Compile each spending path to a tapleaf. We assume the larger the timelock the less
+        // likely a branch would be used, so we order them by likelihood of being used.
+        let mut leaves = primary_leaf
+            .into_iter()
+            .chain(recovery_paths.into_iter().map(|(timelock, path_info)| {
+                let timelock = timelock.into_policy();
+                ConcretePolicy::And(vec![path_info.into_ms_policy(), timelock])
+            }))
+            .map(|policy| {
+                policy
+                    .compile::<miniscript::Tap>()
+                    .map(|ms| descriptor::TapTree::Leaf(Arc::new(ms)))
+                    .map_err(LianaPolicyError::InvalidPolicy)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Create the tree such as the most likely to be used leaves are the least deep.
+        let deepest_leaf = leaves.pop().expect("There is always a recovery path.");
+        let tree = leaves.into_iter().rev().fold(deepest_leaf, |tree, leaf| {
+            descriptor::TapTree::Tree(Arc::new(leaf), Arc::new(tree))
+        });
+
+        descriptor::Tr::new(internal_key, Some(tree))
+            .map(descriptor::Descriptor::Tr)
+            .map_err(LianaPolicyError::InvalidTaprootTree)
+    }
+
     /// Create a descriptor from this spending policy with multipath key expressions. Note this
     /// involves a Miniscript policy compilation: this function is **not deterministic**. If you
     /// are inferring a `LianaPolicy` from a descriptor, generating a descriptor from this
@@ -548,6 +984,11 @@ impl LianaPolicy {
     pub fn into_multipath_descriptor(
         self,
     ) -> descriptor::Descriptor<descriptor::DescriptorPublicKey> {
+        if self.is_taproot {
+            return self
+                .into_tr_descriptor()
+                .expect("This is always checked when creating a LianaPolicy.");
+        }
         let ms = self
             .into_miniscript()
             .expect("This is always checked when creating a LianaPolicy.");
@@ -566,6 +1007,9 @@ pub struct PathSpendInfo {
     /// The keys for which a signature was provided and the number (always >=1) of
     /// signatures provided for this key.
     pub signed_pubkeys: HashMap<bip32::Fingerprint, usize>,
+    /// For a threshold of sub-paths, the spend information of each sub-path. In this case the
+    /// threshold and the signatures count are expressed in number of sub-paths.
+    pub sub_paths: Vec<PathSpendInfo>,
 }
 
 /// Information about a partial spend of Liana coins
@@ -575,7 +1019,7 @@ pub struct PartialSpendInfo {
     pub(super) primary_path: PathSpendInfo,
     /// Number of signatures present for the recovery path, only present for the recovery paths
     /// that are available.
-    pub(super) recovery_paths: BTreeMap<u16, PathSpendInfo>,
+    pub(super) recovery_paths: BTreeMap<RecoveryTimelock, PathSpendInfo>,
 }
 
 impl PartialSpendInfo {
@@ -586,7 +1030,7 @@ impl PartialSpendInfo {
 
     /// Get the number of signatures present for each recovery path. Only present for available
     /// paths.
-    pub fn recovery_paths(&self) -> &BTreeMap<u16, PathSpendInfo> {
+    pub fn recovery_paths(&self) -> &BTreeMap<RecoveryTimelock, PathSpendInfo> {
         &self.recovery_paths
     }
 }
@@ -0,0 +1,272 @@
+//! Human-readable description of a Liana spending policy.
+//!
+//! This presents the spending paths of a descriptor in a structured form, such as it may be
+//! audited without having to reverse-engineer the raw descriptor string.
+
+use crate::descriptors::{LianaDescriptor, PathInfo, RecoveryTimelock};
+
+use std::collections::HashMap;
+
+use miniscript::{bitcoin::bip32, descriptor};
+
+use serde::{Deserialize, Serialize};
+
+/// A key in a spending path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyDescription {
+    /// The fingerprint of the master key this key is derived from.
+    pub fingerprint: bip32::Fingerprint,
+    /// The name given to this key, if any.
+    pub alias: Option<String>,
+    pub key: descriptor::DescriptorPublicKey,
+}
+
+/// A threshold of keys and sub-paths. The threshold applies to the keys and the sub-paths
+/// altogether.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathDescription {
+    pub threshold: usize,
+    pub keys: Vec<KeyDescription>,
+    pub sub_paths: Vec<PathDescription>,
+}
+
+/// A timelocked recovery path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecoveryPathDescription {
+    /// The timelock of this path, as it would be displayed to a user.
+    pub timelock: String,
+    /// Whether the timelock is relative to the confirmation of the coin.
+    pub is_relative: bool,
+    /// The duration of a relative timelock in blocks. For timelocks expressed in time this is an
+    /// estimation assuming 10 minutes blocks.
+    pub blocks: Option<u32>,
+    /// The approximate duration of a relative timelock, for instance "~6 months".
+    pub duration: Option<String>,
+    pub path: PathDescription,
+}
+
+/// The spending paths of a Liana descriptor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyDescription {
+    pub is_taproot: bool,
+    pub primary_path: PathDescription,
+    /// The recovery paths, ordered by timelock.
+    pub recovery_paths: Vec<RecoveryPathDescription>,
+}
+
+/// When a recovery path becomes available for a given coin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    /// The timelock of this path, as it would be displayed to a user.
+    pub timelock: String,
+    /// The first block height at which a transaction spending the coin through this path may be
+    /// mined. `None` for timelocks expressed as a timestamp.
+    pub height: Option<u32>,
+    /// The median time past a block must be over for a transaction spending the coin through this
+    /// path to be mined in the next one. Only set for timelocks expressed as a timestamp.
+    pub timestamp: Option<u32>,
+    /// Whether the height is an estimation (for relative timelocks expressed in time).
+    pub is_estimate: bool,
+}
+
+// Get an approximate human-readable duration from a number of seconds, using the two most
+// significant units. Eg "~1 year and 2 months".
+fn approx_duration(seconds: u32) -> String {
+    const UNITS: [(u32, &str); 5] = [
+        (31_557_600, "year"),
+        (2_629_800, "month"),
+        (86_400, "day"),
+        (3_600, "hour"),
+        (60, "minute"),
+    ];
+
+    let mut rem = seconds;
+    let mut parts = Vec::with_capacity(2);
+    for (unit_secs, unit_name) in UNITS.iter() {
+        let n = rem / unit_secs;
+        if n > 0 {
+            rem -= n * unit_secs;
+            parts.push(format!(
+                "{} {}{}",
+                n,
+                unit_name,
+                if n > 1 { "s" } else { "" }
+            ));
+        } else if !parts.is_empty() {
+            // Don't skip a unit between the two most significant ones.
+            break;
+        }
+        if parts.len() == 2 {
+            break;
+        }
+    }
+
+    if parts.is_empty() {
+        "~0 minutes".to_string()
+    } else {
+        format!("~{}", parts.join(" and "))
+    }
+}
+
+impl PathDescription {
+    fn new(path: &PathInfo, aliases: &HashMap<bip32::Fingerprint, String>) -> PathDescription {
+        let key_desc = |key: &descriptor::DescriptorPublicKey| {
+            let fingerprint = key.master_fingerprint();
+            KeyDescription {
+                fingerprint,
+                alias: aliases.get(&fingerprint).cloned(),
+                key: key.clone(),
+            }
+        };
+
+        match path {
+            PathInfo::Single(key) => PathDescription {
+                threshold: 1,
+                keys: vec![key_desc(key)],
+                sub_paths: Vec::new(),
+            },
+            PathInfo::Multi(thresh, keys) => PathDescription {
+                threshold: *thresh,
+                keys: keys.iter().map(key_desc).collect(),
+                sub_paths: Vec::new(),
+            },
+            PathInfo::Thresh(thresh, subs) => {
+                let (mut keys, mut sub_paths) = (Vec::new(), Vec::new());
+                for sub in subs {
+                    match sub {
+                        PathInfo::Single(key) => keys.push(key_desc(key)),
+                        _ => sub_paths.push(PathDescription::new(sub, aliases)),
+                    }
+                }
+                PathDescription {
+                    threshold: *thresh,
+                    keys,
+                    sub_paths,
+                }
+            }
+        }
+    }
+}
+
+impl TimelineEntry {
+    fn new(timelock: &RecoveryTimelock, coin_height: u32) -> TimelineEntry {
+        let (height, timestamp) = match *timelock {
+            RecoveryTimelock::Blocks(_) | RecoveryTimelock::Time(_) => {
+                (timelock.estimated_blocks().map(|n| coin_height + n), None)
+            }
+            // A transaction with this nLockTime may be mined in the block after this height. The
+            // coin must be confirmed to be spent anyways.
+            RecoveryTimelock::AbsoluteHeight(h) => (Some(std::cmp::max(h + 1, coin_height)), None),
+            RecoveryTimelock::AbsoluteTime(t) => (None, Some(t)),
+        };
+        TimelineEntry {
+            timelock: timelock.to_string(),
+            height,
+            timestamp,
+            is_estimate: matches!(timelock, RecoveryTimelock::Time(_)),
+        }
+    }
+}
+
+impl LianaDescriptor {
+    /// Get a description of the spending paths of this descriptor. Keys are named after the
+    /// alias of their master fingerprint, if present in the given mapping.
+    pub fn description(&self, aliases: &HashMap<bip32::Fingerprint, String>) -> PolicyDescription {
+        let policy = self.policy();
+        let recovery_paths = policy
+            .recovery_paths()
+            .iter()
+            .map(|(timelock, path)| RecoveryPathDescription {
+                timelock: timelock.to_string(),
+                is_relative: timelock.is_relative(),
+                blocks: timelock.estimated_blocks(),
+                duration: timelock.estimated_seconds().map(approx_duration),
+                path: PathDescription::new(path, aliases),
+            })
+            .collect();
+
+        PolicyDescription {
+            is_taproot: policy.is_taproot(),
+            primary_path: PathDescription::new(policy.primary_path(), aliases),
+            recovery_paths,
+        }
+    }
+
+    /// Get when each recovery path becomes available for a coin confirmed at the given height.
+    /// The entries are in the same order as the recovery paths of the policy description.
+    pub fn timeline(&self, coin_height: u32) -> Vec<TimelineEntry> {
+        self.policy()
+            .recovery_paths()
+            .keys()
+            .map(|timelock| TimelineEntry::new(timelock, coin_height))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn duration_description() {
+        assert_eq!(approx_duration(0), "~0 minutes");
+        assert_eq!(approx_duration(600), "~10 minutes");
+        assert_eq!(approx_duration(144 * 600), "~1 day");
+        assert_eq!(approx_duration(52_560 * 600), "~11 months and 30 days");
+        assert_eq!(approx_duration(65_535 * 600), "~1 year and 2 months");
+        assert_eq!(approx_duration(65_535 * 512), "~1 year");
+        assert_eq!(approx_duration(90_000), "~1 day and 1 hour");
+    }
+
+    #[test]
+    fn policy_description() {
+        let desc = LianaDescriptor::from_str("wsh(or_i(and_v(v:pkh([abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(52560)),or_d(pk([aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh([aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*),after(800000)))))").unwrap();
+        let aliases: HashMap<_, _> = [(
+            bip32::Fingerprint::from_str("abcdef01").unwrap(),
+            "Heir".to_string(),
+        )]
+        .iter()
+        .cloned()
+        .collect();
+
+        let description = desc.description(&aliases);
+        assert!(!description.is_taproot);
+        assert_eq!(description.primary_path.threshold, 1);
+        assert_eq!(description.primary_path.keys.len(), 1);
+        assert_eq!(
+            description.primary_path.keys[0].fingerprint,
+            bip32::Fingerprint::from_str("aabb0011").unwrap()
+        );
+        assert_eq!(description.primary_path.keys[0].alias, None);
+
+        // The recovery paths are ordered by timelock, relative ones first.
+        assert_eq!(description.recovery_paths.len(), 2);
+        let heir_path = &description.recovery_paths[0];
+        assert_eq!(heir_path.timelock, "52560 blocks");
+        assert!(heir_path.is_relative);
+        assert_eq!(heir_path.blocks, Some(52560));
+        assert_eq!(
+            heir_path.duration.as_deref(),
+            Some("~11 months and 30 days")
+        );
+        assert_eq!(heir_path.path.keys[0].alias.as_deref(), Some("Heir"));
+        let abs_path = &description.recovery_paths[1];
+        assert_eq!(abs_path.timelock, "block height 800000");
+        assert!(!abs_path.is_relative);
+        assert_eq!(abs_path.blocks, None);
+        assert_eq!(abs_path.duration, None);
+
+        // For a coin confirmed at height 750000, the heir may spend it after the absolute timelock
+        // expires.
+        let timeline = desc.timeline(750_000);
+        assert_eq!(timeline.len(), 2);
+        assert_eq!(timeline[0].height, Some(802_560));
+        assert_eq!(timeline[1].height, Some(800_001));
+        assert!(timeline
+            .iter()
+            .all(|e| e.timestamp.is_none() && !e.is_estimate));
+        let timeline = desc.timeline(850_000);
+        assert_eq!(timeline[1].height, Some(850_000));
+    }
+}
@@ -0,0 +1,428 @@
+//! Encryption of descriptor backups.
+//!
+//! The backup is encrypted using a random symmetric key. This key is in turn made available to
+//! the holder of any of the public keys in the descriptor: for each of them we store the
+//! symmetric key XOR'd with the hash of this public key. Anyone knowing a single xpub of the
+//! descriptor can therefore decrypt the backup, while a third party only learns the number of
+//! keys in the descriptor and the derivation paths used for them. The latter are kept in clear
+//! so that a signing device can be asked for its xpub without knowing the descriptor.
+//!
+//! The encoding is the following, with all integers serialized in big-endian:
+//! - The magic bytes `LIANABAK`.
+//! - The version byte, currently `0x01`.
+//! - The number of derivation paths as a byte, followed by each path as its number of steps on
+//!   one byte followed by each child number on 4 bytes.
+//! - The number of individual secrets as a byte, followed by each 32-byte secret.
+//! - The 12-byte nonce.
+//! - The content encrypted with ChaCha20-Poly1305, using all the above as associated data.
+
+use crate::{
+    descriptors::{analysis::is_unspendable_key, LianaDescriptor},
+    random,
+};
+
+use std::{collections::BTreeSet, convert::TryInto, error, fmt, str};
+
+use chacha20poly1305::{
+    aead::{Aead, NewAead, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use miniscript::{
+    bitcoin::{
+        base64,
+        bip32::{self, ChildNumber, DerivationPath},
+        hashes::{sha256, Hash, HashEngine},
+        key::XOnlyPublicKey,
+        secp256k1,
+    },
+    descriptor::{DescriptorPublicKey, SinglePubKey},
+    ForEachKey,
+};
+
+const MAGIC: &[u8; 8] = b"LIANABAK";
+const VERSION: u8 = 1;
+const SECRET_TAG: &[u8] = b"LIANA_BACKUP_DECRYPTION_SECRET";
+const NONCE_SIZE: usize = 12;
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    Randomness(random::RandomnessError),
+    /// The descriptor has more keys or derivation paths than we can encode.
+    TooManyKeys(usize),
+    InvalidEncoding(String),
+    UnsupportedVersion(u8),
+    /// None of the given keys can decrypt this backup.
+    Decryption,
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Randomness(e) => write!(f, "Error when generating the encryption key: {}", e),
+            Self::TooManyKeys(n) => write!(
+                f,
+                "Too many keys ({}) in the descriptor to encrypt a backup for.",
+                n
+            ),
+            Self::InvalidEncoding(e) => write!(f, "Invalid encrypted backup: {}", e),
+            Self::UnsupportedVersion(v) => {
+                write!(f, "Unsupported encrypted backup version '{}'.", v)
+            }
+            Self::Decryption => write!(f, "The backup could not be decrypted with this key."),
+        }
+    }
+}
+
+impl error::Error for EncryptionError {}
+
+/// A backup encrypted such as it can be decrypted with any of the keys of a descriptor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedBackup {
+    derivation_paths: Vec<DerivationPath>,
+    individual_secrets: Vec<[u8; 32]>,
+    nonce: [u8; NONCE_SIZE],
+    ciphertext: Vec<u8>,
+}
+
+// The secret specific to a key, known to anyone who knows this key.
+fn individual_secret(key: &secp256k1::PublicKey) -> [u8; 32] {
+    let mut engine = sha256::HashEngine::default();
+    engine.input(SECRET_TAG);
+    engine.input(&key.serialize());
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn xor(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut res = [0; 32];
+    for i in 0..32 {
+        res[i] = a[i] ^ b[i];
+    }
+    res
+}
+
+// The public keys in this descriptor, as well as the derivation paths from the master keys to
+// the xpubs. The unspendable internal key of a Taproot descriptor is public knowledge and is
+// therefore never included.
+fn descriptor_keys(
+    desc: &LianaDescriptor,
+) -> (BTreeSet<secp256k1::PublicKey>, BTreeSet<DerivationPath>) {
+    let (mut keys, mut paths) = (BTreeSet::new(), BTreeSet::new());
+    desc.multi_desc.for_each_key(|key| {
+        if is_unspendable_key(key) {
+            return true;
+        }
+        match key {
+            DescriptorPublicKey::Single(single) => {
+                keys.insert(match single.key {
+                    SinglePubKey::FullKey(pk) => pk.inner,
+                    SinglePubKey::XOnly(xpk) => xpk.public_key(secp256k1::Parity::Even),
+                });
+            }
+            DescriptorPublicKey::XPub(xpub) => {
+                keys.insert(xpub.xkey.public_key);
+                if let Some((_, path)) = &xpub.origin {
+                    paths.insert(path.clone());
+                }
+            }
+            DescriptorPublicKey::MultiXPub(xpub) => {
+                keys.insert(xpub.xkey.public_key);
+                if let Some((_, path)) = &xpub.origin {
+                    paths.insert(path.clone());
+                }
+            }
+        }
+        true
+    });
+    (keys, paths)
+}
+
+// A cursor to read the encoded backup.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], EncryptionError> {
+        if self.0.len() < n {
+            return Err(EncryptionError::InvalidEncoding(
+                "unexpected end of data".to_string(),
+            ));
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, EncryptionError> {
+        self.take(1).map(|b| b[0])
+    }
+}
+
+impl EncryptedBackup {
+    /// Encrypt this content (for instance the descriptor string, or a wallet backup) such as it
+    /// can be decrypted by the holder of any key of this descriptor.
+    pub fn encrypt(desc: &LianaDescriptor, content: &[u8]) -> Result<Self, EncryptionError> {
+        let (keys, paths) = descriptor_keys(desc);
+        if keys.len() > u8::MAX as usize || paths.len() > u8::MAX as usize {
+            return Err(EncryptionError::TooManyKeys(keys.len()));
+        }
+
+        let secret = random::random_bytes().map_err(EncryptionError::Randomness)?;
+        let nonce: [u8; NONCE_SIZE] = random::random_bytes()
+            .map_err(EncryptionError::Randomness)?[..NONCE_SIZE]
+            .try_into()
+            .expect("Correct size");
+        let mut backup = EncryptedBackup {
+            derivation_paths: paths.into_iter().collect(),
+            individual_secrets: keys
+                .iter()
+                .map(|key| xor(&secret, &individual_secret(key)))
+                .collect(),
+            nonce,
+            ciphertext: Vec::new(),
+        };
+
+        let cipher = ChaCha20Poly1305::new(&Key::from(secret));
+        let aad = backup.header();
+        backup.ciphertext = cipher
+            .encrypt(
+                &Nonce::from(backup.nonce),
+                Payload {
+                    msg: content,
+                    aad: &aad,
+                },
+            )
+            .expect("Encryption can't fail with a valid key and nonce");
+
+        Ok(backup)
+    }
+
+    /// Encrypt the descriptor itself.
+    pub fn encrypt_descriptor(desc: &LianaDescriptor) -> Result<Self, EncryptionError> {
+        Self::encrypt(desc, desc.to_string().as_bytes())
+    }
+
+    /// The derivation paths from the master keys of the participants to the xpubs used in the
+    /// descriptor. Useful to fetch the xpub of a signing device to decrypt this backup.
+    pub fn derivation_paths(&self) -> &[DerivationPath] {
+        &self.derivation_paths
+    }
+
+    /// Decrypt this backup using a public key of the descriptor.
+    pub fn decrypt(&self, key: &secp256k1::PublicKey) -> Result<Vec<u8>, EncryptionError> {
+        let secret = individual_secret(key);
+        let aad = self.header();
+        for individual_secret in &self.individual_secrets {
+            let key = xor(&secret, individual_secret);
+            let cipher = ChaCha20Poly1305::new(&Key::from(key));
+            let payload = Payload {
+                msg: &self.ciphertext,
+                aad: &aad,
+            };
+            if let Ok(content) = cipher.decrypt(&Nonce::from(self.nonce), payload) {
+                return Ok(content);
+            }
+        }
+        Err(EncryptionError::Decryption)
+    }
+
+    /// Decrypt this backup using an xpub of the descriptor.
+    pub fn decrypt_with_xpub(
+        &self,
+        xpub: &bip32::ExtendedPubKey,
+    ) -> Result<Vec<u8>, EncryptionError> {
+        self.decrypt(&xpub.public_key)
+    }
+
+    /// Decrypt this backup using an x-only public key of the descriptor.
+    pub fn decrypt_with_xonly(&self, key: &XOnlyPublicKey) -> Result<Vec<u8>, EncryptionError> {
+        self.decrypt(&key.public_key(secp256k1::Parity::Even))
+    }
+
+    // Everything but the ciphertext, which is authenticated along with it.
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        header.push(VERSION);
+        header.push(self.derivation_paths.len() as u8);
+        for path in &self.derivation_paths {
+            header.push(path.len() as u8);
+            for child in path {
+                header.extend_from_slice(&u32::from(*child).to_be_bytes());
+            }
+        }
+        header.push(self.individual_secrets.len() as u8);
+        for secret in &self.individual_secrets {
+            header.extend_from_slice(secret);
+        }
+        header.extend_from_slice(&self.nonce);
+        header
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header();
+        bytes.extend_from_slice(&self.ciphertext);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EncryptionError> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(EncryptionError::InvalidEncoding(
+                "not an encrypted backup".to_string(),
+            ));
+        }
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(EncryptionError::UnsupportedVersion(version));
+        }
+
+        let n_paths = reader.byte()?;
+        let mut derivation_paths = Vec::with_capacity(n_paths as usize);
+        for _ in 0..n_paths {
+            let n_steps = reader.byte()?;
+            let mut path = Vec::with_capacity(n_steps as usize);
+            for _ in 0..n_steps {
+                let child = reader.take(4)?.try_into().expect("Correct size");
+                path.push(ChildNumber::from(u32::from_be_bytes(child)));
+            }
+            derivation_paths.push(DerivationPath::from(path));
+        }
+
+        let n_secrets = reader.byte()?;
+        let mut individual_secrets = Vec::with_capacity(n_secrets as usize);
+        for _ in 0..n_secrets {
+            individual_secrets.push(reader.take(32)?.try_into().expect("Correct size"));
+        }
+
+        let nonce = reader.take(NONCE_SIZE)?.try_into().expect("Correct size");
+        let ciphertext = reader.0.to_vec();
+        if ciphertext.is_empty() {
+            return Err(EncryptionError::InvalidEncoding(
+                "missing ciphertext".to_string(),
+            ));
+        }
+
+        Ok(EncryptedBackup {
+            derivation_paths,
+            individual_secrets,
+            nonce,
+            ciphertext,
+        })
+    }
+}
+
+impl fmt::Display for EncryptedBackup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", base64::encode(self.to_bytes()))
+    }
+}
+
+impl str::FromStr for EncryptedBackup {
+    type Err = EncryptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = base64::decode(s.trim())
+            .map_err(|e| EncryptionError::InvalidEncoding(e.to_string()))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptors::LianaPolicy;
+    use std::str::FromStr;
+
+    #[test]
+    fn encrypted_backup_roundtrip() {
+        let desc = LianaDescriptor::from_str("wsh(or_d(multi(1,[573fb35b/48'/1'/0'/2']tpubDFKp9T7WAYDcENSjoifkrpq1gMDF47KGJcJrpxzX23Qor8wuGbrEVs9utNq1MDS8E2WXJSBk1qoPQLpwyokW7DiUNPwFuxQkL7owNkLAb9W/<0;1>/*,[573fb35c/48'/1'/1'/2']tpubDFGezyzuHJPhdP3jHGW7v7Hwes4Hihqv5W2yyCmRY9VZJCRchETvxrMC8uECeJZdxQ14V4iD4DecoArkUSDwj8ogYE9WEv4MNZr12thNHCs/<0;1>/*),and_v(v:multi(2,[573fb35b/48'/1'/2'/2']tpubDDwxQauiaU964vPzt5Vd7jnDHEUtp2Vc34PaWpEXg5TQ3bRccxnc1MKKh88Hi7xiMeZo9Tm6fBcq4UGXqnDtGUniJLjqAD8SjQ8Eci3aSR7/<0;1>/*,[573fb35c/48'/1'/3'/2']tpubDE37XAVB5CQ1x85md3BQ5uHCoMwT5fgT8X13zzCUQ3x5o2jskYxKjj7Qcxt1Jpj4QB8tqspn2dooPCekRuQDYrDHov7J1ueUNu2wcvgRDxr/<0;1>/*),older(1000))))#fccaqlhh").unwrap();
+        let backup = EncryptedBackup::encrypt_descriptor(&desc).unwrap();
+        assert_eq!(backup.individual_secrets.len(), 4);
+        assert_eq!(backup.derivation_paths().len(), 4);
+        assert!(backup
+            .derivation_paths()
+            .contains(&DerivationPath::from_str("m/48'/1'/2'/2'").unwrap()));
+
+        // It survives a roundtrip through its encoding.
+        let backup = EncryptedBackup::from_str(&backup.to_string()).unwrap();
+
+        // Any of the xpubs can decrypt it.
+        for xpub in [
+            "tpubDFKp9T7WAYDcENSjoifkrpq1gMDF47KGJcJrpxzX23Qor8wuGbrEVs9utNq1MDS8E2WXJSBk1qoPQLpwyokW7DiUNPwFuxQkL7owNkLAb9W",
+            "tpubDE37XAVB5CQ1x85md3BQ5uHCoMwT5fgT8X13zzCUQ3x5o2jskYxKjj7Qcxt1Jpj4QB8tqspn2dooPCekRuQDYrDHov7J1ueUNu2wcvgRDxr",
+        ] {
+            let xpub = bip32::ExtendedPubKey::from_str(xpub).unwrap();
+            let content = backup.decrypt_with_xpub(&xpub).unwrap();
+            assert_eq!(
+                LianaDescriptor::from_str(str::from_utf8(&content).unwrap()).unwrap(),
+                desc
+            );
+        }
+
+        // But not another one.
+        let other_xpub = bip32::ExtendedPubKey::from_str("tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr").unwrap();
+        assert!(matches!(
+            backup.decrypt_with_xpub(&other_xpub),
+            Err(EncryptionError::Decryption)
+        ));
+
+        // Tampering with the clear part of the backup is detected.
+        let xpub = bip32::ExtendedPubKey::from_str("tpubDFKp9T7WAYDcENSjoifkrpq1gMDF47KGJcJrpxzX23Qor8wuGbrEVs9utNq1MDS8E2WXJSBk1qoPQLpwyokW7DiUNPwFuxQkL7owNkLAb9W").unwrap();
+        let mut tampered = backup.clone();
+        tampered.derivation_paths.pop();
+        assert!(matches!(
+            tampered.decrypt_with_xpub(&xpub),
+            Err(EncryptionError::Decryption)
+        ));
+
+        // Invalid encodings are refused.
+        let mut bytes = backup.to_bytes();
+        bytes[MAGIC.len()] = 2;
+        assert!(matches!(
+            EncryptedBackup::from_bytes(&bytes),
+            Err(EncryptionError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            EncryptedBackup::from_bytes(&backup.to_bytes()[..50]),
+            Err(EncryptionError::InvalidEncoding(_))
+        ));
+        assert!(matches!(
+            EncryptedBackup::from_str(&desc.to_string()),
+            Err(EncryptionError::InvalidEncoding(_))
+        ));
+    }
+
+    #[test]
+    fn encrypted_backup_taproot() {
+        // A Taproot descriptor with a multisig primary path uses the NUMS point as internal key.
+        let desc = LianaDescriptor::from_str("wsh(or_d(multi(2,[573fb35b/48'/1'/0'/2']tpubDFKp9T7WAYDcENSjoifkrpq1gMDF47KGJcJrpxzX23Qor8wuGbrEVs9utNq1MDS8E2WXJSBk1qoPQLpwyokW7DiUNPwFuxQkL7owNkLAb9W/<0;1>/*,[573fb35c/48'/1'/1'/2']tpubDFGezyzuHJPhdP3jHGW7v7Hwes4Hihqv5W2yyCmRY9VZJCRchETvxrMC8uECeJZdxQ14V4iD4DecoArkUSDwj8ogYE9WEv4MNZr12thNHCs/<0;1>/*),and_v(v:pkh([573fb35d/48'/1'/2'/2']tpubDDwxQauiaU964vPzt5Vd7jnDHEUtp2Vc34PaWpEXg5TQ3bRccxnc1MKKh88Hi7xiMeZo9Tm6fBcq4UGXqnDtGUniJLjqAD8SjQ8Eci3aSR7/<0;1>/*),older(1000))))").unwrap();
+        let policy = desc.policy();
+        let tr_desc = LianaDescriptor::new(
+            LianaPolicy::new_taproot(
+                policy.primary_path().clone(),
+                policy.recovery_paths().clone(),
+            )
+            .unwrap(),
+        );
+        let nums_point = secp256k1::PublicKey::from_str(
+            "0250929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0",
+        )
+        .unwrap();
+        let (keys, _) = descriptor_keys(&tr_desc);
+        assert!(!keys.contains(&nums_point));
+
+        // Only the keys of the spending paths can decrypt the backup, not the NUMS point.
+        let backup = EncryptedBackup::encrypt_descriptor(&tr_desc).unwrap();
+        assert_eq!(backup.individual_secrets.len(), 3);
+        assert_eq!(backup.derivation_paths().len(), 3);
+        assert!(matches!(
+            backup.decrypt(&nums_point),
+            Err(EncryptionError::Decryption)
+        ));
+        let xpub = bip32::ExtendedPubKey::from_str("tpubDDwxQauiaU964vPzt5Vd7jnDHEUtp2Vc34PaWpEXg5TQ3bRccxnc1MKKh88Hi7xiMeZo9Tm6fBcq4UGXqnDtGUniJLjqAD8SjQ8Eci3aSR7").unwrap();
+        let content = backup.decrypt_with_xpub(&xpub).unwrap();
+        assert_eq!(
+            LianaDescriptor::from_str(str::from_utf8(&content).unwrap()).unwrap(),
+            tr_desc
+        );
+    }
+}
@@ -26,8 +26,8 @@ impl error::Error for DescKeyError {}
 /// A public key used in derived descriptors
 #[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
 pub struct DerivedPublicKey {
-    /// Fingerprint of the master xpub and the derivation index used. We don't use a path
-    /// since we never derive at more than one depth.
+    /// Fingerprint of the master xpub and the full derivation path from it. This includes the
+    /// (possibly hardened) steps of the origin of the xpub.
     pub origin: (bip32::Fingerprint, bip32::DerivationPath),
     /// The actual key
     pub key: bitcoin::PublicKey,
@@ -41,7 +41,9 @@ impl fmt::Display for DerivedPublicKey {
         for byte in fingerprint.as_bytes().iter() {
             write!(f, "{:02x}", byte)?;
         }
-        write!(f, "/{}", deriv_path)?;
+        for step in deriv_path.into_iter() {
+            write!(f, "/{}", step)?;
+        }
         write!(f, "]{}", self.key)
     }
 }
@@ -51,10 +53,11 @@ impl str::FromStr for DerivedPublicKey {
 
     fn from_str(s: &str) -> Result<DerivedPublicKey, Self::Err> {
         // The key is always of the form:
-        // [ fingerprint / index ]<key>
+        // [ fingerprint / derivation path ]<key>
+        // The derivation path may be empty, or contain hardened steps from the xpub's origin.
 
-        // 1 + 8 + 1 + 1 + 1 + 66 minimum
-        if s.len() < 78 {
+        // 1 + 8 + 1 + 66 minimum
+        if s.len() < 76 {
             return Err(DescKeyError::DerivedKeyParsing);
         }
 
@@ -73,16 +76,20 @@ impl str::FromStr for DerivedPublicKey {
         let fg_deriv = parts.next().ok_or(DescKeyError::DerivedKeyParsing)?;
         let key_str = parts.next().ok_or(DescKeyError::DerivedKeyParsing)?;
 
-        if fg_deriv.len() < 10 {
+        if fg_deriv.len() < 8 {
             return Err(DescKeyError::DerivedKeyParsing);
         }
         let fingerprint = bip32::Fingerprint::from_str(&fg_deriv[..8])
             .map_err(|_| DescKeyError::DerivedKeyParsing)?;
-        let deriv_path = bip32::DerivationPath::from_str(&fg_deriv[9..])
-            .map_err(|_| DescKeyError::DerivedKeyParsing)?;
-        if deriv_path.into_iter().any(bip32::ChildNumber::is_hardened) {
-            return Err(DescKeyError::DerivedKeyParsing);
-        }
+        // We used to serialize the path along with its 'm' prefix, keep accepting it.
+        let deriv_path_str = &fg_deriv[8..];
+        let deriv_path_str = deriv_path_str.strip_prefix("/m").unwrap_or(deriv_path_str);
+        let deriv_path = match deriv_path_str {
+            "" => bip32::DerivationPath::master(),
+            path if path.starts_with('/') => bip32::DerivationPath::from_str(&format!("m{}", path))
+                .map_err(|_| DescKeyError::DerivedKeyParsing)?,
+            _ => return Err(DescKeyError::DerivedKeyParsing),
+        };
 
         let key =
             bitcoin::PublicKey::from_str(key_str).map_err(|_| DescKeyError::DerivedKeyParsing)?;
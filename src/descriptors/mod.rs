@@ -1,13 +1,17 @@
 use miniscript::{
     bitcoin::{
-        self, bip32,
-        psbt::{Input as PsbtIn, Psbt},
+        self, absolute, bip32,
+        psbt::{Input as PsbtIn, Output as PsbtOut, Psbt},
         secp256k1,
+        taproot::{LeafVersion, TapLeafHash},
     },
-    descriptor, translate_hash_clone, ForEachKey, TranslatePk, Translator,
+    descriptor, translate_hash_clone, ForEachKey, ToPublicKey, TranslatePk, Translator,
 };
 
-use std::{collections::BTreeMap, error, fmt, str};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error, fmt, str,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -17,6 +21,18 @@ pub use keys::*;
 pub mod analysis;
 pub use analysis::*;
 
+pub mod description;
+pub use description::*;
+
+pub mod templates;
+pub use templates::*;
+
+pub mod encryption;
+pub use encryption::*;
+
+pub mod registration;
+pub use registration::*;
+
 const WITNESS_FACTOR: usize = 4;
 
 #[derive(Debug)]
@@ -28,6 +44,8 @@ pub enum LianaDescError {
     InsanePsbt,
     /// Not all inputs' sequence the same, not all inputs signed with the same key, ..
     InconsistentPsbt,
+    /// The receive and change descriptors don't only differ by a derivation step of their keys.
+    IncompatibleSinglePathDescs,
 }
 
 impl std::fmt::Display for LianaDescError {
@@ -38,6 +56,10 @@ impl std::fmt::Display for LianaDescError {
             Self::Policy(e) => write!(f, "{}", e),
             Self::InsanePsbt => write!(f, "Analyzed PSBT is empty or malformed."),
             Self::InconsistentPsbt => write!(f, "Analyzed PSBT is inconsistent across inputs."),
+            Self::IncompatibleSinglePathDescs => write!(
+                f,
+                "The receive and change descriptors must be the same but for a single derivation step of each key."
+            ),
         }
     }
 }
@@ -77,11 +99,74 @@ impl fmt::Display for LianaDescriptor {
 impl str::FromStr for LianaDescriptor {
     type Err = LianaDescError;
 
+    /// Parse a descriptor with multipath keys, or a pair of descriptors with single-path keys for
+    /// receiving and change separated by whitespace (for instance one per line, as exported by
+    /// Bitcoin Core). See [`LianaDescriptor::from_single_path_descriptors`].
     fn from_str(s: &str) -> Result<LianaDescriptor, Self::Err> {
-        // Parse a descriptor and check it is a multipath descriptor corresponding to a valid Liana
-        // spending policy.
-        let desc = descriptor::Descriptor::<descriptor::DescriptorPublicKey>::from_str(s)
-            .map_err(LianaDescError::Miniscript)?;
+        let parse = |s| {
+            descriptor::Descriptor::<descriptor::DescriptorPublicKey>::from_str(s)
+                .map_err(LianaDescError::Miniscript)
+        };
+        let mut descs = s.split_whitespace();
+        match (descs.next(), descs.next(), descs.next()) {
+            (Some(receive_desc), Some(change_desc), None) => {
+                LianaDescriptor::from_single_path_descriptors(
+                    &parse(receive_desc)?,
+                    &parse(change_desc)?,
+                )
+            }
+            _ => LianaDescriptor::from_multipath_desc(parse(s)?),
+        }
+    }
+}
+
+// Merge two single-path keys for the same xpub, whose derivation paths differ at exactly one
+// step, into a multipath key. For instance `xpub/0/*` and `xpub/1/*` into `xpub/<0;1>/*`.
+fn merge_single_path_keys(
+    receive_key: &descriptor::DescriptorPublicKey,
+    change_key: &descriptor::DescriptorPublicKey,
+) -> Option<descriptor::DescriptorPublicKey> {
+    match (receive_key, change_key) {
+        (
+            descriptor::DescriptorPublicKey::XPub(recv_xpub),
+            descriptor::DescriptorPublicKey::XPub(change_xpub),
+        ) => {
+            let (recv_path, change_path) =
+                (&recv_xpub.derivation_path, &change_xpub.derivation_path);
+            let n_diff_steps = recv_path
+                .into_iter()
+                .zip(change_path)
+                .filter(|(r, c)| r != c)
+                .count();
+            if recv_xpub.origin != change_xpub.origin
+                || recv_xpub.xkey != change_xpub.xkey
+                || recv_xpub.wildcard != change_xpub.wildcard
+                || recv_path.len() != change_path.len()
+                || n_diff_steps != 1
+            {
+                return None;
+            }
+            let derivation_paths =
+                descriptor::DerivPaths::new(vec![recv_path.clone(), change_path.clone()])?;
+            Some(descriptor::DescriptorPublicKey::MultiXPub(
+                descriptor::DescriptorMultiXKey {
+                    origin: recv_xpub.origin.clone(),
+                    xkey: recv_xpub.xkey,
+                    derivation_paths,
+                    wildcard: recv_xpub.wildcard,
+                },
+            ))
+        }
+        _ => None,
+    }
+}
+
+impl LianaDescriptor {
+    // Check the given descriptor is a multipath descriptor corresponding to a valid Liana spending
+    // policy.
+    fn from_multipath_desc(
+        desc: descriptor::Descriptor<descriptor::DescriptorPublicKey>,
+    ) -> Result<LianaDescriptor, LianaDescError> {
         LianaPolicy::from_multipath_descriptor(&desc)?;
 
         // Compute the receive and change "sub" descriptors right away. According to our pubkey
@@ -103,6 +188,61 @@ impl str::FromStr for LianaDescriptor {
             change_desc,
         })
     }
+
+    /// Create a Liana descriptor from a pair of descriptors with single-path keys, one for
+    /// receiving and one for change. For instance using `xpub/0/*` and `xpub/1/*` respectively.
+    /// The keys at the same position in both descriptors must be for the same xpub and only differ
+    /// by a single derivation step. They are merged into multipath keys (`xpub/<0;1>/*`), such as
+    /// the receive and change descriptors of the resulting descriptor are the given ones.
+    pub fn from_single_path_descriptors(
+        receive_desc: &descriptor::Descriptor<descriptor::DescriptorPublicKey>,
+        change_desc: &descriptor::Descriptor<descriptor::DescriptorPublicKey>,
+    ) -> Result<LianaDescriptor, LianaDescError> {
+        let desc_keys = |desc: &descriptor::Descriptor<descriptor::DescriptorPublicKey>| {
+            let mut keys = Vec::new();
+            desc.for_each_key(|k| {
+                keys.push(k.clone());
+                true
+            });
+            keys
+        };
+        let (recv_keys, change_keys) = (desc_keys(receive_desc), desc_keys(change_desc));
+        if recv_keys.len() != change_keys.len() {
+            return Err(LianaDescError::IncompatibleSinglePathDescs);
+        }
+        let multi_keys = recv_keys
+            .iter()
+            .zip(change_keys.iter())
+            .map(|(r, c)| Some((r.clone(), merge_single_path_keys(r, c)?)))
+            .collect::<Option<HashMap<_, _>>>()
+            .ok_or(LianaDescError::IncompatibleSinglePathDescs)?;
+
+        struct Merger(HashMap<descriptor::DescriptorPublicKey, descriptor::DescriptorPublicKey>);
+        impl Translator<descriptor::DescriptorPublicKey, descriptor::DescriptorPublicKey, ()> for Merger {
+            fn pk(
+                &mut self,
+                pk: &descriptor::DescriptorPublicKey,
+            ) -> Result<descriptor::DescriptorPublicKey, ()> {
+                self.0.get(pk).cloned().ok_or(())
+            }
+            translate_hash_clone!(
+                descriptor::DescriptorPublicKey,
+                descriptor::DescriptorPublicKey,
+                ()
+            );
+        }
+        let multi_desc = receive_desc
+            .translate_pk(&mut Merger(multi_keys))
+            .map_err(|_| LianaDescError::IncompatibleSinglePathDescs)?;
+
+        // Make sure the merged descriptor derives to the given ones. This rules out descriptors
+        // which differ in more than their keys.
+        let desc = LianaDescriptor::from_multipath_desc(multi_desc)?;
+        if desc.receive_desc != *receive_desc || desc.change_desc != *change_desc {
+            return Err(LianaDescError::IncompatibleSinglePathDescs);
+        }
+        Ok(desc)
+    }
 }
 
 impl fmt::Display for SinglePathLianaDesc {
@@ -163,14 +303,19 @@ impl LianaDescriptor {
         &self.change_desc
     }
 
+    /// Whether this is a Taproot descriptor.
+    pub fn is_taproot(&self) -> bool {
+        matches!(self.multi_desc, descriptor::Descriptor::Tr(..))
+    }
+
     /// Get the spending policy of this descriptor.
     pub fn policy(&self) -> LianaPolicy {
         LianaPolicy::from_multipath_descriptor(&self.multi_desc)
             .expect("We never create a Liana descriptor with an invalid Liana policy.")
     }
 
-    /// Get the value (in blocks) of the smallest relative timelock of the recovery paths.
-    pub fn first_timelock_value(&self) -> u16 {
+    /// Get the smallest relative timelock of the recovery paths.
+    pub fn first_timelock(&self) -> RecoveryTimelock {
         *self
             .policy()
             .recovery_paths
@@ -225,23 +370,39 @@ impl LianaDescriptor {
         &self,
         psbt_in: &PsbtIn,
         txin: &bitcoin::TxIn,
+        lock_time: absolute::LockTime,
     ) -> PartialSpendInfo {
-        // Get the identifier of all the keys that signed this transaction.
-        let pubkeys_signed = psbt_in
-            .partial_sigs
-            .iter()
-            .filter_map(|(pk, _)| psbt_in.bip32_derivation.get(&pk.inner));
+        // Get the identifier of all the keys that signed this transaction. For Taproot, they may
+        // have signed for the key path or for a script path.
+        let pubkeys_signed: Vec<&(bip32::Fingerprint, bip32::DerivationPath)> = if self.is_taproot()
+        {
+            psbt_in
+                .tap_script_sigs
+                .keys()
+                .map(|(xpk, _)| xpk)
+                .chain(psbt_in.tap_key_sig.and(psbt_in.tap_internal_key.as_ref()))
+                .filter_map(|xpk| psbt_in.tap_key_origins.get(xpk).map(|(_, origin)| origin))
+                .collect()
+        } else {
+            psbt_in
+                .partial_sigs
+                .iter()
+                .filter_map(|(pk, _)| psbt_in.bip32_derivation.get(&pk.inner))
+                .collect()
+        };
+        let pubkeys_signed = pubkeys_signed.into_iter();
 
         // Determine the structure of the descriptor. Then compute the spend info for the primary
         // and recovery paths. Only provide the spend info for the recovery path if it is available
-        // (ie if the nSequence is >= to the chosen CSV value).
+        // (ie if the nSequence is >= to the chosen CSV value, or the nLockTime is >= to the chosen
+        // CLTV value, in the same unit).
         let desc_info = self.policy();
         let primary_path = desc_info.primary_path.spend_info(pubkeys_signed.clone());
         let recovery_paths = desc_info
             .recovery_paths
             .iter()
             .filter_map(|(timelock, path_info)| {
-                if txin.sequence.is_height_locked() && txin.sequence.0 >= *timelock as u32 {
+                if timelock.is_satisfied_by(txin.sequence, lock_time) {
                     Some((*timelock, path_info.spend_info(pubkeys_signed.clone())))
                 } else {
                     None
@@ -286,12 +447,14 @@ impl LianaDescriptor {
                 .expect("We checked at least one is present."),
             txins.next().expect("We checked at least one is present."),
         );
-        let spend_info = self.partial_spend_info_txin(first_psbt_in, first_txin);
+        let spend_info =
+            self.partial_spend_info_txin(first_psbt_in, first_txin, psbt.unsigned_tx.lock_time);
         for (psbt_in, txin) in psbt_ins.zip(txins) {
             // TODO: maybe it's better to not error if one of the input has more, or different
             // signatures? Instead of erroring we could ignore the superfluous data?
             if txin.sequence != first_txin.sequence
-                || spend_info != self.partial_spend_info_txin(psbt_in, txin)
+                || spend_info
+                    != self.partial_spend_info_txin(psbt_in, txin, psbt.unsigned_tx.lock_time)
             {
                 return Err(LianaDescError::InconsistentPsbt);
             }
@@ -363,36 +526,109 @@ impl SinglePathLianaDesc {
 /// Map of a raw public key to the xpub used to derive it and its derivation path
 pub type Bip32Deriv = BTreeMap<secp256k1::PublicKey, (bip32::Fingerprint, bip32::DerivationPath)>;
 
+/// Map of a raw x-only public key to the leaves it appears in and the xpub used to derive it and
+/// its derivation path
+pub type TapDeriv = BTreeMap<
+    bitcoin::key::XOnlyPublicKey,
+    (
+        Vec<TapLeafHash>,
+        (bip32::Fingerprint, bip32::DerivationPath),
+    ),
+>;
+
 impl DerivedSinglePathLianaDesc {
     pub fn address(&self, network: bitcoin::Network) -> bitcoin::Address {
         self.0
             .address(network)
-            .expect("A P2WSH always has an address")
+            .expect("A P2WSH or P2TR always has an address")
     }
 
     pub fn script_pubkey(&self) -> bitcoin::ScriptBuf {
         self.0.script_pubkey()
     }
 
+    /// Get the witness script of a P2WSH descriptor.
+    ///
+    /// # Panics
+    /// - If this is a Taproot descriptor.
     pub fn witness_script(&self) -> bitcoin::ScriptBuf {
         self.0.explicit_script().expect("Not a Taproot descriptor")
     }
 
     pub fn bip32_derivations(&self) -> Bip32Deriv {
-        let ms = match self.0 {
-            descriptor::Descriptor::Wsh(ref wsh) => match wsh.as_inner() {
-                descriptor::WshInner::Ms(ms) => ms,
-                descriptor::WshInner::SortedMulti(_) => {
-                    unreachable!("None of our descriptors is a sorted multi")
-                }
-            },
-            _ => unreachable!("All our descriptors are always P2WSH"),
+        let mut derivs = BTreeMap::new();
+        self.0.for_each_key(|k| {
+            derivs.insert(k.key.inner, (k.origin.0, k.origin.1.clone()));
+            true
+        });
+        derivs
+    }
+
+    /// Get the origin of all the keys in a Taproot descriptor along with the hashes of the
+    /// leaves they appear in. The internal key has no leaf hash.
+    ///
+    /// # Panics
+    /// - If this is not a Taproot descriptor.
+    pub fn tap_key_origins(&self) -> TapDeriv {
+        let tr = match self.0 {
+            descriptor::Descriptor::Tr(ref tr) => tr,
+            _ => unreachable!("Must be a Taproot descriptor"),
         };
+        let internal_key = tr.internal_key();
+        let mut origins = TapDeriv::new();
+        origins.insert(
+            internal_key.to_x_only_pubkey(),
+            (
+                Vec::new(),
+                (internal_key.origin.0, internal_key.origin.1.clone()),
+            ),
+        );
+        for (_, ms) in tr.iter_scripts() {
+            let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+            for key in ms.iter_pk() {
+                origins
+                    .entry(key.to_x_only_pubkey())
+                    .or_insert_with(|| (Vec::new(), (key.origin.0, key.origin.1.clone())))
+                    .0
+                    .push(leaf_hash);
+            }
+        }
+        origins
+    }
 
-        // For DerivedPublicKey, Pk::Hash == Self.
-        ms.iter_pk()
-            .map(|k| (k.key.inner, (k.origin.0, k.origin.1)))
-            .collect()
+    /// Populate a PSBT input spending a coin for this descriptor with the information needed by
+    /// signers: the witness script and keys origins for P2WSH, the internal key, Merkle root,
+    /// leaf scripts and keys origins for Taproot.
+    pub fn update_psbt_in(&self, psbt_in: &mut PsbtIn) {
+        if let descriptor::Descriptor::Tr(ref tr) = self.0 {
+            let spend_info = tr.spend_info();
+            psbt_in.tap_internal_key = Some(spend_info.internal_key());
+            psbt_in.tap_merkle_root = spend_info.merkle_root();
+            for (_, ms) in tr.iter_scripts() {
+                let script = ms.encode();
+                let control_block = spend_info
+                    .control_block(&(script.clone(), LeafVersion::TapScript))
+                    .expect("The leaf is part of this tree");
+                psbt_in
+                    .tap_scripts
+                    .insert(control_block, (script, LeafVersion::TapScript));
+            }
+            psbt_in.tap_key_origins = self.tap_key_origins();
+        } else {
+            psbt_in.witness_script = Some(self.witness_script());
+            psbt_in.bip32_derivation = self.bip32_derivations();
+        }
+    }
+
+    /// Populate a PSBT output paying to this descriptor with the origins of the keys, for signers
+    /// to be able to identify it as ours.
+    pub fn update_psbt_out(&self, psbt_out: &mut PsbtOut) {
+        if let descriptor::Descriptor::Tr(ref tr) = self.0 {
+            psbt_out.tap_internal_key = Some(tr.internal_key().to_x_only_pubkey());
+            psbt_out.tap_key_origins = self.tap_key_origins();
+        } else {
+            psbt_out.bip32_derivation = self.bip32_derivations();
+        }
     }
 }
 
@@ -429,7 +665,7 @@ mod tests {
     fn descriptor_creation() {
         let owner_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap());
         let heir_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap());
-        let timelock = 52560;
+        let timelock = RecoveryTimelock::Blocks(52560);
         let policy = LianaPolicy::new(
             owner_key.clone(),
             [(timelock, heir_key.clone())].iter().cloned().collect(),
@@ -460,7 +696,10 @@ mod tests {
         );
         let policy = LianaPolicy::new(
             primary_keys,
-            [(26352, recovery_keys)].iter().cloned().collect(),
+            [(RecoveryTimelock::Blocks(26352), recovery_keys)]
+                .iter()
+                .cloned()
+                .collect(),
         )
         .unwrap();
         assert_eq!(LianaDescriptor::new(policy).to_string(), "wsh(or_d(multi(3,[aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/0/<0;1>/*,[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/0/<0;1>/*,[aabb0013/48'/0'/0'/2']xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/0/<0;1>/*),and_v(v:thresh(2,pkh([aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/1/<0;1>/*),a:pkh([aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/1/<0;1>/*),a:pkh([aabb0013/48'/0'/0'/2']xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/1/<0;1>/*)),older(26352))))#prj7nktq");
@@ -487,7 +726,10 @@ mod tests {
         );
         let policy = LianaPolicy::new(
             primary_keys,
-            [(26352, recovery_keys)].iter().cloned().collect(),
+            [(RecoveryTimelock::Blocks(26352), recovery_keys)]
+                .iter()
+                .cloned()
+                .collect(),
         )
         .unwrap();
         assert_eq!(LianaDescriptor::new(policy).to_string(), "wsh(or_d(multi(3,[aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*,[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*,[aabb0013/48'/0'/0'/2']xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/<0;1>/*),and_v(v:thresh(2,pkh([aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<2;3>/*),a:pkh([aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<2;3>/*),a:pkh([aabb0013/48'/0'/0'/2']xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/<2;3>/*)),older(26352))))#d2h994td");
@@ -499,11 +741,18 @@ mod tests {
         //LianaPolicy::new(owner_key, heir_key, (1 << 22) + 1).unwrap_err();
 
         // You can't use a null timelock in Miniscript.
-        LianaPolicy::new(owner_key, [(0, heir_key)].iter().cloned().collect()).unwrap_err();
+        LianaPolicy::new(
+            owner_key,
+            [(RecoveryTimelock::Blocks(0), heir_key)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .unwrap_err();
 
         let owner_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabb0011/10/4893]xpub661MyMwAqRbcFG59fiikD8UV762quhruT8K8bdjqy6N2o3LG7yohoCdLg1m2HAY1W6rfBrtauHkBhbfA4AQ3iazaJj5wVPhwgaRCHBW2DBg/<0;1>/*").unwrap());
         let heir_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub661MyMwAqRbcFfxf71L4Dx4w5TmyNXrBicTEAM7vLzumxangwATWWgdJPb6xH1JHcJH9S3jNZx3fCnkkB1WyqrqGgavj1rehHcbythmruvZ/24/32/<0;1>/*").unwrap());
-        let timelock = 57600;
+        let timelock = RecoveryTimelock::Blocks(57600);
         let policy = LianaPolicy::new(
             owner_key.clone(),
             [(timelock, heir_key)].iter().cloned().collect(),
@@ -563,7 +812,10 @@ mod tests {
         );
         LianaPolicy::new(
             primary_keys,
-            [(26352, recovery_keys)].iter().cloned().collect(),
+            [(RecoveryTimelock::Blocks(26352), recovery_keys)]
+                .iter()
+                .cloned()
+                .collect(),
         )
         .unwrap_err();
 
@@ -597,7 +849,10 @@ mod tests {
         );
         let err = LianaPolicy::new(
             primary_keys,
-            [(26352, recovery_keys)].iter().cloned().collect(),
+            [(RecoveryTimelock::Blocks(26352), recovery_keys)]
+                .iter()
+                .cloned()
+                .collect(),
         )
         .unwrap_err();
         assert!(matches!(err, LianaPolicyError::DuplicateKey(_)));
@@ -613,7 +868,10 @@ mod tests {
         let recovery_keys = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef02]xpub69cP4Y7S9TWcbSNxmk6CEDBsoaqr3ZEdjHuZcHxEFFKGh569RsJNr2V27XGhsbH9FXgWUEmKXRN7c5wQfq2VPjt31xP9VsYnVUyU8HcVevm/<0;1>/*").unwrap());
         let err = LianaPolicy::new(
             primary_keys,
-            [(26352, recovery_keys)].iter().cloned().collect(),
+            [(RecoveryTimelock::Blocks(26352), recovery_keys)]
+                .iter()
+                .cloned()
+                .collect(),
         )
         .unwrap_err();
         assert!(matches!(err, LianaPolicyError::DuplicateOriginSamePath(_)));
@@ -629,7 +887,10 @@ mod tests {
         let primary_keys = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef02]xpub69cP4Y7S9TWcbSNxmk6CEDBsoaqr3ZEdjHuZcHxEFFKGh569RsJNr2V27XGhsbH9FXgWUEmKXRN7c5wQfq2VPjt31xP9VsYnVUyU8HcVevm/<0;1>/*").unwrap());
         let err = LianaPolicy::new(
             primary_keys,
-            [(26352, recovery_keys)].iter().cloned().collect(),
+            [(RecoveryTimelock::Blocks(26352), recovery_keys)]
+                .iter()
+                .cloned()
+                .collect(),
         )
         .unwrap_err();
         assert!(matches!(err, LianaPolicyError::DuplicateOriginSamePath(_)));
@@ -645,20 +906,23 @@ mod tests {
         let recovery_keys = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub69cP4Y7S9TWcbSNxmk6CEDBsoaqr3ZEdjHuZcHxEFFKGh569RsJNr2V27XGhsbH9FXgWUEmKXRN7c5wQfq2VPjt31xP9VsYnVUyU8HcVevm/<0;1>/*").unwrap());
         LianaPolicy::new(
             primary_keys,
-            [(26352, recovery_keys)].iter().cloned().collect(),
+            [(RecoveryTimelock::Blocks(26352), recovery_keys)]
+                .iter()
+                .cloned()
+                .collect(),
         )
         .unwrap();
 
         // No origin in one of the keys
         let owner_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap());
         let heir_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap());
-        let timelock = 52560;
+        let timelock = RecoveryTimelock::Blocks(52560);
         LianaPolicy::new(owner_key, [(timelock, heir_key)].iter().cloned().collect()).unwrap_err();
 
         // One of the xpub isn't normalized.
         let owner_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap());
         let heir_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabbccdd]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/42'/<0;1>/*").unwrap());
-        let timelock = 52560;
+        let timelock = RecoveryTimelock::Blocks(52560);
         LianaPolicy::new(owner_key, [(timelock, heir_key)].iter().cloned().collect()).unwrap_err();
 
         // A 1-of-N multisig as primary path.
@@ -687,13 +951,13 @@ mod tests {
         LianaDescriptor::from_str("wsh(or_i(pk([abcdef01]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),pk([abcdef01]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))").unwrap_err();
 
         let desc = LianaDescriptor::from_str("wsh(andor(pk([abcdef01]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(1),pk([abcdef01]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))").unwrap();
-        assert_eq!(desc.first_timelock_value(), 1);
+        assert_eq!(desc.first_timelock(), RecoveryTimelock::Blocks(1));
 
         let desc = LianaDescriptor::from_str("wsh(andor(pk([abcdef01]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(42000),pk([abcdef01]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))").unwrap();
-        assert_eq!(desc.first_timelock_value(), 42000);
+        assert_eq!(desc.first_timelock(), RecoveryTimelock::Blocks(42000));
 
         let desc = LianaDescriptor::from_str("wsh(andor(pk([abcdef01]tpubDEN9WSToTyy9ZQfaYqSKfmVqmq1VVLNtYfj3Vkqh67et57eJ5sTKZQBkHqSwPUsoSskJeaYnPttHe2VrkCsKA27kUaN9SDc5zhqeLzKa1rr/<0;1>/*),older(65535),pk([abcdef01]tpubD8LYfn6njiA2inCoxwM7EuN3cuLVcaHAwLYeups13dpevd3nHLRdK9NdQksWXrhLQVxcUZRpnp5CkJ1FhE61WRAsHxDNAkvGkoQkAeWDYjV/<0;1>/*)))").unwrap();
-        assert_eq!(desc.first_timelock_value(), 0xffff);
+        assert_eq!(desc.first_timelock(), RecoveryTimelock::Blocks(0xffff));
     }
 
     #[test]
@@ -733,16 +997,19 @@ mod tests {
 
         LianaPolicy::new(
             prim_path.clone(),
-            [(1, PathInfo::Multi(2, vec![random_desc_key(&secp)]))]
-                .iter()
-                .cloned()
-                .collect(),
+            [(
+                RecoveryTimelock::Blocks(1),
+                PathInfo::Multi(2, vec![random_desc_key(&secp)]),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
         )
         .unwrap_err();
         LianaPolicy::new(
             prim_path.clone(),
             [(
-                1,
+                RecoveryTimelock::Blocks(1),
                 PathInfo::Multi(1, vec![random_desc_key(&secp), random_desc_key(&secp)]),
             )]
             .iter()
@@ -753,7 +1020,7 @@ mod tests {
         LianaPolicy::new(
             prim_path.clone(),
             [(
-                1,
+                RecoveryTimelock::Blocks(1),
                 PathInfo::Multi(0, vec![random_desc_key(&secp), random_desc_key(&secp)]),
             )]
             .iter()
@@ -764,7 +1031,7 @@ mod tests {
         LianaPolicy::new(
             prim_path.clone(),
             [(
-                1,
+                RecoveryTimelock::Blocks(1),
                 PathInfo::Multi(2, vec![random_desc_key(&secp), random_desc_key(&secp)]),
             )]
             .iter()
@@ -775,7 +1042,7 @@ mod tests {
         LianaPolicy::new(
             prim_path.clone(),
             [(
-                1,
+                RecoveryTimelock::Blocks(1),
                 PathInfo::Multi(3, vec![random_desc_key(&secp), random_desc_key(&secp)]),
             )]
             .iter()
@@ -785,28 +1052,506 @@ mod tests {
         .unwrap_err();
         LianaPolicy::new(
             prim_path.clone(),
-            [(1, PathInfo::Multi(3, twenty_eight_keys.clone()))]
-                .iter()
-                .cloned()
-                .collect(),
+            [(
+                RecoveryTimelock::Blocks(1),
+                PathInfo::Multi(3, twenty_eight_keys.clone()),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
         )
         .unwrap();
         LianaPolicy::new(
             prim_path.clone(),
-            [(1, PathInfo::Multi(20, twenty_eight_keys))]
-                .iter()
-                .cloned()
-                .collect(),
+            [(
+                RecoveryTimelock::Blocks(1),
+                PathInfo::Multi(20, twenty_eight_keys),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
         )
         .unwrap();
         LianaPolicy::new(
             prim_path,
-            [(1, PathInfo::Multi(20, twenty_nine_keys))]
+            [(
+                RecoveryTimelock::Blocks(1),
+                PathInfo::Multi(20, twenty_nine_keys),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn time_based_timelocks() {
+        // Parsing of the consensus encoding of relative timelocks.
+        assert_eq!(
+            RecoveryTimelock::from_consensus(1).unwrap(),
+            RecoveryTimelock::Blocks(1)
+        );
+        assert_eq!(
+            RecoveryTimelock::from_consensus((1 << 22) + 4320).unwrap(),
+            RecoveryTimelock::Time(4320)
+        );
+        RecoveryTimelock::from_consensus(0).unwrap_err();
+        RecoveryTimelock::from_consensus(1 << 22).unwrap_err();
+        RecoveryTimelock::from_consensus(0x00_01_0f_00).unwrap_err();
+        RecoveryTimelock::from_consensus((1 << 31) + 1).unwrap_err();
+        RecoveryTimelock::from_consensus((1 << 23) + 1).unwrap_err();
+
+        // Timelocks are ordered by duration, regardless of their unit.
+        assert!(RecoveryTimelock::Time(4320) < RecoveryTimelock::Blocks(26352));
+        assert!(RecoveryTimelock::Blocks(4320) < RecoveryTimelock::Time(26352));
+        assert!(RecoveryTimelock::Blocks(512) < RecoveryTimelock::Time(600));
+        assert_eq!(RecoveryTimelock::Time(4320).estimated_blocks(), Some(3687));
+
+        // Both units may be used in the same descriptor.
+        let owner_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap());
+        let heir_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap());
+        let lawyer_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*").unwrap());
+        let policy = LianaPolicy::new(
+            owner_key,
+            [
+                (RecoveryTimelock::Blocks(26352), heir_key),
+                (RecoveryTimelock::Time(4320), lawyer_key),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap();
+        let desc = LianaDescriptor::new(policy.clone());
+        let desc_str = desc.to_string();
+        assert!(desc_str.contains("older(26352)"));
+        assert!(desc_str.contains("older(4198624)"));
+        let parsed_desc = LianaDescriptor::from_str(&desc_str).unwrap();
+        assert_eq!(parsed_desc.policy(), policy);
+        assert_eq!(desc.first_timelock(), RecoveryTimelock::Time(4320));
+
+        // Only the recovery paths whose timelock is satisfied by the nSequence, in the same unit,
+        // are available.
+        let psbt_in = PsbtIn::default();
+        let mut txin = bitcoin::TxIn {
+            sequence: Sequence::from_height(26352),
+            ..bitcoin::TxIn::default()
+        };
+        let info = desc.partial_spend_info_txin(&psbt_in, &txin, absolute::LockTime::ZERO);
+        assert_eq!(
+            info.recovery_paths.keys().collect::<Vec<_>>(),
+            vec![&RecoveryTimelock::Blocks(26352)]
+        );
+        txin.sequence = Sequence::from_height(26351);
+        let info = desc.partial_spend_info_txin(&psbt_in, &txin, absolute::LockTime::ZERO);
+        assert!(info.recovery_paths.is_empty());
+        txin.sequence = Sequence::from_512_second_intervals(4320);
+        let info = desc.partial_spend_info_txin(&psbt_in, &txin, absolute::LockTime::ZERO);
+        assert_eq!(
+            info.recovery_paths.keys().collect::<Vec<_>>(),
+            vec![&RecoveryTimelock::Time(4320)]
+        );
+        txin.sequence = Sequence::from_512_second_intervals(4319);
+        let info = desc.partial_spend_info_txin(&psbt_in, &txin, absolute::LockTime::ZERO);
+        assert!(info.recovery_paths.is_empty());
+
+        // The same goes for a Taproot descriptor.
+        let tr_policy = LianaPolicy::new_taproot(
+            policy.primary_path().clone(),
+            policy.recovery_paths().clone(),
+        )
+        .unwrap();
+        let tr_desc = LianaDescriptor::new(tr_policy.clone());
+        assert!(tr_desc.to_string().contains("older(4198624)"));
+        assert_eq!(
+            LianaDescriptor::from_str(&tr_desc.to_string())
+                .unwrap()
+                .policy(),
+            tr_policy
+        );
+    }
+
+    #[test]
+    fn absolute_timelocks() {
+        assert_eq!(
+            RecoveryTimelock::from_absolute_consensus(840_000).unwrap(),
+            RecoveryTimelock::AbsoluteHeight(840_000)
+        );
+        assert_eq!(
+            RecoveryTimelock::from_absolute_consensus(1_900_000_000).unwrap(),
+            RecoveryTimelock::AbsoluteTime(1_900_000_000)
+        );
+        RecoveryTimelock::from_absolute_consensus(0).unwrap_err();
+
+        // Absolute timelocks come after all relative ones, heights first.
+        assert!(RecoveryTimelock::Time(0xffff) < RecoveryTimelock::AbsoluteHeight(1));
+        assert!(
+            RecoveryTimelock::AbsoluteHeight(499_999_999)
+                < RecoveryTimelock::AbsoluteTime(500_000_000)
+        );
+        assert_eq!(RecoveryTimelock::AbsoluteHeight(1).estimated_blocks(), None);
+
+        let owner_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap());
+        let heir_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap());
+        let trustee_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*").unwrap());
+        let lawyer_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabb0013/48'/0'/0'/2']xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/<0;1>/*").unwrap());
+
+        // The absolute timelocks must be in the range of their unit.
+        LianaPolicy::new(
+            owner_key.clone(),
+            [(
+                RecoveryTimelock::AbsoluteHeight(500_000_000),
+                heir_key.clone(),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap_err();
+        LianaPolicy::new(
+            owner_key.clone(),
+            [(
+                RecoveryTimelock::AbsoluteTime(499_999_999),
+                heir_key.clone(),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap_err();
+        LianaPolicy::new(
+            owner_key.clone(),
+            [(RecoveryTimelock::AbsoluteHeight(0), heir_key.clone())]
                 .iter()
                 .cloned()
                 .collect(),
         )
         .unwrap_err();
+
+        // Relative and absolute timelocks, in both units, may be mixed in a single policy.
+        let policy = LianaPolicy::new(
+            owner_key.clone(),
+            [
+                (RecoveryTimelock::Blocks(26352), heir_key.clone()),
+                (RecoveryTimelock::AbsoluteHeight(1_000_000), trustee_key),
+                (RecoveryTimelock::AbsoluteTime(1_900_000_000), lawyer_key),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap();
+        let desc = LianaDescriptor::new(policy.clone());
+        let desc_str = desc.to_string();
+        assert!(desc_str.contains("older(26352)"));
+        assert!(desc_str.contains("after(1000000)"));
+        assert!(desc_str.contains("after(1900000000)"));
+        let parsed_desc = LianaDescriptor::from_str(&desc_str).unwrap();
+        assert_eq!(parsed_desc.policy(), policy);
+        assert_eq!(desc.first_timelock(), RecoveryTimelock::Blocks(26352));
+        let tr_policy = LianaPolicy::new_taproot(
+            policy.primary_path().clone(),
+            policy.recovery_paths().clone(),
+        )
+        .unwrap();
+        let tr_desc = LianaDescriptor::new(tr_policy.clone());
+        assert!(tr_desc.to_string().contains("after(1000000)"));
+        assert_eq!(
+            LianaDescriptor::from_str(&tr_desc.to_string())
+                .unwrap()
+                .policy(),
+            tr_policy
+        );
+
+        // An absolute timelock recovery path is available if the nLockTime is set to at least its
+        // value, in the same unit, and the nSequence doesn't disable it.
+        let psbt_in = PsbtIn::default();
+        let txin = bitcoin::TxIn {
+            sequence: RecoveryTimelock::AbsoluteHeight(1_000_000).sequence(),
+            ..bitcoin::TxIn::default()
+        };
+        let available_paths = |txin: &bitcoin::TxIn, lock_time: u32| {
+            desc.partial_spend_info_txin(
+                &psbt_in,
+                txin,
+                absolute::LockTime::from_consensus(lock_time),
+            )
+            .recovery_paths
+            .into_keys()
+            .collect::<Vec<_>>()
+        };
+        assert!(available_paths(&txin, 999_999).is_empty());
+        assert_eq!(
+            available_paths(&txin, 1_000_000),
+            vec![RecoveryTimelock::AbsoluteHeight(1_000_000)]
+        );
+        assert_eq!(
+            available_paths(&txin, 1_900_000_000),
+            vec![RecoveryTimelock::AbsoluteTime(1_900_000_000)]
+        );
+        let final_txin = bitcoin::TxIn {
+            sequence: Sequence::MAX,
+            ..bitcoin::TxIn::default()
+        };
+        assert!(available_paths(&final_txin, 1_000_000).is_empty());
+
+        // A recovery path may not have both a relative and an absolute timelock.
+        let owner_key = "[abcdef01]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*";
+        let heir_key = "[abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*";
+        LianaDescriptor::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:and_v(v:pkh({}),older(10)),after(100))))",
+            owner_key, heir_key
+        ))
+        .unwrap_err();
+    }
+
+    #[test]
+    fn nested_thresholds() {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let (ceo, cfo, board_a, board_b) = (
+            random_desc_key(&secp),
+            random_desc_key(&secp),
+            random_desc_key(&secp),
+            random_desc_key(&secp),
+        );
+        let (heir, lawyer_a, lawyer_b) = (
+            random_desc_key(&secp),
+            random_desc_key(&secp),
+            random_desc_key(&secp),
+        );
+
+        // 2 of (CEO, CFO, 1 of 2 board members) as the primary path. As recovery paths, the heir
+        // along with 1 of 2 lawyers or later 2 of (2 other heirs, 2 other lawyers together). The
+        // sub-paths are given in the order they are compiled to, so the policy roundtrips as is.
+        let prim_path = PathInfo::Thresh(
+            2,
+            vec![
+                PathInfo::Multi(1, vec![board_a.clone(), board_b.clone()]),
+                PathInfo::Single(ceo.clone()),
+                PathInfo::Single(cfo.clone()),
+            ],
+        );
+        let first_reco_path = PathInfo::Thresh(
+            2,
+            vec![
+                PathInfo::Single(heir.clone()),
+                PathInfo::Multi(1, vec![lawyer_a.clone(), lawyer_b.clone()]),
+            ],
+        );
+        let sec_reco_path = PathInfo::Thresh(
+            2,
+            vec![
+                PathInfo::Multi(2, vec![random_desc_key(&secp), random_desc_key(&secp)]),
+                PathInfo::Single(random_desc_key(&secp)),
+                PathInfo::Single(random_desc_key(&secp)),
+            ],
+        );
+        let recovery_paths: BTreeMap<_, _> = [
+            (RecoveryTimelock::Blocks(4032), first_reco_path.clone()),
+            (RecoveryTimelock::Time(8000), sec_reco_path.clone()),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        for policy in [
+            LianaPolicy::new(prim_path.clone(), recovery_paths.clone()).unwrap(),
+            LianaPolicy::new_taproot(prim_path.clone(), recovery_paths.clone()).unwrap(),
+        ] {
+            let desc = LianaDescriptor::new(policy.clone());
+            let parsed_desc = LianaDescriptor::from_str(&desc.to_string()).unwrap();
+            assert_eq!(parsed_desc.policy(), policy);
+        }
+        assert_eq!(prim_path.keys().len(), 4);
+        let (thresh, origins) = prim_path.thresh_origins();
+        assert_eq!(thresh, 2);
+        assert_eq!(origins.len(), 4);
+
+        // A 1-of-N primary path of nested thresholds is flattened by the policy normalization.
+        let prim_path = PathInfo::Thresh(
+            1,
+            vec![
+                PathInfo::Multi(2, vec![cfo.clone(), board_a.clone()]),
+                PathInfo::Single(ceo.clone()),
+            ],
+        );
+        let policy = LianaPolicy::new(prim_path, recovery_paths.clone()).unwrap();
+        let desc = LianaDescriptor::new(policy.clone());
+        assert_eq!(desc.policy(), policy);
+
+        // The thresholds must be within bounds at all depths.
+        let invalid_path = PathInfo::Thresh(
+            2,
+            vec![
+                PathInfo::Single(ceo.clone()),
+                PathInfo::Multi(3, vec![cfo.clone(), board_a.clone()]),
+            ],
+        );
+        assert!(matches!(
+            LianaPolicy::new(invalid_path, recovery_paths.clone()),
+            Err(LianaPolicyError::InvalidMultiThresh(3))
+        ));
+        let invalid_path = PathInfo::Thresh(
+            4,
+            vec![
+                PathInfo::Single(ceo.clone()),
+                PathInfo::Multi(1, vec![cfo.clone(), board_a.clone()]),
+            ],
+        );
+        assert!(matches!(
+            LianaPolicy::new(invalid_path, recovery_paths.clone()),
+            Err(LianaPolicyError::InvalidMultiThresh(4))
+        ));
+        // A signer may not appear twice within a path, even in different sub-paths.
+        let invalid_path = PathInfo::Thresh(
+            2,
+            vec![
+                PathInfo::Single(ceo.clone()),
+                PathInfo::Multi(1, vec![cfo.clone(), ceo.clone()]),
+            ],
+        );
+        LianaPolicy::new(invalid_path, recovery_paths).unwrap_err();
+
+        // The signature status is reported over the tree.
+        let prim_path = PathInfo::Thresh(
+            2,
+            vec![
+                PathInfo::Single(ceo.clone()),
+                PathInfo::Single(cfo.clone()),
+                PathInfo::Multi(1, vec![board_a.clone(), board_b.clone()]),
+            ],
+        );
+        let signer_origin = |key: &descriptor::DescriptorPublicKey| {
+            (
+                key.master_fingerprint(),
+                bip32::DerivationPath::from_str("m/0/42").unwrap(),
+            )
+        };
+        let signed = [signer_origin(&board_b)];
+        let info = prim_path.spend_info(signed.iter());
+        assert_eq!(info.threshold, 2);
+        assert_eq!(info.sigs_count, 1);
+        assert_eq!(info.signed_pubkeys.len(), 1);
+        assert_eq!(info.sub_paths.len(), 3);
+        assert_eq!(info.sub_paths[0].sigs_count, 0);
+        assert_eq!(info.sub_paths[2].threshold, 1);
+        assert_eq!(info.sub_paths[2].sigs_count, 1);
+        // A signature from the other board member doesn't make the path any more complete.
+        let signed = [signer_origin(&board_b), signer_origin(&board_a)];
+        let info = prim_path.spend_info(signed.iter());
+        assert_eq!(info.sigs_count, 1);
+        assert_eq!(info.sub_paths[2].sigs_count, 2);
+        assert_eq!(info.signed_pubkeys.len(), 2);
+        let signed = [signer_origin(&board_b), signer_origin(&cfo)];
+        let info = prim_path.spend_info(signed.iter());
+        assert_eq!(info.sigs_count, 2);
+        assert!(info.sub_paths[0].sub_paths.is_empty());
+
+        // Through the descriptor's partial spend info, only the recovery paths which are available
+        // are reported.
+        let desc = LianaDescriptor::new(
+            LianaPolicy::new(
+                prim_path,
+                [(RecoveryTimelock::Blocks(4032), first_reco_path)]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            )
+            .unwrap(),
+        );
+        let mut psbt_in = PsbtIn::default();
+        for (i, key) in [&heir, &lawyer_a].iter().enumerate() {
+            let dummy_privkey = secp256k1::SecretKey::from_slice(&[i as u8 + 1; 32]).unwrap();
+            let dummy_pubkey = secp256k1::PublicKey::from_secret_key(&secp, &dummy_privkey);
+            let dummy_sig = bitcoin::ecdsa::Signature::from_str("30440220264d47ed3fd613e4ac34303c59a0e558d41e487a68af5c5d4bb790f6ccf218ab02203213fe4d51729f9852a28f7d22b2ecb2b096eaf07ad44638af77e4bdbdd4462901").unwrap();
+            psbt_in
+                .bip32_derivation
+                .insert(dummy_pubkey, signer_origin(key));
+            psbt_in
+                .partial_sigs
+                .insert(bitcoin::PublicKey::new(dummy_pubkey), dummy_sig);
+        }
+        let txin = bitcoin::TxIn {
+            sequence: Sequence::from_height(4032),
+            ..bitcoin::TxIn::default()
+        };
+        let info = desc.partial_spend_info_txin(&psbt_in, &txin, absolute::LockTime::ZERO);
+        assert_eq!(info.primary_path().sigs_count, 0);
+        let reco_info = &info.recovery_paths()[&RecoveryTimelock::Blocks(4032)];
+        assert_eq!(reco_info.threshold, 2);
+        assert_eq!(reco_info.sigs_count, 2);
+        assert_eq!(reco_info.sub_paths[1].sigs_count, 1);
+    }
+
+    #[test]
+    fn taproot_descriptor() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let owner_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap());
+        let heir_key = PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*").unwrap());
+        let recovery_paths: BTreeMap<_, _> = [(RecoveryTimelock::Blocks(52560), heir_key)]
+            .iter()
+            .cloned()
+            .collect();
+
+        // With a single primary key, it's used as the internal key and the recovery path is the
+        // only leaf.
+        let policy = LianaPolicy::new_taproot(owner_key.clone(), recovery_paths.clone()).unwrap();
+        assert!(policy.is_taproot());
+        let desc = LianaDescriptor::new(policy.clone());
+        assert!(desc.is_taproot());
+        assert_eq!(desc.to_string(), "tr([abcdef01]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*,and_v(v:pk([abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(52560)))#0mt7e93c");
+        let parsed_desc = LianaDescriptor::from_str(&desc.to_string()).unwrap();
+        assert_eq!(parsed_desc, desc);
+        assert_eq!(parsed_desc.policy(), policy);
+        let address = desc
+            .receive_descriptor()
+            .derive(0.into(), &secp)
+            .address(bitcoin::Network::Bitcoin);
+        assert_eq!(address.address_type(), Some(bitcoin::AddressType::P2tr));
+
+        // A spend through the script path is still cheaper than through the P2WSH equivalent.
+        let wsh_desc =
+            LianaDescriptor::new(LianaPolicy::new(owner_key, recovery_paths.clone()).unwrap());
+        assert!(!wsh_desc.is_taproot());
+        assert!(desc.max_sat_weight() < wsh_desc.max_sat_weight());
+
+        // With a multisig as primary path, it's a leaf too and the internal key is unspendable.
+        let primary_keys = PathInfo::Multi(
+            2,
+            vec![
+                descriptor::DescriptorPublicKey::from_str("[aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap(),
+                descriptor::DescriptorPublicKey::from_str("[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*").unwrap(),
+            ],
+        );
+        let policy = LianaPolicy::new_taproot(primary_keys, recovery_paths).unwrap();
+        let desc = LianaDescriptor::new(policy.clone());
+        assert_eq!(desc.to_string(), "tr(xpub661MyMwAqRbcF7x4ctfx7exnM2R8ucmdKZaBfNdUJReq8wG5gHPBuChYfRrhkhZFLNyss76GoC9L1Y72Rv6xjqC7QJensfuejTZF2HqSmA9/<0;1>/*,{multi_a(2,[aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*,[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*),and_v(v:pk([abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/<0;1>/*),older(52560))})#6qk95200");
+        let parsed_desc = LianaDescriptor::from_str(&desc.to_string()).unwrap();
+        assert_eq!(parsed_desc.policy(), policy);
+        assert!(desc.all_xpubs_net_is(bitcoin::Network::Bitcoin));
+
+        // The same descriptor with a spendable internal key would have it as part of the primary
+        // path.
+        let desc_str = desc.to_string();
+        let internal_key = desc_str
+            .strip_prefix("tr(")
+            .unwrap()
+            .split(',')
+            .next()
+            .unwrap();
+        let spendable_desc = descriptor::Descriptor::<descriptor::DescriptorPublicKey>::from_str(
+            &desc_str
+                .split('#')
+                .next()
+                .unwrap()
+                .replace(internal_key, "[aabb0013]xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/<0;1>/*"),
+        )
+        .unwrap();
+        let spendable_policy = LianaPolicy::from_multipath_descriptor(&spendable_desc);
+        assert!(matches!(
+            spendable_policy,
+            Err(LianaPolicyError::IncompatibleDesc)
+        ));
     }
 
     fn roundtrip(desc_str: &str) {
@@ -826,6 +1571,110 @@ mod tests {
         roundtrip("wsh(or_d(multi(3,[aabbccdd]xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*,[aabb0011/10/4893]xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR/<0;1>/*,[aabb0022]xpub67zuTXF9Ln4731avKTBSawoVVNRuMfmRvkL7kLUaLBRqma9ZqdHBJg9qx8cPUm3oNQMiXT4TmGovXNoQPuwg17RFcVJ8YrnbcooN7pxVJqC/<0;1>/*),and_v(v:pk([aabbccdd]xpub69cP4Y7S9TWcbSNxmk6CEDBsoaqr3ZEdjHuZcHxEFFKGh569RsJNr2V27XGhsbH9FXgWUEmKXRN7c5wQfq2VPjt31xP9VsYnVUyU8HcVevm/<0;1>/*),older(26352))))#kjajav3j");
     }
 
+    #[test]
+    fn non_standard_derivations() {
+        let secp = secp256k1::Secp256k1::verification_only();
+
+        // Legacy pairs of single-path descriptors, with extra unhardened steps before the
+        // wildcard for one of the keys.
+        let receive_desc = descriptor::Descriptor::<descriptor::DescriptorPublicKey>::from_str("wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/0/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/3/0/*),older(52560))))").unwrap();
+        let change_desc = descriptor::Descriptor::<descriptor::DescriptorPublicKey>::from_str("wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/1/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/3/1/*),older(52560))))").unwrap();
+        let desc =
+            LianaDescriptor::from_single_path_descriptors(&receive_desc, &change_desc).unwrap();
+        assert_eq!(desc.receive_descriptor(), &receive_desc);
+        assert_eq!(desc.change_descriptor(), &change_desc);
+        let desc_str = desc.to_string();
+        assert!(desc_str.contains("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*"));
+        assert!(desc_str.contains("xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/3/<0;1>/*"));
+        assert_eq!(LianaDescriptor::from_str(&desc_str).unwrap(), desc);
+
+        // The pair can be parsed from a string, one descriptor per line.
+        assert_eq!(
+            LianaDescriptor::from_str(&format!("{}\n{}\n", receive_desc, change_desc)).unwrap(),
+            desc
+        );
+        assert!(LianaDescriptor::from_str(&format!("{} {}", receive_desc, receive_desc)).is_err());
+        assert!(LianaDescriptor::from_str(&format!(
+            "{} {} {}",
+            receive_desc, change_desc, change_desc
+        ))
+        .is_err());
+
+        // Mismatching descriptors or keys are refused.
+        for change_desc_str in [
+            // Not the same xpub.
+            "wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub69cP4Y7S9TWcbSNxmk6CEDBsoaqr3ZEdjHuZcHxEFFKGh569RsJNr2V27XGhsbH9FXgWUEmKXRN7c5wQfq2VPjt31xP9VsYnVUyU8HcVevm/1/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/3/1/*),older(52560))))",
+            // Not the same origin.
+            "wsh(or_d(pk([aabbccdd/48'/0'/1'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/1/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/3/1/*),older(52560))))",
+            // Paths differing by more than one step.
+            "wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/1/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/4/1/*),older(52560))))",
+            // Same keys.
+            "wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/0/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/3/0/*),older(52560))))",
+            // Not the same timelock.
+            "wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/1/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/3/1/*),older(52561))))",
+        ] {
+            let change_desc =
+                descriptor::Descriptor::<descriptor::DescriptorPublicKey>::from_str(
+                    change_desc_str,
+                )
+                .unwrap();
+            assert!(matches!(
+                LianaDescriptor::from_single_path_descriptors(&receive_desc, &change_desc),
+                Err(LianaDescError::IncompatibleSinglePathDescs)
+            ));
+        }
+
+        // Hardened steps are refused, even in the change derivation path.
+        LianaDescriptor::from_str("wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1'>/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7/3/<0;1>/*),older(52560))))").unwrap_err();
+        LianaDescriptor::from_str("wsh(or_d(pk([aabbccdd/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*),and_v(v:pkh([aabb0011]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe/7'/<0;1>/*),older(52560))))").unwrap_err();
+
+        // The derived keys have the full derivation path from the master key as origin, and
+        // roundtrip through their string representation.
+        let der_desc = desc.change_descriptor().derive(42.into(), &secp);
+        let origins: Vec<_> = der_desc.bip32_derivations().values().cloned().collect();
+        assert!(origins.contains(&(
+            bip32::Fingerprint::from_str("aabbccdd").unwrap(),
+            bip32::DerivationPath::from_str("m/48'/0'/0'/2'/1/42").unwrap()
+        )));
+        assert!(origins.contains(&(
+            bip32::Fingerprint::from_str("aabb0011").unwrap(),
+            bip32::DerivationPath::from_str("m/7/3/1/42").unwrap()
+        )));
+        der_desc.0.for_each_key(|key| {
+            let key_str = key.to_string();
+            assert!(
+                key_str.starts_with("[aabbccdd/48'/0'/0'/2'/1/42]")
+                    || key_str.starts_with("[aabb0011/7/3/1/42]")
+            );
+            assert_eq!(&DerivedPublicKey::from_str(&key_str).unwrap(), key);
+            true
+        });
+        let der_desc_str = der_desc.0.to_string();
+        assert_eq!(
+            descriptor::Descriptor::<DerivedPublicKey>::from_str(&der_desc_str).unwrap(),
+            der_desc.0
+        );
+
+        // Derived keys without a derivation path, or with the legacy 'm' prefix, are parsed too.
+        let key = DerivedPublicKey::from_str(
+            "[aabbccdd]02e24913be26dbcfdf8e8e94870b28725cdae09b448b6c127767bf0154e3a3c8e5",
+        )
+        .unwrap();
+        assert!(key.origin.1.is_empty());
+        let key = DerivedPublicKey::from_str(
+            "[aabbccdd/m/0/1]02e24913be26dbcfdf8e8e94870b28725cdae09b448b6c127767bf0154e3a3c8e5",
+        )
+        .unwrap();
+        assert_eq!(
+            key.to_string(),
+            "[aabbccdd/0/1]02e24913be26dbcfdf8e8e94870b28725cdae09b448b6c127767bf0154e3a3c8e5"
+        );
+        DerivedPublicKey::from_str(
+            "[aabbccdd0/1]02e24913be26dbcfdf8e8e94870b28725cdae09b448b6c127767bf0154e3a3c8e5",
+        )
+        .unwrap_err();
+    }
+
     fn psbt_from_str(psbt_str: &str) -> Psbt {
         Psbt::from_str(psbt_str).unwrap()
     }
@@ -853,19 +1702,21 @@ mod tests {
         assert!(info.recovery_paths.is_empty());
 
         // If we set the sequence too low we still won't have the recovery path info.
-        unsigned_single_psbt.unsigned_tx.input[0].sequence =
-            Sequence::from_height(desc_info.recovery_paths.keys().next().unwrap() - 1);
+        unsigned_single_psbt.unsigned_tx.input[0].sequence = Sequence::from_height(
+            desc_info.recovery_paths.keys().next().unwrap().value() as u16 - 1,
+        );
         let info = desc.partial_spend_info(&unsigned_single_psbt).unwrap();
         assert!(info.recovery_paths.is_empty());
 
         // Now if we set the sequence at the right value we'll have it.
         let timelock = *desc_info.recovery_paths.keys().next().unwrap();
-        unsigned_single_psbt.unsigned_tx.input[0].sequence = Sequence::from_height(timelock);
+        unsigned_single_psbt.unsigned_tx.input[0].sequence = timelock.sequence();
         let info = desc.partial_spend_info(&unsigned_single_psbt).unwrap();
         assert!(info.recovery_paths.contains_key(&timelock));
 
         // Even if it's a bit too high (as long as it's still a block height and activated)
-        unsigned_single_psbt.unsigned_tx.input[0].sequence = Sequence::from_height(timelock + 42);
+        unsigned_single_psbt.unsigned_tx.input[0].sequence =
+            Sequence::from_height(timelock.value() as u16 + 42);
         let info = desc.partial_spend_info(&unsigned_single_psbt).unwrap();
         let recov_info = info.recovery_paths.get(&timelock).unwrap();
         assert_eq!(recov_info.threshold, 1);
@@ -885,7 +1736,7 @@ mod tests {
         assert!(info.recovery_paths.is_empty());
 
         // Now enable the recovery path and add a signature for the recovery key.
-        signed_single_psbt.unsigned_tx.input[0].sequence = Sequence::from_height(timelock);
+        signed_single_psbt.unsigned_tx.input[0].sequence = timelock.sequence();
         let recov_pubkey = bitcoin::PublicKey {
             compressed: true,
             inner: *signed_single_psbt.inputs[0]
@@ -942,7 +1793,7 @@ mod tests {
         // Enable the recovery path, it should show no recovery sig.
         let mut rec_psbt = psbt.clone();
         for txin in rec_psbt.unsigned_tx.input.iter_mut() {
-            txin.sequence = Sequence::from_height(timelock);
+            txin.sequence = timelock.sequence();
         }
         let info = desc.partial_spend_info(&rec_psbt).unwrap();
         assert!(rec_psbt
@@ -963,7 +1814,8 @@ mod tests {
         // If the sequence of one of the input is different from the other ones, it'll return
         // an error since the analysis is on the whole transaction.
         let mut inconsistent_psbt = psbt.clone();
-        inconsistent_psbt.unsigned_tx.input[0].sequence = Sequence::from_height(timelock + 1);
+        inconsistent_psbt.unsigned_tx.input[0].sequence =
+            Sequence::from_height(timelock.value() as u16 + 1);
         assert!(desc
             .partial_spend_info(&inconsistent_psbt)
             .unwrap_err()
@@ -1003,7 +1855,7 @@ mod tests {
                 descriptor::DescriptorPublicKey::from_str("[ffd63c8d/48'/1'/0'/2']tpubDExA3EC3iAsPxPhFn4j6gMiVup6V2eH3qKyk69RcTc9TTNRfFYVPad8bJD5FCHVQxyBT4izKsvr7Btd2R4xmQ1hZkvsqGBaeE82J71uTK4N/<0;1>/*").unwrap(),
             ],
         ));
-        assert_eq!(info.recovery_paths, [(2, PathInfo::Multi(
+        assert_eq!(info.recovery_paths, [(RecoveryTimelock::Blocks(2), PathInfo::Multi(
             2,
             vec![
                 descriptor::DescriptorPublicKey::from_str("[636adf3f/48'/1'/1'/2']tpubDDvF2khuoBBj8vcSjQfa7iKaxsQZE7YjJ7cJL8A8eaneadMPKbHSpoSr4JD1F5LUvWD82HCxdtSppGfrMUmiNbFxrA2EHEVLnrdCFNFe75D/<0;1>/*").unwrap(),
@@ -1025,9 +1877,9 @@ mod tests {
         let liana_policy = LianaPolicy::new(
             prim_path.clone(),
             [
-                (26784, first_reco_path.clone()),
-                (53568, sec_reco_path.clone()),
-                (62496, third_reco_path.clone()),
+                (RecoveryTimelock::Blocks(26784), first_reco_path.clone()),
+                (RecoveryTimelock::Blocks(53568), sec_reco_path.clone()),
+                (RecoveryTimelock::Blocks(62496), third_reco_path.clone()),
             ]
             .iter()
             .cloned()
@@ -1092,11 +1944,19 @@ mod tests {
         psbt.unsigned_tx.input[0].sequence = bitcoin::Sequence::from_height(26784);
         let partial_info = desc.partial_spend_info(&psbt).unwrap();
         assert_eq!(partial_info.recovery_paths.len(), 1);
-        assert_eq!(partial_info.recovery_paths[&26784].threshold, 3);
-        assert_eq!(partial_info.recovery_paths[&26784].sigs_count, 1);
         assert_eq!(
-            partial_info.recovery_paths[&26784].signed_pubkeys.len(),
-            partial_info.recovery_paths[&26784].sigs_count
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(26784)].threshold,
+            3
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(26784)].sigs_count,
+            1
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(26784)]
+                .signed_pubkeys
+                .len(),
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(26784)].sigs_count
         );
 
         // Now enable the second recovery path and make the signature be for this path.
@@ -1121,10 +1981,22 @@ mod tests {
             partial_info.primary_path.signed_pubkeys.len()
         );
         assert_eq!(partial_info.recovery_paths.len(), 2);
-        assert_eq!(partial_info.recovery_paths[&26784].threshold, 3);
-        assert_eq!(partial_info.recovery_paths[&26784].sigs_count, 0);
-        assert_eq!(partial_info.recovery_paths[&53568].threshold, 2);
-        assert_eq!(partial_info.recovery_paths[&53568].sigs_count, 1);
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(26784)].threshold,
+            3
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(26784)].sigs_count,
+            0
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(53568)].threshold,
+            2
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(53568)].sigs_count,
+            1
+        );
         for rec_path in partial_info.recovery_paths.values() {
             assert_eq!(rec_path.sigs_count, rec_path.signed_pubkeys.len());
         }
@@ -1151,12 +2023,30 @@ mod tests {
             partial_info.primary_path.signed_pubkeys.len()
         );
         assert_eq!(partial_info.recovery_paths.len(), 3);
-        assert_eq!(partial_info.recovery_paths[&26784].threshold, 3);
-        assert_eq!(partial_info.recovery_paths[&26784].sigs_count, 0);
-        assert_eq!(partial_info.recovery_paths[&53568].threshold, 2);
-        assert_eq!(partial_info.recovery_paths[&53568].sigs_count, 0);
-        assert_eq!(partial_info.recovery_paths[&62496].threshold, 1);
-        assert_eq!(partial_info.recovery_paths[&62496].sigs_count, 1);
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(26784)].threshold,
+            3
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(26784)].sigs_count,
+            0
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(53568)].threshold,
+            2
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(53568)].sigs_count,
+            0
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(62496)].threshold,
+            1
+        );
+        assert_eq!(
+            partial_info.recovery_paths[&RecoveryTimelock::Blocks(62496)].sigs_count,
+            1
+        );
         for rec_path in partial_info.recovery_paths.values() {
             assert_eq!(rec_path.sigs_count, rec_path.signed_pubkeys.len());
         }
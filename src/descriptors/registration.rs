@@ -0,0 +1,293 @@
+//! Registration files for air-gapped signing devices.
+//!
+//! A signing device must know the descriptor to check the transactions it signs, and to show our
+//! change and receive addresses. Devices reachable over USB are given the descriptor directly, but
+//! air-gapped ones import it from a file instead. The content of the files is deterministic for a
+//! given descriptor, name and network.
+
+use crate::descriptors::LianaDescriptor;
+
+use std::{collections::BTreeMap, error, fmt};
+
+use miniscript::{
+    bitcoin::{bip32::ChildNumber, secp256k1, Network},
+    descriptor::DescriptorPublicKey,
+    ForEachKey,
+};
+use serde::Serialize;
+
+/// The maximum length of a wallet name on a Jade.
+const JADE_MAX_NAME_LEN: usize = 16;
+
+/// The format of a registration file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationFormat {
+    /// The JSON file imported by the Coldcard for miniscript wallets.
+    Coldcard,
+    /// The parameters of the Jade `register_descriptor` request, as a JSON file.
+    Jade,
+    /// The BIP129 (Bitcoin Secure Multisig Setup) descriptor record, unencrypted.
+    Bsms,
+}
+
+impl RegistrationFormat {
+    /// The extension of files in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Coldcard | Self::Jade => "json",
+            Self::Bsms => "bsms",
+        }
+    }
+}
+
+impl fmt::Display for RegistrationFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Coldcard => write!(f, "Coldcard"),
+            Self::Jade => write!(f, "Jade"),
+            Self::Bsms => write!(f, "BSMS"),
+        }
+    }
+}
+
+/// An error related to creating a registration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistrationError {
+    /// The wallet name must be non-empty printable ASCII.
+    InvalidName(String),
+    /// The wallet name is longer than this maximum length supported by the device.
+    NameTooLong(usize),
+}
+
+impl fmt::Display for RegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(
+                f,
+                "Invalid wallet name '{}': it must be non-empty printable ASCII.",
+                name
+            ),
+            Self::NameTooLong(max) => {
+                write!(f, "Wallet name too long: at most {} characters.", max)
+            }
+        }
+    }
+}
+
+impl error::Error for RegistrationError {}
+
+#[derive(Serialize)]
+struct ColdcardFile<'a> {
+    name: &'a str,
+    desc: String,
+}
+
+#[derive(Serialize)]
+struct JadeFile<'a> {
+    network: &'static str,
+    descriptor_name: &'a str,
+    descriptor: String,
+    datavalues: BTreeMap<String, String>,
+}
+
+// The descriptor string, without its checksum.
+fn descriptor_no_checksum(desc: &LianaDescriptor) -> String {
+    let desc_str = desc.to_string();
+    desc_str
+        .split('#')
+        .next()
+        .expect("Split always returns a first item")
+        .to_string()
+}
+
+// The keys of the descriptor without their derivation steps (`[aabbccdd/48'/0'/0'/2']xpub..`),
+// in the order they first appear in the descriptor.
+fn descriptor_keys(desc: &LianaDescriptor) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    desc.multi_desc.for_each_key(|key| {
+        let key_str = key.to_string();
+        let xkey_start = key_str.find(']').map(|i| i + 1).unwrap_or(0);
+        let key_end = key_str[xkey_start..]
+            .find('/')
+            .map(|i| xkey_start + i)
+            .unwrap_or_else(|| key_str.len());
+        let key_str = key_str[..key_end].to_string();
+        if !keys.contains(&key_str) {
+            keys.push(key_str);
+        }
+        true
+    });
+    keys
+}
+
+fn check_name(name: &str, max_len: Option<usize>) -> Result<(), RegistrationError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+        return Err(RegistrationError::InvalidName(name.to_string()));
+    }
+    if let Some(max_len) = max_len {
+        if name.len() > max_len {
+            return Err(RegistrationError::NameTooLong(max_len));
+        }
+    }
+    Ok(())
+}
+
+fn coldcard_file(desc: &LianaDescriptor, name: &str) -> Result<String, RegistrationError> {
+    check_name(name, None)?;
+    let file = ColdcardFile {
+        name,
+        desc: desc.to_string(),
+    };
+    Ok(serde_json::to_string_pretty(&file).expect("Serialization can't fail"))
+}
+
+fn jade_file(
+    desc: &LianaDescriptor,
+    name: &str,
+    network: Network,
+) -> Result<String, RegistrationError> {
+    check_name(name, Some(JADE_MAX_NAME_LEN))?;
+    // The descriptor is given as a template with the keys replaced by placeholders, with their
+    // derivation steps kept.
+    let mut descriptor = descriptor_no_checksum(desc);
+    let mut datavalues = BTreeMap::new();
+    for (i, key) in descriptor_keys(desc).into_iter().enumerate() {
+        let placeholder = format!("@{}", i);
+        descriptor = descriptor.replace(&key, &placeholder);
+        datavalues.insert(placeholder, key);
+    }
+    let network = match network {
+        Network::Bitcoin => "mainnet",
+        Network::Testnet | Network::Signet => "testnet",
+        _ => "localtest",
+    };
+    let file = JadeFile {
+        network,
+        descriptor_name: name,
+        descriptor,
+        datavalues,
+    };
+    Ok(serde_json::to_string_pretty(&file).expect("Serialization can't fail"))
+}
+
+fn bsms_file(desc: &LianaDescriptor, network: Network) -> String {
+    // If all the keys use the standard receive and change derivation steps they are abbreviated
+    // and restricted to these, as in the BIP example.
+    let standard_paths = desc.multi_desc.for_each_key(|key| match key {
+        DescriptorPublicKey::MultiXPub(xpub) => {
+            xpub.derivation_paths
+                .paths()
+                .iter()
+                .map(|path| path.as_ref())
+                .collect::<Vec<_>>()
+                == vec![[ChildNumber::from(0)], [ChildNumber::from(1)]]
+        }
+        _ => false,
+    });
+    let (template, restrictions) = if standard_paths {
+        (
+            descriptor_no_checksum(desc).replace("/<0;1>/*", "/**"),
+            "/0/*,/1/*",
+        )
+    } else {
+        (descriptor_no_checksum(desc), "No path restrictions")
+    };
+    let first_address = desc
+        .receive_descriptor()
+        .derive(
+            ChildNumber::from(0),
+            &secp256k1::Secp256k1::verification_only(),
+        )
+        .address(network);
+    format!(
+        "BSMS 1.0\n{}\n{}\n{}",
+        template, restrictions, first_address
+    )
+}
+
+/// Get the content of the file to register this descriptor under this name on an air-gapped
+/// signing device, in this format. The name isn't part of a BSMS record.
+pub fn registration_file(
+    desc: &LianaDescriptor,
+    name: &str,
+    network: Network,
+    format: RegistrationFormat,
+) -> Result<String, RegistrationError> {
+    let mut content = match format {
+        RegistrationFormat::Coldcard => coldcard_file(desc, name)?,
+        RegistrationFormat::Jade => jade_file(desc, name, network)?,
+        RegistrationFormat::Bsms => bsms_file(desc, network),
+    };
+    content.push('\n');
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // Check the registration files against the golden files in the testdata directory.
+    #[test]
+    fn registration_files() {
+        let desc = LianaDescriptor::from_str("wsh(or_d(multi(2,[636adf3f/48'/1'/0'/2']tpubDEE9FvWbG4kg4gxDNrALgrWLiHwNMXNs8hk6nXNPw4VHKot16xd2251vwi2M6nsyQTkak5FJNHVHkCcuzmvpSbWHdumX3DxpDm89iTfSBaL/<0;1>/*,[ffd63c8d/48'/1'/0'/2']tpubDExA3EC3iAsPxPhFn4j6gMiVup6V2eH3qKyk69RcTc9TTNRfFYVPad8bJD5FCHVQxyBT4izKsvr7Btd2R4xmQ1hZkvsqGBaeE82J71uTK4N/<0;1>/*),and_v(v:multi(2,[636adf3f/48'/1'/1'/2']tpubDDvF2khuoBBj8vcSjQfa7iKaxsQZE7YjJ7cJL8A8eaneadMPKbHSpoSr4JD1F5LUvWD82HCxdtSppGfrMUmiNbFxrA2EHEVLnrdCFNFe75D/<0;1>/*,[ffd63c8d/48'/1'/1'/2']tpubDFMs44FD4kFt3M7Z317cFh5tdKEGN8tyQRY6Q5gcSha4NtxZfGmTVRMbsD1bWN469LstXU4aVSARDxrvxFCUjHeegfEY2cLSazMBkNCmDPD/<0;1>/*),older(2))))#xcf6jr2r").unwrap();
+
+        assert_eq!(
+            registration_file(
+                &desc,
+                "Liana",
+                Network::Testnet,
+                RegistrationFormat::Coldcard
+            )
+            .unwrap(),
+            include_str!("testdata/registration_coldcard.json")
+        );
+        assert_eq!(
+            registration_file(&desc, "Liana", Network::Testnet, RegistrationFormat::Jade).unwrap(),
+            include_str!("testdata/registration_jade.json")
+        );
+        assert_eq!(
+            registration_file(&desc, "Liana", Network::Testnet, RegistrationFormat::Bsms).unwrap(),
+            include_str!("testdata/registration.bsms")
+        );
+
+        // Keys with other derivation steps are kept as is in BSMS records.
+        let desc = LianaDescriptor::from_str("wsh(or_d(pk([636adf3f/48'/1'/0'/2']tpubDEE9FvWbG4kg4gxDNrALgrWLiHwNMXNs8hk6nXNPw4VHKot16xd2251vwi2M6nsyQTkak5FJNHVHkCcuzmvpSbWHdumX3DxpDm89iTfSBaL/<0;1>/*),and_v(v:pkh([636adf3f/48'/1'/0'/2']tpubDEE9FvWbG4kg4gxDNrALgrWLiHwNMXNs8hk6nXNPw4VHKot16xd2251vwi2M6nsyQTkak5FJNHVHkCcuzmvpSbWHdumX3DxpDm89iTfSBaL/<2;3>/*),older(52596))))").unwrap();
+        let bsms =
+            registration_file(&desc, "Liana", Network::Testnet, RegistrationFormat::Bsms).unwrap();
+        let lines: Vec<&str> = bsms.lines().collect();
+        assert_eq!(lines[1], descriptor_no_checksum(&desc));
+        assert_eq!(lines[2], "No path restrictions");
+        // The same xpub is a single key for the Jade, with different derivation steps.
+        let jade =
+            registration_file(&desc, "Liana", Network::Testnet, RegistrationFormat::Jade).unwrap();
+        assert!(
+            jade.contains("\"wsh(or_d(pk(@0/<0;1>/*),and_v(v:pkh(@0/<2;3>/*),older(52596))))\"")
+        );
+        assert!(!jade.contains("@1"));
+
+        // Names must be valid for the device.
+        assert_eq!(
+            registration_file(&desc, "", Network::Testnet, RegistrationFormat::Coldcard),
+            Err(RegistrationError::InvalidName("".to_string()))
+        );
+        assert_eq!(
+            registration_file(
+                &desc,
+                "Liana\n",
+                Network::Testnet,
+                RegistrationFormat::Coldcard
+            ),
+            Err(RegistrationError::InvalidName("Liana\n".to_string()))
+        );
+        assert_eq!(
+            registration_file(
+                &desc,
+                "Liana family savings",
+                Network::Testnet,
+                RegistrationFormat::Jade
+            ),
+            Err(RegistrationError::NameTooLong(16))
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Templates for common spending policies.
+//!
+//! A template produces a [LianaPolicy] out of a list of signers and a list of timelocks, whose
+//! meaning depends on the template. Signers are given as xpubs with an origin (for instance
+//! `[aabbccdd/48'/0'/0'/2']xpub...`). A signer may be used in more than one spending path, in
+//! which case a different multipath derivation is used for each: the n-th usage of a signer is
+//! derived at `<2n;2n+1>/*`.
+
+use crate::descriptors::{LianaPolicy, LianaPolicyError, PathInfo, RecoveryTimelock};
+
+use std::{collections::BTreeMap, error, fmt, str};
+
+use miniscript::{
+    bitcoin::bip32::{self, ExtendedPubKey},
+    descriptor::{DerivPaths, DescriptorMultiXKey, DescriptorPublicKey, DescriptorXKey, Wildcard},
+};
+
+#[derive(Debug)]
+pub enum TemplateError {
+    UnknownTemplate(String),
+    /// The number of keys given for this template is invalid.
+    InvalidKeysCount(usize),
+    /// The number of timelocks given for this template is invalid.
+    InvalidTimelocksCount(usize),
+    /// The timelocks must be given in increasing order.
+    UnorderedTimelocks,
+    InvalidKey(Box<DescriptorPublicKey>),
+    Policy(LianaPolicyError),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownTemplate(name) => write!(f, "Unknown policy template '{}'.", name),
+            Self::InvalidKeysCount(n) => {
+                write!(f, "Invalid number of keys '{}' for this template.", n)
+            }
+            Self::InvalidTimelocksCount(n) => {
+                write!(f, "Invalid number of timelocks '{}' for this template.", n)
+            }
+            Self::UnorderedTimelocks => write!(f, "Timelocks must be given in increasing order."),
+            Self::InvalidKey(key) => write!(
+                f,
+                "Invalid key '{}'. Need an xpub with an origin and no derivation step, that is of the form '[aaff0099/48'/0'/0'/2']xpub...'.",
+                key
+            ),
+            Self::Policy(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for TemplateError {}
+
+impl From<LianaPolicyError> for TemplateError {
+    fn from(e: LianaPolicyError) -> TemplateError {
+        TemplateError::Policy(e)
+    }
+}
+
+/// A common spending policy layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PolicyTemplate {
+    /// The owner may spend at any time, an heir may after a timelock.
+    ///
+    /// Keys: `[owner, heir]`. Timelocks: `[heir_timelock]`.
+    SimpleInheritance,
+    /// All of N signers must sign, after a timelock N among them and a safety net key may.
+    ///
+    /// Keys: `[signer_1, .., signer_N, safety_net]` with `N >= 2`. Timelocks: `[timelock]`.
+    ExpandingMultisig,
+    /// All of N signers must sign, and one less signature is required after each timelock.
+    ///
+    /// Keys: `[signer_1, .., signer_N]` with `N >= 2`. Timelocks: between 1 and `N - 1` of them,
+    /// in increasing order.
+    DecayingMultisig,
+    /// The owner may spend at any time. After a timelock, a backup key may spend along with a
+    /// third-party recovery service, for instance after it verified the identity of the owner.
+    ///
+    /// Keys: `[owner, backup, service]`. Timelocks: `[recovery_timelock]`.
+    ThirdPartyRecovery,
+}
+
+impl PolicyTemplate {
+    pub const ALL: [PolicyTemplate; 4] = [
+        PolicyTemplate::SimpleInheritance,
+        PolicyTemplate::ExpandingMultisig,
+        PolicyTemplate::DecayingMultisig,
+        PolicyTemplate::ThirdPartyRecovery,
+    ];
+
+    /// The name of this template, as used for parsing it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PolicyTemplate::SimpleInheritance => "simple-inheritance",
+            PolicyTemplate::ExpandingMultisig => "expanding-multisig",
+            PolicyTemplate::DecayingMultisig => "decaying-multisig",
+            PolicyTemplate::ThirdPartyRecovery => "third-party-recovery",
+        }
+    }
+
+    /// Create the spending policy for this template from the given signers and timelocks. See
+    /// the documentation of each template for the expected keys and timelocks.
+    pub fn policy(
+        &self,
+        keys: &[DescriptorPublicKey],
+        timelocks: &[RecoveryTimelock],
+    ) -> Result<LianaPolicy, TemplateError> {
+        let mut signers = Signers::new(keys)?;
+        let n_keys = keys.len();
+        let (primary_path, recovery_paths) = match self {
+            PolicyTemplate::SimpleInheritance => {
+                check_counts(n_keys == 2, timelocks.len() == 1, keys, timelocks)?;
+                let primary_path = PathInfo::Single(signers.next(0));
+                let recovery_path = PathInfo::Single(signers.next(1));
+                (primary_path, vec![(timelocks[0], recovery_path)])
+            }
+            PolicyTemplate::ExpandingMultisig => {
+                check_counts(n_keys >= 3, timelocks.len() == 1, keys, timelocks)?;
+                let n_signers = n_keys - 1;
+                let primary_path = signers.multi(n_signers, 0..n_signers);
+                let recovery_path = signers.multi(n_signers, 0..n_keys);
+                (primary_path, vec![(timelocks[0], recovery_path)])
+            }
+            PolicyTemplate::DecayingMultisig => {
+                check_counts(
+                    n_keys >= 2,
+                    !timelocks.is_empty() && timelocks.len() < n_keys,
+                    keys,
+                    timelocks,
+                )?;
+                let primary_path = signers.multi(n_keys, 0..n_keys);
+                let recovery_paths = timelocks
+                    .iter()
+                    .enumerate()
+                    .map(|(i, timelock)| (*timelock, signers.multi(n_keys - 1 - i, 0..n_keys)))
+                    .collect();
+                (primary_path, recovery_paths)
+            }
+            PolicyTemplate::ThirdPartyRecovery => {
+                check_counts(n_keys == 3, timelocks.len() == 1, keys, timelocks)?;
+                let primary_path = PathInfo::Single(signers.next(0));
+                let recovery_path = signers.multi(2, 1..3);
+                (primary_path, vec![(timelocks[0], recovery_path)])
+            }
+        };
+
+        if timelocks.windows(2).any(|tls| tls[0] >= tls[1]) {
+            return Err(TemplateError::UnorderedTimelocks);
+        }
+        let recovery_paths: BTreeMap<_, _> = recovery_paths.into_iter().collect();
+        Ok(LianaPolicy::new(primary_path, recovery_paths)?)
+    }
+}
+
+impl fmt::Display for PolicyTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl str::FromStr for PolicyTemplate {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PolicyTemplate::ALL
+            .iter()
+            .find(|template| template.name() == s)
+            .copied()
+            .ok_or_else(|| TemplateError::UnknownTemplate(s.to_string()))
+    }
+}
+
+fn check_counts(
+    valid_keys: bool,
+    valid_timelocks: bool,
+    keys: &[DescriptorPublicKey],
+    timelocks: &[RecoveryTimelock],
+) -> Result<(), TemplateError> {
+    if !valid_keys {
+        return Err(TemplateError::InvalidKeysCount(keys.len()));
+    }
+    if !valid_timelocks {
+        return Err(TemplateError::InvalidTimelocksCount(timelocks.len()));
+    }
+    Ok(())
+}
+
+// The signers of a template, along with how many times each of them was used so far.
+struct Signers {
+    xpubs: Vec<DescriptorXKey<ExtendedPubKey>>,
+    uses: Vec<u32>,
+}
+
+impl Signers {
+    pub fn new(keys: &[DescriptorPublicKey]) -> Result<Signers, TemplateError> {
+        let xpubs = keys
+            .iter()
+            .map(|key| match key {
+                DescriptorPublicKey::XPub(xpub)
+                    if xpub.origin.is_some()
+                        && xpub.derivation_path.is_empty()
+                        && xpub.wildcard != Wildcard::Hardened =>
+                {
+                    Ok(xpub.clone())
+                }
+                _ => Err(TemplateError::InvalidKey(key.clone().into())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let uses = vec![0; xpubs.len()];
+        Ok(Signers { xpubs, uses })
+    }
+
+    // Get the multipath key for the next usage of the signer at this index.
+    pub fn next(&mut self, index: usize) -> DescriptorPublicKey {
+        let xpub = &self.xpubs[index];
+        let n = self.uses[index];
+        self.uses[index] += 1;
+        let derivation_paths = DerivPaths::new(vec![
+            vec![bip32::ChildNumber::from_normal_idx(2 * n).expect("Won't overflow")].into(),
+            vec![bip32::ChildNumber::from_normal_idx(2 * n + 1).expect("Won't overflow")].into(),
+        ])
+        .expect("Not empty");
+        DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+            origin: xpub.origin.clone(),
+            xkey: xpub.xkey,
+            derivation_paths,
+            wildcard: Wildcard::Unhardened,
+        })
+    }
+
+    // A multisig among the signers in this range.
+    pub fn multi(&mut self, thresh: usize, range: std::ops::Range<usize>) -> PathInfo {
+        PathInfo::Multi(thresh, range.map(|i| self.next(i)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn templates() {
+        for template in PolicyTemplate::ALL.iter() {
+            assert_eq!(
+                PolicyTemplate::from_str(&template.to_string()).unwrap(),
+                *template
+            );
+        }
+        assert!(matches!(
+            PolicyTemplate::from_str("yolo"),
+            Err(TemplateError::UnknownTemplate(_))
+        ));
+
+        let (owner, heir, third) = (
+            DescriptorPublicKey::from_str("[aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW").unwrap(),
+            DescriptorPublicKey::from_str("[aabb0012/48'/0'/0'/2']xpub6Bw79HbNSeS2xXw1sngPE3ehnk1U3iSPCgLYzC9LpN8m9nDuaKLZvkg8QXxL5pDmEmQtYscmUD8B9MkAAZbh6vxPzNXMaLfGQ9Sb3z85qhR").unwrap(),
+            DescriptorPublicKey::from_str("[abcdef01]xpub688Hn4wScQAAiYJLPg9yH27hUpfZAUnmJejRQBCiwfP5PEDzjWMNW1wChcninxr5gyavFqbbDjdV1aK5USJz8NDVjUy7FRQaaqqXHh5SbXe").unwrap(),
+        );
+        let timelock = RecoveryTimelock::Blocks(52560);
+
+        let policy = PolicyTemplate::SimpleInheritance
+            .policy(&[owner.clone(), heir.clone()], &[timelock])
+            .unwrap();
+        assert!(matches!(policy.primary_path(), PathInfo::Single(_)));
+        assert_eq!(policy.recovery_paths().len(), 1);
+        assert_eq!(
+            policy.primary_path().keys()[0].to_string(),
+            "[aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*"
+        );
+
+        // The same signer is derived differently for each of its usages.
+        let policy = PolicyTemplate::ExpandingMultisig
+            .policy(&[owner.clone(), heir.clone(), third.clone()], &[timelock])
+            .unwrap();
+        assert!(matches!(policy.primary_path(), PathInfo::Multi(2, keys) if keys.len() == 2));
+        let reco_path = &policy.recovery_paths()[&timelock];
+        assert!(matches!(reco_path, PathInfo::Multi(2, keys) if keys.len() == 3));
+        assert!(reco_path.keys()[0].to_string().ends_with("/<2;3>/*"));
+        assert!(reco_path.keys()[2].to_string().ends_with("/<0;1>/*"));
+
+        let timelocks = [RecoveryTimelock::Blocks(4032), timelock];
+        let policy = PolicyTemplate::DecayingMultisig
+            .policy(&[owner.clone(), heir.clone(), third.clone()], &timelocks)
+            .unwrap();
+        assert!(matches!(policy.primary_path(), PathInfo::Multi(3, _)));
+        assert!(matches!(
+            policy.recovery_paths()[&timelocks[0]],
+            PathInfo::Multi(2, _)
+        ));
+        assert!(matches!(
+            policy.recovery_paths()[&timelocks[1]],
+            PathInfo::Multi(1, _)
+        ));
+        assert!(matches!(
+            PolicyTemplate::DecayingMultisig.policy(
+                &[owner.clone(), heir.clone(), third.clone()],
+                &[timelock, timelocks[0]]
+            ),
+            Err(TemplateError::UnorderedTimelocks)
+        ));
+        assert!(matches!(
+            PolicyTemplate::DecayingMultisig.policy(&[owner.clone(), heir.clone()], &timelocks),
+            Err(TemplateError::InvalidTimelocksCount(2))
+        ));
+
+        let policy = PolicyTemplate::ThirdPartyRecovery
+            .policy(&[owner.clone(), heir.clone(), third.clone()], &[timelock])
+            .unwrap();
+        assert!(matches!(policy.primary_path(), PathInfo::Single(_)));
+        assert!(matches!(
+            policy.recovery_paths()[&timelock],
+            PathInfo::Multi(2, _)
+        ));
+
+        // Invalid number of keys or timelocks, invalid keys.
+        assert!(matches!(
+            PolicyTemplate::SimpleInheritance.policy(std::slice::from_ref(&owner), &[timelock]),
+            Err(TemplateError::InvalidKeysCount(1))
+        ));
+        assert!(matches!(
+            PolicyTemplate::ThirdPartyRecovery
+                .policy(&[owner.clone(), heir.clone(), third.clone()], &[]),
+            Err(TemplateError::InvalidTimelocksCount(0))
+        ));
+        let multipath_key = DescriptorPublicKey::from_str("[aabb0011/48'/0'/0'/2']xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW/<0;1>/*").unwrap();
+        assert!(matches!(
+            PolicyTemplate::SimpleInheritance.policy(&[multipath_key, heir.clone()], &[timelock]),
+            Err(TemplateError::InvalidKey(_))
+        ));
+        let no_origin_key = DescriptorPublicKey::from_str("xpub6Eze7yAT3Y1wGrnzedCNVYDXUqa9NmHVWck5emBaTbXtURbe1NWZbK9bsz1TiVE7Cz341PMTfYgFw1KdLWdzcM1UMFTcdQfCYhhXZ2HJvTW").unwrap();
+        assert!(matches!(
+            PolicyTemplate::SimpleInheritance.policy(&[no_origin_key, heir.clone()], &[timelock]),
+            Err(TemplateError::InvalidKey(_))
+        ));
+
+        // The policy is still checked: the same key can't be given for two roles.
+        assert!(matches!(
+            PolicyTemplate::ThirdPartyRecovery
+                .policy(&[owner.clone(), heir.clone(), heir], &[timelock]),
+            Err(TemplateError::Policy(LianaPolicyError::DuplicateKey(_)))
+        ));
+    }
+}
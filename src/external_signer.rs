@@ -0,0 +1,382 @@
+//! External signer module
+//!
+//! Interface to signing devices through an external command. It speaks the protocol of the
+//! `-signer` option of bitcoind (`enumerate`, `signtx`, `getdescriptors`), extended with a
+//! `register` subcommand to register a descriptor on a device. The command is called with global
+//! arguments followed by the subcommand and must print a JSON response on its standard output.
+//! Errors are reported as a JSON object with an `error` field. See `doc/API.md` for details.
+
+use std::{
+    error, fmt,
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+    str::FromStr,
+    thread, time,
+};
+
+use miniscript::bitcoin::{bip32, psbt::Psbt, Network};
+use serde::{Deserialize, Serialize};
+
+/// How long the signer command may run before it is killed. It's generous since signing may
+/// require the user to interact with the device.
+pub const SIGNER_COMMAND_TIMEOUT: time::Duration = time::Duration::from_secs(5 * 60);
+
+/// An error related to using an external signer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalSignerError {
+    /// The signer command is empty.
+    EmptyCommand,
+    /// The signer command could not be run.
+    Spawn(String),
+    /// The signer command exited with a non-zero status, with this error output.
+    CommandFailed(String),
+    /// The signer command did not complete in time and was killed.
+    Timeout,
+    /// The signer command did not output a valid response.
+    InvalidResponse(String),
+    /// The signer command returned an error.
+    Signer(String),
+}
+
+impl fmt::Display for ExternalSignerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::EmptyCommand => write!(f, "The external signer command is empty."),
+            Self::Spawn(e) => write!(f, "Failed to run the external signer command: '{}'.", e),
+            Self::CommandFailed(e) => write!(f, "External signer command failed: '{}'.", e),
+            Self::Timeout => write!(f, "The external signer command timed out."),
+            Self::InvalidResponse(e) => {
+                write!(f, "Invalid response from the external signer: '{}'.", e)
+            }
+            Self::Signer(e) => write!(f, "External signer error: '{}'.", e),
+        }
+    }
+}
+
+impl error::Error for ExternalSignerError {}
+
+/// A signing device, as reported by the `enumerate` command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignerDevice {
+    pub fingerprint: bip32::Fingerprint,
+    /// The type of the device, for instance "ledger".
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub device_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+/// The descriptors a device exposes for an account, as reported by the `getdescriptors` command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignerDescriptors {
+    pub receive: Vec<String>,
+    pub internal: Vec<String>,
+}
+
+// The response to the signtx command.
+#[derive(Debug, Deserialize)]
+struct SignTxResponse {
+    psbt: String,
+}
+
+// The response to the register command.
+#[derive(Debug, Deserialize)]
+struct RegisterResponse {
+    hmac: Option<String>,
+}
+
+// Read this output until its end in a new thread.
+fn read_in_background(
+    mut output: impl Read + Send + 'static,
+) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        output.read_to_end(&mut buf).map(|_| buf)
+    })
+}
+
+/// An external signer command, used to talk to the signing devices.
+#[derive(Debug, Clone)]
+pub struct ExternalSigner {
+    command: Vec<String>,
+    network: Network,
+    timeout: time::Duration,
+}
+
+impl ExternalSigner {
+    /// The command is given as the program to run followed by its own arguments, if any.
+    pub fn new(command: Vec<String>, network: Network) -> Self {
+        Self {
+            command,
+            network,
+            timeout: SIGNER_COMMAND_TIMEOUT,
+        }
+    }
+
+    // The name of our network for the `--chain` argument.
+    fn chain(&self) -> &'static str {
+        match self.network {
+            Network::Bitcoin => "main",
+            Network::Testnet => "test",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+            _ => unreachable!("No other network is supported."),
+        }
+    }
+
+    // Run the command with these arguments and parse its JSON output. If given, the stdin
+    // content is written to the standard input of the command (with the `--stdin` flag). The
+    // command is killed if it doesn't complete before the timeout.
+    fn run<T: serde::de::DeserializeOwned>(
+        &self,
+        fingerprint: Option<&bip32::Fingerprint>,
+        args: &[&str],
+        stdin: Option<&str>,
+    ) -> Result<T, ExternalSignerError> {
+        let (program, program_args) = self
+            .command
+            .split_first()
+            .ok_or(ExternalSignerError::EmptyCommand)?;
+        let mut command = Command::new(program);
+        command.args(program_args);
+        if stdin.is_some() {
+            command.arg("--stdin");
+        }
+        if let Some(fingerprint) = fingerprint {
+            command.args(["--fingerprint", &fingerprint.to_string()]);
+        }
+        command
+            .args(["--chain", self.chain()])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        log::debug!("Running external signer command: {:?}", command);
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| ExternalSignerError::Spawn(e.to_string()))?;
+
+        // Write the input and read the outputs in the background so neither us nor the command
+        // block on a full pipe while we wait for it to exit. The standard input is closed once
+        // written. The command may exit without reading it, its result is what matters.
+        let mut child_stdin = child.stdin.take().expect("Stdin was piped");
+        let stdin = stdin.unwrap_or_default().to_string();
+        thread::spawn(move || {
+            if let Err(e) = child_stdin.write_all(stdin.as_bytes()) {
+                log::debug!("Error writing to the external signer command: {}", e);
+            }
+        });
+        let stdout_reader = read_in_background(child.stdout.take().expect("Stdout was piped"));
+        let stderr_reader = read_in_background(child.stderr.take().expect("Stderr was piped"));
+        let start = time::Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if start.elapsed() < self.timeout => {
+                    thread::sleep(time::Duration::from_millis(50))
+                }
+                Ok(None) => {
+                    log::error!("External signer command timed out, killing it.");
+                    if let Err(e) = child.kill() {
+                        log::error!("Error killing the external signer command: {}", e);
+                    }
+                    // Reap it. Don't wait for the output readers, the pipes may be kept open by
+                    // processes the command spawned.
+                    let _ = child.wait();
+                    return Err(ExternalSignerError::Timeout);
+                }
+                Err(e) => return Err(ExternalSignerError::Spawn(e.to_string())),
+            }
+        };
+        let join_output = |reader: thread::JoinHandle<io::Result<Vec<u8>>>| {
+            reader
+                .join()
+                .expect("Reading the output must not panic")
+                .map_err(|e| ExternalSignerError::Spawn(e.to_string()))
+        };
+        let (stdout, stderr) = (join_output(stdout_reader)?, join_output(stderr_reader)?);
+        if !status.success() {
+            return Err(ExternalSignerError::CommandFailed(
+                String::from_utf8_lossy(&stderr).trim().to_string(),
+            ));
+        }
+
+        let response: serde_json::Value = serde_json::from_slice(&stdout)
+            .map_err(|e| ExternalSignerError::InvalidResponse(e.to_string()))?;
+        if let Some(error) = response.get("error") {
+            return Err(ExternalSignerError::Signer(
+                error
+                    .as_str()
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| error.to_string()),
+            ));
+        }
+        serde_json::from_value(response)
+            .map_err(|e| ExternalSignerError::InvalidResponse(e.to_string()))
+    }
+
+    /// List the signing devices available.
+    pub fn enumerate(&self) -> Result<Vec<SignerDevice>, ExternalSignerError> {
+        // A device which can't be used (for instance because it's locked) is reported along
+        // with an error, ignore it.
+        let devices: Vec<serde_json::Value> = self.run(None, &["enumerate"], None)?;
+        Ok(devices
+            .into_iter()
+            .filter(|dev| dev.get("error").is_none())
+            .filter_map(|dev| serde_json::from_value(dev).ok())
+            .collect())
+    }
+
+    /// Have the device with this fingerprint sign this PSBT. Returns the signed PSBT.
+    pub fn sign_psbt(
+        &self,
+        fingerprint: &bip32::Fingerprint,
+        psbt: &Psbt,
+    ) -> Result<Psbt, ExternalSignerError> {
+        let stdin = format!("signtx {}\n", psbt);
+        let res: SignTxResponse = self.run(Some(fingerprint), &[], Some(&stdin))?;
+        Psbt::from_str(&res.psbt).map_err(|e| ExternalSignerError::InvalidResponse(e.to_string()))
+    }
+
+    /// Get the descriptors of the device with this fingerprint for this account.
+    pub fn get_descriptors(
+        &self,
+        fingerprint: &bip32::Fingerprint,
+        account: u32,
+    ) -> Result<SignerDescriptors, ExternalSignerError> {
+        self.run(
+            Some(fingerprint),
+            &["getdescriptors", "--account", &account.to_string()],
+            None,
+        )
+    }
+
+    /// Register this descriptor under this name on the device with this fingerprint. Returns the
+    /// proof of registration for devices which need one to be provided when signing.
+    pub fn register(
+        &self,
+        fingerprint: &bip32::Fingerprint,
+        descriptor: &str,
+        name: &str,
+    ) -> Result<Option<String>, ExternalSignerError> {
+        let res: RegisterResponse = self.run(
+            Some(fingerprint),
+            &["register", "--desc", descriptor, "--name", name],
+            None,
+        )?;
+        Ok(res.hmac)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testutils::{fake_signer, tmp_dir};
+
+    use std::fs;
+
+    #[test]
+    fn external_signer_protocol() {
+        let dir = tmp_dir();
+        let fingerprint = bip32::Fingerprint::from_str("aabbccdd").unwrap();
+
+        // The arguments are passed as for bitcoind's signer, and the PSBT through stdin.
+        let script = r#"
+case "$*" in
+    "--chain regtest enumerate")
+        echo '[{"fingerprint": "aabbccdd", "type": "ledger", "model": "ledger_nano_s"}, {"type": "trezor", "error": "Device locked"}]'
+        ;;
+    "--stdin --fingerprint aabbccdd --chain regtest")
+        read cmd psbt
+        echo "{\"psbt\": \"$psbt\"}"
+        ;;
+    "--fingerprint aabbccdd --chain regtest getdescriptors --account 0")
+        echo '{"receive": ["wpkh([aabbccdd/84h/1h/0h]tpubD/0/*)"], "internal": ["wpkh([aabbccdd/84h/1h/0h]tpubD/1/*)"]}'
+        ;;
+    "--fingerprint aabbccdd --chain regtest register --desc wsh(pk(A)) --name Liana")
+        echo '{"hmac": "deadbeef"}'
+        ;;
+    *)
+        echo '{"error": "Could not find device with specified fingerprint", "code": -3}'
+        ;;
+esac
+"#;
+        let path = fake_signer(&dir, script);
+        let signer =
+            ExternalSigner::new(vec![path.to_str().unwrap().to_string()], Network::Regtest);
+
+        assert_eq!(
+            signer.enumerate().unwrap(),
+            vec![SignerDevice {
+                fingerprint,
+                device_type: Some("ledger".to_string()),
+                model: Some("ledger_nano_s".to_string()),
+            }]
+        );
+        let psbt = Psbt::from_str("cHNidP8BAHECAAAAAUSHuliRtuCX1S6JxRuDRqDCKkWfKmWL5sV9ukZ/wzvfAAAAAAD9////AogTAAAAAAAAFgAUIxe7UY6LJ6y5mFBoWTOoVispDmdwFwAAAAAAABYAFKqO83TK+t/KdpAt21z2HGC7/Z2FAAAAAAABASsQJwAAAAAAACIAIIIySQjGCTeyx/rKUQx8qobjhJeNCiVCliBJPdyRX6XKAQVBIQI2cqWpc9UAW2gZt2WkKjvi8KoMCui00pRlL6wG32uKDKxzZHapFNYASzIYkEdH9bJz6nnqUG3uBB8kiK1asmgiBgI2cqWpc9UAW2gZt2WkKjvi8KoMCui00pRlL6wG32uKDAz1rML9AAAAAG8AAAAiBgMLcbOxsfLe6+3r1UcjQo77HY0As8OKE4l37yj0/qhIyQyKZPKpAAAAAG8AAAAAAAA=").unwrap();
+        assert_eq!(signer.sign_psbt(&fingerprint, &psbt).unwrap(), psbt);
+        assert_eq!(
+            signer.get_descriptors(&fingerprint, 0).unwrap(),
+            SignerDescriptors {
+                receive: vec!["wpkh([aabbccdd/84h/1h/0h]tpubD/0/*)".to_string()],
+                internal: vec!["wpkh([aabbccdd/84h/1h/0h]tpubD/1/*)".to_string()],
+            }
+        );
+        assert_eq!(
+            signer.register(&fingerprint, "wsh(pk(A))", "Liana"),
+            Ok(Some("deadbeef".to_string()))
+        );
+
+        // Errors returned by the signer are reported, as well as failures of the command.
+        let other_fingerprint = bip32::Fingerprint::from_str("00112233").unwrap();
+        assert_eq!(
+            signer.get_descriptors(&other_fingerprint, 0),
+            Err(ExternalSignerError::Signer(
+                "Could not find device with specified fingerprint".to_string()
+            ))
+        );
+        let path = fake_signer(&dir, "echo 'No device' >&2; exit 1");
+        let signer =
+            ExternalSigner::new(vec![path.to_str().unwrap().to_string()], Network::Regtest);
+        assert_eq!(
+            signer.enumerate(),
+            Err(ExternalSignerError::CommandFailed("No device".to_string()))
+        );
+        let signer = ExternalSigner::new(
+            vec![dir.join("non_existent").to_str().unwrap().to_string()],
+            Network::Regtest,
+        );
+        assert!(matches!(
+            signer.enumerate(),
+            Err(ExternalSignerError::Spawn(_))
+        ));
+        let signer = ExternalSigner::new(Vec::new(), Network::Regtest);
+        assert_eq!(signer.enumerate(), Err(ExternalSignerError::EmptyCommand));
+
+        // A command which doesn't complete in time is killed.
+        let path = fake_signer(&dir, "sleep 60");
+        let mut signer =
+            ExternalSigner::new(vec![path.to_str().unwrap().to_string()], Network::Regtest);
+        signer.timeout = time::Duration::from_millis(500);
+        let start = time::Instant::now();
+        assert_eq!(signer.enumerate(), Err(ExternalSignerError::Timeout));
+        assert!(start.elapsed() < time::Duration::from_secs(30));
+
+        // The program and its own arguments may contain spaces.
+        let path = fake_signer(
+            &dir.join("signer dir"),
+            r#"[ "$1" = "--config file" ] && [ "$2" = "--chain" ] && echo '[]'"#,
+        );
+        let signer = ExternalSigner::new(
+            vec![
+                path.to_str().unwrap().to_string(),
+                "--config file".to_string(),
+            ],
+            Network::Regtest,
+        );
+        assert_eq!(signer.enumerate(), Ok(Vec::new()));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,9 @@
 use crate::{
-    commands::{CoinStatus, LabelItem},
+    backup::WalletBackup,
+    commands::{CoinStatus, CoinsOrderBy, CoinsQuery, LabelItem},
+    descriptors::{LianaDescriptor, RecoveryTimelock},
     jsonrpc::{Error, Params, Request, Response},
-    DaemonControl,
+    psbt, DaemonControl,
 };
 
 use std::{
@@ -10,7 +12,7 @@ use std::{
     str::FromStr,
 };
 
-use miniscript::bitcoin::{self, psbt::PartiallySignedTransaction as Psbt};
+use miniscript::bitcoin::{self, bip32, psbt::PartiallySignedTransaction as Psbt};
 
 fn create_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
     let destinations = params
@@ -56,8 +58,11 @@ fn update_spend(control: &DaemonControl, params: Params) -> Result<serde_json::V
         .get(0, "psbt")
         .ok_or_else(|| Error::invalid_params("Missing 'psbt' parameter."))?
         .as_str()
-        .and_then(|s| Psbt::from_str(s).ok())
-        .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))?;
+        .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))
+        .and_then(|s| {
+            psbt::from_base64(s)
+                .map_err(|e| Error::invalid_params(format!("Invalid 'psbt' parameter: {}", e)))
+        })?;
     control.update_spend(psbt)?;
 
     Ok(serde_json::json!({}))
@@ -87,6 +92,200 @@ fn broadcast_spend(control: &DaemonControl, params: Params) -> Result<serde_json
     Ok(serde_json::json!({}))
 }
 
+fn sign_spend(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let txid = params
+        .get(0, "txid")
+        .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::Txid::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'txid' parameter."))?;
+    let res = control.sign_spend(&txid)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn signer_fingerprint(params: &Params, index: usize) -> Result<bip32::Fingerprint, Error> {
+    params
+        .get(index, "fingerprint")
+        .ok_or_else(|| Error::invalid_params("Missing 'fingerprint' parameter."))?
+        .as_str()
+        .and_then(|s| bip32::Fingerprint::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'fingerprint' parameter."))
+}
+
+fn sign_spend_external(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let txid = params
+        .get(0, "txid")
+        .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::Txid::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'txid' parameter."))?;
+    let fingerprint = signer_fingerprint(&params, 1)?;
+    let res = control.sign_spend_external(&txid, &fingerprint)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn register_descriptor(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let fingerprint = signer_fingerprint(&params, 0)?;
+    let res = control.register_descriptor(&fingerprint)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn get_signer_descriptors(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let fingerprint = signer_fingerprint(&params, 0)?;
+    let account = params
+        .get(1, "account")
+        .map(|account| {
+            account
+                .as_u64()
+                .and_then(|a| a.try_into().ok())
+                .ok_or_else(|| Error::invalid_params("Invalid 'account' parameter."))
+        })
+        .transpose()?
+        .unwrap_or(0);
+    let res = control.get_signer_descriptors(&fingerprint, account)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn sign_message(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let address = params
+        .get(0, "address")
+        .ok_or_else(|| Error::invalid_params("Missing 'address' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::Address::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'address' parameter."))?;
+    let message = params
+        .get(1, "message")
+        .ok_or_else(|| Error::invalid_params("Missing 'message' parameter."))?
+        .as_str()
+        .ok_or_else(|| Error::invalid_params("Invalid 'message' parameter."))?;
+    let psbt = params
+        .get(2, "psbt")
+        .map(|psbt| {
+            psbt.as_str()
+                .and_then(|s| psbt::from_base64(s).ok())
+                .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))
+        })
+        .transpose()?;
+    let res = control.sign_message(address, message, psbt)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn verify_message(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let address = params
+        .get(0, "address")
+        .ok_or_else(|| Error::invalid_params("Missing 'address' parameter."))?
+        .as_str()
+        .and_then(|s| bitcoin::Address::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'address' parameter."))?;
+    let message = params
+        .get(1, "message")
+        .ok_or_else(|| Error::invalid_params("Missing 'message' parameter."))?
+        .as_str()
+        .ok_or_else(|| Error::invalid_params("Invalid 'message' parameter."))?;
+    let signature = params
+        .get(2, "signature")
+        .ok_or_else(|| Error::invalid_params("Missing 'signature' parameter."))?
+        .as_str()
+        .ok_or_else(|| Error::invalid_params("Invalid 'signature' parameter."))?;
+    let res = control.verify_message(address, message, signature)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn create_proof_of_reserves(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let message = params
+        .get(0, "message")
+        .ok_or_else(|| Error::invalid_params("Missing 'message' parameter."))?
+        .as_str()
+        .ok_or_else(|| Error::invalid_params("Invalid 'message' parameter."))?;
+    let outpoints = params
+        .get(1, "outpoints")
+        .map(|outpoints| {
+            outpoints
+                .as_array()
+                .and_then(|arr| {
+                    arr.iter()
+                        .map(|entry| {
+                            entry
+                                .as_str()
+                                .and_then(|e| bitcoin::OutPoint::from_str(e).ok())
+                        })
+                        .collect::<Option<Vec<bitcoin::OutPoint>>>()
+                })
+                .ok_or_else(|| Error::invalid_params("Invalid 'outpoints' parameter."))
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let res = control.create_proof_of_reserves(message, &outpoints)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn verify_proof_of_reserves(
+    control: &DaemonControl,
+    params: Params,
+) -> Result<serde_json::Value, Error> {
+    let message = params
+        .get(0, "message")
+        .ok_or_else(|| Error::invalid_params("Missing 'message' parameter."))?
+        .as_str()
+        .ok_or_else(|| Error::invalid_params("Invalid 'message' parameter."))?;
+    let psbt: Psbt = params
+        .get(1, "psbt")
+        .ok_or_else(|| Error::invalid_params("Missing 'psbt' parameter."))?
+        .as_str()
+        .and_then(|s| psbt::from_base64(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'psbt' parameter."))?;
+    let res = control.verify_proof_of_reserves(message, &psbt)?;
+
+    Ok(serde_json::json!(&res))
+}
+
+fn list_spend(control: &DaemonControl, params: Option<Params>) -> Result<serde_json::Value, Error> {
+    let psbt_version = params
+        .as_ref()
+        .and_then(|p| p.get(0, "psbt_version"))
+        .map(|v| {
+            v.as_u64()
+                .filter(|v| *v == 0 || *v == 2)
+                .ok_or_else(|| Error::invalid_params("Invalid 'psbt_version' parameter."))
+        })
+        .transpose()?
+        .unwrap_or(0);
+    let res = control.list_spend();
+    if psbt_version == 0 {
+        return Ok(serde_json::json!(&res));
+    }
+
+    let spend_txs: Vec<serde_json::Value> = res
+        .spend_txs
+        .iter()
+        .map(|entry| {
+            let mut entry_json = serde_json::json!(entry);
+            entry_json["psbt"] = serde_json::json!(psbt::to_base64_v2(&entry.psbt));
+            entry_json
+        })
+        .collect();
+    Ok(serde_json::json!({ "spend_txs": spend_txs }))
+}
+
 fn list_coins(control: &DaemonControl, params: Option<Params>) -> Result<serde_json::Value, Error> {
     let statuses_arg = params
         .as_ref()
@@ -132,10 +331,103 @@ fn list_coins(control: &DaemonControl, params: Option<Params>) -> Result<serde_j
     } else {
         Vec::new()
     };
-    let res = control.list_coins(&statuses, &outpoints);
+    let query = list_coins_query(params.as_ref())?;
+    let res = control.list_coins(&statuses, &outpoints, &query);
     Ok(serde_json::json!(&res))
 }
 
+// Parse the optional filtering, ordering and pagination parameters of 'listcoins'.
+fn list_coins_query(params: Option<&Params>) -> Result<CoinsQuery, Error> {
+    let param = |index: usize, name: &str| {
+        params
+            .and_then(|p| p.get(index, name))
+            .filter(|v| !v.is_null())
+    };
+    let u64_param = |index: usize, name: &str| -> Result<Option<u64>, Error> {
+        param(index, name)
+            .map(|v| {
+                v.as_u64()
+                    .ok_or_else(|| Error::invalid_params(format!("Invalid '{}' parameter.", name)))
+            })
+            .transpose()
+    };
+    let height_param = |index: usize, name: &str| -> Result<Option<i32>, Error> {
+        u64_param(index, name)?
+            .map(|h| {
+                h.try_into()
+                    .map_err(|_| Error::invalid_params(format!("Invalid '{}' parameter.", name)))
+            })
+            .transpose()
+    };
+
+    // The database only supports signed 64-bit integers.
+    let i64_range_param = |index: usize, name: &str| -> Result<Option<u64>, Error> {
+        u64_param(index, name)?
+            .map(|v| {
+                if v > i64::MAX as u64 {
+                    Err(Error::invalid_params(format!(
+                        "Invalid '{}' parameter.",
+                        name
+                    )))
+                } else {
+                    Ok(v)
+                }
+            })
+            .transpose()
+    };
+
+    let limit = i64_range_param(2, "limit")?;
+    let offset = i64_range_param(3, "offset")?.unwrap_or(0);
+    let order_by = param(4, "order_by")
+        .map(|v| {
+            v.as_str()
+                .and_then(CoinsOrderBy::from_arg)
+                .ok_or_else(|| Error::invalid_params("Invalid 'order_by' parameter."))
+        })
+        .transpose()?;
+    let descending = param(5, "descending")
+        .map(|v| {
+            v.as_bool()
+                .ok_or_else(|| Error::invalid_params("Invalid 'descending' parameter."))
+        })
+        .transpose()?
+        .unwrap_or(false);
+    if descending && order_by.is_none() {
+        return Err(Error::invalid_params(
+            "The 'descending' parameter requires the 'order_by' parameter.",
+        ));
+    }
+    let min_amount = u64_param(6, "min_amount")?.map(bitcoin::Amount::from_sat);
+    let max_amount = u64_param(7, "max_amount")?.map(bitcoin::Amount::from_sat);
+    let is_change = param(8, "is_change")
+        .map(|v| {
+            v.as_bool()
+                .ok_or_else(|| Error::invalid_params("Invalid 'is_change' parameter."))
+        })
+        .transpose()?;
+    let label = param(9, "label")
+        .map(|v| {
+            v.as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| Error::invalid_params("Invalid 'label' parameter."))
+        })
+        .transpose()?;
+    let min_height = height_param(10, "min_height")?;
+    let max_height = height_param(11, "max_height")?;
+
+    Ok(CoinsQuery {
+        min_amount,
+        max_amount,
+        is_change,
+        label,
+        min_height,
+        max_height,
+        order_by: order_by.map(|o| (o, descending)),
+        limit,
+        offset,
+    })
+}
+
 fn list_confirmed(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
     let start: u32 = params
         .get(0, "start")
@@ -163,6 +455,27 @@ fn list_confirmed(control: &DaemonControl, params: Params) -> Result<serde_json:
     ))
 }
 
+fn list_history(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    // The database only supports signed 64-bit integers.
+    let limit: u64 = params
+        .get(0, "limit")
+        .ok_or_else(|| Error::invalid_params("Missing 'limit' parameter."))?
+        .as_u64()
+        .filter(|l| *l <= i64::MAX as u64)
+        .ok_or_else(|| Error::invalid_params("Invalid 'limit' parameter."))?;
+    let offset: u64 = params
+        .get(1, "offset")
+        .map(|o| {
+            o.as_u64()
+                .filter(|o| *o <= i64::MAX as u64)
+                .ok_or_else(|| Error::invalid_params("Invalid 'offset' parameter."))
+        })
+        .transpose()?
+        .unwrap_or(0);
+
+    Ok(serde_json::json!(&control.list_history(limit, offset)))
+}
+
 fn list_transactions(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
     let txids: Vec<bitcoin::Txid> = params
         .get(0, "txids")
@@ -201,12 +514,21 @@ fn create_recovery(control: &DaemonControl, params: Params) -> Result<serde_json
         .ok_or_else(|| Error::invalid_params("Missing 'feerate' parameter."))?
         .as_u64()
         .ok_or_else(|| Error::invalid_params("Invalid 'feerate' parameter."))?;
-    let timelock: Option<u16> = params
+    // A relative timelock is given as an integer, an absolute one as 'after(<value>)'.
+    let timelock: Option<RecoveryTimelock> = params
         .get(2, "timelock")
         .map(|tl| {
-            tl.as_u64()
-                .and_then(|tl| tl.try_into().ok())
-                .ok_or_else(|| Error::invalid_params("Invalid 'timelock' parameter."))
+            if let Some(tl) = tl.as_str() {
+                tl.strip_prefix("after(")
+                    .and_then(|tl| tl.strip_suffix(')'))
+                    .and_then(|tl| u32::from_str(tl).ok())
+                    .and_then(|tl| RecoveryTimelock::from_absolute_consensus(tl).ok())
+            } else {
+                tl.as_u64()
+                    .and_then(|tl| tl.try_into().ok())
+                    .and_then(|tl| RecoveryTimelock::from_consensus(tl).ok())
+            }
+            .ok_or_else(|| Error::invalid_params("Invalid 'timelock' parameter."))
         })
         .transpose()?;
 
@@ -214,6 +536,31 @@ fn create_recovery(control: &DaemonControl, params: Params) -> Result<serde_json
     Ok(serde_json::json!(&res))
 }
 
+fn create_migration(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let descriptor = params
+        .get(0, "descriptor")
+        .ok_or_else(|| Error::invalid_params("Missing 'descriptor' parameter."))?
+        .as_str()
+        .and_then(|s| LianaDescriptor::from_str(s).ok())
+        .ok_or_else(|| Error::invalid_params("Invalid 'descriptor' parameter."))?;
+    let feerate: u64 = params
+        .get(1, "feerate")
+        .ok_or_else(|| Error::invalid_params("Missing 'feerate' parameter."))?
+        .as_u64()
+        .ok_or_else(|| Error::invalid_params("Invalid 'feerate' parameter."))?;
+    let batch_size: Option<usize> = params
+        .get(2, "batch_size")
+        .map(|b| {
+            b.as_u64()
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(|| Error::invalid_params("Invalid 'batch_size' parameter."))
+        })
+        .transpose()?;
+
+    let res = control.create_migration(&descriptor, feerate, batch_size)?;
+    Ok(serde_json::json!(&res))
+}
+
 fn update_labels(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
     let mut items = HashMap::new();
     for (item, value) in params
@@ -276,6 +623,49 @@ fn get_labels(control: &DaemonControl, params: Params) -> Result<serde_json::Val
     Ok(serde_json::json!(control.get_labels(&items)))
 }
 
+fn get_policy(control: &DaemonControl, params: Option<Params>) -> Result<serde_json::Value, Error> {
+    let aliases = match params.as_ref().and_then(|p| p.get(0, "aliases")) {
+        Some(aliases) => aliases
+            .as_object()
+            .and_then(|obj| {
+                obj.into_iter()
+                    .map(|(k, v)| {
+                        let fg = bip32::Fingerprint::from_str(k).ok()?;
+                        Some((fg, v.as_str()?.to_string()))
+                    })
+                    .collect::<Option<HashMap<bip32::Fingerprint, String>>>()
+            })
+            .ok_or_else(|| Error::invalid_params("Invalid 'aliases' parameter."))?,
+        None => HashMap::new(),
+    };
+    let coin_height: Option<u32> = params
+        .as_ref()
+        .and_then(|p| p.get(1, "coin_height"))
+        .map(|h| {
+            h.as_u64()
+                .and_then(|h| h.try_into().ok())
+                .ok_or_else(|| Error::invalid_params("Invalid 'coin_height' parameter."))
+        })
+        .transpose()?;
+
+    Ok(serde_json::json!(control.get_policy(&aliases, coin_height)))
+}
+
+fn restore_backup(control: &DaemonControl, params: Params) -> Result<serde_json::Value, Error> {
+    let backup = params
+        .get(0, "backup")
+        .ok_or_else(|| Error::invalid_params("Missing 'backup' parameter."))?;
+    // The backup may be given either as a JSON object or as its serialization.
+    let backup = match backup.as_str() {
+        Some(s) => WalletBackup::from_json(s),
+        None => WalletBackup::from_json(&backup.to_string()),
+    }
+    .map_err(|e| Error::invalid_params(format!("Invalid 'backup' parameter: {}", e)))?;
+
+    control.restore_backup(&backup)?;
+    Ok(serde_json::json!({}))
+}
+
 /// Handle an incoming JSONRPC2 request.
 pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response, Error> {
     let result = match req.method.as_str() {
@@ -285,6 +675,18 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
                 .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
             broadcast_spend(control, params)?
         }
+        "createmigration" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'descriptor' and 'feerate' parameters.")
+            })?;
+            create_migration(control, params)?
+        }
+        "createproofofreserves" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'message' parameter."))?;
+            create_proof_of_reserves(control, params)?
+        }
         "createrecovery" => {
             let params = req.params.ok_or_else(|| {
                 Error::invalid_params("Missing 'address' and 'feerate' parameters.")
@@ -305,8 +707,20 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
                 .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
             delete_spend(control, params)?
         }
+        "enumeratesigners" => serde_json::json!(&control.enumerate_signers()?),
+        "getbackup" => serde_json::json!(&control.get_backup()),
         "getinfo" => serde_json::json!(&control.get_info()),
         "getnewaddress" => serde_json::json!(&control.get_new_address()),
+        "getpolicy" => {
+            let params = req.params;
+            get_policy(control, params)?
+        }
+        "getsignerdescriptors" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'fingerprint' parameter."))?;
+            get_signer_descriptors(control, params)?
+        }
         "listcoins" => {
             let params = req.params;
             list_coins(control, params)?
@@ -319,7 +733,17 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
             })?;
             list_confirmed(control, params)?
         }
-        "listspendtxs" => serde_json::json!(&control.list_spend()),
+        "listexpiredcoins" => serde_json::json!(&control.list_expired_coins()),
+        "listhistory" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'limit' parameter."))?;
+            list_history(control, params)?
+        }
+        "listspendtxs" => {
+            let params = req.params;
+            list_spend(control, params)?
+        }
         "listtransactions" => {
             let params = req.params.ok_or_else(|| {
                 Error::invalid_params(
@@ -328,6 +752,36 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
             })?;
             list_transactions(control, params)?
         }
+        "registerdescriptor" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'fingerprint' parameter."))?;
+            register_descriptor(control, params)?
+        }
+        "restorebackup" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'backup' parameter."))?;
+            restore_backup(control, params)?
+        }
+        "signmessage" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'address' and 'message' parameters.")
+            })?;
+            sign_message(control, params)?
+        }
+        "signspend" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'txid' parameter."))?;
+            sign_spend(control, params)?
+        }
+        "signspendexternal" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'txid' and 'fingerprint' parameters.")
+            })?;
+            sign_spend_external(control, params)?
+        }
         "startrescan" => {
             let params = req
                 .params
@@ -353,6 +807,18 @@ pub fn handle_request(control: &DaemonControl, req: Request) -> Result<Response,
                 .ok_or_else(|| Error::invalid_params("Missing 'items' parameter."))?;
             get_labels(control, params)?
         }
+        "verifymessage" => {
+            let params = req.params.ok_or_else(|| {
+                Error::invalid_params("Missing 'address', 'message' and 'signature' parameters.")
+            })?;
+            verify_message(control, params)?
+        }
+        "verifyproofofreserves" => {
+            let params = req
+                .params
+                .ok_or_else(|| Error::invalid_params("Missing 'message' and 'psbt' parameters."))?;
+            verify_proof_of_reserves(control, params)?
+        }
         _ => {
             return Err(Error::method_not_found());
         }
@@ -164,12 +164,33 @@ impl From<commands::CommandError> for Error {
             | commands::CommandError::SpendFinalization(..)
             | commands::CommandError::InsaneRescanTimestamp(..)
             | commands::CommandError::AlreadyRescanning
-            | commands::CommandError::RecoveryNotAvailable => {
+            | commands::CommandError::RecoveryNotAvailable
+            | commands::CommandError::MigratedCoin(..)
+            | commands::CommandError::MigrationToSameDescriptor
+            | commands::CommandError::NoCoinToMigrate
+            | commands::CommandError::InvalidMigrationBatchSize(..)
+            | commands::CommandError::TooManyMigrationTransactions(..)
+            | commands::CommandError::BackupDescriptorMismatch(..)
+            | commands::CommandError::BackupNetworkMismatch(..)
+            | commands::CommandError::InvalidBackupLabel(..)
+            | commands::CommandError::NoHotSigner
+            | commands::CommandError::EncryptedHotSigner(..)
+            | commands::CommandError::HotSigning(..)
+            | commands::CommandError::SigningPolicyViolation(..)
+            | commands::CommandError::UnknownAddress(..)
+            | commands::CommandError::Bip322(..)
+            | commands::CommandError::NoConfirmedCoin
+            | commands::CommandError::ProofOfReserves(..)
+            | commands::CommandError::NoExternalSigner
+            | commands::CommandError::ForeignSigner(..) => {
                 Error::new(ErrorCode::InvalidParams, e.to_string())
             }
             commands::CommandError::FetchingTransaction(..)
             | commands::CommandError::SanityCheckFailure(_)
-            | commands::CommandError::RescanTrigger(..) => {
+            | commands::CommandError::RescanTrigger(..)
+            | commands::CommandError::DescriptorImport(..)
+            | commands::CommandError::HotSignerStorage(..)
+            | commands::CommandError::ExternalSigner(..) => {
                 Error::new(ErrorCode::InternalError, e.to_string())
             }
             commands::CommandError::TxBroadcast(_) => {
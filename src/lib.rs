@@ -1,3 +1,5 @@
+pub mod backup;
+pub mod bip322;
 mod bitcoin;
 pub mod commands;
 pub mod config;
@@ -5,9 +7,12 @@ pub mod config;
 mod daemonize;
 mod database;
 pub mod descriptors;
+pub mod external_signer;
 #[cfg(feature = "daemon")]
 mod jsonrpc;
+pub mod psbt;
 mod random;
+pub mod reserves;
 pub mod signer;
 #[cfg(test)]
 mod testutils;
@@ -181,12 +186,36 @@ fn setup_sqlite(
         None
     };
     let sqlite = SqliteDb::new(db_path, options, secp)?;
+    if sqlite.maybe_switch_descriptor(&config.main_descriptor, secp)? {
+        log::warn!(
+            "Switched the wallet to the new descriptor '{}'. The chain will be rescanned since the start of the migration.",
+            config.main_descriptor
+        );
+    }
     sqlite.sanity_check(config.bitcoin_config.network, &config.main_descriptor)?;
     log::info!("Database initialized and checked.");
 
     Ok(sqlite)
 }
 
+// Fetch from the Bitcoin backend the transactions of our coins that aren't stored in database, and
+// store them. Those that can't be fetched will be retried by the poller upon new coin events.
+fn store_missing_txs(bit: &impl BitcoinInterface, db: &impl DatabaseInterface) {
+    let mut db_conn = db.connection();
+    let txs: Vec<miniscript::bitcoin::Transaction> = db_conn
+        .missing_txids()
+        .iter()
+        .filter_map(|txid| match bit.wallet_transaction(txid) {
+            Some((tx, _)) => Some(tx),
+            None => {
+                log::warn!("Could not get wallet transaction '{}'", txid);
+                None
+            }
+        })
+        .collect();
+    db_conn.new_txs(&txs);
+}
+
 // Try to copy the watchonly wallet from its former location on Windows to its new location.
 fn copy_watchonly_wallet(
     bitcoind_cookie_path: &path::Path,
@@ -279,7 +308,10 @@ fn setup_bitcoind(
         .as_ref()
         .ok_or(StartupError::MissingBitcoindConfig)?;
     let bitcoind = BitcoinD::new(bitcoind_config, wo_path_str)?;
-    bitcoind.node_sanity_checks(config.bitcoin_config.network)?;
+    bitcoind.node_sanity_checks(
+        config.bitcoin_config.network,
+        config.main_descriptor.is_taproot(),
+    )?;
     if fresh_data_dir {
         log::info!("Creating a new watchonly wallet on bitcoind.");
         bitcoind.create_watchonly_wallet(&config.main_descriptor)?;
@@ -318,6 +350,9 @@ pub struct DaemonControl {
     // FIXME: Should we require Sync on DatabaseInterface rather than using a Mutex?
     db: sync::Arc<sync::Mutex<dyn DatabaseInterface>>,
     secp: secp256k1::Secp256k1<secp256k1::VerifyOnly>,
+    // Held while the hot signers sign a Spend, so concurrent requests can't get around their
+    // daily limits.
+    signing_lock: sync::Arc<sync::Mutex<()>>,
 }
 
 impl DaemonControl {
@@ -332,6 +367,7 @@ impl DaemonControl {
             bitcoin,
             db,
             secp,
+            signing_lock: sync::Arc::new(sync::Mutex::new(())),
         }
     }
 
@@ -399,6 +435,10 @@ impl DaemonHandle {
             )?)) as sync::Arc<sync::Mutex<dyn BitcoinInterface>>,
         };
 
+        // Some of the wallet transactions may not be stored yet, for instance right after
+        // upgrading from a database version that didn't store them. Fetch them now.
+        store_missing_txs(&bit, &db);
+
         // If we are on a UNIX system and they told us to daemonize, do it now.
         // NOTE: it's safe to daemonize now, as we don't carry any open DB connection
         // https://www.sqlite.org/howtocorrupt.html#_carrying_an_open_database_connection_across_a_fork_
@@ -738,6 +778,8 @@ mod tests {
             daemon: false,
             log_level: log::LevelFilter::Debug,
             main_descriptor: desc,
+            signer_policies: Vec::new(),
+            signer_command: None,
         };
 
         // Start the daemon in a new thread so the current one acts as the bitcoind server.
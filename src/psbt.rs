@@ -0,0 +1,572 @@
+//! PSBT versions module
+//!
+//! We store and sign version 0 PSBTs (BIP174), but newer signers and coordinators exchange version
+//! 2 PSBTs (BIP370). Those don't contain the unsigned transaction: its fields are spread across the
+//! global, input and output maps instead. The conversion is performed on the key-value maps
+//! directly, so that every other field (scripts, derivation paths, previous transactions,
+//! signatures, ..) is preserved as is.
+
+use std::{convert::TryInto, error, fmt, io};
+
+use miniscript::bitcoin::{
+    base64,
+    blockdata::locktime::absolute,
+    consensus::{self, encode::VarInt, Decodable},
+    psbt::Psbt,
+    OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+
+const PSBT_MAGIC: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_TX_VERSION: u8 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const PSBT_GLOBAL_VERSION: u8 = 0xFB;
+
+const PSBT_IN_PREVIOUS_TXID: u8 = 0x0E;
+const PSBT_IN_OUTPUT_INDEX: u8 = 0x0F;
+const PSBT_IN_SEQUENCE: u8 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+
+const PSBT_OUT_AMOUNT: u8 = 0x03;
+const PSBT_OUT_SCRIPT: u8 = 0x04;
+
+/// An error related to parsing a PSBT of either version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PsbtError {
+    /// The PSBT is not valid base64.
+    Base64(String),
+    /// The PSBT could not be parsed.
+    Deserialization(String),
+    /// Only versions 0 and 2 are supported.
+    UnsupportedVersion(u32),
+    /// This field is required in a version 2 PSBT but is missing.
+    MissingField(&'static str),
+    /// This field of a version 2 PSBT has an invalid value.
+    InvalidField(&'static str),
+    /// The locktimes required by the inputs can't all be satisfied by the transaction.
+    IncompatibleLocktimes,
+}
+
+impl fmt::Display for PsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Base64(e) => write!(f, "Invalid base64 encoding: '{}'.", e),
+            Self::Deserialization(e) => write!(f, "Failed to parse PSBT: '{}'.", e),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported PSBT version: {}.", v),
+            Self::MissingField(field) => write!(f, "Missing required field '{}'.", field),
+            Self::InvalidField(field) => write!(f, "Invalid value for field '{}'.", field),
+            Self::IncompatibleLocktimes => {
+                write!(f, "The inputs require both a height and a time locktime.")
+            }
+        }
+    }
+}
+
+impl error::Error for PsbtError {}
+
+// A key-value pair of a PSBT map. The key includes its type.
+type Pair = (Vec<u8>, Vec<u8>);
+
+// A PSBT as a list of key-value maps, without interpretation of their content besides what's
+// necessary to know the number of input and output maps.
+#[derive(Debug)]
+struct RawPsbt {
+    version: u32,
+    global: Vec<Pair>,
+    inputs: Vec<Vec<Pair>>,
+    outputs: Vec<Vec<Pair>>,
+}
+
+fn deser_err(e: impl fmt::Display) -> PsbtError {
+    PsbtError::Deserialization(e.to_string())
+}
+
+fn read_map(cursor: &mut io::Cursor<&[u8]>) -> Result<Vec<Pair>, PsbtError> {
+    let mut map: Vec<Pair> = Vec::new();
+    loop {
+        let key = Vec::<u8>::consensus_decode(cursor).map_err(deser_err)?;
+        if key.is_empty() {
+            return Ok(map);
+        }
+        let value = Vec::<u8>::consensus_decode(cursor).map_err(deser_err)?;
+        if map.iter().any(|(k, _)| *k == key) {
+            return Err(deser_err("duplicate key"));
+        }
+        map.push((key, value));
+    }
+}
+
+fn write_map(buf: &mut Vec<u8>, map: &[Pair]) {
+    for (key, value) in map {
+        buf.extend_from_slice(&consensus::serialize(key));
+        buf.extend_from_slice(&consensus::serialize(value));
+    }
+    buf.push(0x00);
+}
+
+// Get the value for the key of this type without key data.
+fn get(map: &[Pair], key_type: u8) -> Option<&[u8]> {
+    map.iter()
+        .find(|(k, _)| k[..] == [key_type])
+        .map(|(_, v)| &v[..])
+}
+
+// Remove the entry for the key of this type without key data, returning its value.
+fn take(map: &mut Vec<Pair>, key_type: u8) -> Option<Vec<u8>> {
+    let index = map.iter().position(|(k, _)| k[..] == [key_type])?;
+    Some(map.remove(index).1)
+}
+
+fn le_u32(value: &[u8], field: &'static str) -> Result<u32, PsbtError> {
+    let bytes: [u8; 4] = value
+        .try_into()
+        .map_err(|_| PsbtError::InvalidField(field))?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn count(value: Option<&[u8]>, field: &'static str) -> Result<usize, PsbtError> {
+    let value = value.ok_or(PsbtError::MissingField(field))?;
+    let count: VarInt =
+        consensus::deserialize(value).map_err(|_| PsbtError::InvalidField(field))?;
+    count
+        .0
+        .try_into()
+        .map_err(|_| PsbtError::InvalidField(field))
+}
+
+impl RawPsbt {
+    fn deserialize(bytes: &[u8]) -> Result<Self, PsbtError> {
+        if !bytes.starts_with(PSBT_MAGIC) {
+            return Err(deser_err("invalid magic"));
+        }
+        let mut cursor = io::Cursor::new(bytes);
+        cursor.set_position(PSBT_MAGIC.len() as u64);
+
+        let global = read_map(&mut cursor)?;
+        let version = get(&global, PSBT_GLOBAL_VERSION)
+            .map(|v| le_u32(v, "version"))
+            .transpose()?
+            .unwrap_or(0);
+        let (n_inputs, n_outputs) = match version {
+            0 => {
+                let tx: Transaction = get(&global, PSBT_GLOBAL_UNSIGNED_TX)
+                    .ok_or(PsbtError::MissingField("unsigned transaction"))
+                    .and_then(|tx| consensus::deserialize(tx).map_err(deser_err))?;
+                (tx.input.len(), tx.output.len())
+            }
+            2 => (
+                count(get(&global, PSBT_GLOBAL_INPUT_COUNT), "input count")?,
+                count(get(&global, PSBT_GLOBAL_OUTPUT_COUNT), "output count")?,
+            ),
+            v => return Err(PsbtError::UnsupportedVersion(v)),
+        };
+
+        // Don't trust the counts for allocating, each map takes at least a byte.
+        let mut inputs = Vec::new();
+        for _ in 0..n_inputs {
+            inputs.push(read_map(&mut cursor)?);
+        }
+        let mut outputs = Vec::new();
+        for _ in 0..n_outputs {
+            outputs.push(read_map(&mut cursor)?);
+        }
+        if cursor.position() != bytes.len() as u64 {
+            return Err(deser_err("trailing data"));
+        }
+
+        Ok(Self {
+            version,
+            global,
+            inputs,
+            outputs,
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = PSBT_MAGIC.to_vec();
+        write_map(&mut buf, &self.global);
+        for map in self.inputs.iter().chain(self.outputs.iter()) {
+            write_map(&mut buf, map);
+        }
+        buf
+    }
+
+    // Convert a version 2 PSBT to version 0, by reconstructing the unsigned transaction.
+    fn into_v0(mut self) -> Result<Self, PsbtError> {
+        let tx_version = take(&mut self.global, PSBT_GLOBAL_TX_VERSION)
+            .ok_or(PsbtError::MissingField("transaction version"))?;
+        let tx_version = le_u32(&tx_version, "transaction version")? as i32;
+        let fallback_locktime = take(&mut self.global, PSBT_GLOBAL_FALLBACK_LOCKTIME)
+            .map(|v| le_u32(&v, "fallback locktime"))
+            .transpose()?;
+        // Modifiability flags are only meaningful to construct the transaction, which is done
+        // by the time it's given to us.
+        for key_type in &[
+            PSBT_GLOBAL_INPUT_COUNT,
+            PSBT_GLOBAL_OUTPUT_COUNT,
+            PSBT_GLOBAL_TX_MODIFIABLE,
+            PSBT_GLOBAL_VERSION,
+        ] {
+            take(&mut self.global, *key_type);
+        }
+
+        let mut txins = Vec::with_capacity(self.inputs.len());
+        let mut required_locktimes = Vec::new();
+        for input in self.inputs.iter_mut() {
+            let txid: Txid = take(input, PSBT_IN_PREVIOUS_TXID)
+                .ok_or(PsbtError::MissingField("previous txid"))
+                .and_then(|v| {
+                    consensus::deserialize(&v).map_err(|_| PsbtError::InvalidField("previous txid"))
+                })?;
+            let vout = take(input, PSBT_IN_OUTPUT_INDEX)
+                .ok_or(PsbtError::MissingField("output index"))
+                .and_then(|v| le_u32(&v, "output index"))?;
+            let sequence = take(input, PSBT_IN_SEQUENCE)
+                .map(|v| le_u32(&v, "sequence"))
+                .transpose()?
+                .map(Sequence)
+                .unwrap_or(Sequence::MAX);
+            let time = take(input, PSBT_IN_REQUIRED_TIME_LOCKTIME)
+                .map(|v| le_u32(&v, "required time locktime"))
+                .transpose()?;
+            let height = take(input, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)
+                .map(|v| le_u32(&v, "required height locktime"))
+                .transpose()?;
+            if time.is_some() || height.is_some() {
+                required_locktimes.push((height, time));
+            }
+            txins.push(TxIn {
+                previous_output: OutPoint { txid, vout },
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: Witness::new(),
+            });
+        }
+
+        let mut txouts = Vec::with_capacity(self.outputs.len());
+        for output in self.outputs.iter_mut() {
+            let amount = take(output, PSBT_OUT_AMOUNT).ok_or(PsbtError::MissingField("amount"))?;
+            let amount: [u8; 8] = amount[..]
+                .try_into()
+                .map_err(|_| PsbtError::InvalidField("amount"))?;
+            let value: u64 = i64::from_le_bytes(amount)
+                .try_into()
+                .map_err(|_| PsbtError::InvalidField("amount"))?;
+            let script_pubkey = take(output, PSBT_OUT_SCRIPT)
+                .map(ScriptBuf::from)
+                .ok_or(PsbtError::MissingField("script"))?;
+            txouts.push(TxOut {
+                value,
+                script_pubkey,
+            });
+        }
+
+        // As per BIP370, a height locktime is preferred if all the inputs with a requirement
+        // can be satisfied by one. Otherwise all of them must be satisfiable by a time locktime.
+        let lock_time = if required_locktimes.is_empty() {
+            fallback_locktime.unwrap_or(0)
+        } else if required_locktimes.iter().all(|(h, _)| h.is_some()) {
+            required_locktimes
+                .iter()
+                .filter_map(|(h, _)| *h)
+                .max()
+                .expect("Not empty")
+        } else if required_locktimes.iter().all(|(_, t)| t.is_some()) {
+            required_locktimes
+                .iter()
+                .filter_map(|(_, t)| *t)
+                .max()
+                .expect("Not empty")
+        } else {
+            return Err(PsbtError::IncompatibleLocktimes);
+        };
+
+        let tx = Transaction {
+            version: tx_version,
+            lock_time: absolute::LockTime::from_consensus(lock_time),
+            input: txins,
+            output: txouts,
+        };
+        self.global.insert(
+            0,
+            (vec![PSBT_GLOBAL_UNSIGNED_TX], consensus::serialize(&tx)),
+        );
+        self.version = 0;
+
+        Ok(self)
+    }
+}
+
+/// Parse a PSBT of either version 0 or version 2. A version 2 PSBT is converted to version 0.
+pub fn deserialize(bytes: &[u8]) -> Result<Psbt, PsbtError> {
+    let raw = RawPsbt::deserialize(bytes)?;
+    if raw.version == 0 {
+        return Psbt::deserialize(bytes).map_err(deser_err);
+    }
+    let bytes = raw.into_v0()?.serialize();
+    Psbt::deserialize(&bytes).map_err(deser_err)
+}
+
+/// Parse a base64-encoded PSBT of either version 0 or version 2.
+pub fn from_base64(s: &str) -> Result<Psbt, PsbtError> {
+    let bytes = base64::decode(s.trim()).map_err(|e| PsbtError::Base64(e.to_string()))?;
+    deserialize(&bytes)
+}
+
+/// Serialize this PSBT as version 2.
+pub fn serialize_v2(psbt: &Psbt) -> Vec<u8> {
+    let mut raw = RawPsbt::deserialize(&psbt.serialize()).expect("Serialized by rust-bitcoin");
+    let tx = &psbt.unsigned_tx;
+
+    take(&mut raw.global, PSBT_GLOBAL_UNSIGNED_TX);
+    take(&mut raw.global, PSBT_GLOBAL_VERSION);
+    raw.global.extend(vec![
+        (
+            vec![PSBT_GLOBAL_TX_VERSION],
+            tx.version.to_le_bytes().to_vec(),
+        ),
+        (
+            vec![PSBT_GLOBAL_FALLBACK_LOCKTIME],
+            tx.lock_time.to_consensus_u32().to_le_bytes().to_vec(),
+        ),
+        (
+            vec![PSBT_GLOBAL_INPUT_COUNT],
+            consensus::serialize(&VarInt(tx.input.len() as u64)),
+        ),
+        (
+            vec![PSBT_GLOBAL_OUTPUT_COUNT],
+            consensus::serialize(&VarInt(tx.output.len() as u64)),
+        ),
+        (vec![PSBT_GLOBAL_VERSION], 2u32.to_le_bytes().to_vec()),
+    ]);
+    for (input, txin) in raw.inputs.iter_mut().zip(tx.input.iter()) {
+        input.extend(vec![
+            (
+                vec![PSBT_IN_PREVIOUS_TXID],
+                consensus::serialize(&txin.previous_output.txid),
+            ),
+            (
+                vec![PSBT_IN_OUTPUT_INDEX],
+                txin.previous_output.vout.to_le_bytes().to_vec(),
+            ),
+            (
+                vec![PSBT_IN_SEQUENCE],
+                txin.sequence.0.to_le_bytes().to_vec(),
+            ),
+        ]);
+    }
+    for (output, txout) in raw.outputs.iter_mut().zip(tx.output.iter()) {
+        output.extend(vec![
+            (
+                vec![PSBT_OUT_AMOUNT],
+                (txout.value as i64).to_le_bytes().to_vec(),
+            ),
+            (
+                vec![PSBT_OUT_SCRIPT],
+                txout.script_pubkey.as_bytes().to_vec(),
+            ),
+        ]);
+    }
+    raw.version = 2;
+
+    raw.serialize()
+}
+
+/// Serialize this PSBT as version 2, encoded in base64.
+pub fn to_base64_v2(psbt: &Psbt) -> String {
+    base64::encode(serialize_v2(psbt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{descriptors::LianaDescriptor, signer::HotSigner};
+
+    use std::str::FromStr;
+
+    use miniscript::bitcoin::{
+        bip32,
+        psbt::{Input as PsbtIn, Output as PsbtOut},
+        secp256k1, Network,
+    };
+
+    // A PSBT with all the fields we populate when creating a spend, and a signer for it.
+    fn liana_psbt() -> (Psbt, HotSigner) {
+        let secp = secp256k1::Secp256k1::new();
+        let signer = HotSigner::generate(Network::Bitcoin).unwrap();
+        let fingerprint = signer.fingerprint(&secp);
+        let xpub = signer.xpub_at(&bip32::DerivationPath::master(), &secp);
+        let desc = LianaDescriptor::from_str(&format!("wsh(or_d(pk([{}]{}/<0;1>/*),and_v(v:pkh([aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000))))", fingerprint, xpub)).unwrap();
+        let receive_desc = desc
+            .receive_descriptor()
+            .derive(bip32::ChildNumber::from(0), &secp);
+        let change_desc = desc
+            .change_descriptor()
+            .derive(bip32::ChildNumber::from(3), &secp);
+
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::from_consensus(0),
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 100_000,
+                script_pubkey: receive_desc.script_pubkey(),
+            }],
+        };
+        let unsigned_tx = Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::from_consensus(800_000),
+            input: vec![TxIn {
+                previous_output: OutPoint::new(prev_tx.txid(), 0),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..TxIn::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: 60_000,
+                    script_pubkey: ScriptBuf::new_v0_p2wpkh(
+                        &miniscript::bitcoin::WPubkeyHash::from_str(
+                            "e8df018c7e326cc253faac7e46cdc51e68542c42",
+                        )
+                        .unwrap(),
+                    ),
+                },
+                TxOut {
+                    value: 39_000,
+                    script_pubkey: change_desc.script_pubkey(),
+                },
+            ],
+        };
+
+        let mut psbt_in = PsbtIn {
+            witness_utxo: Some(prev_tx.output[0].clone()),
+            non_witness_utxo: Some(prev_tx),
+            ..PsbtIn::default()
+        };
+        receive_desc.update_psbt_in(&mut psbt_in);
+        let mut change_out = PsbtOut::default();
+        change_desc.update_psbt_out(&mut change_out);
+        let psbt = Psbt {
+            unsigned_tx,
+            version: 0,
+            xpub: Default::default(),
+            proprietary: Default::default(),
+            unknown: Default::default(),
+            inputs: vec![psbt_in],
+            outputs: vec![PsbtOut::default(), change_out],
+        };
+        (psbt, signer)
+    }
+
+    #[test]
+    fn psbt_v2_roundtrip() {
+        let (psbt, signer) = liana_psbt();
+        assert!(psbt.inputs[0].witness_script.is_some());
+        assert!(!psbt.inputs[0].bip32_derivation.is_empty());
+        assert!(psbt.inputs[0].non_witness_utxo.is_some());
+        assert!(!psbt.outputs[1].bip32_derivation.is_empty());
+
+        // Version 0 PSBTs are parsed as usual.
+        assert_eq!(deserialize(&psbt.serialize()), Ok(psbt.clone()));
+        assert_eq!(from_base64(&psbt.to_string()), Ok(psbt.clone()));
+
+        // A version 2 PSBT doesn't contain the unsigned transaction, and can't be parsed as a
+        // version 0 one. It's converted back without losing any field.
+        let v2 = serialize_v2(&psbt);
+        let raw = RawPsbt::deserialize(&v2).unwrap();
+        assert_eq!(raw.version, 2);
+        assert!(get(&raw.global, PSBT_GLOBAL_UNSIGNED_TX).is_none());
+        assert_eq!(
+            get(&raw.global, PSBT_GLOBAL_FALLBACK_LOCKTIME),
+            Some(&800_000u32.to_le_bytes()[..])
+        );
+        assert_eq!(
+            get(&raw.outputs[0], PSBT_OUT_AMOUNT),
+            Some(&60_000i64.to_le_bytes()[..])
+        );
+        assert!(Psbt::deserialize(&v2).is_err());
+        assert_eq!(deserialize(&v2), Ok(psbt.clone()));
+        assert_eq!(from_base64(&to_base64_v2(&psbt)), Ok(psbt.clone()));
+
+        // The same goes for a signed PSBT.
+        let secp = secp256k1::Secp256k1::new();
+        let signed_psbt = signer.sign_psbt(psbt, &secp).unwrap();
+        assert_eq!(signed_psbt.inputs[0].partial_sigs.len(), 1);
+        assert_eq!(
+            from_base64(&to_base64_v2(&signed_psbt)),
+            Ok(signed_psbt.clone())
+        );
+    }
+
+    #[test]
+    fn psbt_v2_parsing() {
+        let (psbt, _) = liana_psbt();
+        let raw_v2 = || RawPsbt::deserialize(&serialize_v2(&psbt)).unwrap();
+
+        // The locktime required by the inputs prevails over the fallback one. A height locktime
+        // is preferred when possible.
+        let mut raw = raw_v2();
+        raw.inputs[0].push((
+            vec![PSBT_IN_REQUIRED_HEIGHT_LOCKTIME],
+            800_010u32.to_le_bytes().to_vec(),
+        ));
+        raw.inputs[0].push((
+            vec![PSBT_IN_REQUIRED_TIME_LOCKTIME],
+            1_700_000_000u32.to_le_bytes().to_vec(),
+        ));
+        let parsed = deserialize(&raw.serialize()).unwrap();
+        assert_eq!(parsed.unsigned_tx.lock_time.to_consensus_u32(), 800_010);
+
+        // If there is no locktime at all, it's 0. Sequence defaults to final.
+        let mut raw = raw_v2();
+        take(&mut raw.global, PSBT_GLOBAL_FALLBACK_LOCKTIME);
+        take(&mut raw.inputs[0], PSBT_IN_SEQUENCE);
+        let parsed = deserialize(&raw.serialize()).unwrap();
+        assert_eq!(parsed.unsigned_tx.lock_time.to_consensus_u32(), 0);
+        assert_eq!(parsed.unsigned_tx.input[0].sequence, Sequence::MAX);
+        assert_eq!(parsed.inputs, psbt.inputs);
+
+        // Required fields must be present and valid.
+        let mut raw = raw_v2();
+        take(&mut raw.outputs[1], PSBT_OUT_SCRIPT);
+        assert_eq!(
+            deserialize(&raw.serialize()),
+            Err(PsbtError::MissingField("script"))
+        );
+        let mut raw = raw_v2();
+        take(&mut raw.inputs[0], PSBT_IN_OUTPUT_INDEX);
+        raw.inputs[0].push((vec![PSBT_IN_OUTPUT_INDEX], vec![0]));
+        assert_eq!(
+            deserialize(&raw.serialize()),
+            Err(PsbtError::InvalidField("output index"))
+        );
+        let mut raw = raw_v2();
+        take(&mut raw.global, PSBT_GLOBAL_TX_VERSION);
+        assert_eq!(
+            deserialize(&raw.serialize()),
+            Err(PsbtError::MissingField("transaction version"))
+        );
+
+        // Unsupported versions and garbage are rejected.
+        let mut raw = raw_v2();
+        take(&mut raw.global, PSBT_GLOBAL_VERSION);
+        raw.global
+            .push((vec![PSBT_GLOBAL_VERSION], 1u32.to_le_bytes().to_vec()));
+        assert_eq!(
+            deserialize(&raw.serialize()),
+            Err(PsbtError::UnsupportedVersion(1))
+        );
+        let mut v2 = serialize_v2(&psbt);
+        v2.push(0x00);
+        assert!(matches!(
+            deserialize(&v2),
+            Err(PsbtError::Deserialization(_))
+        ));
+        assert!(matches!(from_base64("Liana!"), Err(PsbtError::Base64(_))));
+    }
+}
@@ -0,0 +1,387 @@
+//! Proof of reserves module
+//!
+//! Prove the ownership of a set of coins without moving them, following BIP127. A proof is a
+//! transaction spending the coins along with a "commitment input" which spends a non-existent
+//! coin derived from a message. The transaction is therefore never valid, but its signatures
+//! prove control of the coins at the time the message was chosen.
+
+use std::{collections::HashSet, error, fmt};
+
+use miniscript::{
+    bitcoin::{
+        self,
+        blockdata::{locktime::absolute, opcodes},
+        hashes::{sha256d, Hash},
+        psbt::{Input as PsbtIn, Psbt},
+        secp256k1, sighash,
+    },
+    interpreter::{KeySigPair, SatisfiedConstraint},
+    psbt::PsbtExt,
+    Interpreter,
+};
+
+/// The prefix of the message hashed to get the commitment outpoint. Same as in the BDK
+/// implementation of BIP127.
+const MESSAGE_PREFIX: &str = "Proof-of-Reserves: ";
+
+/// An error related to a proof of reserves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReservesError {
+    /// The proof isn't a transaction with a commitment input and a single output.
+    InsaneProof,
+    /// The commitment input of the proof is not for this message.
+    MessageMismatch,
+    /// The input at this index could not be finalized: it's missing signatures or has invalid
+    /// ones.
+    Finalization(usize, String),
+    /// The coin spent by this input isn't unspent and confirmed in the best chain.
+    UnknownCoin(bitcoin::OutPoint),
+    /// The signature of the input at this index is invalid.
+    InvalidSignature(usize, String),
+    /// This outpoint is spent by more than one input of the proof.
+    DuplicateInput(bitcoin::OutPoint),
+    /// A signature of the input at this index does not commit to the whole proof (it is not
+    /// `SIGHASH_ALL`).
+    UnsafeSighashType(usize),
+}
+
+impl fmt::Display for ReservesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InsaneProof => write!(
+                f,
+                "Not a proof of reserves: it must have a commitment input, at least one coin and a single output."
+            ),
+            Self::MessageMismatch => write!(f, "The proof is not for this message."),
+            Self::Finalization(i, e) => {
+                write!(f, "Failed to finalize input at index {}: '{}'.", i, e)
+            }
+            Self::UnknownCoin(op) => write!(
+                f,
+                "Coin at '{}' is not an unspent confirmed coin of the best chain.",
+                op
+            ),
+            Self::InvalidSignature(i, e) => {
+                write!(f, "Invalid signature for input at index {}: '{}'.", i, e)
+            }
+            Self::DuplicateInput(op) => {
+                write!(f, "Coin at '{}' is spent more than once by the proof.", op)
+            }
+            Self::UnsafeSighashType(i) => write!(
+                f,
+                "A signature for input at index {} does not commit to the whole proof.",
+                i
+            ),
+        }
+    }
+}
+
+impl error::Error for ReservesError {}
+
+/// The non-existent outpoint the commitment input of a proof for this message spends.
+pub fn commitment_outpoint(message: &str) -> bitcoin::OutPoint {
+    let hash = sha256d::Hash::hash(format!("{}{}", MESSAGE_PREFIX, message).as_bytes());
+    bitcoin::OutPoint {
+        txid: bitcoin::Txid::from_raw_hash(hash),
+        vout: 0,
+    }
+}
+
+// The PSBT input for the commitment input. There is nothing to sign, so it's finalized already.
+// The spent output is only needed for the signers to compute the sighash of the other inputs.
+fn commitment_psbt_in() -> PsbtIn {
+    PsbtIn {
+        witness_utxo: Some(bitcoin::TxOut {
+            value: 0,
+            script_pubkey: bitcoin::ScriptBuf::builder()
+                .push_opcode(opcodes::OP_TRUE)
+                .into_script(),
+        }),
+        final_script_sig: Some(bitcoin::ScriptBuf::new()),
+        final_script_witness: Some(bitcoin::Witness::new()),
+        ..PsbtIn::default()
+    }
+}
+
+/// Create the PSBT of a proof of reserves for these coins, committing to this message. Each coin
+/// is given as its outpoint along with the PSBT input populated with the information needed by
+/// the signers (including the spent output).
+pub fn proof_psbt(message: &str, coins: Vec<(bitcoin::OutPoint, PsbtIn)>) -> Psbt {
+    let mut txins = Vec::with_capacity(coins.len() + 1);
+    let mut psbt_ins = Vec::with_capacity(coins.len() + 1);
+    txins.push(bitcoin::TxIn {
+        previous_output: commitment_outpoint(message),
+        ..bitcoin::TxIn::default()
+    });
+    psbt_ins.push(commitment_psbt_in());
+
+    let mut value: u64 = 0;
+    for (outpoint, psbt_in) in coins {
+        value += psbt_in
+            .witness_utxo
+            .as_ref()
+            .expect("The spent output must be provided.")
+            .value;
+        txins.push(bitcoin::TxIn {
+            previous_output: outpoint,
+            ..bitcoin::TxIn::default()
+        });
+        psbt_ins.push(psbt_in);
+    }
+
+    // The proof pays the whole value to an unspendable output, in case it would somehow ever be
+    // valid.
+    let tx = bitcoin::Transaction {
+        version: 1,
+        lock_time: absolute::LockTime::ZERO,
+        input: txins,
+        output: vec![bitcoin::TxOut {
+            value,
+            script_pubkey: bitcoin::ScriptBuf::builder()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+        }],
+    };
+    let mut psbt = Psbt::from_unsigned_tx(tx).expect("The transaction is unsigned.");
+    psbt.inputs = psbt_ins;
+    psbt
+}
+
+// Whether this signature commits to the whole transaction.
+fn is_sighash_all(key_sig: &KeySigPair) -> bool {
+    match key_sig {
+        KeySigPair::Ecdsa(_, sig) => sig.hash_ty == sighash::EcdsaSighashType::All,
+        KeySigPair::Schnorr(_, sig) => matches!(
+            sig.hash_ty,
+            sighash::TapSighashType::Default | sighash::TapSighashType::All
+        ),
+    }
+}
+
+/// Check this signed proof of reserves commits to this message and returns the total value of the
+/// coins it proves ownership of. The `unspent_txout` function must return the output at the given
+/// outpoint if it is unspent and confirmed, as the information in the PSBT isn't trusted.
+pub fn verify_proof<C: secp256k1::Verification>(
+    psbt: &Psbt,
+    message: &str,
+    unspent_txout: impl Fn(&bitcoin::OutPoint) -> Option<bitcoin::TxOut>,
+    secp: &secp256k1::Secp256k1<C>,
+) -> Result<bitcoin::Amount, ReservesError> {
+    let tx = &psbt.unsigned_tx;
+    if tx.input.len() < 2 || tx.input.len() != psbt.inputs.len() || tx.output.len() != 1 {
+        return Err(ReservesError::InsaneProof);
+    }
+    if tx.input[0].previous_output != commitment_outpoint(message) {
+        return Err(ReservesError::MessageMismatch);
+    }
+
+    // A coin may not be counted twice.
+    let mut outpoints = HashSet::with_capacity(tx.input.len());
+    for txin in &tx.input {
+        if !outpoints.insert(txin.previous_output) {
+            return Err(ReservesError::DuplicateInput(txin.previous_output));
+        }
+    }
+
+    // Get the outputs spent by the proof from the chain, except for the commitment input's.
+    let mut prevouts = Vec::with_capacity(tx.input.len());
+    prevouts.push(
+        psbt.inputs[0]
+            .witness_utxo
+            .clone()
+            .ok_or(ReservesError::InsaneProof)?,
+    );
+    for txin in tx.input.iter().skip(1) {
+        let txout = unspent_txout(&txin.previous_output)
+            .ok_or(ReservesError::UnknownCoin(txin.previous_output))?;
+        prevouts.push(txout);
+    }
+
+    // Finalize the PSBT to get the witnesses. The commitment input is already.
+    let mut psbt = psbt.clone();
+    for (psbt_in, prevout) in psbt.inputs.iter_mut().zip(prevouts.iter()).skip(1) {
+        psbt_in.witness_utxo = Some(prevout.clone());
+    }
+    for i in 1..psbt.inputs.len() {
+        if psbt.inputs[i].final_script_witness.is_none() {
+            psbt.finalize_inp_mut(secp, i)
+                .map_err(|e| ReservesError::Finalization(i, e.to_string()))?;
+        }
+    }
+    let final_tx = psbt.extract_tx();
+
+    // Finally, check the witness of each input against the Script of the coin it spends. All the
+    // signatures must commit to the whole proof, otherwise they could be reused in a proof for
+    // another message.
+    let prevouts_all = sighash::Prevouts::All(&prevouts);
+    let mut value: u64 = 0;
+    for (i, txin) in final_tx.input.iter().enumerate().skip(1) {
+        let interpreter = Interpreter::from_txdata(
+            &prevouts[i].script_pubkey,
+            &txin.script_sig,
+            &txin.witness,
+            txin.sequence,
+            final_tx.lock_time,
+        )
+        .map_err(|e| ReservesError::InvalidSignature(i, e.to_string()))?;
+        for constraint in interpreter.iter(secp, &final_tx, i, &prevouts_all) {
+            match constraint.map_err(|e| ReservesError::InvalidSignature(i, e.to_string()))? {
+                SatisfiedConstraint::PublicKey { key_sig }
+                | SatisfiedConstraint::PublicKeyHash { key_sig, .. }
+                    if !is_sighash_all(&key_sig) =>
+                {
+                    return Err(ReservesError::UnsafeSighashType(i));
+                }
+                _ => {}
+            }
+        }
+        value += prevouts[i].value;
+    }
+
+    Ok(bitcoin::Amount::from_sat(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{descriptors::LianaDescriptor, signer::HotSigner};
+    use bitcoin::bip32;
+
+    use std::{collections::HashMap, str::FromStr};
+
+    #[test]
+    fn proof_of_reserves() {
+        let secp = secp256k1::Secp256k1::new();
+        let network = bitcoin::Network::Bitcoin;
+        let signer = HotSigner::generate(network).unwrap();
+        let fingerprint = signer.fingerprint(&secp);
+        let xpub = signer.xpub_at(&bip32::DerivationPath::master(), &secp);
+        let desc = LianaDescriptor::from_str(&format!("wsh(or_d(pk([{}]{}/<0;1>/*),and_v(v:pkh([aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*),older(10000))))", fingerprint, xpub)).unwrap();
+
+        // Two coins of ours, and the "chain" they are unspent in.
+        let mut utxos = HashMap::new();
+        let coins: Vec<(bitcoin::OutPoint, PsbtIn)> = (0..2u32)
+            .map(|i| {
+                let outpoint = bitcoin::OutPoint {
+                    txid: bitcoin::Txid::from_raw_hash(sha256d::Hash::hash(&i.to_be_bytes())),
+                    vout: i,
+                };
+                let derived_desc = desc
+                    .receive_descriptor()
+                    .derive(bip32::ChildNumber::from(i), &secp);
+                let txout = bitcoin::TxOut {
+                    value: 100_000 * (u64::from(i) + 1),
+                    script_pubkey: derived_desc.script_pubkey(),
+                };
+                utxos.insert(outpoint, txout.clone());
+                let mut psbt_in = PsbtIn {
+                    witness_utxo: Some(txout),
+                    ..PsbtIn::default()
+                };
+                derived_desc.update_psbt_in(&mut psbt_in);
+                (outpoint, psbt_in)
+            })
+            .collect();
+        let unspent_txout = |op: &bitcoin::OutPoint| utxos.get(op).cloned();
+
+        // The proof commits to the message and its output has the value of all the coins.
+        let message = "Liana reserves, block 800000";
+        let psbt = proof_psbt(message, coins);
+        assert_eq!(psbt.unsigned_tx.input.len(), 3);
+        assert_eq!(
+            psbt.unsigned_tx.input[0].previous_output,
+            commitment_outpoint(message)
+        );
+        assert_eq!(psbt.unsigned_tx.output[0].value, 300_000);
+
+        // It is only valid once signed, for this message.
+        assert!(matches!(
+            verify_proof(&psbt, message, unspent_txout, &secp),
+            Err(ReservesError::Finalization(1, _))
+        ));
+        let psbt = signer.sign_psbt(psbt, &secp).unwrap();
+        assert_eq!(
+            verify_proof(&psbt, message, unspent_txout, &secp),
+            Ok(bitcoin::Amount::from_sat(300_000))
+        );
+        assert_eq!(
+            verify_proof(&psbt, "Another message", unspent_txout, &secp),
+            Err(ReservesError::MessageMismatch)
+        );
+
+        // If one of the coins is spent, the proof isn't valid anymore.
+        let spent_op = psbt.unsigned_tx.input[2].previous_output;
+        assert_eq!(
+            verify_proof(
+                &psbt,
+                message,
+                |op| unspent_txout(op).filter(|_| op != &spent_op),
+                &secp
+            ),
+            Err(ReservesError::UnknownCoin(spent_op))
+        );
+
+        // A coin may not be spent twice by the proof, nor the commitment outpoint.
+        for dup_index in [1, 0] {
+            let mut dup_psbt = psbt.clone();
+            let txin = dup_psbt.unsigned_tx.input[dup_index].clone();
+            let outpoint = txin.previous_output;
+            dup_psbt.unsigned_tx.input.push(txin);
+            dup_psbt.inputs.push(dup_psbt.inputs[dup_index].clone());
+            assert_eq!(
+                verify_proof(&dup_psbt, message, unspent_txout, &secp),
+                Err(ReservesError::DuplicateInput(outpoint))
+            );
+        }
+
+        // A proof lying about the value of a coin isn't valid either.
+        assert!(matches!(
+            verify_proof(
+                &psbt,
+                message,
+                |op| unspent_txout(op).map(|txout| bitcoin::TxOut {
+                    value: txout.value + 1,
+                    ..txout
+                }),
+                &secp
+            ),
+            Err(ReservesError::Finalization(1, _))
+        ));
+
+        // The signatures must all be SIGHASH_ALL, otherwise they could be reused for another
+        // message. Replace the signature of the first coin by a SIGHASH_ALL|ANYONECANPAY one.
+        let mut acp_psbt = psbt;
+        let seed = bip39::Mnemonic::from_str(&signer.mnemonic_str())
+            .unwrap()
+            .to_seed("");
+        let master_xpriv = bip32::ExtendedPrivKey::new_master(network, &seed).unwrap();
+        let psbt_in = &acp_psbt.inputs[1];
+        let (pubkey, (_, der_path)) = psbt_in
+            .bip32_derivation
+            .iter()
+            .find(|(_, (fg, _))| *fg == fingerprint)
+            .unwrap();
+        let privkey = master_xpriv.derive_priv(&secp, der_path).unwrap();
+        let hash_ty = sighash::EcdsaSighashType::AllPlusAnyoneCanPay;
+        let sighash = sighash::SighashCache::new(&acp_psbt.unsigned_tx)
+            .segwit_signature_hash(
+                1,
+                psbt_in.witness_script.as_ref().unwrap(),
+                psbt_in.witness_utxo.as_ref().unwrap().value,
+                hash_ty,
+            )
+            .unwrap();
+        let sig = secp.sign_ecdsa_low_r(
+            &secp256k1::Message::from_slice(sighash.as_byte_array()).unwrap(),
+            &privkey.private_key,
+        );
+        let pubkey = bitcoin::PublicKey::new(*pubkey);
+        acp_psbt.inputs[1]
+            .partial_sigs
+            .insert(pubkey, bitcoin::ecdsa::Signature { sig, hash_ty });
+        assert_eq!(
+            verify_proof(&acp_psbt, message, unspent_txout, &secp),
+            Err(ReservesError::UnsafeSighashType(1))
+        );
+    }
+}
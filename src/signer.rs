@@ -3,24 +3,31 @@
 //! Some helpers to facilitate the usage of a signer in client of the Liana daemon. For now
 //! only contains a hot signer.
 
-use crate::random;
+use crate::{descriptors::LianaDescriptor, random};
 
 use std::{
     convert::TryInto,
     error, fmt, fs,
     io::{self, Write},
     path,
-    str::FromStr,
+    str::{self, FromStr},
 };
 
+use chacha20poly1305::{
+    aead::{Aead, NewAead, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
 use miniscript::bitcoin::{
-    self,
+    self, base64,
     bip32::{self, Error as Bip32Error},
     ecdsa,
     hashes::Hash,
-    psbt::Psbt,
-    secp256k1, sighash,
+    key::TapTweak,
+    psbt::{Input as PsbtIn, Psbt, PsbtSighashType},
+    secp256k1, sighash, taproot,
 };
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
 
 /// An error related to using a signer.
 #[derive(Debug)]
@@ -29,8 +36,16 @@ pub enum SignerError {
     Mnemonic(bip39::Error),
     Bip32(Bip32Error),
     MnemonicStorage(io::Error),
+    /// The encrypted mnemonic file could not be parsed.
+    InvalidEncryptedMnemonic(String),
+    /// Wrong passphrase, or the encrypted mnemonic was tampered with.
+    MnemonicDecryption,
+    /// A mnemonic protected by a BIP39 passphrase may only be stored encrypted.
+    PlaintextPassphrase,
     InsanePsbt,
     IncompletePsbt,
+    /// The PSBT did not pass the checks of the verifying signer.
+    Refused(SigningRefusal),
 }
 
 impl fmt::Display for SignerError {
@@ -40,27 +55,432 @@ impl fmt::Display for SignerError {
             Self::Mnemonic(s) => write!(f, "Error when working with mnemonics: {}", s),
             Self::Bip32(e) => write!(f, "BIP32 error: {}", e),
             Self::MnemonicStorage(e) => write!(f, "BIP39 mnemonic storage error: {}", e),
+            Self::InvalidEncryptedMnemonic(e) => write!(f, "Invalid encrypted mnemonic: {}", e),
+            Self::MnemonicDecryption => write!(
+                f,
+                "Failed to decrypt the mnemonic. Is the passphrase correct?"
+            ),
+            Self::PlaintextPassphrase => write!(
+                f,
+                "Refusing to store a mnemonic protected by a BIP39 passphrase unencrypted."
+            ),
             Self::InsanePsbt => write!(f, "Information contained in the PSBT is wrong."),
             Self::IncompletePsbt => write!(
                 f,
                 "The PSBT is missing some information necessary for signing."
             ),
+            Self::Refused(r) => write!(f, "Refused to sign the PSBT: {}", r),
         }
     }
 }
 
 impl error::Error for SignerError {}
 
+/// The reason a verifying signer refused to sign a PSBT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SigningRefusal {
+    /// The input at this index does not spend a coin from the descriptor.
+    ForeignInput(usize),
+    /// The output at this index has derivation information but does not derive from the
+    /// descriptor.
+    InvalidChangeOutput(usize),
+    /// The input at this index requests a signature with another sighash type than `ALL`.
+    UnsafeSighashType(usize),
+    /// The previous transaction of the input at this index does not match the spent coin.
+    InvalidPreviousTransaction(usize),
+    /// The fees of the transaction are out of the configured bounds.
+    FeeOutOfBounds {
+        /// Absolute fee in sats.
+        fee: u64,
+        /// Feerate in sats/vb.
+        feerate: u64,
+    },
+}
+
+impl fmt::Display for SigningRefusal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ForeignInput(i) => write!(
+                f,
+                "input at index {} does not spend a coin from the descriptor",
+                i
+            ),
+            Self::InvalidChangeOutput(i) => write!(
+                f,
+                "output at index {} is not a change output of the descriptor",
+                i
+            ),
+            Self::UnsafeSighashType(i) => write!(
+                f,
+                "input at index {} requests a sighash type other than ALL",
+                i
+            ),
+            Self::InvalidPreviousTransaction(i) => write!(
+                f,
+                "previous transaction of input at index {} does not match the spent coin",
+                i
+            ),
+            Self::FeeOutOfBounds { fee, feerate } => write!(
+                f,
+                "fee of {} sats ({} sats/vb) is out of bounds",
+                fee, feerate
+            ),
+        }
+    }
+}
+
+/// The bounds on the fees of the transactions a verifying signer accepts to sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeBounds {
+    /// Minimum feerate in sats/vb.
+    pub min_feerate: u64,
+    /// Maximum feerate in sats/vb.
+    pub max_feerate: u64,
+    /// Maximum absolute fee in sats.
+    pub max_fee: u64,
+}
+
+impl Default for FeeBounds {
+    /// The same bounds as the ones enforced by the daemon when creating a transaction.
+    fn default() -> Self {
+        Self {
+            min_feerate: 1,
+            max_feerate: 1_000,
+            max_fee: bitcoin::blockdata::constants::COIN_VALUE,
+        }
+    }
+}
+
+// Whether this script derives from the receive or change descriptor at any of the indexes found
+// at the end of these derivation paths. Note we only check the keys derivation paths to find the
+// index: the derived Script is what matters.
+fn derives_from<'a, C: secp256k1::Verification>(
+    descriptor: &LianaDescriptor,
+    script_pubkey: &bitcoin::Script,
+    der_paths: impl Iterator<Item = &'a bip32::DerivationPath>,
+    secp: &secp256k1::Secp256k1<C>,
+) -> bool {
+    der_paths
+        .filter_map(|path| path.into_iter().last().filter(|index| index.is_normal()))
+        .any(|index| {
+            [
+                descriptor.receive_descriptor(),
+                descriptor.change_descriptor(),
+            ]
+            .iter()
+            .any(|desc| desc.derive(*index, secp).script_pubkey().as_script() == script_pubkey)
+        })
+}
+
+/// Check this PSBT is safe to sign for a participant in this descriptor: all its inputs spend
+/// coins from the descriptor, the outputs with derivation information really derive from it, the
+/// signatures requested are all `SIGHASH_ALL` and the fees are within the given bounds.
+///
+/// The value of the coins spent is only committed to by Taproot signatures. For other descriptors
+/// the full previous transaction of each input is required, so the fees can't be lied about.
+pub fn check_psbt<C: secp256k1::Verification>(
+    psbt: &Psbt,
+    descriptor: &LianaDescriptor,
+    fee_bounds: &FeeBounds,
+    secp: &secp256k1::Secp256k1<C>,
+) -> Result<(), SignerError> {
+    let tx = &psbt.unsigned_tx;
+    if tx.input.len() != psbt.inputs.len() || tx.output.len() != psbt.outputs.len() {
+        return Err(SignerError::InsanePsbt);
+    }
+
+    let allowed_sighashes = if descriptor.is_taproot() {
+        vec![
+            PsbtSighashType::from(sighash::TapSighashType::Default),
+            PsbtSighashType::from(sighash::TapSighashType::All),
+        ]
+    } else {
+        vec![PsbtSighashType::from(sighash::EcdsaSighashType::All)]
+    };
+    let mut in_value: u64 = 0;
+    for (i, (txin, psbt_in)) in tx.input.iter().zip(psbt.inputs.iter()).enumerate() {
+        let prev_txo = psbt_in
+            .witness_utxo
+            .as_ref()
+            .ok_or(SignerError::IncompletePsbt)?;
+        match psbt_in.non_witness_utxo {
+            Some(ref prev_tx) => {
+                let prev_out = txin.previous_output;
+                if prev_tx.txid() != prev_out.txid
+                    || prev_tx.output.get(prev_out.vout as usize) != Some(prev_txo)
+                {
+                    return Err(SignerError::Refused(
+                        SigningRefusal::InvalidPreviousTransaction(i),
+                    ));
+                }
+            }
+            None if !descriptor.is_taproot() => return Err(SignerError::IncompletePsbt),
+            None => {}
+        }
+        in_value = in_value
+            .checked_add(prev_txo.value)
+            .ok_or(SignerError::InsanePsbt)?;
+        let der_paths = psbt_in
+            .bip32_derivation
+            .values()
+            .map(|(_, path)| path)
+            .chain(psbt_in.tap_key_origins.values().map(|(_, (_, path))| path));
+        if !derives_from(descriptor, &prev_txo.script_pubkey, der_paths, secp) {
+            return Err(SignerError::Refused(SigningRefusal::ForeignInput(i)));
+        }
+        if let Some(sighash_type) = psbt_in.sighash_type {
+            if !allowed_sighashes.contains(&sighash_type) {
+                return Err(SignerError::Refused(SigningRefusal::UnsafeSighashType(i)));
+            }
+        }
+    }
+
+    let mut out_value: u64 = 0;
+    for (i, (txo, psbt_out)) in tx.output.iter().zip(psbt.outputs.iter()).enumerate() {
+        out_value = out_value
+            .checked_add(txo.value)
+            .ok_or(SignerError::InsanePsbt)?;
+        if psbt_out.bip32_derivation.is_empty() && psbt_out.tap_key_origins.is_empty() {
+            continue;
+        }
+        let der_paths = psbt_out
+            .bip32_derivation
+            .values()
+            .map(|(_, path)| path)
+            .chain(psbt_out.tap_key_origins.values().map(|(_, (_, path))| path));
+        if !derives_from(descriptor, &txo.script_pubkey, der_paths, secp) {
+            return Err(SignerError::Refused(SigningRefusal::InvalidChangeOutput(i)));
+        }
+    }
+
+    let fee = in_value
+        .checked_sub(out_value)
+        .ok_or(SignerError::InsanePsbt)?;
+    let tx_vb = (tx.vsize() + descriptor.max_sat_vbytes() * tx.input.len()) as u64;
+    let feerate = fee.checked_div(tx_vb).ok_or(SignerError::InsanePsbt)?;
+    if feerate < fee_bounds.min_feerate
+        || feerate > fee_bounds.max_feerate
+        || fee > fee_bounds.max_fee
+    {
+        return Err(SignerError::Refused(SigningRefusal::FeeOutOfBounds {
+            fee,
+            feerate,
+        }));
+    }
+
+    Ok(())
+}
+
 pub const MNEMONICS_FOLDER_NAME: &str = "mnemonics";
+/// The extension of the files containing an encrypted mnemonic.
+pub const ENCRYPTED_MNEMONIC_EXTENSION: &str = "enc";
+/// The version of the encrypted mnemonic format produced by this version of Liana.
+pub const ENCRYPTED_MNEMONIC_VERSION: u32 = 1;
+// Bounds on the KDF parameters we accept when reading an encrypted mnemonic, so a crafted file
+// can't make us use an unreasonable amount of memory or CPU time (1GiB of memory at most).
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const MAX_SCRYPT_LOG_N: u8 = 24;
+const MAX_SCRYPT_P: u32 = 16;
 
-// TODO: zeroize, mlock, etc.. For now we don't even encrypt the seed on disk so that'd be
-// overkill.
+// TODO: mlock, etc..
 /// A signer that keeps the key on the laptop. Based on BIP39.
 pub struct HotSigner {
     mnemonic: bip39::Mnemonic,
+    // The BIP39 passphrase, empty if none.
+    passphrase: String,
     master_xpriv: bip32::ExtendedPrivKey,
 }
 
+// The mnemonic is zeroized on drop by the bip39 crate.
+impl Drop for HotSigner {
+    fn drop(&mut self) {
+        self.passphrase.zeroize();
+        self.master_xpriv.private_key.non_secure_erase();
+    }
+}
+
+/// The parameters of the scrypt key derivation function used to derive the encryption key of a
+/// mnemonic from a passphrase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScryptParams {
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl Default for ScryptParams {
+    /// The parameters recommended by the scrypt crate (32MiB of memory).
+    fn default() -> Self {
+        let params = scrypt::Params::recommended();
+        Self {
+            log_n: params.log_n(),
+            r: params.r(),
+            p: params.p(),
+        }
+    }
+}
+
+impl ScryptParams {
+    // Whether these parameters are within the bounds we accept for decryption.
+    fn is_sane(&self) -> bool {
+        // Scrypt uses 128 * r * 2^log_n bytes of memory.
+        let memory = (self.r as u64)
+            .checked_mul(128)
+            .and_then(|m| m.checked_shl(self.log_n.into()));
+        self.log_n <= MAX_SCRYPT_LOG_N
+            && self.p <= MAX_SCRYPT_P
+            && memory.map(|m| m <= MAX_SCRYPT_MEMORY).unwrap_or(false)
+    }
+}
+
+/// A BIP39 mnemonic encrypted with a passphrase, as stored on disk.
+///
+/// The encryption key is derived from the passphrase using scrypt and the mnemonic is encrypted
+/// using ChaCha20-Poly1305. The KDF parameters and salt are stored alongside the ciphertext, as
+/// well as the fingerprint of the master key so the signer can be identified without the
+/// passphrase. The version, fingerprint and KDF parameters are authenticated as associated data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedMnemonic {
+    version: u32,
+    fingerprint: bip32::Fingerprint,
+    scrypt: ScryptParams,
+    /// Base64-encoded salt for the KDF.
+    salt: String,
+    /// Base64-encoded nonce for the cipher.
+    nonce: String,
+    /// Base64-encoded encrypted mnemonic.
+    ciphertext: String,
+}
+
+// Derive the encryption key from this passphrase.
+fn derive_key(
+    passphrase: &str,
+    salt: &[u8],
+    params: &ScryptParams,
+) -> Result<Zeroizing<[u8; 32]>, SignerError> {
+    let params = scrypt::Params::new(params.log_n, params.r, params.p)
+        .map_err(|e| SignerError::InvalidEncryptedMnemonic(e.to_string()))?;
+    let mut key = Zeroizing::new([0; 32]);
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, key.as_mut())
+        .expect("32 is a valid output length");
+    Ok(key)
+}
+
+// The clear data authenticated along with the encrypted mnemonic.
+fn associated_data(
+    version: u32,
+    fingerprint: &bip32::Fingerprint,
+    params: &ScryptParams,
+) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(4 + 4 + 1 + 4 + 4);
+    aad.extend_from_slice(&version.to_be_bytes());
+    aad.extend_from_slice(fingerprint.as_bytes());
+    aad.push(params.log_n);
+    aad.extend_from_slice(&params.r.to_be_bytes());
+    aad.extend_from_slice(&params.p.to_be_bytes());
+    aad
+}
+
+fn decode_base64(s: &str) -> Result<Vec<u8>, SignerError> {
+    base64::decode(s).map_err(|e| SignerError::InvalidEncryptedMnemonic(e.to_string()))
+}
+
+impl EncryptedMnemonic {
+    fn encrypt(
+        signer: &HotSigner,
+        passphrase: &str,
+        params: ScryptParams,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+    ) -> Result<Self, SignerError> {
+        let random_32bytes = random::random_bytes().map_err(SignerError::Randomness)?;
+        let (salt, nonce) = (&random_32bytes[..16], &random_32bytes[16..28]);
+        let nonce: [u8; 12] = nonce.try_into().expect("12 bytes");
+        let key = derive_key(passphrase, salt, &params)?;
+        let fingerprint = signer.fingerprint(secp);
+        let plaintext = signer.storage_str();
+        let ciphertext = ChaCha20Poly1305::new(&Key::from(*key))
+            .encrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: plaintext.as_bytes(),
+                    aad: &associated_data(ENCRYPTED_MNEMONIC_VERSION, &fingerprint, &params),
+                },
+            )
+            .expect("Encrypting in memory never fails");
+
+        Ok(Self {
+            version: ENCRYPTED_MNEMONIC_VERSION,
+            fingerprint,
+            scrypt: params,
+            salt: base64::encode(salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        })
+    }
+
+    /// Read an encrypted mnemonic from its JSON serialization.
+    pub fn from_json(s: &str) -> Result<Self, SignerError> {
+        let encrypted: Self = serde_json::from_str(s)
+            .map_err(|e| SignerError::InvalidEncryptedMnemonic(e.to_string()))?;
+        if encrypted.version > ENCRYPTED_MNEMONIC_VERSION {
+            return Err(SignerError::InvalidEncryptedMnemonic(format!(
+                "unsupported version '{}'",
+                encrypted.version
+            )));
+        }
+        if !encrypted.scrypt.is_sane() {
+            return Err(SignerError::InvalidEncryptedMnemonic(format!(
+                "unreasonable scrypt parameters {:?}",
+                encrypted.scrypt
+            )));
+        }
+        Ok(encrypted)
+    }
+
+    /// Serialize this encrypted mnemonic to JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serialization can't fail")
+    }
+
+    /// The fingerprint of the master key derived from this mnemonic.
+    pub fn fingerprint(&self) -> bip32::Fingerprint {
+        self.fingerprint
+    }
+
+    /// Decrypt the mnemonic using the passphrase it was encrypted with.
+    pub fn decrypt(
+        &self,
+        network: bitcoin::Network,
+        passphrase: &str,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+    ) -> Result<HotSigner, SignerError> {
+        let salt = decode_base64(&self.salt)?;
+        let nonce: [u8; 12] = decode_base64(&self.nonce)?.try_into().map_err(|_| {
+            SignerError::InvalidEncryptedMnemonic("invalid nonce length".to_string())
+        })?;
+        let ciphertext = decode_base64(&self.ciphertext)?;
+        let key = derive_key(passphrase, &salt, &self.scrypt)?;
+        let plaintext = Zeroizing::new(
+            ChaCha20Poly1305::new(&Key::from(*key))
+                .decrypt(
+                    &Nonce::from(nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: &associated_data(self.version, &self.fingerprint, &self.scrypt),
+                    },
+                )
+                .map_err(|_| SignerError::MnemonicDecryption)?,
+        );
+        let content = str::from_utf8(&plaintext).map_err(|_| SignerError::MnemonicDecryption)?;
+
+        let signer = HotSigner::from_storage_str(network, content)?;
+        if signer.fingerprint(secp) != self.fingerprint {
+            return Err(SignerError::MnemonicDecryption);
+        }
+        Ok(signer)
+    }
+}
+
 // TODO: instead of copying them here we could have a util module with those helpers.
 // Create a directory with no permission for group and other users.
 fn create_dir(path: &path::Path) -> io::Result<()> {
@@ -98,15 +518,33 @@ fn create_file(path: &path::Path) -> Result<fs::File, std::io::Error> {
     };
 }
 
+// Remove a file created with `create_file`.
+fn remove_file(path: &path::Path) -> io::Result<()> {
+    // Read-only files can't be removed on Windows.
+    #[cfg(not(unix))]
+    {
+        let mut permissions = fs::metadata(path)?.permissions();
+        permissions.set_readonly(false);
+        fs::set_permissions(path, permissions)?;
+    }
+
+    fs::remove_file(path)
+}
+
 impl HotSigner {
     fn from_mnemonic(
         network: bitcoin::Network,
         mnemonic: bip39::Mnemonic,
+        passphrase: String,
     ) -> Result<Self, SignerError> {
-        let master_xpriv = bip32::ExtendedPrivKey::new_master(network, &mnemonic.to_seed(""))
-            .map_err(SignerError::Bip32)?;
+        let mut seed = mnemonic.to_seed(&passphrase);
+        let master_xpriv =
+            bip32::ExtendedPrivKey::new_master(network, &seed).map_err(SignerError::Bip32);
+        seed.zeroize();
+        let master_xpriv = master_xpriv?;
         Ok(Self {
             mnemonic,
+            passphrase,
             master_xpriv,
         })
     }
@@ -117,12 +555,44 @@ impl HotSigner {
         let random_32bytes = random::random_bytes().map_err(SignerError::Randomness)?;
         let mnemonic =
             bip39::Mnemonic::from_entropy(&random_32bytes[..16]).map_err(SignerError::Mnemonic)?;
-        Self::from_mnemonic(network, mnemonic)
+        Self::from_mnemonic(network, mnemonic, String::new())
     }
 
+    /// Create a hot signer from a BIP39 mnemonic of any valid length (12 to 24 words), without a
+    /// passphrase.
     pub fn from_str(network: bitcoin::Network, s: &str) -> Result<Self, SignerError> {
+        Self::from_str_with_passphrase(network, s, "")
+    }
+
+    /// Create a hot signer from a BIP39 mnemonic of any valid length (12 to 24 words), protected
+    /// by the given BIP39 passphrase.
+    pub fn from_str_with_passphrase(
+        network: bitcoin::Network,
+        s: &str,
+        passphrase: &str,
+    ) -> Result<Self, SignerError> {
         let mnemonic = bip39::Mnemonic::from_str(s).map_err(SignerError::Mnemonic)?;
-        Self::from_mnemonic(network, mnemonic)
+        Self::from_mnemonic(network, mnemonic, passphrase.to_string())
+    }
+
+    // The content of the mnemonic file: the mnemonic words, followed on a new line by the BIP39
+    // passphrase if there is one.
+    fn storage_str(&self) -> Zeroizing<String> {
+        let mut content = Zeroizing::new(self.mnemonic_str());
+        if !self.passphrase.is_empty() {
+            content.push('\n');
+            content.push_str(&self.passphrase);
+        }
+        content
+    }
+
+    fn from_storage_str(network: bitcoin::Network, s: &str) -> Result<Self, SignerError> {
+        match s.split_once('\n') {
+            Some((mnemonic, passphrase)) => {
+                Self::from_str_with_passphrase(network, mnemonic, passphrase)
+            }
+            None => Self::from_str(network, s),
+        }
     }
 
     fn mnemonics_folder(datadir_root: &path::Path, network: bitcoin::Network) -> path::PathBuf {
@@ -135,33 +605,105 @@ impl HotSigner {
         .collect()
     }
 
-    /// Read all the mnemonics from the datadir for the given network.
+    // The paths to the plaintext and the encrypted mnemonics files in the datadir.
+    fn mnemonics_paths(
+        datadir_root: &path::Path,
+        network: bitcoin::Network,
+    ) -> Result<(Vec<path::PathBuf>, Vec<path::PathBuf>), SignerError> {
+        let (mut plaintext, mut encrypted) = (Vec::new(), Vec::new());
+
+        let entries = fs::read_dir(Self::mnemonics_folder(datadir_root, network))
+            .map_err(SignerError::MnemonicStorage)?;
+        for entry in entries {
+            let path = entry.map_err(SignerError::MnemonicStorage)?.path();
+            if path.extension() == Some(ENCRYPTED_MNEMONIC_EXTENSION.as_ref()) {
+                encrypted.push(path);
+            } else {
+                plaintext.push(path);
+            }
+        }
+
+        Ok((plaintext, encrypted))
+    }
+
+    /// Read all the plaintext mnemonics from the datadir for the given network. Encrypted
+    /// mnemonics are ignored, see [`HotSigner::encrypted_from_datadir`].
     pub fn from_datadir(
         datadir_root: &path::Path,
         network: bitcoin::Network,
     ) -> Result<Vec<Self>, SignerError> {
         let mut signers = Vec::new();
 
-        let mnemonic_paths = fs::read_dir(Self::mnemonics_folder(datadir_root, network))
-            .map_err(SignerError::MnemonicStorage)?;
-        for entry in mnemonic_paths {
-            let mnemonic = fs::read_to_string(entry.map_err(SignerError::MnemonicStorage)?.path())
-                .map_err(SignerError::MnemonicStorage)?;
-            signers.push(Self::from_str(network, &mnemonic)?);
+        for path in Self::mnemonics_paths(datadir_root, network)?.0 {
+            let mnemonic =
+                Zeroizing::new(fs::read_to_string(path).map_err(SignerError::MnemonicStorage)?);
+            signers.push(Self::from_storage_str(network, &mnemonic)?);
         }
 
         Ok(signers)
     }
 
+    /// Read all the encrypted mnemonics from the datadir for the given network. They need to be
+    /// decrypted using their passphrase to get a signer.
+    pub fn encrypted_from_datadir(
+        datadir_root: &path::Path,
+        network: bitcoin::Network,
+    ) -> Result<Vec<EncryptedMnemonic>, SignerError> {
+        let mut encrypted = Vec::new();
+
+        for path in Self::mnemonics_paths(datadir_root, network)?.1 {
+            let content = fs::read_to_string(path).map_err(SignerError::MnemonicStorage)?;
+            encrypted.push(EncryptedMnemonic::from_json(&content)?);
+        }
+
+        Ok(encrypted)
+    }
+
+    /// Encrypt all the plaintext mnemonics in the datadir for the given network with this
+    /// passphrase, and remove the plaintext files. Returns the fingerprints of the migrated
+    /// signers. A plaintext mnemonic for which there is already an encrypted one is left as is.
+    pub fn encrypt_datadir(
+        datadir_root: &path::Path,
+        network: bitcoin::Network,
+        passphrase: &str,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+    ) -> Result<Vec<bip32::Fingerprint>, SignerError> {
+        let mut fingerprints = Vec::new();
+
+        for path in Self::mnemonics_paths(datadir_root, network)?.0 {
+            let mnemonic =
+                Zeroizing::new(fs::read_to_string(&path).map_err(SignerError::MnemonicStorage)?);
+            let signer = Self::from_storage_str(network, &mnemonic)?;
+            let encrypted_path = Self::mnemonics_folder(datadir_root, network)
+                .join(Self::encrypted_file_name(&signer.fingerprint(secp)));
+            if encrypted_path.exists() {
+                log::warn!(
+                    "Not encrypting mnemonic at '{}': an encrypted mnemonic for this signer exists already.",
+                    path.display()
+                );
+                continue;
+            }
+            signer.store_encrypted(datadir_root, network, passphrase, secp)?;
+            remove_file(&path).map_err(SignerError::MnemonicStorage)?;
+            fingerprints.push(signer.fingerprint(secp));
+        }
+
+        Ok(fingerprints)
+    }
+
     /// The BIP39 mnemonics from which the master key of this signer is derived.
-    pub fn words(&self) -> [&'static str; 12] {
-        let words: Vec<&'static str> = self.mnemonic.word_iter().collect();
-        words.try_into().expect("Always 12 words")
+    pub fn words(&self) -> Vec<&'static str> {
+        self.mnemonic.word_iter().collect()
+    }
+
+    /// Whether the seed of this signer is protected by a BIP39 passphrase.
+    pub fn has_passphrase(&self) -> bool {
+        !self.passphrase.is_empty()
     }
 
     /// The BIP39 mnemonic words as a string.
     pub fn mnemonic_str(&self) -> String {
-        let mut mnemonic_str = String::with_capacity(12 * 7);
+        let mut mnemonic_str = String::with_capacity(self.mnemonic.word_count() * 7);
         let words = self.words();
 
         for (i, word) in words.iter().enumerate() {
@@ -182,32 +724,96 @@ impl HotSigner {
         self.master_xpriv.fingerprint(secp)
     }
 
-    /// Store the mnemonic in a file within the given "data directory".
-    /// The file is stored within a "mnemonics" folder, with the filename set to the fingerprint of
-    /// the master xpub corresponding to this mnemonic.
-    pub fn store(
-        &self,
+    // Write this content to a new file in the mnemonics folder.
+    fn write_mnemonic_file(
         datadir_root: &path::Path,
         network: bitcoin::Network,
-        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+        file_name: String,
+        content: &[u8],
     ) -> Result<(), SignerError> {
         let mut mnemonics_folder = Self::mnemonics_folder(datadir_root, network);
         if !mnemonics_folder.exists() {
             create_dir(&mnemonics_folder).map_err(SignerError::MnemonicStorage)?;
         }
 
-        // This will fail if a file with this fingerprint exists already.
-        mnemonics_folder.push(format!("mnemonic-{:x}.txt", self.fingerprint(secp)));
+        // This will fail if a file with this name exists already.
+        mnemonics_folder.push(file_name);
         let mnemonic_path = mnemonics_folder;
         let mut mnemonic_file =
             create_file(&mnemonic_path).map_err(SignerError::MnemonicStorage)?;
         mnemonic_file
-            .write_all(self.mnemonic_str().as_bytes())
+            .write_all(content)
             .map_err(SignerError::MnemonicStorage)?;
 
         Ok(())
     }
 
+    /// Store the mnemonic in a file within the given "data directory".
+    /// The file is stored within a "mnemonics" folder, with the filename set to the fingerprint of
+    /// the master xpub corresponding to this mnemonic.
+    ///
+    /// A signer protected by a BIP39 passphrase can't be stored in plaintext, see
+    /// [`HotSigner::store_encrypted`].
+    pub fn store(
+        &self,
+        datadir_root: &path::Path,
+        network: bitcoin::Network,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+    ) -> Result<(), SignerError> {
+        if self.has_passphrase() {
+            return Err(SignerError::PlaintextPassphrase);
+        }
+        Self::write_mnemonic_file(
+            datadir_root,
+            network,
+            format!("mnemonic-{:x}.txt", self.fingerprint(secp)),
+            self.storage_str().as_bytes(),
+        )
+    }
+
+    /// Store the mnemonic encrypted with this passphrase in a file within the given "data
+    /// directory". See [`HotSigner::store`].
+    pub fn store_encrypted(
+        &self,
+        datadir_root: &path::Path,
+        network: bitcoin::Network,
+        passphrase: &str,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+    ) -> Result<(), SignerError> {
+        self.store_encrypted_with_params(
+            datadir_root,
+            network,
+            passphrase,
+            ScryptParams::default(),
+            secp,
+        )
+    }
+
+    fn store_encrypted_with_params(
+        &self,
+        datadir_root: &path::Path,
+        network: bitcoin::Network,
+        passphrase: &str,
+        params: ScryptParams,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+    ) -> Result<(), SignerError> {
+        let encrypted = EncryptedMnemonic::encrypt(self, passphrase, params, secp)?;
+        Self::write_mnemonic_file(
+            datadir_root,
+            network,
+            Self::encrypted_file_name(&encrypted.fingerprint),
+            encrypted.to_json().as_bytes(),
+        )
+    }
+
+    // The name of the file containing the encrypted mnemonic for this fingerprint.
+    fn encrypted_file_name(fingerprint: &bip32::Fingerprint) -> String {
+        format!(
+            "mnemonic-{:x}.{}",
+            fingerprint, ENCRYPTED_MNEMONIC_EXTENSION
+        )
+    }
+
     fn xpriv_at(
         &self,
         der_path: &bip32::DerivationPath,
@@ -230,7 +836,8 @@ impl HotSigner {
 
     /// Sign all inputs of the given PSBT.
     ///
-    /// **This does not perform any check. It will blindly sign anything that's passed.**
+    /// **This does not perform any check. It will blindly sign anything that's passed.** Use
+    /// [`HotSigner::sign_psbt_checked`] to verify the PSBT against the wallet's descriptor first.
     pub fn sign_psbt(
         &self,
         mut psbt: Psbt,
@@ -241,6 +848,32 @@ impl HotSigner {
 
         // Sign each input in the PSBT.
         for i in 0..psbt.inputs.len() {
+            // Inputs already finalized, such as the commitment input of a proof of reserves, don't
+            // need our signature.
+            if psbt.inputs[i].final_script_witness.is_some() {
+                continue;
+            }
+
+            // Taproot inputs are signed separately.
+            if !psbt.inputs[i].tap_key_origins.is_empty() {
+                let prevouts = psbt
+                    .inputs
+                    .iter()
+                    .map(|psbt_in| psbt_in.witness_utxo.clone())
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(SignerError::IncompletePsbt)?;
+                let prevouts = sighash::Prevouts::All(&prevouts);
+                self.sign_taproot_input(
+                    &mut psbt.inputs[i],
+                    i,
+                    &mut sighash_cache,
+                    &prevouts,
+                    master_fingerprint,
+                    secp,
+                )?;
+                continue;
+            }
+
             // First of all compute the sighash for this input. We assume P2WSH spend: the sighash
             // script code is always the witness script.
             let witscript = psbt.inputs[i]
@@ -285,6 +918,82 @@ impl HotSigner {
         Ok(psbt)
     }
 
+    /// Sign all inputs of the given PSBT, after having checked it is safe to sign for a
+    /// participant in this descriptor. See [`check_psbt`].
+    pub fn sign_psbt_checked<C: secp256k1::Signing + secp256k1::Verification>(
+        &self,
+        psbt: Psbt,
+        descriptor: &LianaDescriptor,
+        fee_bounds: &FeeBounds,
+        secp: &secp256k1::Secp256k1<C>,
+    ) -> Result<Psbt, SignerError> {
+        check_psbt(&psbt, descriptor, fee_bounds, secp)?;
+        self.sign_psbt(psbt, secp)
+    }
+
+    // Provide a signature for all the keys of ours in this Taproot input, whether for the key
+    // path or for the script path(s).
+    fn sign_taproot_input(
+        &self,
+        psbt_in: &mut PsbtIn,
+        input_index: usize,
+        sighash_cache: &mut sighash::SighashCache<&bitcoin::Transaction>,
+        prevouts: &sighash::Prevouts<bitcoin::TxOut>,
+        master_fingerprint: bip32::Fingerprint,
+        secp: &secp256k1::Secp256k1<impl secp256k1::Signing>,
+    ) -> Result<(), SignerError> {
+        let sig_type = sighash::TapSighashType::Default;
+        for (xpk, (leaf_hashes, (fingerprint, der_path))) in psbt_in.tap_key_origins.clone() {
+            if fingerprint != master_fingerprint {
+                continue;
+            }
+            let privkey = self.xpriv_at(&der_path, secp).to_priv();
+            let keypair = secp256k1::KeyPair::from_secret_key(secp, &privkey.inner);
+            if keypair.x_only_public_key().0 != xpk {
+                return Err(SignerError::InsanePsbt);
+            }
+
+            // The internal key signs for the key path, tweaked with the Merkle root.
+            if psbt_in.tap_internal_key == Some(xpk) {
+                let sighash = sighash_cache
+                    .taproot_key_spend_signature_hash(input_index, prevouts, sig_type)
+                    .map_err(|_| SignerError::InsanePsbt)?;
+                let sighash = secp256k1::Message::from_slice(sighash.as_byte_array())
+                    .expect("Sighash is always 32 bytes.");
+                let keypair = keypair
+                    .tap_tweak(
+                        &secp256k1::Secp256k1::verification_only(),
+                        psbt_in.tap_merkle_root,
+                    )
+                    .to_inner();
+                let sig = secp.sign_schnorr_no_aux_rand(&sighash, &keypair);
+                psbt_in.tap_key_sig = Some(taproot::Signature {
+                    sig,
+                    hash_ty: sig_type,
+                });
+            }
+
+            // Then sign for each of the leaves this key appears in.
+            for leaf_hash in leaf_hashes {
+                let sighash = sighash_cache
+                    .taproot_script_spend_signature_hash(input_index, prevouts, leaf_hash, sig_type)
+                    .map_err(|_| SignerError::InsanePsbt)?;
+                let sighash = secp256k1::Message::from_slice(sighash.as_byte_array())
+                    .expect("Sighash is always 32 bytes.");
+                let sig = secp.sign_schnorr_no_aux_rand(&sighash, &keypair);
+                psbt_in.tap_script_sigs.insert(
+                    (xpk, leaf_hash),
+                    taproot::Signature {
+                        sig,
+                        hash_ty: sig_type,
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
     /// Change the network of generated extended keys. Note this value only has to do with the
     /// BIP32 encoding of those keys (xpubs, tpubs, ..) but does not affect any data (whether it is
     /// the keys or the mnemonics).
@@ -300,6 +1009,7 @@ mod tests {
     use miniscript::{
         bitcoin::{locktime::absolute, psbt::Input as PsbtIn},
         descriptor::{DerivPaths, DescriptorMultiXKey, DescriptorPublicKey, Wildcard},
+        psbt::PsbtExt,
     };
     use std::collections::{BTreeMap, HashSet};
 
@@ -357,6 +1067,168 @@ mod tests {
         fs::remove_dir_all(tmp_dir).unwrap();
     }
 
+    #[test]
+    fn hot_signer_passphrase() {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let network = bitcoin::Network::Bitcoin;
+
+        // The 24-words test vector from BIP39 with the "TREZOR" passphrase.
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+        let signer = HotSigner::from_str_with_passphrase(network, mnemonic, "TREZOR").unwrap();
+        assert_eq!(signer.words().len(), 24);
+        assert_eq!(signer.mnemonic_str(), mnemonic);
+        assert!(signer.has_passphrase());
+        let xpriv = bip32::ExtendedPrivKey::from_str("xprv9s21ZrQH143K32qBagUJAMU2LsHg3ka7jqMcV98Y7gVeVyNStwYS3U7yVVoDZ4btbRNf4h6ibWpY22iRmXq35qgLs79f312g2kj5539ebPM").unwrap();
+        assert_eq!(
+            signer.xpub_at(&bip32::DerivationPath::master(), &secp),
+            bip32::ExtendedPubKey::from_priv(&secp, &xpriv)
+        );
+        let no_passphrase = HotSigner::from_str(network, mnemonic).unwrap();
+        assert!(!no_passphrase.has_passphrase());
+        assert_ne!(no_passphrase.fingerprint(&secp), signer.fingerprint(&secp));
+
+        // The passphrase is only ever stored encrypted, along with the mnemonic.
+        let tmp_dir = tmp_dir();
+        fs::create_dir_all(&tmp_dir).unwrap();
+        assert!(matches!(
+            signer.store(&tmp_dir, network, &secp),
+            Err(SignerError::PlaintextPassphrase)
+        ));
+        assert!(!HotSigner::mnemonics_folder(&tmp_dir, network).exists());
+        fs::remove_dir_all(&tmp_dir).unwrap();
+
+        let params = ScryptParams {
+            log_n: 4,
+            r: 8,
+            p: 1,
+        };
+        let encrypted = EncryptedMnemonic::encrypt(&signer, "encryption", params, &secp).unwrap();
+        let decrypted = encrypted.decrypt(network, "encryption", &secp).unwrap();
+        assert_eq!(decrypted.fingerprint(&secp), signer.fingerprint(&secp));
+        assert!(decrypted.has_passphrase());
+    }
+
+    #[test]
+    fn hot_signer_encrypted_storage() {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let tmp_dir = tmp_dir();
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let network = bitcoin::Network::Bitcoin;
+        // Cheap parameters to not slow down the tests.
+        let params = ScryptParams {
+            log_n: 4,
+            r: 8,
+            p: 1,
+        };
+
+        // Store one mnemonic encrypted and one in plaintext.
+        let signer_a = HotSigner::generate(network).unwrap();
+        signer_a
+            .store_encrypted_with_params(&tmp_dir, network, "passphrase", params, &secp)
+            .unwrap();
+        let signer_b = HotSigner::generate(network).unwrap();
+        signer_b.store(&tmp_dir, network, &secp).unwrap();
+
+        // Only the plaintext one is read as a signer.
+        let signers = HotSigner::from_datadir(&tmp_dir, network).unwrap();
+        assert_eq!(signers.len(), 1);
+        assert_eq!(signers[0].words(), signer_b.words());
+
+        // The encrypted one can be identified without the passphrase, and decrypted with it.
+        let encrypted = HotSigner::encrypted_from_datadir(&tmp_dir, network).unwrap();
+        assert_eq!(encrypted.len(), 1);
+        assert_eq!(encrypted[0].fingerprint(), signer_a.fingerprint(&secp));
+        assert!(matches!(
+            encrypted[0].decrypt(network, "wrong passphrase", &secp),
+            Err(SignerError::MnemonicDecryption)
+        ));
+        assert_eq!(
+            encrypted[0]
+                .decrypt(network, "passphrase", &secp)
+                .unwrap()
+                .words(),
+            signer_a.words()
+        );
+
+        // It roundtrips through JSON, and any tampering is detected.
+        let json = encrypted[0].to_json();
+        assert_eq!(EncryptedMnemonic::from_json(&json).unwrap(), encrypted[0]);
+        let mut tampered = encrypted[0].clone();
+        tampered.scrypt.log_n = 5;
+        assert!(matches!(
+            tampered.decrypt(network, "passphrase", &secp),
+            Err(SignerError::MnemonicDecryption)
+        ));
+        let mut tampered = encrypted[0].clone();
+        tampered.fingerprint = signer_b.fingerprint(&secp);
+        assert!(matches!(
+            tampered.decrypt(network, "passphrase", &secp),
+            Err(SignerError::MnemonicDecryption)
+        ));
+        let mut tampered = encrypted[0].clone();
+        tampered.version = 0;
+        assert!(matches!(
+            tampered.decrypt(network, "passphrase", &secp),
+            Err(SignerError::MnemonicDecryption)
+        ));
+
+        // Unreasonable KDF parameters are refused.
+        for (log_n, r, p) in [(25, 1, 1), (20, 16, 1), (4, 8, 17), (4, u32::MAX, 1)] {
+            let mut insane = encrypted[0].clone();
+            insane.scrypt = ScryptParams { log_n, r, p };
+            assert!(matches!(
+                EncryptedMnemonic::from_json(&insane.to_json()),
+                Err(SignerError::InvalidEncryptedMnemonic(_))
+            ));
+        }
+        assert!(matches!(
+            EncryptedMnemonic::from_json(&json.replace("\"version\": 1", "\"version\": 2")),
+            Err(SignerError::InvalidEncryptedMnemonic(_))
+        ));
+
+        // Migrate the plaintext mnemonic. The default parameters are used.
+        assert_eq!(
+            HotSigner::encrypt_datadir(&tmp_dir, network, "other passphrase", &secp).unwrap(),
+            vec![signer_b.fingerprint(&secp)]
+        );
+        assert!(HotSigner::from_datadir(&tmp_dir, network)
+            .unwrap()
+            .is_empty());
+        let encrypted = HotSigner::encrypted_from_datadir(&tmp_dir, network).unwrap();
+        assert_eq!(encrypted.len(), 2);
+        let migrated = encrypted
+            .iter()
+            .find(|enc| enc.fingerprint() == signer_b.fingerprint(&secp))
+            .unwrap();
+        assert_eq!(migrated.scrypt, ScryptParams::default());
+        assert_eq!(
+            migrated
+                .decrypt(network, "other passphrase", &secp)
+                .unwrap()
+                .words(),
+            signer_b.words()
+        );
+
+        // A plaintext mnemonic for which an encrypted one exists already is left untouched.
+        signer_a.store(&tmp_dir, network, &secp).unwrap();
+        assert!(
+            HotSigner::encrypt_datadir(&tmp_dir, network, "other passphrase", &secp)
+                .unwrap()
+                .is_empty()
+        );
+        let signers = HotSigner::from_datadir(&tmp_dir, network).unwrap();
+        assert_eq!(signers.len(), 1);
+        assert_eq!(signers[0].words(), signer_a.words());
+        assert_eq!(
+            HotSigner::encrypted_from_datadir(&tmp_dir, network)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        fs::remove_dir_all(tmp_dir).unwrap();
+    }
+
     #[test]
     fn hot_signer_sign() {
         let secp = secp256k1::Secp256k1::new();
@@ -420,9 +1292,14 @@ mod tests {
             wildcard: Wildcard::Unhardened,
         });
         let recov_keys = descriptors::PathInfo::Single(recov_key);
-        let policy =
-            descriptors::LianaPolicy::new(prim_keys, [(46, recov_keys)].iter().cloned().collect())
-                .unwrap();
+        let policy = descriptors::LianaPolicy::new(
+            prim_keys,
+            [(descriptors::RecoveryTimelock::Blocks(46), recov_keys)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .unwrap();
         let desc = descriptors::LianaDescriptor::new(policy);
 
         // Create a dummy PSBT spending a coin from this descriptor with a single input and single
@@ -575,6 +1452,324 @@ mod tests {
         assert_eq!(psbt.inputs[1].partial_sigs.len(), 1);
     }
 
+    #[test]
+    fn hot_signer_sign_taproot() {
+        let secp = secp256k1::Secp256k1::new();
+        let network = bitcoin::Network::Bitcoin;
+        let (signer_a, signer_b, signer_c) = (
+            HotSigner::generate(network).unwrap(),
+            HotSigner::generate(network).unwrap(),
+            HotSigner::generate(network).unwrap(),
+        );
+        let multi_key = |signer: &HotSigner| {
+            let origin_der = bip32::DerivationPath::from_str("m/48'/0'/0'/2'").unwrap();
+            DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+                origin: Some((signer.fingerprint(&secp), origin_der.clone())),
+                xkey: signer.xpub_at(&origin_der, &secp),
+                derivation_paths: DerivPaths::new(vec![
+                    bip32::DerivationPath::from_str("m/0").unwrap(),
+                    bip32::DerivationPath::from_str("m/1").unwrap(),
+                ])
+                .unwrap(),
+                wildcard: Wildcard::Unhardened,
+            })
+        };
+
+        // Create a dummy PSBT spending a coin from this descriptor with a single input and single
+        // (external) output.
+        let dummy_psbt = |desc: &descriptors::LianaDescriptor, sequence| {
+            let spent_coin_desc = desc.receive_descriptor().derive(42.into(), &secp);
+            let mut psbt_in = PsbtIn {
+                witness_utxo: Some(bitcoin::TxOut {
+                    value: 19_000,
+                    script_pubkey: spent_coin_desc.script_pubkey(),
+                }),
+                ..PsbtIn::default()
+            };
+            spent_coin_desc.update_psbt_in(&mut psbt_in);
+            Psbt {
+                unsigned_tx: bitcoin::Transaction {
+                    version: 2,
+                    lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+                    input: vec![bitcoin::TxIn {
+                        sequence,
+                        previous_output: bitcoin::OutPoint::from_str(
+                            "4613e078e4cdbb0fce1bc6e44b028f0e11621a134a1605efdc456c32d155c922:19",
+                        )
+                        .unwrap(),
+                        ..bitcoin::TxIn::default()
+                    }],
+                    output: vec![bitcoin::TxOut {
+                        value: 18_420,
+                        script_pubkey: bitcoin::Address::from_str(
+                            "bc1qvklensptw5lk7d470ds60pcpsr0psdpgyvwepv",
+                        )
+                        .unwrap()
+                        .payload
+                        .script_pubkey(),
+                    }],
+                },
+                version: 0,
+                xpub: BTreeMap::new(),
+                proprietary: BTreeMap::new(),
+                unknown: BTreeMap::new(),
+                inputs: vec![psbt_in],
+                outputs: vec![Default::default()],
+            }
+        };
+
+        // A single key as primary path: it signs for the key path.
+        let policy = descriptors::LianaPolicy::new_taproot(
+            descriptors::PathInfo::Single(multi_key(&signer_a)),
+            [(
+                descriptors::RecoveryTimelock::Blocks(46),
+                descriptors::PathInfo::Single(multi_key(&signer_b)),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap();
+        let desc = descriptors::LianaDescriptor::new(policy);
+        let psbt = dummy_psbt(&desc, bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME);
+        assert!(psbt.inputs[0].tap_key_sig.is_none());
+        let psbt = signer_c.sign_psbt(psbt, &secp).unwrap();
+        assert!(psbt.inputs[0].tap_key_sig.is_none());
+        assert!(psbt.inputs[0].tap_script_sigs.is_empty());
+        let mut psbt = signer_a.sign_psbt(psbt, &secp).unwrap();
+        assert!(psbt.inputs[0].tap_key_sig.is_some());
+        assert!(psbt.inputs[0].tap_script_sigs.is_empty());
+        let spend_info = desc.partial_spend_info(&psbt).unwrap();
+        assert_eq!(spend_info.primary_path().sigs_count, 1);
+        assert!(spend_info.recovery_paths().is_empty());
+        psbt.finalize_mut(&secp).unwrap();
+
+        // The recovery key signs for its leaf once the timelock is available.
+        let psbt = dummy_psbt(&desc, bitcoin::Sequence::from_height(46));
+        let mut psbt = signer_b.sign_psbt(psbt, &secp).unwrap();
+        assert!(psbt.inputs[0].tap_key_sig.is_none());
+        assert_eq!(psbt.inputs[0].tap_script_sigs.len(), 1);
+        let spend_info = desc.partial_spend_info(&psbt).unwrap();
+        assert_eq!(spend_info.primary_path().sigs_count, 0);
+        assert_eq!(
+            spend_info.recovery_paths()[&descriptors::RecoveryTimelock::Blocks(46)].sigs_count,
+            1
+        );
+        psbt.finalize_mut(&secp).unwrap();
+
+        // A multisig as primary path: the key path is unspendable and all signers sign for a leaf.
+        let policy = descriptors::LianaPolicy::new_taproot(
+            descriptors::PathInfo::Multi(2, vec![multi_key(&signer_a), multi_key(&signer_b)]),
+            [(
+                descriptors::RecoveryTimelock::Blocks(46),
+                descriptors::PathInfo::Single(multi_key(&signer_c)),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap();
+        let desc = descriptors::LianaDescriptor::new(policy);
+        let psbt = dummy_psbt(&desc, bitcoin::Sequence::ENABLE_RBF_NO_LOCKTIME);
+        let psbt = signer_a.sign_psbt(psbt, &secp).unwrap();
+        assert_eq!(psbt.inputs[0].tap_script_sigs.len(), 1);
+        let psbt = signer_b.sign_psbt(psbt, &secp).unwrap();
+        assert!(psbt.inputs[0].tap_key_sig.is_none());
+        assert_eq!(psbt.inputs[0].tap_script_sigs.len(), 2);
+        let spend_info = desc.partial_spend_info(&psbt).unwrap();
+        assert_eq!(spend_info.primary_path().sigs_count, 2);
+        assert_eq!(spend_info.primary_path().threshold, 2);
+        let mut psbt = psbt;
+        psbt.finalize_mut(&secp).unwrap();
+    }
+
+    #[test]
+    fn hot_signer_sign_checked() {
+        let secp = secp256k1::Secp256k1::new();
+        let network = bitcoin::Network::Bitcoin;
+        let (prim_signer, recov_signer) = (
+            HotSigner::generate(network).unwrap(),
+            HotSigner::generate(network).unwrap(),
+        );
+        let multi_key = |signer: &HotSigner| {
+            let origin_der = bip32::DerivationPath::from_str("m/48'/0'/0'/2'").unwrap();
+            DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+                origin: Some((signer.fingerprint(&secp), origin_der.clone())),
+                xkey: signer.xpub_at(&origin_der, &secp),
+                derivation_paths: DerivPaths::new(vec![
+                    bip32::DerivationPath::from_str("m/0").unwrap(),
+                    bip32::DerivationPath::from_str("m/1").unwrap(),
+                ])
+                .unwrap(),
+                wildcard: Wildcard::Unhardened,
+            })
+        };
+        let policy = descriptors::LianaPolicy::new(
+            descriptors::PathInfo::Single(multi_key(&prim_signer)),
+            [(
+                descriptors::RecoveryTimelock::Blocks(46),
+                descriptors::PathInfo::Single(multi_key(&recov_signer)),
+            )]
+            .iter()
+            .cloned()
+            .collect(),
+        )
+        .unwrap();
+        let desc = descriptors::LianaDescriptor::new(policy);
+
+        // A PSBT spending a coin from the descriptor to an external address, with change.
+        let spent_coin_desc = desc.receive_descriptor().derive(42.into(), &secp);
+        let change_desc = desc.change_descriptor().derive(3.into(), &secp);
+        let mut change_out = bitcoin::psbt::Output::default();
+        change_desc.update_psbt_out(&mut change_out);
+        let spent_txo = bitcoin::TxOut {
+            value: 100_000,
+            script_pubkey: spent_coin_desc.script_pubkey(),
+        };
+        let mut prev_tx = bitcoin::Transaction {
+            version: 2,
+            lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+            input: vec![bitcoin::TxIn::default()],
+            output: vec![bitcoin::TxOut::default(); 19],
+        };
+        prev_tx.output.push(spent_txo.clone());
+        let dummy_psbt = Psbt {
+            unsigned_tx: bitcoin::Transaction {
+                version: 2,
+                lock_time: absolute::LockTime::Blocks(absolute::Height::ZERO),
+                input: vec![bitcoin::TxIn {
+                    previous_output: bitcoin::OutPoint::new(prev_tx.txid(), 19),
+                    ..bitcoin::TxIn::default()
+                }],
+                output: vec![
+                    bitcoin::TxOut {
+                        value: 50_000,
+                        script_pubkey: bitcoin::Address::from_str(
+                            "bc1qvklensptw5lk7d470ds60pcpsr0psdpgyvwepv",
+                        )
+                        .unwrap()
+                        .payload
+                        .script_pubkey(),
+                    },
+                    bitcoin::TxOut {
+                        value: 40_000,
+                        script_pubkey: change_desc.script_pubkey(),
+                    },
+                ],
+            },
+            version: 0,
+            xpub: BTreeMap::new(),
+            proprietary: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+            inputs: vec![PsbtIn {
+                witness_script: Some(spent_coin_desc.witness_script()),
+                bip32_derivation: spent_coin_desc.bip32_derivations(),
+                witness_utxo: Some(spent_txo),
+                non_witness_utxo: Some(prev_tx.clone()),
+                ..PsbtIn::default()
+            }],
+            outputs: vec![bitcoin::psbt::Output::default(), change_out],
+        };
+        let bounds = FeeBounds::default();
+
+        // A sane PSBT gets signed.
+        let psbt = prim_signer
+            .sign_psbt_checked(dummy_psbt.clone(), &desc, &bounds, &secp)
+            .unwrap();
+        assert_eq!(psbt.inputs[0].partial_sigs.len(), 1);
+
+        // An input which does not spend a coin from the descriptor is refused.
+        let mut psbt = dummy_psbt.clone();
+        let foreign_spk = desc
+            .receive_descriptor()
+            .derive(43.into(), &secp)
+            .script_pubkey();
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().script_pubkey = foreign_spk.clone();
+        psbt.inputs[0].non_witness_utxo.as_mut().unwrap().output[19].script_pubkey = foreign_spk;
+        psbt.unsigned_tx.input[0].previous_output.txid =
+            psbt.inputs[0].non_witness_utxo.as_ref().unwrap().txid();
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(psbt, &desc, &bounds, &secp),
+            Err(SignerError::Refused(SigningRefusal::ForeignInput(0)))
+        ));
+
+        // The previous transaction is required, and must match the spent coin. Otherwise the
+        // value of the coin, hence the fees, could be lied about.
+        let mut psbt = dummy_psbt.clone();
+        psbt.inputs[0].non_witness_utxo = None;
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(psbt, &desc, &bounds, &secp),
+            Err(SignerError::IncompletePsbt)
+        ));
+        let mut psbt = dummy_psbt.clone();
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().value = 90_010;
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(psbt, &desc, &bounds, &secp),
+            Err(SignerError::Refused(
+                SigningRefusal::InvalidPreviousTransaction(0)
+            ))
+        ));
+        let mut psbt = dummy_psbt.clone();
+        psbt.unsigned_tx.input[0].previous_output.vout = 18;
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(psbt, &desc, &bounds, &secp),
+            Err(SignerError::Refused(
+                SigningRefusal::InvalidPreviousTransaction(0)
+            ))
+        ));
+        let mut psbt = dummy_psbt.clone();
+        psbt.inputs[0].non_witness_utxo.as_mut().unwrap().output[0].value = 1;
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(psbt, &desc, &bounds, &secp),
+            Err(SignerError::Refused(
+                SigningRefusal::InvalidPreviousTransaction(0)
+            ))
+        ));
+
+        // A change output which does not derive from the descriptor is refused.
+        let mut psbt = dummy_psbt.clone();
+        psbt.unsigned_tx.output[1].script_pubkey = psbt.unsigned_tx.output[0].script_pubkey.clone();
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(psbt, &desc, &bounds, &secp),
+            Err(SignerError::Refused(SigningRefusal::InvalidChangeOutput(1)))
+        ));
+
+        // Only SIGHASH_ALL signatures may be requested.
+        let mut psbt = dummy_psbt.clone();
+        psbt.inputs[0].sighash_type = Some(sighash::EcdsaSighashType::None.into());
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(psbt, &desc, &bounds, &secp),
+            Err(SignerError::Refused(SigningRefusal::UnsafeSighashType(0)))
+        ));
+        let mut psbt = dummy_psbt.clone();
+        psbt.inputs[0].sighash_type = Some(sighash::EcdsaSighashType::All.into());
+        prim_signer
+            .sign_psbt_checked(psbt, &desc, &bounds, &secp)
+            .unwrap();
+
+        // The fee must be within bounds.
+        let low_fee_bounds = FeeBounds {
+            max_fee: 9_999,
+            ..FeeBounds::default()
+        };
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(dummy_psbt.clone(), &desc, &low_fee_bounds, &secp),
+            Err(SignerError::Refused(SigningRefusal::FeeOutOfBounds {
+                fee: 10_000,
+                ..
+            }))
+        ));
+        let mut psbt = dummy_psbt;
+        psbt.unsigned_tx.output[1].value = 49_990;
+        assert!(matches!(
+            prim_signer.sign_psbt_checked(psbt, &desc, &bounds, &secp),
+            Err(SignerError::Refused(SigningRefusal::FeeOutOfBounds {
+                feerate: 0,
+                ..
+            }))
+        ));
+    }
+
     #[test]
     fn signer_set_net() {
         let secp = secp256k1::Secp256k1::signing_only();
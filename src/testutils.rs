@@ -1,7 +1,10 @@
 use crate::{
-    bitcoin::{BitcoinInterface, Block, BlockChainTip, SyncProgress, UTxO},
+    bitcoin::{BitcoinInterface, Block, BlockChainTip, ExpiredOutpoint, SyncProgress, UTxO},
     config::{BitcoinConfig, Config},
-    database::{BlockInfo, Coin, CoinStatus, DatabaseConnection, DatabaseInterface, LabelItem},
+    database::{
+        BlockInfo, Coin, CoinStatus, CoinsOrderBy, CoinsQuery, DatabaseConnection,
+        DatabaseInterface, ExpiredCoin, LabelItem, SpendTxStatus, WalletTransaction,
+    },
     descriptors, DaemonHandle,
 };
 
@@ -19,6 +22,9 @@ use miniscript::{
     descriptor,
 };
 
+/// The median time past of the genesis block of the dummy chain.
+pub const DUMMY_GENESIS_MTP: u32 = 1_600_000_000;
+
 pub struct DummyBitcoind {
     pub txs: HashMap<Txid, (Transaction, Option<Block>)>,
 }
@@ -71,7 +77,7 @@ impl BitcoinInterface for DummyBitcoind {
     fn confirmed_coins(
         &self,
         _: &[bitcoin::OutPoint],
-    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<bitcoin::OutPoint>) {
+    ) -> (Vec<(bitcoin::OutPoint, i32, u32)>, Vec<ExpiredOutpoint>) {
         (Vec::new(), Vec::new())
     }
 
@@ -98,6 +104,10 @@ impl BitcoinInterface for DummyBitcoind {
         todo!()
     }
 
+    fn import_descriptor(&self, _: &descriptors::LianaDescriptor) -> Result<(), String> {
+        Ok(())
+    }
+
     fn rescan_progress(&self) -> Option<f64> {
         None
     }
@@ -110,12 +120,33 @@ impl BitcoinInterface for DummyBitcoind {
         todo!()
     }
 
+    fn median_time_past(&self, height: i32) -> Option<u32> {
+        // One block every 10 minutes from the genesis block up to the tip.
+        if height < 0 || height > self.chain_tip().height {
+            return None;
+        }
+        Some(DUMMY_GENESIS_MTP + height as u32 * 600)
+    }
+
     fn wallet_transaction(
         &self,
         txid: &bitcoin::Txid,
     ) -> Option<(bitcoin::Transaction, Option<Block>)> {
         self.txs.get(txid).cloned()
     }
+
+    fn is_in_mempool(&self, _: &bitcoin::Txid) -> bool {
+        false
+    }
+
+    fn unspent_txout(&self, outpoint: &bitcoin::OutPoint) -> Option<bitcoin::TxOut> {
+        // We don't keep track of spent coins, assume all outputs of confirmed transactions are
+        // unspent.
+        self.txs
+            .get(&outpoint.txid)
+            .filter(|(_, block)| block.is_some())
+            .and_then(|(tx, _)| tx.output.get(outpoint.vout as usize).cloned())
+    }
 }
 
 struct DummyDbState {
@@ -123,7 +154,11 @@ struct DummyDbState {
     change_index: bip32::ChildNumber,
     curr_tip: Option<BlockChainTip>,
     coins: HashMap<bitcoin::OutPoint, Coin>,
-    spend_txs: HashMap<bitcoin::Txid, (Psbt, Option<u32>)>,
+    spend_txs: HashMap<bitcoin::Txid, (Psbt, Option<u32>, SpendTxStatus)>,
+    labels: HashMap<String, String>,
+    txs: HashMap<bitcoin::Txid, bitcoin::Transaction>,
+    expired_coins: Vec<ExpiredCoin>,
+    signed_spends: HashMap<(bip32::Fingerprint, bitcoin::Txid), (bitcoin::Amount, u32)>,
 }
 
 pub struct DummyDatabase {
@@ -147,6 +182,10 @@ impl DummyDatabase {
                 curr_tip: None,
                 coins: HashMap::new(),
                 spend_txs: HashMap::new(),
+                labels: HashMap::new(),
+                txs: HashMap::new(),
+                expired_coins: Vec::new(),
+                signed_spends: HashMap::new(),
             })),
         }
     }
@@ -156,6 +195,12 @@ impl DummyDatabase {
             self.db.write().unwrap().coins.insert(coin.outpoint, coin);
         }
     }
+
+    pub fn insert_txs(&mut self, txs: Vec<Transaction>) {
+        for tx in txs {
+            self.db.write().unwrap().txs.insert(tx.txid(), tx);
+        }
+    }
 }
 
 impl DatabaseConnection for DummyDatabase {
@@ -234,6 +279,57 @@ impl DatabaseConnection for DummyDatabase {
             .collect()
     }
 
+    fn query_coins(
+        &mut self,
+        statuses: &[CoinStatus],
+        outpoints: &[bitcoin::OutPoint],
+        query: &CoinsQuery,
+    ) -> Vec<Coin> {
+        assert!(
+            query.label.is_none(),
+            "Labels aren't supported by the dummy db"
+        );
+        let height = |c: &Coin| c.block_info.map(|b| b.height);
+        let mut coins: Vec<Coin> = self
+            .coins(statuses, outpoints)
+            .into_values()
+            .filter(|c| {
+                query.min_amount.map(|a| c.amount >= a).unwrap_or(true)
+                    && query.max_amount.map(|a| c.amount <= a).unwrap_or(true)
+                    && query.is_change.map(|ch| c.is_change == ch).unwrap_or(true)
+                    && query
+                        .min_height
+                        .map(|h| height(c) >= Some(h))
+                        .unwrap_or(true)
+                    && query
+                        .max_height
+                        .map(|h| height(c).map(|c_h| c_h <= h).unwrap_or(false))
+                        .unwrap_or(true)
+            })
+            .collect();
+        // There is no insertion order in the dummy db, default to ordering by outpoint.
+        coins.sort_by_key(|c| c.outpoint);
+        if let Some((order_by, descending)) = query.order_by {
+            coins.sort_by(|a, b| {
+                let ord = match order_by {
+                    CoinsOrderBy::Amount => a.amount.cmp(&b.amount),
+                    CoinsOrderBy::BlockHeight => height(a).cmp(&height(b)),
+                    CoinsOrderBy::DerivationIndex => a.derivation_index.cmp(&b.derivation_index),
+                };
+                if descending {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            });
+        }
+        coins
+            .into_iter()
+            .skip(query.offset as usize)
+            .take(query.limit.map(|l| l as usize).unwrap_or(usize::MAX))
+            .collect()
+    }
+
     fn list_spending_coins(&mut self) -> HashMap<bitcoin::OutPoint, Coin> {
         let mut result = HashMap::new();
         for (k, v) in self.db.read().unwrap().coins.iter() {
@@ -250,12 +346,30 @@ impl DatabaseConnection for DummyDatabase {
         }
     }
 
-    fn remove_coins(&mut self, outpoints: &[bitcoin::OutPoint]) {
-        for op in outpoints {
-            self.db.write().unwrap().coins.remove(op);
+    fn expire_coins(&mut self, coins: &[(bitcoin::OutPoint, Option<bitcoin::Txid>)]) {
+        let mut db = self.db.write().unwrap();
+        for (op, conflicting_txid) in coins {
+            if let Some(coin) = db.coins.remove(op) {
+                db.expired_coins.insert(
+                    0,
+                    ExpiredCoin {
+                        outpoint: coin.outpoint,
+                        amount: coin.amount,
+                        derivation_index: coin.derivation_index,
+                        is_change: coin.is_change,
+                        expired_at: 0,
+                        conflicting_txid: *conflicting_txid,
+                        previous_descriptor_id: None,
+                    },
+                );
+            }
         }
     }
 
+    fn list_expired_coins(&mut self) -> Vec<ExpiredCoin> {
+        self.db.read().unwrap().expired_coins.clone()
+    }
+
     fn confirm_coins<'a>(&mut self, outpoints: &[(bitcoin::OutPoint, i32, u32)]) {
         for (op, height, time) in outpoints {
             let mut db = self.db.write().unwrap();
@@ -320,7 +434,7 @@ impl DatabaseConnection for DummyDatabase {
             .write()
             .unwrap()
             .spend_txs
-            .insert(txid, (psbt.clone(), None));
+            .insert(txid, (psbt.clone(), None, SpendTxStatus::default()));
     }
 
     fn spend_tx(&mut self, txid: &bitcoin::Txid) -> Option<Psbt> {
@@ -333,7 +447,7 @@ impl DatabaseConnection for DummyDatabase {
             .map(|x| x.0)
     }
 
-    fn list_spend(&mut self) -> Vec<(Psbt, Option<u32>)> {
+    fn list_spend(&mut self) -> Vec<(Psbt, Option<u32>, SpendTxStatus)> {
         self.db
             .read()
             .unwrap()
@@ -343,14 +457,73 @@ impl DatabaseConnection for DummyDatabase {
             .collect()
     }
 
+    fn mark_spend_broadcast(&mut self, txid: &bitcoin::Txid) {
+        if let Some((_, _, status)) = self.db.write().unwrap().spend_txs.get_mut(txid) {
+            status.broadcast_at = Some(1);
+            status.in_mempool = true;
+        }
+    }
+
+    fn update_spend_status(&mut self, statuses: &[(bitcoin::Txid, SpendTxStatus)]) {
+        let mut db = self.db.write().unwrap();
+        for (txid, new_status) in statuses {
+            if let Some((_, _, status)) = db.spend_txs.get_mut(txid) {
+                *status = SpendTxStatus {
+                    broadcast_at: status.broadcast_at.or(new_status.broadcast_at),
+                    ..*new_status
+                };
+            }
+        }
+    }
+
     fn delete_spend(&mut self, txid: &bitcoin::Txid) {
         self.db.write().unwrap().spend_txs.remove(txid);
     }
 
+    fn record_signed_spend(
+        &mut self,
+        fingerprint: bip32::Fingerprint,
+        txid: &bitcoin::Txid,
+        amount: bitcoin::Amount,
+    ) {
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32;
+        self.db
+            .write()
+            .unwrap()
+            .signed_spends
+            .entry((fingerprint, *txid))
+            .or_insert((amount, now));
+    }
+
+    fn signed_spends_amount(
+        &mut self,
+        fingerprint: bip32::Fingerprint,
+        since: u32,
+        exclude_txid: &bitcoin::Txid,
+    ) -> bitcoin::Amount {
+        self.db
+            .read()
+            .unwrap()
+            .signed_spends
+            .iter()
+            .filter(|((fg, txid), (_, signed_at))| {
+                *fg == fingerprint && txid != exclude_txid && *signed_at >= since
+            })
+            .map(|(_, (amount, _))| *amount)
+            .sum()
+    }
+
     fn rollback_tip(&mut self, _: &BlockChainTip) {
         todo!()
     }
 
+    fn timestamp(&mut self) -> u32 {
+        1_690_000_000
+    }
+
     fn rescan_timestamp(&mut self) -> Option<u32> {
         None
     }
@@ -363,12 +536,32 @@ impl DatabaseConnection for DummyDatabase {
         todo!()
     }
 
-    fn update_labels(&mut self, _items: &HashMap<LabelItem, String>) {
-        todo!()
+    fn set_migration_descriptor(&mut self, _: &descriptors::LianaDescriptor) {}
+
+    fn previous_descriptors(&mut self) -> HashMap<i64, descriptors::LianaDescriptor> {
+        HashMap::new()
     }
 
-    fn labels(&mut self, _items: &HashSet<LabelItem>) -> HashMap<String, String> {
-        todo!()
+    fn update_labels(&mut self, items: &HashMap<LabelItem, String>) {
+        let mut db = self.db.write().unwrap();
+        for (item, label) in items {
+            db.labels.insert(item.to_string(), label.clone());
+        }
+    }
+
+    fn labels(&mut self, items: &HashSet<LabelItem>) -> HashMap<String, String> {
+        let db = self.db.read().unwrap();
+        items
+            .iter()
+            .filter_map(|item| {
+                let item = item.to_string();
+                db.labels.get(&item).map(|label| (item, label.clone()))
+            })
+            .collect()
+    }
+
+    fn all_labels(&mut self) -> HashMap<String, String> {
+        self.db.read().unwrap().labels.clone()
     }
 
     fn list_txids(&mut self, start: u32, end: u32, limit: u64) -> Vec<bitcoin::Txid> {
@@ -399,6 +592,99 @@ impl DatabaseConnection for DummyDatabase {
         txids_and_time.truncate(limit as usize);
         txids_and_time.into_iter().map(|(txid, _)| txid).collect()
     }
+
+    fn list_history_txids(&mut self, limit: u64, offset: u64) -> Vec<bitcoin::Txid> {
+        let mut txids_and_time: Vec<(bitcoin::Txid, Option<u32>)> = Vec::new();
+        for coin in self.db.read().unwrap().coins.values() {
+            let rows = std::iter::once((coin.outpoint.txid, coin.block_info.map(|b| b.time)))
+                .chain(
+                    coin.spend_txid
+                        .map(|txid| (txid, coin.spend_block.map(|b| b.time))),
+                );
+            for row in rows {
+                if !txids_and_time.contains(&row) {
+                    txids_and_time.push(row);
+                }
+            }
+        }
+        // Unconfirmed first, then most recent first. Break ties by txid since there is no
+        // insertion order in the dummy db.
+        txids_and_time.sort_by(|(txid_a, t_a), (txid_b, t_b)| match (t_a, t_b) {
+            (None, None) => txid_a.cmp(txid_b),
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (Some(a), Some(b)) => b.cmp(a).then(txid_a.cmp(txid_b)),
+        });
+        txids_and_time
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(txid, _)| txid)
+            .collect()
+    }
+
+    fn coins_by_txids(&mut self, txids: &[bitcoin::Txid]) -> HashMap<bitcoin::OutPoint, Coin> {
+        self.db
+            .read()
+            .unwrap()
+            .coins
+            .clone()
+            .into_iter()
+            .filter(|(op, c)| {
+                txids.contains(&op.txid)
+                    || c.spend_txid
+                        .map(|txid| txids.contains(&txid))
+                        .unwrap_or(false)
+            })
+            .collect()
+    }
+
+    fn new_txs(&mut self, txs: &[bitcoin::Transaction]) {
+        let mut db = self.db.write().unwrap();
+        for tx in txs {
+            db.txs.insert(tx.txid(), tx.clone());
+        }
+    }
+
+    fn missing_txids(&mut self) -> Vec<bitcoin::Txid> {
+        let db = self.db.read().unwrap();
+        db.coins
+            .values()
+            .flat_map(|c| std::iter::once(c.outpoint.txid).chain(c.spend_txid))
+            .filter(|txid| !db.txs.contains_key(txid))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    fn list_wallet_transactions(
+        &mut self,
+        txids: &[bitcoin::Txid],
+    ) -> HashMap<bitcoin::Txid, WalletTransaction> {
+        let db = self.db.read().unwrap();
+        txids
+            .iter()
+            .filter_map(|txid| {
+                let transaction = db.txs.get(txid)?.clone();
+                let block_info = db.coins.values().find_map(|c| {
+                    if c.outpoint.txid == *txid {
+                        c.block_info
+                    } else if c.spend_txid == Some(*txid) {
+                        c.spend_block
+                    } else {
+                        None
+                    }
+                });
+                Some((
+                    *txid,
+                    WalletTransaction {
+                        transaction,
+                        block_info,
+                    },
+                ))
+            })
+            .collect()
+    }
 }
 
 pub struct DummyLiana {
@@ -424,11 +710,46 @@ pub fn tmp_dir() -> path::PathBuf {
     ))
 }
 
+/// Write a fake external signer script to this directory and return its path.
+#[cfg(unix)]
+pub fn fake_signer(dir: &path::Path, script: &str) -> path::PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::create_dir_all(dir).unwrap();
+    let path = dir.join("fake_signer.sh");
+    fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o700)).unwrap();
+    path
+}
+
 impl DummyLiana {
     /// Creates a new DummyLiana interface
     pub fn new(
         bitcoin_interface: impl BitcoinInterface + 'static,
         database: impl DatabaseInterface + 'static,
+    ) -> DummyLiana {
+        let owner_key = descriptors::PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabbccdd]xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*").unwrap());
+        let heir_key = descriptors::PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*").unwrap());
+        let policy = descriptors::LianaPolicy::new(
+            owner_key,
+            [(descriptors::RecoveryTimelock::Blocks(10_000), heir_key)]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+        .unwrap();
+        let desc = descriptors::LianaDescriptor::new(policy);
+
+        DummyLiana::new_with_config(bitcoin_interface, database, desc, |_| {})
+    }
+
+    /// Creates a new DummyLiana interface for this descriptor, with a configuration tweaked by
+    /// this function
+    pub fn new_with_config(
+        bitcoin_interface: impl BitcoinInterface + 'static,
+        database: impl DatabaseInterface + 'static,
+        desc: descriptors::LianaDescriptor,
+        configure: impl FnOnce(&mut Config),
     ) -> DummyLiana {
         let tmp_dir = tmp_dir();
         fs::create_dir_all(&tmp_dir).unwrap();
@@ -440,16 +761,7 @@ impl DummyLiana {
             network,
             poll_interval_secs: time::Duration::from_secs(2),
         };
-
-        let owner_key = descriptors::PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabbccdd]xpub68JJTXc1MWK8KLW4HGLXZBJknja7kDUJuFHnM424LbziEXsfkh1WQCiEjjHw4zLqSUm4rvhgyGkkuRowE9tCJSgt3TQB5J3SKAbZ2SdcKST/<0;1>/*").unwrap());
-        let heir_key = descriptors::PathInfo::Single(descriptor::DescriptorPublicKey::from_str("[aabbccdd]xpub68JJTXc1MWK8PEQozKsRatrUHXKFNkD1Cb1BuQU9Xr5moCv87anqGyXLyUd4KpnDyZgo3gz4aN1r3NiaoweFW8UutBsBbgKHzaD5HkTkifK/<0;1>/*").unwrap());
-        let policy = descriptors::LianaPolicy::new(
-            owner_key,
-            [(10_000, heir_key)].iter().cloned().collect(),
-        )
-        .unwrap();
-        let desc = descriptors::LianaDescriptor::new(policy);
-        let config = Config {
+        let mut config = Config {
             bitcoin_config,
             bitcoind_config: None,
             data_dir: Some(data_dir),
@@ -457,7 +769,10 @@ impl DummyLiana {
             daemon: false,
             log_level: log::LevelFilter::Debug,
             main_descriptor: desc,
+            signer_policies: Vec::new(),
+            signer_command: None,
         };
+        configure(&mut config);
 
         let handle = DaemonHandle::start(config, Some(bitcoin_interface), Some(database)).unwrap();
         DummyLiana { tmp_dir, handle }
@@ -304,11 +304,23 @@ def test_deposit_replacement(lianad, bitcoind):
 
     # Make sure we registered the unconfirmed coin. Then RBF the deposit tx.
     wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) == 1)
+    coin = lianad.rpc.listcoins()["coins"][0]
+    assert lianad.rpc.getinfo()["expired_coins"] == 0
+    assert lianad.rpc.listexpiredcoins()["coins"] == []
     txid = bitcoind.rpc.sendrawtransaction(conflicting_tx)
 
     # We must forget about the deposit.
     wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) == 0)
 
+    # But we keep track of why it disappeared.
+    assert lianad.rpc.getinfo()["expired_coins"] == 1
+    expired_coins = lianad.rpc.listexpiredcoins()["coins"]
+    assert len(expired_coins) == 1
+    assert expired_coins[0]["outpoint"] == coin["outpoint"]
+    assert expired_coins[0]["amount"] == coin["amount"]
+    assert expired_coins[0]["address"] == coin["address"]
+    assert expired_coins[0]["conflicting_txid"] == txid
+
     # Send a new one, it'll be detected.
     addr = lianad.rpc.getnewaddress()["address"]
     bitcoind.rpc.sendtoaddress(addr, 2)
@@ -102,6 +102,18 @@ class Lianad(TailableProc):
 
         return psbt
 
+    def update_descriptor(self, multi_desc):
+        """Set a new main descriptor in the configuration. Takes effect at the next start."""
+        with open(self.conf_file, "r") as f:
+            conf = f.read()
+        conf = conf.replace(
+            f'main_descriptor = "{self.multi_desc}"', f'main_descriptor = "{multi_desc}"'
+        )
+        with open(self.conf_file, "w") as f:
+            f.write(conf)
+        self.multi_desc = multi_desc
+        self.receive_desc, self.change_desc = multi_desc.singlepath_descriptors()
+
     def restart_fresh(self, bitcoind):
         """Delete the internal state of the wallet and restart."""
         self.stop()
@@ -30,6 +30,24 @@ def test_getinfo(lianad):
     assert res["rescan_progress"] is None
 
 
+def test_getpolicy(lianad):
+    wait_for(lambda: lianad.rpc.getinfo()["block_height"] == 101)
+    res = lianad.rpc.getpolicy()
+    assert res["coin_height"] == 101
+    policy = res["policy"]
+    assert len(policy["primary_path"]["keys"]) > 0
+    assert len(policy["recovery_paths"]) == len(res["timeline"])
+    reco_path = policy["recovery_paths"][0]
+    assert res["timeline"][0]["height"] == 101 + reco_path["blocks"]
+
+    # Keys can be given a name after their master fingerprint.
+    fg = policy["primary_path"]["keys"][0]["fingerprint"]
+    res = lianad.rpc.getpolicy({fg: "Alice"}, 42)
+    assert res["coin_height"] == 42
+    assert res["policy"]["primary_path"]["keys"][0]["alias"] == "Alice"
+    assert res["timeline"][0]["height"] == 42 + reco_path["blocks"]
+
+
 def test_getaddress(lianad):
     res = lianad.rpc.getnewaddress()
     assert "address" in res
@@ -318,6 +336,63 @@ def test_listcoins(lianad, bitcoind):
             lianad.rpc.listcoins(statuses, outpoints)
 
 
+def test_listcoins_query(lianad, bitcoind):
+    # Receive three coins, one of which remains unconfirmed.
+    addresses = [lianad.rpc.getnewaddress()["address"] for _ in range(3)]
+    txids = [bitcoind.rpc.sendtoaddress(addresses[0], 1)]
+    bitcoind.generate_block(1, wait_for_mempool=txids[0])
+    txids.append(bitcoind.rpc.sendtoaddress(addresses[1], 3))
+    bitcoind.generate_block(1, wait_for_mempool=txids[1])
+    txids.append(bitcoind.rpc.sendtoaddress(addresses[2], 2))
+    wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) == 3)
+    first_height = bitcoind.rpc.getblockcount() - 1
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 2)
+    coins = lianad.rpc.listcoins()["coins"]
+    assert [c["outpoint"][:64] for c in coins] == txids
+    assert all(not c["is_change"] for c in coins)
+
+    # We can paginate and order the coins.
+    outpoints = lambda res: [c["outpoint"][:64] for c in res["coins"]]
+    assert outpoints(lianad.rpc.listcoins(limit=2)) == txids[:2]
+    assert outpoints(lianad.rpc.listcoins(limit=2, offset=2)) == txids[2:]
+    assert outpoints(lianad.rpc.listcoins(order_by="amount")) == [
+        txids[0],
+        txids[2],
+        txids[1],
+    ]
+    assert outpoints(
+        lianad.rpc.listcoins(order_by="amount", descending=True, limit=1)
+    ) == [txids[1]]
+    assert outpoints(
+        lianad.rpc.listcoins(order_by="derivation_index", descending=True)
+    ) == list(reversed(txids))
+
+    # We can filter them by amount, change and block height.
+    assert outpoints(lianad.rpc.listcoins(min_amount=2 * COIN)) == txids[1:]
+    assert outpoints(
+        lianad.rpc.listcoins(min_amount=2 * COIN, max_amount=2 * COIN)
+    ) == [txids[2]]
+    assert outpoints(lianad.rpc.listcoins(is_change=True)) == []
+    assert outpoints(lianad.rpc.listcoins(min_height=first_height + 1)) == [txids[1]]
+    assert outpoints(lianad.rpc.listcoins(max_height=first_height)) == [txids[0]]
+
+    # And by label.
+    lianad.rpc.updatelabels({addresses[0]: "From Alice", txids[2]: "alice again"})
+    assert outpoints(lianad.rpc.listcoins(label="ALICE")) == [txids[0], txids[2]]
+    assert outpoints(lianad.rpc.listcoins(label="again")) == [txids[2]]
+
+    with pytest.raises(RpcError, match="Invalid 'order_by' parameter."):
+        lianad.rpc.listcoins(order_by="txid")
+    with pytest.raises(
+        RpcError, match="The 'descending' parameter requires the 'order_by' parameter."
+    ):
+        lianad.rpc.listcoins(descending=True)
+    with pytest.raises(RpcError, match="Invalid 'limit' parameter."):
+        lianad.rpc.listcoins(limit=2**63)
+    with pytest.raises(RpcError, match="Invalid 'offset' parameter."):
+        lianad.rpc.listcoins(offset=2**64 - 1)
+
+
 def test_jsonrpc_server(lianad, bitcoind):
     """Test passing parameters as a list or a mapping."""
     addr = lianad.rpc.getnewaddress()["address"]
@@ -523,7 +598,21 @@ def test_broadcast_spend(lianad, bitcoind):
 
     # Now we've signed and stored it, the daemon will take care of finalizing
     # the PSBT before broadcasting the transaction.
+    spend_entry = lambda: next(
+        e
+        for e in lianad.rpc.listspendtxs()["spend_txs"]
+        if PSBT.from_base64(e["psbt"]).tx.txid().hex() == txid
+    )
+    assert spend_entry()["status"] == "unbroadcast"
+    assert spend_entry()["broadcast_at"] is None
     lianad.rpc.broadcastspend(txid)
+    assert spend_entry()["status"] == "mempool"
+    assert spend_entry()["broadcast_at"] is not None
+
+    # Once it's mined the Spend is marked as confirmed.
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: spend_entry()["status"] == "confirmed")
+    assert spend_entry()["confirmation_height"] == bitcoind.rpc.getblockcount()
 
 
 def test_start_rescan(lianad, bitcoind):
@@ -793,6 +882,59 @@ def test_listtransactions(lianad, bitcoind):
     assert bit_txids == txids
 
 
+def test_listhistory(lianad, bitcoind):
+    """Test the wallet history, from the most recent to the oldest transaction."""
+    assert lianad.rpc.listhistory(10)["transactions"] == []
+
+    # Receive two coins in two separate transactions.
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid_a = bitcoind.rpc.sendtoaddress(addr, 0.1)
+    bitcoind.generate_block(1, wait_for_mempool=txid_a)
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid_b = bitcoind.rpc.sendtoaddress(addr, 0.2)
+    wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) == 2)
+
+    # The unconfirmed deposit comes first.
+    txs = lianad.rpc.listhistory(10)["transactions"]
+    assert [tx["txid"] for tx in txs] == [txid_b, txid_a]
+    assert txs[0]["direction"] == "incoming"
+    assert txs[0]["amount"] == int(0.2 * COIN)
+    assert txs[0]["fee"] is None and txs[0]["height"] is None
+    assert txs[1]["direction"] == "incoming"
+    assert txs[1]["amount"] == int(0.1 * COIN)
+    assert txs[1]["height"] == bitcoind.rpc.getblockcount()
+
+    # Spend the first coin to an external address.
+    bitcoind.generate_block(1, wait_for_mempool=txid_b)
+    coin = next(
+        c
+        for c in lianad.rpc.listcoins()["coins"]
+        if c["outpoint"].startswith(txid_a)
+    )
+    spend_tx = spend_coins(lianad, bitcoind, [coin])
+    spend_txid = get_txid(spend_tx)
+    wait_for(lambda: len(lianad.rpc.listhistory(10)["transactions"]) == 3)
+
+    txs = lianad.rpc.listhistory(10)["transactions"]
+    assert txs[0]["txid"] == spend_txid
+    assert txs[0]["direction"] == "outgoing"
+    assert -int(0.1 * COIN) <= txs[0]["amount"] < 0
+    assert txs[0]["fee"] > 0 and txs[0]["feerate"] > 0
+
+    # We can page through the history.
+    assert [tx["txid"] for tx in lianad.rpc.listhistory(2)["transactions"]] == [
+        spend_txid,
+        txid_b,
+    ]
+    page = lianad.rpc.listhistory(2, 2)["transactions"]
+    assert [tx["txid"] for tx in page] == [txid_a]
+    assert lianad.rpc.listhistory(2, 3)["transactions"] == []
+    with pytest.raises(RpcError, match="Invalid 'limit' parameter."):
+        lianad.rpc.listhistory(2**63)
+    with pytest.raises(RpcError, match="Invalid 'offset' parameter."):
+        lianad.rpc.listhistory(2, 2**63)
+
+
 def test_create_recovery(lianad, bitcoind):
     """Test the sweep of coins that are available through the timelocked path."""
     # Start by getting a few coins
@@ -841,6 +983,68 @@ def test_create_recovery(lianad, bitcoind):
     sign_and_broadcast(lianad, bitcoind, reco_psbt, recovery=True)
 
 
+def test_createmigration(lianad, bitcoind):
+    """Test migrating the funds of the wallet to a new descriptor."""
+    # Start by getting a few coins, and label one of them.
+    destinations = {
+        lianad.rpc.getnewaddress()["address"]: 0.1,
+        lianad.rpc.getnewaddress()["address"]: 0.2,
+        lianad.rpc.getnewaddress()["address"]: 0.3,
+    }
+    txid = bitcoind.rpc.sendmany("", destinations)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 3)
+    old_coins = lianad.rpc.listcoins()["coins"]
+    lianad.rpc.updatelabels({old_coins[0]["outpoint"]: "old-coin"})
+
+    # The new descriptor uses the same keys, with a longer timelock.
+    new_desc = Descriptor.from_str(
+        str(lianad.multi_desc).split("#")[0].replace("older(10)", "older(20)")
+    )
+    with pytest.raises(RpcError, match="same as the current one"):
+        lianad.rpc.createmigration(str(lianad.multi_desc), 2)
+
+    # Sweep the coins in two transactions.
+    res = lianad.rpc.createmigration(str(new_desc), 2, 2)
+    assert len(res["transactions"]) == 2
+    txids = []
+    for entry in res["transactions"]:
+        psbt = PSBT.from_base64(entry["psbt"])
+        assert len(psbt.tx.vout) == 1
+        txids.append(sign_and_broadcast(lianad, bitcoind, psbt))
+    bitcoind.generate_block(1, wait_for_mempool=txids)
+    wait_for(
+        lambda: all(
+            c["spend_info"] is not None and c["spend_info"]["height"] is not None
+            for c in lianad.rpc.listcoins()["coins"]
+        )
+    )
+
+    # Restart with the new descriptor. The wallet will switch to it and pick up the coins it
+    # received since the start of the migration.
+    lianad.stop()
+    lianad.update_descriptor(new_desc)
+    lianad.start()
+    assert lianad.is_in_log("Switched the wallet to the new descriptor")
+    main_desc = lianad.rpc.getinfo()["descriptors"]["main"]
+    assert main_desc.split("#")[0] == str(new_desc).split("#")[0]
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 2)
+    new_coins = lianad.rpc.listcoins(["confirmed"])["coins"]
+    assert sorted(c["address"] for c in new_coins) == sorted(
+        e["address"] for e in res["transactions"]
+    )
+    assert all(c["is_change"] for c in new_coins)
+
+    # The coins of the former descriptor are still part of the history, along with their labels.
+    wait_for(lambda: len(lianad.rpc.listcoins(["spent"])["coins"]) == 3)
+    spent_coins = lianad.rpc.listcoins(["spent"])["coins"]
+    assert sorted((c["outpoint"], c["address"]) for c in spent_coins) == sorted(
+        (c["outpoint"], c["address"]) for c in old_coins
+    )
+    outpoint = old_coins[0]["outpoint"]
+    assert lianad.rpc.getlabels([outpoint])["labels"] == {outpoint: "old-coin"}
+
+
 def test_labels(lianad, bitcoind):
     """Test the creation and updating of labels."""
     # We can set a label for an address.
@@ -952,3 +1156,152 @@ def test_labels(lianad, bitcoind):
     assert res[inexistent_txid] == "inex_txid"
     assert res[inexistent_outpoint] == "inex_outpoint"
     assert res[random_address] == "bitcoind-addr"
+
+
+def test_backup(lianad, bitcoind):
+    """Test we can restore the labels and history of a wallet from a backup."""
+    # Receive a coin and label it.
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 1)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) == 1)
+    coin = lianad.rpc.listcoins()["coins"][0]
+    lianad.rpc.updatelabels({addr: "addr", txid: "deposit", coin["outpoint"]: "coin"})
+
+    # The backup contains the descriptor and the labels.
+    backup = lianad.rpc.getbackup()
+    assert backup["version"] == 1
+    assert backup["descriptor"] == str(lianad.multi_desc)
+    assert backup["descriptor"].endswith("#" + backup["checksum"])
+    assert backup["network"] == "regtest"
+    assert backup["labels"] == {addr: "addr", txid: "deposit", coin["outpoint"]: "coin"}
+
+    # A backup whose checksum doesn't match the descriptor is refused.
+    with pytest.raises(RpcError, match="Backup checksum .* does not match"):
+        lianad.rpc.restorebackup({**backup, "checksum": "aaaaaaaa"})
+
+    # Move time forward one day as bitcoind will rescan the last 2 hours of block upon
+    # importing a descriptor.
+    now = int(time.time())
+    bitcoind.rpc.setmocktime(now + 60 * 60 * 24)
+    bitcoind.generate_block(10)
+
+    # After losing the wallet state, restoring the backup recovers the labels and rescans
+    # the chain from the wallet birthday.
+    lianad.restart_fresh(bitcoind)
+    assert len(lianad.rpc.listcoins()["coins"]) == 0
+    assert lianad.rpc.getlabels([addr])["labels"] == {}
+    lianad.rpc.restorebackup(backup)
+    wait_for(lambda: lianad.rpc.getinfo()["rescan_progress"] is None)
+    wait_for(
+        lambda: lianad.rpc.getinfo()["block_height"] == bitcoind.rpc.getblockcount()
+    )
+    assert lianad.rpc.listcoins()["coins"][0]["outpoint"] == coin["outpoint"]
+    labels = lianad.rpc.getlabels([addr, txid, coin["outpoint"]])["labels"]
+    assert labels == backup["labels"]
+    # The birthday of the new wallet is the one of the backup.
+    assert lianad.rpc.getbackup()["birthday"] == backup["birthday"]
+
+
+def test_sign_message(lianad, bitcoind):
+    """Test we can prove control of one of our addresses with a BIP322 signature."""
+    addr = lianad.rpc.getnewaddress()["address"]
+    message = "I control this address."
+
+    # We can't sign for an address that isn't ours.
+    with pytest.raises(RpcError, match="is not ours"):
+        lianad.rpc.signmessage(bitcoind.rpc.getnewaddress(), message)
+
+    # The PSBT of the virtual transaction can't be finalized until signed.
+    res = lianad.rpc.signmessage(addr, message)
+    assert res["signature"] is None
+    signed_psbt = lianad.signer.sign_psbt(PSBT.from_base64(res["psbt"]))
+    res = lianad.rpc.signmessage(addr, message, signed_psbt.to_base64())
+    sig = res["signature"]
+    assert sig is not None
+
+    # The signature is only valid for this message and this address.
+    assert lianad.rpc.verifymessage(addr, message, sig)["valid"]
+    assert not lianad.rpc.verifymessage(addr, "I don't.", sig)["valid"]
+    other_addr = lianad.rpc.getnewaddress()["address"]
+    assert not lianad.rpc.verifymessage(other_addr, message, sig)["valid"]
+
+    # A PSBT for another message can't be used.
+    with pytest.raises(RpcError, match="not for this message"):
+        lianad.rpc.signmessage(addr, "I don't.", signed_psbt.to_base64())
+
+
+def test_proof_of_reserves(lianad, bitcoind):
+    """Test we can prove ownership of our coins without moving them."""
+    message = "Liana reserves audit."
+
+    # We need a confirmed coin to create a proof.
+    with pytest.raises(RpcError, match="No confirmed coin"):
+        lianad.rpc.createproofofreserves(message)
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.5)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 1)
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.2)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins(["confirmed"])["coins"]) == 2)
+
+    # The proof is only valid once signed, and for this message.
+    res = lianad.rpc.createproofofreserves(message)
+    with pytest.raises(RpcError, match="Failed to finalize"):
+        lianad.rpc.verifyproofofreserves(message, res["psbt"])
+    signed_psbt = lianad.signer.sign_psbt(PSBT.from_base64(res["psbt"]))
+    res = lianad.rpc.verifyproofofreserves(message, signed_psbt.to_base64())
+    assert res["amount"] == 70_000_000
+    with pytest.raises(RpcError, match="not for this message"):
+        lianad.rpc.verifyproofofreserves("Another message.", signed_psbt.to_base64())
+
+    # Once one of the coins is spent, the proof isn't valid anymore.
+    coin = lianad.rpc.listcoins(["confirmed"])["coins"][0]
+    destinations = {bitcoind.rpc.getnewaddress(): 100_000}
+    res = lianad.rpc.createspend(destinations, [coin["outpoint"]], 2)
+    spend_psbt = lianad.signer.sign_psbt(PSBT.from_base64(res["psbt"]))
+    lianad.rpc.updatespend(spend_psbt.to_base64())
+    spend_txid = spend_psbt.tx.txid().hex()
+    lianad.rpc.broadcastspend(spend_txid)
+    bitcoind.generate_block(1, wait_for_mempool=spend_txid)
+    with pytest.raises(RpcError, match="is not an unspent confirmed coin"):
+        lianad.rpc.verifyproofofreserves(message, signed_psbt.to_base64())
+
+
+def test_psbt_v2(lianad, bitcoind):
+    """Test we can export stored Spends as version 2 PSBTs and import them back."""
+    addr = lianad.rpc.getnewaddress()["address"]
+    txid = bitcoind.rpc.sendtoaddress(addr, 0.1)
+    bitcoind.generate_block(1, wait_for_mempool=txid)
+    wait_for(lambda: len(lianad.rpc.listcoins()["coins"]) == 1)
+    outpoints = [c["outpoint"] for c in lianad.rpc.listcoins()["coins"]]
+    destinations = {bitcoind.rpc.getnewaddress(): 200_000}
+    res = lianad.rpc.createspend(destinations, outpoints, 2)
+    lianad.rpc.updatespend(res["psbt"])
+
+    # Stored Spends are returned as version 0 PSBTs by default.
+    assert lianad.rpc.listspendtxs()["spend_txs"][0]["psbt"] == res["psbt"]
+    assert lianad.rpc.listspendtxs(0)["spend_txs"][0]["psbt"] == res["psbt"]
+    with pytest.raises(RpcError, match="Invalid 'psbt_version' parameter"):
+        lianad.rpc.listspendtxs(1)
+
+    # They can be exported as version 2, and imported back without losing anything.
+    psbt_v2 = lianad.rpc.listspendtxs(2)["spend_txs"][0]["psbt"]
+    assert psbt_v2 != res["psbt"]
+    lianad.rpc.updatespend(psbt_v2)
+    spend_txs = lianad.rpc.listspendtxs()["spend_txs"]
+    assert len(spend_txs) == 1
+    assert spend_txs[0]["psbt"] == res["psbt"]
+
+    # Signatures given in a version 2 PSBT are merged too. We can't sign version 2 PSBTs
+    # in the test framework, so sign first and convert through the daemon.
+    signed_psbt = lianad.signer.sign_psbt(PSBT.from_base64(res["psbt"]))
+    lianad.rpc.updatespend(signed_psbt.to_base64())
+    signed_v2 = lianad.rpc.listspendtxs(2)["spend_txs"][0]["psbt"]
+    lianad.rpc.delspendtx(signed_psbt.tx.txid().hex())
+    lianad.rpc.updatespend(res["psbt"])
+    lianad.rpc.updatespend(signed_v2)
+    stored_psbt = PSBT.from_base64(lianad.rpc.listspendtxs()["spend_txs"][0]["psbt"])
+    assert len(stored_psbt.i[0].map[PSBT_IN_PARTIAL_SIG]) > 0